@@ -0,0 +1,40 @@
+//! Mixes arbitrary samples between arbitrary channel counts, both the
+//! block and the sample by sample paths the audio thread takes have to
+//! handle every layout without panicking and agree with each other
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../src/mix.rs"]
+mod mix;
+
+use mix::{MixMatrix, Mixer};
+
+fuzz_target!(|data: &[u8]| {
+    let [inputs, outputs, rest @ ..] = data else {
+        return;
+    };
+    let inputs = (*inputs % 8) as usize + 1;
+    let outputs = (*outputs % 8) as usize + 1;
+    let samples: Vec<f32> = rest
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .filter(|sample| sample.is_finite())
+        .collect();
+
+    let matrix = MixMatrix::default_for(inputs, outputs);
+    let frames = samples.len() / inputs;
+    let mut block = vec![0.0; frames * outputs];
+    matrix.apply_frames(&samples[..frames * inputs], &mut block);
+
+    let mut mixer = Mixer::new(matrix);
+    let mut source = samples.iter().copied();
+    let single: Vec<f32> = (0..block.len())
+        .map(|_| mixer.next(|| source.next().unwrap_or_default()))
+        .collect();
+
+    for (a, b) in block.iter().zip(&single) {
+        assert!((a - b).abs() <= 1e-3 * a.abs().max(b.abs()).max(1.0));
+    }
+});
@@ -0,0 +1,21 @@
+//! Parses arbitrary text as a config file and writes an entry back into
+//! it, malformed files have to be rejected with an error instead of
+//! panicking
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../src/config/parser.rs"]
+mod parser;
+
+fuzz_target!(|input: &str| {
+    let parsed = parser::parse(input);
+
+    let path = ["profiles".to_string(), "fuzz".to_string()];
+    let written = parser::set_entries(input, &path, &[("gain", parser::Value::Float(-6.0))]);
+    // Writing an entry into a valid file keeps it valid
+    if parsed.is_ok() {
+        parser::parse(&written).expect("the written config doesn't parse");
+    }
+});
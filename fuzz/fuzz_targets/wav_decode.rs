@@ -0,0 +1,16 @@
+//! Decodes arbitrary bytes as a WAV file, malformed files have to be
+//! rejected with an error instead of panicking
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../src/wav/decode.rs"]
+mod decode;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(audio) = decode::decode(data) {
+        assert!(audio.channels > 0 && audio.sample_rate > 0);
+        assert_eq!(audio.samples.len() % audio.channels as usize, 0);
+    }
+});
@@ -0,0 +1,71 @@
+//! Time alignment of two recordings of the same sound by finding the
+//! offset where they correlate best
+
+use crate::fft::fft;
+
+/// Offset found between two signals
+#[derive(Debug, Clone, Copy)]
+pub struct Alignment {
+    /// Samples the second signal is delayed by relative to the first,
+    /// negative when the second signal is ahead
+    pub lag: isize,
+    /// Normalized correlation at the offset, 1 for identical signals and
+    /// -1 for identical signals with the opposite polarity
+    pub correlation: f64,
+}
+
+/// Finds the offset of at most `max_lag` samples either way where the
+/// `second` signal lines up with the `first`, [None] when either is
+/// silent
+pub fn align(first: &[f32], second: &[f32], max_lag: usize) -> Option<Alignment> {
+    let length = (first.len() + second.len()).next_power_of_two();
+    let transform = |signal: &[f32]| {
+        let mut re: Vec<f64> = signal.iter().map(|&sample| sample as f64).collect();
+        re.resize(length, 0.0);
+        let mut im = vec![0.0; length];
+        fft(&mut re, &mut im, false);
+        (re, im)
+    };
+    let (first_re, first_im) = transform(first);
+    let (mut re, mut im) = transform(second);
+
+    // Multiplying by the conjugate of the first gives the correlation
+    // of the second against the first at each lag
+    for index in 0..length {
+        let (a_re, a_im) = (first_re[index], -first_im[index]);
+        let (b_re, b_im) = (re[index], im[index]);
+        re[index] = a_re * b_re - a_im * b_im;
+        im[index] = a_re * b_im + a_im * b_re;
+    }
+    fft(&mut re, &mut im, true);
+
+    let energy = |signal: &[f32]| signal.iter().map(|&s| s as f64 * s as f64).sum::<f64>();
+    let norm = (energy(first) * energy(second)).sqrt();
+    if norm <= f64::EPSILON {
+        return None;
+    }
+
+    let max_lag = max_lag.min(length / 2 - 1) as isize;
+    (-max_lag..=max_lag)
+        .map(|lag| {
+            // Negative lags wrap around to the end of the correlation
+            let index = lag.rem_euclid(length as isize) as usize;
+            Alignment {
+                lag,
+                correlation: re[index] / norm,
+            }
+        })
+        .max_by(|a, b| a.correlation.abs().total_cmp(&b.correlation.abs()))
+}
+
+/// Overlapping parts of the `first` and `second` signals once the second
+/// is moved back by the `lag`
+pub fn overlap<'a>(first: &'a [f32], second: &'a [f32], lag: isize) -> (&'a [f32], &'a [f32]) {
+    let (first, second) = if lag >= 0 {
+        (first, second.get(lag as usize..).unwrap_or_default())
+    } else {
+        (first.get(lag.unsigned_abs()..).unwrap_or_default(), second)
+    };
+    let length = first.len().min(second.len());
+    (&first[..length], &second[..length])
+}
@@ -0,0 +1,47 @@
+//! Selection of ALSA devices by their raw identifiers (e.g. `alsa:hw:1,0`)
+//! so the hardware can be opened directly, bypassing PulseAudio or
+//! PipeWire to find out whether the sound server is the problem
+
+use crate::log::debug;
+use alsa::ctl::Ctl;
+
+/// Prefix of device names that are raw ALSA identifiers
+pub const PREFIX: &str = "alsa:";
+
+/// Name ALSA lists the device with the `identifier` as. Cards and
+/// devices given by number (`hw:1,0`) are listed by the id of the card
+/// (`hw:CARD=PCH,DEV=0`), other identifiers are listed as they are
+pub fn listed_name(identifier: &str) -> String {
+    let Some((interface, params)) = identifier.split_once(':') else {
+        return identifier.to_string();
+    };
+
+    let params: Vec<&str> = params.split(',').collect();
+    let (card, device) = match params.as_slice() {
+        [card] => (*card, "0"),
+        [card, device] => (*card, *device),
+        _ => return identifier.to_string(),
+    };
+    if card.contains('=') || device.contains('=') {
+        return identifier.to_string();
+    }
+
+    let card = match card.parse::<u32>() {
+        Ok(index) => match card_id(index) {
+            Some(id) => id,
+            None => return identifier.to_string(),
+        },
+        Err(_) => card.to_string(),
+    };
+    format!("{interface}:CARD={card},DEV={device}")
+}
+
+/// Id of the sound card with the `index` (e.g. "PCH"), [None] when
+/// there is no such card
+fn card_id(index: u32) -> Option<String> {
+    let ctl = Ctl::new(&format!("hw:{index}"), false)
+        .inspect_err(|err| debug!("failed to open ALSA card {index}: {err}"))
+        .ok()?;
+    let info = ctl.card_info().ok()?;
+    info.get_id().ok().map(str::to_string)
+}
@@ -0,0 +1,243 @@
+//! Analysis of the captured audio on a background thread, detecting the
+//! glitches and measuring the weighted levels and the levels of the
+//! bands, so the input callback only applies the gain and passes the
+//! samples on
+
+use crate::{
+    bands::BandFilter,
+    glitch::{Glitch, GlitchDetector, GlitchKind},
+    meter::LevelMeter,
+    stats::StreamStats,
+    weighting::{Weighting, WeightingFilter},
+};
+use cpal::StreamConfig;
+use ringbuf::{HeapProducer, HeapRb};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Captured audio held while waiting to be analysed
+const BUFFER: Duration = Duration::from_secs(1);
+/// Time between analysing the captured samples
+const ANALYSIS_INTERVAL: Duration = Duration::from_millis(5);
+/// Frames analysed at a time
+const BLOCK_FRAMES: usize = 1024;
+
+/// Analysis applied to the captured audio
+pub struct Analysis {
+    /// Linear gain applied to the captured samples before they are
+    /// measured, glitches are found in the untouched capture
+    pub gain: f32,
+    /// Frequency weighting of the measured levels
+    pub weighting: Weighting,
+    /// Filter measuring the levels of the bands
+    pub band_filter: Option<BandFilter>,
+}
+
+/// Input callback side of the buffer the captured audio is analysed
+/// from, only whole frames are passed on
+pub struct AnalysisTap {
+    producer: HeapProducer<f32>,
+    channels: usize,
+    stats: Arc<StreamStats>,
+}
+
+impl AnalysisTap {
+    /// Passes the untouched captured `samples` on to be analysed, the
+    /// frames that don't fit are counted and left out of the analysis
+    pub fn push(&mut self, samples: &[f32]) {
+        let room = self.producer.free_len() / self.channels * self.channels;
+        let pushed = self
+            .producer
+            .push_slice(&samples[..room.min(samples.len())]);
+        if pushed < samples.len() {
+            self.stats
+                .analysis_dropped
+                .fetch_add((samples.len() - pushed) as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Background thread analysing the captured audio passed on through its
+/// [AnalysisTap]
+pub struct Analyzer {
+    /// Whether the analysis should stop
+    stop: Arc<AtomicBool>,
+    /// Thread doing the analysis
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Analyzer {
+    /// Starts analysing the audio captured in the `config` format,
+    /// counting the glitches in the `stats` and passing them on through
+    /// the `glitches` buffer, and recording the levels in the `meter`.
+    /// Returns the tap the input callback passes the captured audio to
+    pub fn start(
+        config: &StreamConfig,
+        stats: Arc<StreamStats>,
+        meter: Arc<LevelMeter>,
+        analysis: Analysis,
+        mut glitches: HeapProducer<Glitch>,
+    ) -> (Self, AnalysisTap) {
+        let channels = config.channels.max(1) as usize;
+        let capacity = (BUFFER.as_secs_f64() * config.sample_rate.0 as f64) as usize * channels;
+        let ring: HeapRb<f32> = HeapRb::new(capacity);
+        let (producer, mut consumer) = ring.split();
+        let tap = AnalysisTap {
+            producer,
+            channels,
+            stats: stats.clone(),
+        };
+
+        let Analysis {
+            gain,
+            weighting,
+            mut band_filter,
+        } = analysis;
+        let mut filter = WeightingFilter::new(weighting, config.sample_rate.0, config.channels);
+        let mut detector = GlitchDetector::new(config.sample_rate.0, config.channels);
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                // Whole frames are taken as the tap only holds whole frames
+                let mut captured = vec![0.0f32; BLOCK_FRAMES * channels];
+                let mut gained = Vec::with_capacity(captured.len());
+
+                while !stop.load(Ordering::Relaxed) {
+                    thread::sleep(ANALYSIS_INTERVAL);
+
+                    loop {
+                        let count = consumer.pop_slice(&mut captured);
+                        if count == 0 {
+                            break;
+                        }
+                        let data = &captured[..count];
+
+                        detector.process(data, |glitch| {
+                            let counter = match glitch.kind {
+                                GlitchKind::Dropout => &stats.dropouts,
+                                GlitchKind::Jump => &stats.sample_jumps,
+                            };
+                            counter.fetch_add(1, Ordering::Relaxed);
+                            // Glitches past the pending limit are counted
+                            // but not logged
+                            _ = glitches.push(glitch);
+                        });
+
+                        let data = if gain == 1.0 {
+                            data
+                        } else {
+                            gained.clear();
+                            gained.extend(data.iter().map(|sample| sample * gain));
+                            &gained
+                        };
+                        match &mut filter {
+                            Some(filter) => meter.record(filter.process(data)),
+                            None => meter.record(data),
+                        }
+                        if let Some(band_filter) = &mut band_filter {
+                            let (energies, frames) = band_filter.process(data);
+                            meter.record_bands(energies, frames);
+                        }
+                    }
+                }
+            }
+        });
+
+        (
+            Self {
+                stop,
+                handle: Some(handle),
+            },
+            tap,
+        )
+    }
+}
+
+/// Analysis stops when the analyzer is dropped along with the streams
+impl Drop for Analyzer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::glitch;
+    use cpal::{BufferSize, SampleRate};
+    use std::time::Instant;
+
+    fn config(sample_rate: u32, channels: u16) -> StreamConfig {
+        StreamConfig {
+            channels,
+            sample_rate: SampleRate(sample_rate),
+            buffer_size: BufferSize::Default,
+        }
+    }
+
+    #[test]
+    fn tap_only_passes_whole_frames() {
+        let stats = Arc::new(StreamStats::default());
+        let (producer, mut consumer) = HeapRb::new(5).split();
+        let mut tap = AnalysisTap {
+            producer,
+            channels: 2,
+            stats: stats.clone(),
+        };
+
+        tap.push(&[0.1, 0.2, 0.3, 0.4, 0.5, 0.6]);
+        assert_eq!(stats.analysis_dropped.load(Ordering::Relaxed), 2);
+        let mut taken = [0.0; 5];
+        assert_eq!(consumer.pop_slice(&mut taken), 4);
+        assert_eq!(taken[..4], [0.1, 0.2, 0.3, 0.4]);
+    }
+
+    #[test]
+    fn analyzer_measures_the_gained_levels_and_finds_glitches() {
+        let stats = Arc::new(StreamStats::default());
+        let meter = Arc::new(LevelMeter::new(1));
+        let (glitches, mut glitch_consumer) = glitch::channel();
+        let analysis = Analysis {
+            gain: 2.0,
+            weighting: Weighting::Z,
+            band_filter: None,
+        };
+        let (analyzer, mut tap) = Analyzer::start(
+            &config(1000, 1),
+            stats.clone(),
+            meter.clone(),
+            analysis,
+            glitches,
+        );
+
+        // Signal with a 10 ms run of zeros in the middle
+        let mut samples = vec![0.25; 500];
+        samples.extend([0.0; 10]);
+        samples.extend([0.25; 100]);
+        tap.push(&samples);
+
+        let start = Instant::now();
+        while stats.dropouts.load(Ordering::Relaxed) == 0 && start.elapsed() < BUFFER {
+            thread::sleep(ANALYSIS_INTERVAL);
+        }
+        drop(analyzer);
+
+        let glitch = glitch_consumer.pop().unwrap();
+        assert_eq!(glitch.kind, GlitchKind::Dropout);
+        assert_eq!((glitch.frame, glitch.frames), (500, 10));
+        assert!(glitch_consumer.pop().is_none());
+        assert_eq!(meter.take().peak, 0.5);
+        assert_eq!(stats.analysis_dropped.load(Ordering::Relaxed), 0);
+    }
+}
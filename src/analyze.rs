@@ -0,0 +1,166 @@
+//! Analysis of an existing recording with the same measurements as the
+//! monitor, so recordings made elsewhere can be checked the same way
+
+use crate::{
+    bands::{self, BandFilter, BANDS},
+    error::ChemicError,
+    log::info,
+    loudness,
+    meter::to_db,
+    wav,
+};
+use std::{io, path::Path};
+
+/// Length of the windows the noise floor is measured over
+const NOISE_WINDOW_SECONDS: f64 = 0.05;
+/// DC offset above which the offset is pointed out
+const DC_OFFSET_WARNING: f32 = 0.001;
+
+/// Reads the WAV file at the `path` and prints its levels, loudness,
+/// clipping, DC offset, noise floor and spectrum
+pub fn run(path: &Path) -> Result<(), ChemicError> {
+    let audio = wav::read(path).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!("Failed to read {}: {err}", path.display()),
+        )
+    })?;
+    let channels = audio.channels.max(1) as usize;
+    let frames = audio.samples.len() / channels;
+    let seconds = frames as f64 / audio.sample_rate.max(1) as f64;
+
+    println!(
+        "{}: {seconds:.1}s, {}Hz, {} channel{}\n",
+        path.display(),
+        audio.sample_rate,
+        channels,
+        if channels == 1 { "" } else { "s" }
+    );
+    if frames == 0 {
+        println!("The file has no audio");
+        return Ok(());
+    }
+
+    let peak = audio
+        .samples
+        .iter()
+        .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+    let clipped = audio
+        .samples
+        .iter()
+        .filter(|sample| sample.abs() >= 1.0)
+        .count();
+    let lufs = loudness::integrated(&audio.samples, audio.sample_rate, audio.channels);
+    let mono: Vec<f32> = audio
+        .samples
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    let noise = noise_floor(&mono, audio.sample_rate);
+
+    let db = |value: Option<f32>| match value {
+        Some(db) => format!("{db:.1} dBFS"),
+        None => "silent".to_string(),
+    };
+    println!("Peak:          {}", db(to_db(peak)));
+    println!("RMS:           {}", db(to_db(rms(&audio.samples))));
+    match lufs {
+        Some(lufs) => println!("Loudness:      {lufs:.1} LUFS integrated"),
+        None => println!("Loudness:      below the gate"),
+    }
+    println!("Noise floor:   {}", db(to_db(noise)));
+    match clipped {
+        0 => println!("Clipping:      none"),
+        clipped => println!(
+            "Clipping:      {clipped} sample{} at full scale",
+            if clipped == 1 { "" } else { "s" }
+        ),
+    }
+
+    for channel in 0..channels {
+        let sum: f64 = audio
+            .samples
+            .iter()
+            .skip(channel)
+            .step_by(channels)
+            .map(|&sample| sample as f64)
+            .sum();
+        let offset = (sum / frames as f64) as f32;
+        let label = if channels == 1 {
+            "DC offset:     ".to_string()
+        } else {
+            format!("DC offset {}:   ", channel + 1)
+        };
+        let warning = if offset.abs() >= DC_OFFSET_WARNING {
+            ", check the input for a faulty preamp or converter"
+        } else {
+            ""
+        };
+        println!("{label}{:+.5} ({:+.3}%){warning}", offset, offset * 100.0);
+    }
+    info!(
+        "analyzed {}: peak {peak:.3}, loudness {lufs:?} LUFS, {clipped} clipped",
+        path.display()
+    );
+
+    print_spectrum(&mono, audio.sample_rate);
+    Ok(())
+}
+
+/// Prints the level of each one third octave band of the mono `samples`
+/// relative to the loudest band
+fn print_spectrum(samples: &[f32], sample_rate: u32) {
+    let mut filter = BandFilter::new(sample_rate, 1);
+    let (energies, frames) = filter.process(samples);
+    let levels: Vec<Option<f32>> = energies
+        .iter()
+        .map(|&energy| to_db((energy / frames.max(1) as f64).sqrt() as f32))
+        .collect();
+    let Some(loudest) = levels.iter().flatten().copied().reduce(f32::max) else {
+        return;
+    };
+
+    match bands::tilt(energies) {
+        Some(tilt) => println!("Spectral tilt: {tilt:+.1} dB/oct\n"),
+        None => println!(),
+    }
+    println!("Spectrum relative to the loudest band\n");
+    for (band, level) in levels.iter().enumerate().take(BANDS) {
+        let frequency = bands::center_frequency(band);
+        if frequency >= sample_rate as f64 * 0.45 {
+            break;
+        }
+        let label = if frequency >= 1000.0 {
+            format!("{:.1} kHz", frequency / 1000.0)
+        } else {
+            format!("{frequency:.0} Hz")
+        };
+        match level {
+            Some(level) => {
+                let relative = level - loudest;
+                // One character for each decibel above 60 dB below the
+                // loudest band
+                let bar = "#".repeat((60.0 + relative.max(-60.0)).round() as usize);
+                println!("{label:>9}  {relative:>6.1} dB  {bar}");
+            }
+            None => println!("{label:>9}  silent"),
+        }
+    }
+}
+
+/// Root mean square of the `samples`
+pub fn rms(samples: &[f32]) -> f32 {
+    let sum: f64 = samples
+        .iter()
+        .map(|&sample| sample as f64 * sample as f64)
+        .sum();
+    (sum / samples.len().max(1) as f64).sqrt() as f32
+}
+
+/// RMS level of the quietest tenth of the windows of the mono `samples`
+pub fn noise_floor(samples: &[f32], sample_rate: u32) -> f32 {
+    let window = ((NOISE_WINDOW_SECONDS * sample_rate as f64) as usize).max(1);
+    let mut levels: Vec<f32> = samples.chunks(window).map(rms).collect();
+    levels.sort_by(f32::total_cmp);
+    levels.get(levels.len() / 10).copied().unwrap_or_default()
+}
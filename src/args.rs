@@ -0,0 +1,1248 @@
+//! Parsing of the command line arguments
+
+use crate::{
+    bed::BedSource,
+    completions::Shell,
+    config::{Config, Profile},
+    controls::Volume,
+    events::EventFormat,
+    generate::TestSignal,
+    lang::Language,
+    log::LogFormat,
+    mix::Downmix,
+    pattern::DevicePattern,
+    processor::ProcessorSpec,
+    record::SegmentLimit,
+    wav::SampleFormat,
+    weighting::Weighting,
+    DeviceType,
+};
+use std::{env, io, iter::Peekable, path::PathBuf, str::FromStr};
+
+/// Command to run, selected by the first argument
+#[derive(Default, Clone)]
+pub enum Command {
+    /// Play the input device through the output device
+    #[default]
+    Monitor,
+    /// Print the shell completion script for a shell
+    Completions(Shell),
+    /// Print the names of the available devices
+    Devices(Option<DeviceType>),
+    /// Print the supported stream configs of the named device
+    Configs(String),
+    /// Watch the input device in the background for silence and failures
+    Daemon,
+    /// Print devices as they are added and removed
+    Watch,
+    /// Test each input device in turn and compare their levels
+    SweepInputs,
+    /// Measure how fast the audio processing path runs
+    Bench,
+    /// Check the polarity of the input by playing and capturing pulses
+    Polarity,
+    /// Estimate the reverberation time of the room with noise bursts
+    Rt60,
+    /// Measure the level of a reference sound to calibrate the input
+    /// in dB SPL, optionally with the level of the reference sound
+    Calibrate(Option<f32>),
+    /// Play a test tone and report the harmonics captured by the input,
+    /// optionally with the frequency of the tone
+    Harmonics(Option<f32>),
+    /// Guide connecting the output to the input and run the loopback
+    /// measurements
+    Loopback,
+    /// Monitor for a long time reporting the stability of the streams
+    Soak,
+    /// Record while a key is held and play the take back once let go
+    PushToRecord,
+    /// Spoken test of the output channels and a recording of the input
+    Guided,
+    /// Speech to text check of a test sentence read into the input
+    Intelligibility(PathBuf),
+    /// Differences between two recordings of the same sound
+    Compare(PathBuf, PathBuf),
+    /// Residual of two inputs carrying the same source subtracted
+    NullTest(Option<String>),
+    /// Capture of a reference file played through the output
+    Reference(PathBuf),
+    /// Levels, loudness and spectrum of an existing recording
+    Analyze(PathBuf),
+    /// Conversion of a WAV file to another sample rate or channels
+    Convert(PathBuf, PathBuf),
+    /// Sweep of the buffer sizes finding the smallest without underruns
+    Tune,
+    /// Table of the latency and underrun rate at each buffer size
+    LatencyReport,
+    /// Test signal written to a WAV file
+    Generate,
+    /// Tone played through each channel of the output in turn
+    SpeakerTest,
+    /// Check that the left and right headphone channels aren't swapped
+    LeftRight,
+    /// Guided setup of the devices and input gain for new users
+    Setup,
+    /// Trial of every advertised stream config of the named device
+    Audit(String),
+    /// Streams opened and closed over and over to stress the driver
+    Stress,
+    /// Check of whether the input can be opened by two streams at once
+    SharedAccess,
+}
+
+/// Value expected after a flag
+#[derive(Clone, Copy)]
+pub enum FlagValue {
+    /// Name of a device of the provided type
+    Device(DeviceType),
+    /// Path to a file
+    Path,
+    /// Any other value
+    Text,
+}
+
+/// Description of a flag accepted on the command line
+pub struct Flag {
+    /// Short form of the flag (Without the leading dash)
+    pub short: &'static str,
+    /// Long form of the flag (Without the leading dashes)
+    pub long: &'static str,
+    /// Value the flag expects if any
+    pub value: Option<FlagValue>,
+    /// Description of the flag
+    pub help: &'static str,
+}
+
+/// Range of tempos the metronome can click at in beats per minute
+const MIN_BPM: f64 = 20.0;
+const MAX_BPM: f64 = 400.0;
+
+/// All of the flags accepted by the monitor command
+pub const FLAGS: &[Flag] = &[
+    Flag {
+        short: "d",
+        long: "default",
+        value: None,
+        help: "Use the default input and output devices",
+    },
+    Flag {
+        short: "ld",
+        long: "last",
+        value: None,
+        help: "Use the input and output devices chosen last time",
+    },
+    Flag {
+        short: "ni",
+        long: "non-interactive",
+        value: None,
+        help: "Never prompt, using the requested or default devices and failing when one is missing",
+    },
+    Flag {
+        short: "dly",
+        long: "delay",
+        value: None,
+        help: "Delay the audio played back to the output device",
+    },
+    Flag {
+        short: "ndly",
+        long: "no-delay",
+        value: None,
+        help: "Don't delay the audio, even when the profile does",
+    },
+    Flag {
+        short: "sdt",
+        long: "sidetone",
+        value: None,
+        help: "Monitor with the lowest latency, playing the captured audio without conversion or effects",
+    },
+    Flag {
+        short: "nsdt",
+        long: "no-sidetone",
+        value: None,
+        help: "Don't monitor as sidetone, even when the profile does",
+    },
+    Flag {
+        short: "bs",
+        long: "buffer-size",
+        value: Some(FlagValue::Text),
+        help: "Buffer size in frames for both devices instead of the smallest they support",
+    },
+    Flag {
+        short: "jb",
+        long: "jitter-buffer",
+        value: Some(FlagValue::Text),
+        help: "Milliseconds of audio the jitter buffer holds before adapting to the jitter (default 20)",
+    },
+    Flag {
+        short: "q",
+        long: "quiet",
+        value: None,
+        help: "Only print errors, hiding the banner, device info and hints",
+    },
+    Flag {
+        short: "e",
+        long: "events",
+        value: Some(FlagValue::Text),
+        help: "Print machine readable events to stdout instead of the normal output (json)",
+    },
+    Flag {
+        short: "pl",
+        long: "plain",
+        value: None,
+        help: "Disable colors and screen redrawing in the output",
+    },
+    Flag {
+        short: "a",
+        long: "accessible",
+        value: None,
+        help: "Announce the input level as periodic text lines instead of a bar meter",
+    },
+    Flag {
+        short: "na",
+        long: "no-accessible",
+        value: None,
+        help: "Show the bar meter, even when the profile uses text lines",
+    },
+    Flag {
+        short: "b",
+        long: "bands",
+        value: None,
+        help: "Show the input level in one third octave bands instead of a bar meter",
+    },
+    Flag {
+        short: "nb",
+        long: "no-bands",
+        value: None,
+        help: "Show the bar meter, even when the profile uses the bands",
+    },
+    Flag {
+        short: "mi",
+        long: "meter-interval",
+        value: Some(FlagValue::Text),
+        help: "Seconds between the text level lines (default 2)",
+    },
+    Flag {
+        short: "v",
+        long: "verbose",
+        value: None,
+        help: "Log debug information to stderr, repeat (-vv) for more detail",
+    },
+    Flag {
+        short: "lf",
+        long: "log-file",
+        value: Some(FlagValue::Path),
+        help: "Append stream errors, device changes and results to a log file",
+    },
+    Flag {
+        short: "lfmt",
+        long: "log-format",
+        value: Some(FlagValue::Text),
+        help: "Format of the log file (text or json)",
+    },
+    Flag {
+        short: "l",
+        long: "lang",
+        value: Some(FlagValue::Text),
+        help: "Language for prompts and messages (en, es, de)",
+    },
+    Flag {
+        short: "i",
+        long: "input",
+        value: Some(FlagValue::Device(DeviceType::Input)),
+        help: "Name of the input device to use",
+    },
+    Flag {
+        short: "o",
+        long: "output",
+        value: Some(FlagValue::Device(DeviceType::Output)),
+        help: "Name of the output device to use",
+    },
+    Flag {
+        short: "ir",
+        long: "input-regex",
+        value: Some(FlagValue::Text),
+        help: "Use the first input device whose name matches the regular expression",
+    },
+    Flag {
+        short: "or",
+        long: "output-regex",
+        value: Some(FlagValue::Text),
+        help: "Use the first output device whose name matches the regular expression",
+    },
+    Flag {
+        short: "r",
+        long: "sample-rate",
+        value: Some(FlagValue::Text),
+        help: "Sample rate to use for both devices, or to convert to",
+    },
+    Flag {
+        short: "ch",
+        long: "channels",
+        value: Some(FlagValue::Text),
+        help: "Number of channels to convert to or generate",
+    },
+    Flag {
+        short: "sig",
+        long: "signal",
+        value: Some(FlagValue::Text),
+        help: "Test signal generate writes: sweep, tone, tone:<HZ> or noise",
+    },
+    Flag {
+        short: "out",
+        long: "out",
+        value: Some(FlagValue::Path),
+        help: "Path of the WAV file generate writes",
+    },
+    Flag {
+        short: "lvl",
+        long: "level",
+        value: Some(FlagValue::Text),
+        help: "Level in dBFS of the generated signal (the peak of tones and sweeps and the RMS of noise) or speaker-test and left-right tone (default -20)",
+    },
+    Flag {
+        short: "inv",
+        long: "invert",
+        value: None,
+        help: "Invert the polarity of the monitored audio",
+    },
+    Flag {
+        short: "ninv",
+        long: "no-invert",
+        value: None,
+        help: "Keep the polarity of the monitored audio, even when the profile inverts it",
+    },
+    Flag {
+        short: "dm",
+        long: "downmix",
+        value: Some(FlagValue::Text),
+        help: "How channels are mixed into a mono output (average, left, right or power-sum)",
+    },
+    Flag {
+        short: "w",
+        long: "weighting",
+        value: Some(FlagValue::Text),
+        help: "Frequency weighting of the level meter and noise measurements (a, c or z)",
+    },
+    Flag {
+        short: "g",
+        long: "gain",
+        value: Some(FlagValue::Text),
+        help: "Gain in decibels applied to the captured input before it is metered, monitored and recorded",
+    },
+    Flag {
+        short: "vol",
+        long: "volume",
+        value: Some(FlagValue::Text),
+        help: "Volume of the monitored output from 0 to 100 or in decibels (e.g. 50 or -6dB), changed while monitoring with the volume keys",
+    },
+    Flag {
+        short: "osg",
+        long: "set-os-gain",
+        value: Some(FlagValue::Text),
+        help: "Set the input volume of the input device in the OS mixer from 0 to 100 percent (e.g. 80%) before monitoring",
+    },
+    Flag {
+        short: "cl",
+        long: "ceiling",
+        value: Some(FlagValue::Text),
+        help: "Level in dBFS the monitored output is never played above (default -3dBFS)",
+    },
+    Flag {
+        short: "bed",
+        long: "bed",
+        value: Some(FlagValue::Text),
+        help: "Mix a reference tone (tone or tone:<HZ>) or a looped WAV file into the monitored output",
+    },
+    Flag {
+        short: "bl",
+        long: "bed-level",
+        value: Some(FlagValue::Text),
+        help: "Level of the bed in decibels, the peak of the tone or the gain of the file (default -20)",
+    },
+    Flag {
+        short: "proc",
+        long: "processor",
+        value: Some(FlagValue::Text),
+        help: "Process the monitored output with a stage (highpass[:HZ], gate[:DB] or plugin:<PATH>), repeat for more stages",
+    },
+    Flag {
+        short: "met",
+        long: "metronome",
+        value: Some(FlagValue::Text),
+        help: "Mix a metronome click at the beats per minute into the monitored output (20 to 400)",
+    },
+    Flag {
+        short: "st",
+        long: "silence-threshold",
+        value: Some(FlagValue::Text),
+        help: "Level in dBFS below which the daemon and soak test consider the input silent (default -60)",
+    },
+    Flag {
+        short: "sto",
+        long: "silence-timeout",
+        value: Some(FlagValue::Text),
+        help: "Seconds of silence before the daemon reports it (default 10)",
+    },
+    Flag {
+        short: "dur",
+        long: "duration",
+        value: Some(FlagValue::Text),
+        help: "Seconds each input is tested for by sweep-inputs (default 3) or of audio processed by bench (default 30)",
+    },
+    Flag {
+        short: "hr",
+        long: "hours",
+        value: Some(FlagValue::Text),
+        help: "Hours the soak test runs for (default 8)",
+    },
+    Flag {
+        short: "cy",
+        long: "cycles",
+        value: Some(FlagValue::Text),
+        help: "Times the stress test opens and closes the streams (default 100)",
+    },
+    Flag {
+        short: "rec",
+        long: "record",
+        value: Some(FlagValue::Path),
+        help: "Record the captured input to a WAV file while monitoring, the path may use {device}, {date}, {time} and {seq}",
+    },
+    Flag {
+        short: "rp",
+        long: "record-processed",
+        value: None,
+        help: "Also record the processed monitor audio to a second file ending in -processed",
+    },
+    Flag {
+        short: "nrp",
+        long: "no-record-processed",
+        value: None,
+        help: "Only record the captured input, even when the profile records the processed audio",
+    },
+    Flag {
+        short: "seg",
+        long: "segment",
+        value: Some(FlagValue::Text),
+        help: "Continue recordings in a new file after a length (e.g. 30m or 2h) or size (e.g. 500MB)",
+    },
+    Flag {
+        short: "trim",
+        long: "trim-silence",
+        value: Some(FlagValue::Text),
+        help: "Trim the silence below a level in dBFS (e.g. -50) from the start and end of recordings",
+    },
+    Flag {
+        short: "norm",
+        long: "normalize",
+        value: Some(FlagValue::Text),
+        help: "Bring the peak of recordings to a level in dBFS (e.g. -1dBFS) when they are saved",
+    },
+    Flag {
+        short: "bits",
+        long: "bits",
+        value: Some(FlagValue::Text),
+        help: "Bit depth recordings and converted files are saved with (16, 24 or 32f)",
+    },
+    Flag {
+        short: "dth",
+        long: "dither",
+        value: None,
+        help: "Add TPDF dither when saving 16 or 24 bit files",
+    },
+    Flag {
+        short: "ndth",
+        long: "no-dither",
+        value: None,
+        help: "Don't add dither, even when the profile does",
+    },
+    Flag {
+        short: "p",
+        long: "profile",
+        value: Some(FlagValue::Text),
+        help: "Use the named profile from the config file",
+    },
+    Flag {
+        short: "c",
+        long: "config",
+        value: Some(FlagValue::Path),
+        help: "Load the config file from a custom path",
+    },
+];
+
+/// Names of the commands that can be provided as the first argument
+pub const COMMANDS: &[&str] = &[
+    "completions",
+    "devices",
+    "configs",
+    "daemon",
+    "watch",
+    "sweep-inputs",
+    "bench",
+    "polarity",
+    "rt60",
+    "calibrate",
+    "harmonics",
+    "loopback",
+    "soak",
+    "push-to-record",
+    "guided",
+    "intelligibility",
+    "compare",
+    "null-test",
+    "reference",
+    "analyze",
+    "convert",
+    "tune",
+    "latency-report",
+    "generate",
+    "speaker-test",
+    "left-right",
+    "setup",
+    "audit",
+    "stress",
+    "shared-access",
+];
+
+/// Arguments provided on the command line
+#[derive(Default, Clone)]
+pub struct Args {
+    /// Command to run
+    pub command: Command,
+    /// Whether to use the default device
+    pub default: bool,
+    /// Whether to use the devices chosen last time
+    pub last: bool,
+    /// Whether to never prompt, failing instead
+    pub non_interactive: bool,
+    /// Whether to delay the audio, [None] when neither `--delay` or
+    /// `--no-delay` was provided
+    pub delay: Option<bool>,
+    /// Whether to monitor as sidetone with the lowest latency
+    pub sidetone: Option<bool>,
+    /// Buffer size in frames requested from both devices
+    pub buffer_size: Option<u32>,
+    /// Milliseconds of audio the jitter buffer targets at least
+    pub jitter_buffer: Option<f64>,
+    /// Whether to only print errors
+    pub quiet: bool,
+    /// Whether to disable colors and screen redrawing
+    pub plain: bool,
+    /// Format of the events to print to stdout
+    pub events: Option<EventFormat>,
+    /// Whether to show the input level as text lines for screen readers
+    pub accessible: Option<bool>,
+    /// Whether to show the input level in one third octave bands
+    pub bands: Option<bool>,
+    /// Seconds between the text level lines
+    pub meter_interval: Option<f64>,
+    /// Verbosity of the debug logging (Number of times -v was provided)
+    pub verbose: u8,
+    /// Path of the log file to write to
+    pub log_file: Option<PathBuf>,
+    /// Format of the log file
+    pub log_format: LogFormat,
+    /// Language to use for prompts and messages
+    pub lang: Option<Language>,
+    /// Name of the config profile to use
+    pub profile: Option<String>,
+    /// Path to a config file to use instead of the default location
+    pub config: Option<PathBuf>,
+    /// Name of the input device to use
+    pub input: Option<String>,
+    /// Name of the output device to use
+    pub output: Option<String>,
+    /// Pattern matching the name of the input device to use
+    pub input_regex: Option<DevicePattern>,
+    /// Pattern matching the name of the output device to use
+    pub output_regex: Option<DevicePattern>,
+    /// Names of the input devices to use in order of preference when
+    /// no input is provided
+    pub preferred_inputs: Vec<String>,
+    /// Names of the output devices to use in order of preference when
+    /// no output is provided
+    pub preferred_outputs: Vec<String>,
+    /// Sample rate to use for both devices
+    pub sample_rate: Option<u32>,
+    /// Number of channels files are converted to or generated with
+    pub channels: Option<u16>,
+    /// Test signal to generate
+    pub signal: Option<TestSignal>,
+    /// Path of the generated file
+    pub out: Option<PathBuf>,
+    /// Level in dBFS of the generated signal
+    pub level: Option<f32>,
+    /// How channels are mixed into a mono output
+    pub downmix: Option<Downmix>,
+    /// Frequency weighting of the measured levels
+    pub weighting: Option<Weighting>,
+    /// Whether to invert the polarity of the monitored audio
+    pub invert: Option<bool>,
+    /// Gain in decibels applied to the captured input
+    pub gain: Option<f32>,
+    /// Volume of the monitored output
+    pub volume: Option<Volume>,
+    /// Input volume to set in the OS mixer in percent
+    pub set_os_gain: Option<f32>,
+    /// Tone or file mixed into the monitored output
+    pub bed: Option<BedSource>,
+    /// Stages processing the monitored output in order
+    pub processors: Vec<ProcessorSpec>,
+    /// Level of the bed in decibels
+    pub bed_level: Option<f32>,
+    /// Beats per minute of the metronome mixed into the output
+    pub metronome: Option<f64>,
+    /// Level in dBFS below which the input is considered silent
+    pub silence_threshold: Option<f32>,
+    /// Seconds of silence before it is reported
+    pub silence_timeout: Option<f64>,
+    /// Seconds each input is tested for, or of audio processed by bench
+    pub duration: Option<f64>,
+    /// Hours the soak test runs for
+    pub hours: Option<f64>,
+    /// Times the stress test opens and closes the streams
+    pub cycles: Option<u32>,
+    /// Path of the WAV file the input is recorded to
+    pub record: Option<PathBuf>,
+    /// Whether the processed monitor audio is recorded as well
+    pub record_processed: Option<bool>,
+    /// Limit of the length of each recorded file
+    pub segment: Option<SegmentLimit>,
+    /// Level in dBFS below which the start and end of recordings are
+    /// trimmed as silence
+    pub trim_silence: Option<f32>,
+    /// Level in dBFS the peak of recordings is normalized to
+    pub normalize: Option<f32>,
+    /// Level in dBFS the monitored output is never played above
+    pub ceiling: Option<f32>,
+    /// Format the samples of recordings are saved in
+    pub bits: Option<SampleFormat>,
+    /// Whether dither is added when saving integer samples
+    pub dither: Option<bool>,
+}
+
+impl Args {
+    /// Parses the arguments provided to the program
+    pub fn parse() -> io::Result<Self> {
+        Self::parse_from(env::args().skip(1))
+    }
+
+    fn parse_from(args: impl IntoIterator<Item = String>) -> io::Result<Self> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter().peekable();
+
+        parsed.command = parse_command(&mut args)?;
+
+        while let Some(arg) = args.next() {
+            match arg.to_lowercase().as_str() {
+                "default" | "--default" | "d" | "-d" => parsed.default = true,
+                "--last" | "-ld" => parsed.last = true,
+                "--non-interactive" | "-ni" => parsed.non_interactive = true,
+                "delay" | "--delay" | "dly" | "-dly" => parsed.delay = Some(true),
+                "--no-delay" | "-ndly" => parsed.delay = Some(false),
+                "sidetone" | "--sidetone" | "sdt" | "-sdt" => parsed.sidetone = Some(true),
+                "--no-sidetone" | "-nsdt" => parsed.sidetone = Some(false),
+                "--buffer-size" | "-bs" => parsed.buffer_size = Some(take_parsed(&mut args, &arg)?),
+                "--jitter-buffer" | "-jb" => {
+                    parsed.jitter_buffer = Some(take_parsed(&mut args, &arg)?)
+                }
+                "--quiet" | "-q" => parsed.quiet = true,
+                "--plain" | "-pl" => parsed.plain = true,
+                "--events" | "-e" => parsed.events = Some(take_parsed(&mut args, &arg)?),
+                "--accessible" | "-a" => parsed.accessible = Some(true),
+                "--no-accessible" | "-na" => parsed.accessible = Some(false),
+                "--bands" | "-b" => parsed.bands = Some(true),
+                "--no-bands" | "-nb" => parsed.bands = Some(false),
+                "--meter-interval" | "-mi" => {
+                    parsed.meter_interval = Some(take_parsed(&mut args, &arg)?)
+                }
+                "--verbose" | "-v" => parsed.verbose = parsed.verbose.saturating_add(1),
+                "-vv" => parsed.verbose = parsed.verbose.saturating_add(2),
+                "--log-file" | "-lf" => parsed.log_file = Some(take_value(&mut args, &arg)?.into()),
+                "--log-format" | "-lfmt" => parsed.log_format = take_parsed(&mut args, &arg)?,
+                "--lang" | "-l" => parsed.lang = Some(take_parsed(&mut args, &arg)?),
+                "--profile" | "-p" => parsed.profile = Some(take_value(&mut args, &arg)?),
+                "--config" | "-c" => parsed.config = Some(take_value(&mut args, &arg)?.into()),
+                "--input" | "-i" => parsed.input = Some(take_value(&mut args, &arg)?),
+                "--output" | "-o" => parsed.output = Some(take_value(&mut args, &arg)?),
+                "--input-regex" | "-ir" | "--output-regex" | "-or" => {
+                    let value = take_value(&mut args, &arg)?;
+                    let pattern = value.parse().map_err(|err| {
+                        io::Error::new(io::ErrorKind::InvalidInput, format!("{arg}: {err}"))
+                    })?;
+                    match arg.to_lowercase().as_str() {
+                        "--input-regex" | "-ir" => parsed.input_regex = Some(pattern),
+                        _ => parsed.output_regex = Some(pattern),
+                    }
+                }
+                "--sample-rate" | "--rate" | "-r" => {
+                    parsed.sample_rate = Some(take_parsed(&mut args, &arg)?)
+                }
+                "--channels" | "-ch" => {
+                    let channels: u16 = take_parsed(&mut args, &arg)?;
+                    if channels == 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("{arg} must be at least 1"),
+                        ));
+                    }
+                    parsed.channels = Some(channels);
+                }
+                "--invert" | "-inv" => parsed.invert = Some(true),
+                "--no-invert" | "-ninv" => parsed.invert = Some(false),
+                "--downmix" | "-dm" => parsed.downmix = Some(take_parsed(&mut args, &arg)?),
+                "--weighting" | "-w" => parsed.weighting = Some(take_parsed(&mut args, &arg)?),
+                "--silence-threshold" | "-st" => {
+                    parsed.silence_threshold = Some(take_parsed(&mut args, &arg)?)
+                }
+                "--duration" | "-dur" => parsed.duration = Some(take_parsed(&mut args, &arg)?),
+                "--hours" | "-hr" => parsed.hours = Some(take_parsed(&mut args, &arg)?),
+                "--cycles" | "-cy" => parsed.cycles = Some(take_parsed(&mut args, &arg)?),
+                "--record" | "-rec" => parsed.record = Some(take_value(&mut args, &arg)?.into()),
+                "--record-processed" | "-rp" => parsed.record_processed = Some(true),
+                "--no-record-processed" | "-nrp" => parsed.record_processed = Some(false),
+                "--volume" | "-vol" => parsed.volume = Some(take_parsed(&mut args, &arg)?),
+                "--set-os-gain" | "-osg" => {
+                    let value = take_value(&mut args, &arg)?;
+                    let percent = value
+                        .trim()
+                        .trim_end_matches('%')
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|percent| (0.0..=100.0).contains(percent))
+                        .ok_or_else(|| {
+                            io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                format!("{arg} must be between 0 and 100 percent (e.g. 80%)"),
+                            )
+                        })?;
+                    parsed.set_os_gain = Some(percent);
+                }
+                "--bed" | "-bed" => parsed.bed = Some(take_parsed(&mut args, &arg)?),
+                "--processor" | "-proc" => parsed.processors.push(take_parsed(&mut args, &arg)?),
+                "--bed-level" | "-bl" => parsed.bed_level = Some(take_parsed(&mut args, &arg)?),
+                "--metronome" | "-met" => {
+                    let value = take_value(&mut args, &arg)?;
+                    parsed.metronome = Some(parse_tempo(&value).map_err(|err| {
+                        io::Error::new(io::ErrorKind::InvalidInput, format!("{arg}: {err}"))
+                    })?);
+                }
+                "--gain" | "-g" => {
+                    let gain: f32 = take_parsed(&mut args, &arg)?;
+                    if !gain.is_finite() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("Invalid value \"{gain}\" for {arg}"),
+                        ));
+                    }
+                    parsed.gain = Some(gain);
+                }
+                "--segment" | "-seg" => parsed.segment = Some(take_parsed(&mut args, &arg)?),
+                "--trim-silence" | "-trim" => {
+                    let threshold: f32 = take_parsed(&mut args, &arg)?;
+                    if !threshold.is_finite() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("Invalid value \"{threshold}\" for {arg}"),
+                        ));
+                    }
+                    parsed.trim_silence = Some(threshold);
+                }
+                "--bits" | "-bits" => parsed.bits = Some(take_parsed(&mut args, &arg)?),
+                "--dither" | "-dth" => parsed.dither = Some(true),
+                "--no-dither" | "-ndth" => parsed.dither = Some(false),
+                "--normalize" | "-norm" => {
+                    let value = take_value(&mut args, &arg)?;
+                    parsed.normalize = Some(parse_peak_level(&value).map_err(|err| {
+                        io::Error::new(io::ErrorKind::InvalidInput, format!("{arg}: {err}"))
+                    })?);
+                }
+                "--signal" | "-sig" => parsed.signal = Some(take_parsed(&mut args, &arg)?),
+                "--out" | "-out" => parsed.out = Some(take_value(&mut args, &arg)?.into()),
+                "--level" | "-lvl" => {
+                    let value = take_value(&mut args, &arg)?;
+                    parsed.level = Some(parse_peak_level(&value).map_err(|err| {
+                        io::Error::new(io::ErrorKind::InvalidInput, format!("{arg}: {err}"))
+                    })?);
+                }
+                "--ceiling" | "-cl" => {
+                    let value = take_value(&mut args, &arg)?;
+                    parsed.ceiling = Some(parse_peak_level(&value).map_err(|err| {
+                        io::Error::new(io::ErrorKind::InvalidInput, format!("{arg}: {err}"))
+                    })?);
+                }
+                "--silence-timeout" | "-sto" => {
+                    parsed.silence_timeout = Some(take_parsed(&mut args, &arg)?)
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Unknown argument \"{arg}\""),
+                    ))
+                }
+            }
+        }
+
+        for (name, pattern, flags) in [
+            (
+                &parsed.input,
+                &parsed.input_regex,
+                "--input and --input-regex",
+            ),
+            (
+                &parsed.output,
+                &parsed.output_regex,
+                "--output and --output-regex",
+            ),
+        ] {
+            if name.is_some() && pattern.is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{flags} can't be used together"),
+                ));
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    /// Applies the `--profile` from the `config`, or its default profile
+    /// when there is one, then resolves the device aliases of the config
+    pub fn apply_config(&mut self, config: &Config) -> io::Result<()> {
+        if let Some(profile) = config.profile(self.profile.as_deref())? {
+            self.apply_profile(profile);
+        }
+        self.resolve_aliases(config);
+        Ok(())
+    }
+
+    /// Replaces the input and output device names that are aliases in
+    /// the `config` with the names of the devices they refer to
+    pub fn resolve_aliases(&mut self, config: &Config) {
+        let names = [&mut self.input, &mut self.output]
+            .into_iter()
+            .flatten()
+            .chain(&mut self.preferred_inputs)
+            .chain(&mut self.preferred_outputs);
+        for name in names {
+            if let Some(device) = config.alias(name) {
+                *name = device.to_string();
+            }
+        }
+    }
+
+    /// Fills in any settings that weren't provided on the command
+    /// line using the values from the provided `profile`
+    fn apply_profile(&mut self, profile: &Profile) {
+        for (value, setting) in [
+            (&mut self.delay, profile.delay),
+            (&mut self.sidetone, profile.sidetone),
+            (&mut self.accessible, profile.accessible),
+            (&mut self.bands, profile.bands),
+            (&mut self.invert, profile.invert),
+            (&mut self.dither, profile.dither),
+            (&mut self.record_processed, profile.record_processed),
+        ] {
+            if value.is_none() {
+                *value = setting;
+            }
+        }
+
+        // A pattern on the command line takes the place of the device
+        if self.input.is_none() && self.input_regex.is_none() {
+            self.input.clone_from(&profile.input);
+        }
+        if self.output.is_none() && self.output_regex.is_none() {
+            self.output.clone_from(&profile.output);
+        }
+        self.preferred_inputs.clone_from(&profile.preferred_inputs);
+        self.preferred_outputs
+            .clone_from(&profile.preferred_outputs);
+        if self.sample_rate.is_none() {
+            self.sample_rate = profile.sample_rate;
+        }
+        if self.buffer_size.is_none() {
+            self.buffer_size = profile.buffer_size;
+        }
+        if self.jitter_buffer.is_none() {
+            self.jitter_buffer = profile.jitter_buffer;
+        }
+        if self.meter_interval.is_none() {
+            self.meter_interval = profile.meter_interval;
+        }
+        if self.downmix.is_none() {
+            self.downmix = profile.downmix;
+        }
+        if self.weighting.is_none() {
+            self.weighting = profile.weighting;
+        }
+        if self.gain.is_none() {
+            self.gain = profile.gain;
+        }
+        if self.volume.is_none() {
+            self.volume = profile.volume;
+        }
+        if self.trim_silence.is_none() {
+            self.trim_silence = profile.trim_silence;
+        }
+        if self.normalize.is_none() {
+            self.normalize = profile.normalize;
+        }
+        if self.ceiling.is_none() {
+            self.ceiling = profile.ceiling;
+        }
+        if self.bits.is_none() {
+            self.bits = profile.bits;
+        }
+        if self.processors.is_empty() {
+            self.processors.clone_from(&profile.processors);
+        }
+        if self.bed.is_none() {
+            self.bed.clone_from(&profile.bed);
+        }
+        if self.bed_level.is_none() {
+            self.bed_level = profile.bed_level;
+        }
+        if self.metronome.is_none() {
+            self.metronome = profile.metronome;
+        }
+        if self.record.is_none() {
+            self.record.clone_from(&profile.record);
+        }
+        if self.segment.is_none() {
+            self.segment = profile.segment;
+        }
+    }
+}
+
+impl Command {
+    /// Whether the command asks the user questions while it runs, beyond
+    /// choosing the devices
+    pub fn asks_questions(&self) -> bool {
+        matches!(
+            self,
+            Command::Setup
+                | Command::LeftRight
+                | Command::Loopback
+                | Command::Calibrate(_)
+                | Command::PushToRecord
+                | Command::Intelligibility(_)
+        )
+    }
+}
+
+/// Parses the command from the first argument, consuming it and
+/// any values it expects when it names a command
+fn parse_command(args: &mut Peekable<impl Iterator<Item = String>>) -> io::Result<Command> {
+    let command = match args.peek().map(|arg| arg.to_lowercase()).as_deref() {
+        Some("completions") => {
+            args.next();
+            Command::Completions(take_parsed(args, "completions")?)
+        }
+        Some("devices") => {
+            args.next();
+            let ty = match args.peek().map(|arg| arg.to_lowercase()).as_deref() {
+                Some("input") => Some(DeviceType::Input),
+                Some("output") => Some(DeviceType::Output),
+                _ => None,
+            };
+            if ty.is_some() {
+                args.next();
+            }
+            Command::Devices(ty)
+        }
+        Some("configs") => {
+            args.next();
+            Command::Configs(take_value(args, "configs")?)
+        }
+        Some("daemon") => {
+            args.next();
+            Command::Daemon
+        }
+        Some("watch") => {
+            args.next();
+            Command::Watch
+        }
+        Some("sweep-inputs") => {
+            args.next();
+            Command::SweepInputs
+        }
+        Some("bench") => {
+            args.next();
+            Command::Bench
+        }
+        Some("polarity") => {
+            args.next();
+            Command::Polarity
+        }
+        Some("rt60") => {
+            args.next();
+            Command::Rt60
+        }
+        Some("calibrate") => {
+            args.next();
+            // The reference level is optional, flags may follow directly
+            let spl = match args.peek() {
+                Some(value) if value.parse::<f32>().is_ok() => {
+                    Some(take_parsed(args, "calibrate")?)
+                }
+                _ => None,
+            };
+            Command::Calibrate(spl)
+        }
+        Some("harmonics") => {
+            args.next();
+            let frequency = match args.peek() {
+                Some(value) if value.parse::<f32>().is_ok() => {
+                    Some(take_parsed(args, "harmonics")?)
+                }
+                _ => None,
+            };
+            Command::Harmonics(frequency)
+        }
+        Some("loopback") => {
+            args.next();
+            Command::Loopback
+        }
+        Some("soak") => {
+            args.next();
+            Command::Soak
+        }
+        Some("push-to-record") => {
+            args.next();
+            Command::PushToRecord
+        }
+        Some("guided") => {
+            args.next();
+            Command::Guided
+        }
+        Some("intelligibility") => {
+            args.next();
+            Command::Intelligibility(PathBuf::from(take_value(args, "intelligibility")?))
+        }
+        Some("compare") => {
+            args.next();
+            let first = take_value(args, "compare")?;
+            let second = take_value(args, "compare")?;
+            Command::Compare(PathBuf::from(first), PathBuf::from(second))
+        }
+        Some("null-test") => {
+            args.next();
+            // The second input is optional, flags may follow directly
+            let second = match args.peek() {
+                Some(value) if !value.starts_with('-') => args.next(),
+                _ => None,
+            };
+            Command::NullTest(second)
+        }
+        Some("reference") => {
+            args.next();
+            Command::Reference(PathBuf::from(take_value(args, "reference")?))
+        }
+        Some("analyze") => {
+            args.next();
+            Command::Analyze(PathBuf::from(take_value(args, "analyze")?))
+        }
+        Some("convert") => {
+            args.next();
+            let input = take_value(args, "convert")?;
+            let output = take_value(args, "convert")?;
+            Command::Convert(PathBuf::from(input), PathBuf::from(output))
+        }
+        Some("tune") => {
+            args.next();
+            Command::Tune
+        }
+        Some("latency-report") => {
+            args.next();
+            Command::LatencyReport
+        }
+        Some("generate") => {
+            args.next();
+            Command::Generate
+        }
+        Some("speaker-test") => {
+            args.next();
+            Command::SpeakerTest
+        }
+        Some("left-right") => {
+            args.next();
+            Command::LeftRight
+        }
+        Some("setup") => {
+            args.next();
+            Command::Setup
+        }
+        Some("audit") => {
+            args.next();
+            Command::Audit(take_value(args, "audit")?)
+        }
+        Some("stress") => {
+            args.next();
+            Command::Stress
+        }
+        Some("shared-access") => {
+            args.next();
+            Command::SharedAccess
+        }
+        _ => Command::Monitor,
+    };
+
+    Ok(command)
+}
+
+/// Parses a peak level in dBFS with an optional unit (e.g. "-1dBFS",
+/// "-1 dB" or "-1"), levels above full scale would clip
+pub fn parse_peak_level(value: &str) -> Result<f32, String> {
+    let lower = value.trim().to_lowercase();
+    let number = lower
+        .strip_suffix("dbfs")
+        .or_else(|| lower.strip_suffix("db"))
+        .unwrap_or(&lower);
+    match number.trim().parse::<f32>() {
+        Ok(level) if level.is_finite() && level <= 0.0 => Ok(level),
+        Ok(_) => Err(format!("peak level \"{value}\" must be at most 0 dBFS")),
+        Err(_) => Err(format!("invalid peak level \"{value}\" (e.g. -1dBFS)")),
+    }
+}
+
+/// Parses the tempo of the metronome in beats per minute
+pub fn parse_tempo(value: &str) -> Result<f64, String> {
+    match value.trim().parse::<f64>() {
+        Ok(bpm) if (MIN_BPM..=MAX_BPM).contains(&bpm) => Ok(bpm),
+        Ok(_) => Err(format!(
+            "tempo \"{value}\" must be between {MIN_BPM} and {MAX_BPM} BPM"
+        )),
+        Err(_) => Err(format!("invalid tempo \"{value}\"")),
+    }
+}
+
+/// Takes the value following the `flag` argument and parses it
+fn take_parsed<T: FromStr>(args: &mut impl Iterator<Item = String>, flag: &str) -> io::Result<T> {
+    let value = take_value(args, flag)?;
+    value.parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid value \"{value}\" for {flag}"),
+        )
+    })
+}
+
+/// Takes the value following the `flag` argument
+fn take_value(args: &mut impl Iterator<Item = String>, flag: &str) -> io::Result<String> {
+    args.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Missing value for {flag}"),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> io::Result<Args> {
+        Args::parse_from(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn flags_take_their_values() {
+        let args = parse(&[
+            "configs",
+            "USB Mic",
+            "-i",
+            "USB Mic",
+            "--gain",
+            "-3.5",
+            "-bs",
+            "256",
+            "--normalize",
+            "-1dBFS",
+            "-DLY",
+            "-vv",
+        ])
+        .unwrap();
+        assert!(matches!(&args.command, Command::Configs(name) if name == "USB Mic"));
+        assert_eq!(args.input.as_deref(), Some("USB Mic"));
+        assert_eq!(args.gain, Some(-3.5));
+        assert_eq!(args.buffer_size, Some(256));
+        assert_eq!(args.normalize, Some(-1.0));
+        assert_eq!(args.delay, Some(true));
+        assert_eq!(args.verbose, 2);
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        for args in [
+            &["--gain"][..],
+            &["--gain", "inf"],
+            &["--buffer-size", "many"],
+            &["--channels", "0"],
+            &["--normalize", "3dB"],
+            &["--metronome", "1000"],
+            &["-i", "USB Mic", "-ir", "usb"],
+        ] {
+            let err = parse(args).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn unknown_arguments_are_rejected() {
+        let err = parse(&["--delay", "--dealy"]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(err.to_string(), "Unknown argument \"--dealy\"");
+    }
+
+    #[test]
+    fn command_line_takes_priority_over_the_profile() {
+        let profile = Profile {
+            input: Some("Profile Mic".to_string()),
+            output: Some("Profile Speakers".to_string()),
+            gain: Some(6.0),
+            buffer_size: Some(512),
+            ..Profile::default()
+        };
+
+        let mut args = parse(&["-i", "USB Mic", "-g", "-3"]).unwrap();
+        args.apply_profile(&profile);
+        assert_eq!(args.input.as_deref(), Some("USB Mic"));
+        assert_eq!(args.output.as_deref(), Some("Profile Speakers"));
+        assert_eq!(args.gain, Some(-3.0));
+        assert_eq!(args.buffer_size, Some(512));
+        // Settings in neither are left to the defaults
+        assert_eq!(args.sample_rate, None);
+        assert_eq!(args.delay, None);
+
+        // Switches of the profile can be turned off and on
+        let profile = Profile {
+            delay: Some(true),
+            invert: Some(false),
+            dither: Some(true),
+            ..Profile::default()
+        };
+        let mut args = parse(&["--no-delay", "-inv"]).unwrap();
+        args.apply_profile(&profile);
+        assert_eq!(args.delay, Some(false));
+        assert_eq!(args.invert, Some(true));
+        assert_eq!(args.dither, Some(true));
+
+        // Processors of the command line replace those of the profile
+        let profile = Profile {
+            processors: vec![ProcessorSpec::HighPass(80.0)],
+            metronome: Some(90.0),
+            record: Some("profile.wav".into()),
+            ..Profile::default()
+        };
+        let mut args = parse(&["-proc", "gate", "-rec", "take.wav"]).unwrap();
+        args.apply_profile(&profile);
+        assert_eq!(args.processors, [ProcessorSpec::Gate(-50.0)]);
+        assert_eq!(args.metronome, Some(90.0));
+        assert_eq!(args.record, Some("take.wav".into()));
+
+        // A pattern replaces the device of the profile
+        let mut args = parse(&["-ir", "usb"]).unwrap();
+        args.apply_profile(&profile);
+        assert_eq!(args.input, None);
+    }
+}
@@ -0,0 +1,296 @@
+//! Audit of the stream configs a device advertises, opening a brief
+//! stream with each combination of sample format, channel count, sample
+//! rate and buffer size and checking that audio actually flows, to expose
+//! drivers that advertise configs they can't deliver
+
+use crate::{error::ChemicError, find_device, format_sample_rate, log::info, DeviceType};
+use cpal::{
+    traits::{DeviceTrait, StreamTrait},
+    BufferSize, BuildStreamError, Data, Host, PlayStreamError, SampleFormat, SampleRate, Stream,
+    StreamConfig, SupportedBufferSize, SupportedStreamConfigRange,
+};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Length of each trial stream
+const TRIAL_LENGTH: Duration = Duration::from_millis(300);
+/// Time a trial stream runs before its audio is counted, some drivers
+/// take a moment to start delivering audio
+const SETTLE: Duration = Duration::from_millis(100);
+/// Fraction of the expected frames a working stream has to deliver
+const MIN_DELIVERED: f64 = 0.5;
+/// Sample rates tried within each supported range
+const AUDIT_SAMPLE_RATES: &[u32] = &[
+    8000, 16000, 22050, 32000, 44100, 48000, 88200, 96000, 176400, 192000,
+];
+
+/// Outcome of opening a trial stream
+enum Outcome {
+    /// Audio flowed at the `delivered` fraction of the expected rate
+    Works { delivered: f64 },
+    /// The stream couldn't be built
+    BuildFailed(BuildStreamError),
+    /// The stream was built but couldn't be started
+    PlayFailed(PlayStreamError),
+    /// The stream started but delivered only the `delivered` fraction of
+    /// the expected audio
+    Starved { delivered: f64 },
+    /// The stream reported `errors` while running
+    StreamErrors(usize),
+}
+
+impl Outcome {
+    fn works(&self) -> bool {
+        matches!(self, Outcome::Works { .. })
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Works { delivered } => write!(f, "Works ({:.0}%)", delivered * 100.0),
+            Outcome::BuildFailed(err) => write!(f, "Failed to open: {err}"),
+            Outcome::PlayFailed(err) => write!(f, "Failed to start: {err}"),
+            Outcome::Starved { delivered } => {
+                write!(f, "No audio ({:.0}% delivered)", delivered * 100.0)
+            }
+            Outcome::StreamErrors(errors) => write!(f, "{errors} stream errors"),
+        }
+    }
+}
+
+/// Opens a trial stream with every combination of the configs the device
+/// named `name` advertises as an input and an output, printing whether
+/// each works. Fails when an advertised config doesn't work
+pub fn run(host: &Host, name: &str) -> Result<(), ChemicError> {
+    let mut found = false;
+    let mut tried = 0;
+    let mut failed = 0;
+
+    for ty in [DeviceType::Input, DeviceType::Output] {
+        let Ok(device) = find_device(host, ty, name) else {
+            continue;
+        };
+        found = true;
+
+        let (_, ranges) = device.supported_configs(ty);
+        let ranges = ranges.map_err(|source| ChemicError::LoadConfigs {
+            ty,
+            device: device.name.clone(),
+            source,
+        })?;
+
+        println!("== == == == {ty} audit: {} == == == ==", device.name);
+        if ranges.is_empty() {
+            println!("No supported configs\n");
+            continue;
+        }
+        println!(
+            "{:<8}{:<10}{:<14}{:<10}Result",
+            "Format", "Channels", "Sample Rate", "Buffer"
+        );
+
+        for range in &ranges {
+            for (config, sample_format) in trial_configs(range) {
+                let outcome = trial(&device.device, ty, &config, sample_format);
+                info!(
+                    "{ty} audit of \"{}\": {sample_format} {}ch {}Hz buffer {:?}: {outcome}",
+                    device.name, config.channels, config.sample_rate.0, config.buffer_size
+                );
+
+                let buffer = match config.buffer_size {
+                    BufferSize::Default => "default".to_string(),
+                    BufferSize::Fixed(frames) => frames.to_string(),
+                };
+                println!(
+                    "{:<8}{:<10}{:<14}{:<10}{outcome}",
+                    sample_format.to_string(),
+                    config.channels,
+                    format_sample_rate(config.sample_rate),
+                    buffer
+                );
+
+                tried += 1;
+                if !outcome.works() {
+                    failed += 1;
+                }
+            }
+        }
+        println!();
+    }
+
+    if !found {
+        return Err(ChemicError::NoDeviceNamed(name.to_string()));
+    }
+
+    println!("{} of {tried} advertised configs work", tried - failed);
+    if failed > 0 {
+        return Err(ChemicError::CheckFailed(format!(
+            "{failed} of the advertised configs don't work"
+        )));
+    }
+    Ok(())
+}
+
+/// Configs tried for a supported `range`, each of the sample rates it
+/// supports with the default buffer and the smallest and largest buffers
+/// it reports
+fn trial_configs(range: &SupportedStreamConfigRange) -> Vec<(StreamConfig, SampleFormat)> {
+    let mut sample_rates: Vec<u32> = AUDIT_SAMPLE_RATES
+        .iter()
+        .copied()
+        .filter(|&rate| range.min_sample_rate().0 <= rate && rate <= range.max_sample_rate().0)
+        .collect();
+    // Ranges that include none of the common rates are tried at their
+    // own limits
+    if sample_rates.is_empty() {
+        sample_rates.push(range.min_sample_rate().0);
+        sample_rates.push(range.max_sample_rate().0);
+        sample_rates.dedup();
+    }
+
+    let mut buffer_sizes = vec![BufferSize::Default];
+    if let SupportedBufferSize::Range { min, max } = *range.buffer_size() {
+        buffer_sizes.push(BufferSize::Fixed(min));
+        if max != min {
+            buffer_sizes.push(BufferSize::Fixed(max));
+        }
+    }
+
+    sample_rates
+        .into_iter()
+        .flat_map(|rate| {
+            buffer_sizes.iter().map(move |buffer_size| {
+                let config = StreamConfig {
+                    channels: range.channels(),
+                    sample_rate: SampleRate(rate),
+                    buffer_size: *buffer_size,
+                };
+                (config, range.sample_format())
+            })
+        })
+        .collect()
+}
+
+/// Opens a stream on the `device` used as the `ty` of device with the
+/// `config` for the [TRIAL_LENGTH], checking that audio flows
+fn trial(
+    device: &cpal::Device,
+    ty: DeviceType,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+) -> Outcome {
+    let counters = StreamCounters::default();
+    let stream = match open_counted(device, ty, config, sample_format, &counters) {
+        Ok(stream) => stream,
+        Err(err) => return Outcome::BuildFailed(err),
+    };
+    if let Err(err) = stream.play() {
+        return Outcome::PlayFailed(err);
+    }
+
+    thread::sleep(SETTLE);
+    let start = counters.samples();
+    thread::sleep(TRIAL_LENGTH);
+    let counted = counters.samples() - start;
+    drop(stream);
+
+    let expected =
+        config.sample_rate.0 as f64 * config.channels as f64 * TRIAL_LENGTH.as_secs_f64();
+    let delivered = counted as f64 / expected.max(1.0);
+    let errors = counters.errors();
+
+    if errors > 0 {
+        Outcome::StreamErrors(errors)
+    } else if delivered < MIN_DELIVERED {
+        Outcome::Starved { delivered }
+    } else {
+        Outcome::Works { delivered }
+    }
+}
+
+/// Counts of the samples passed through a stream and the errors it
+/// reported
+#[derive(Clone, Default)]
+pub struct StreamCounters {
+    samples: Arc<AtomicUsize>,
+    errors: Arc<AtomicUsize>,
+}
+
+impl StreamCounters {
+    /// Interleaved samples passed through the stream
+    pub fn samples(&self) -> usize {
+        self.samples.load(Ordering::Relaxed)
+    }
+
+    /// Errors reported by the stream
+    pub fn errors(&self) -> usize {
+        self.errors.load(Ordering::Relaxed)
+    }
+}
+
+/// Builds a stream on the `device` used as the `ty` of device with the
+/// `config` in its own `sample_format`, discarding the input or playing
+/// silence while counting into the `counters`
+pub fn open_counted(
+    device: &cpal::Device,
+    ty: DeviceType,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    counters: &StreamCounters,
+) -> Result<Stream, BuildStreamError> {
+    let on_error = {
+        let errors = counters.errors.clone();
+        move |_| {
+            errors.fetch_add(1, Ordering::Relaxed);
+        }
+    };
+    let samples = counters.samples.clone();
+    match ty {
+        DeviceType::Input => device.build_input_stream_raw(
+            config,
+            sample_format,
+            move |data: &Data, _| {
+                samples.fetch_add(data.len(), Ordering::Relaxed);
+            },
+            on_error,
+            None,
+        ),
+        DeviceType::Output => device.build_output_stream_raw(
+            config,
+            sample_format,
+            move |data: &mut Data, _| {
+                fill_silence(data);
+                samples.fetch_add(data.len(), Ordering::Relaxed);
+            },
+            on_error,
+            None,
+        ),
+    }
+}
+
+/// Fills the output `data` with silence, the midpoint of the range for
+/// unsigned formats
+fn fill_silence(data: &mut Data) {
+    fn fill<T: cpal::SizedSample>(data: &mut Data) {
+        if let Some(samples) = data.as_slice_mut::<T>() {
+            samples.fill(T::EQUILIBRIUM);
+        }
+    }
+
+    match data.sample_format() {
+        SampleFormat::U8 => fill::<u8>(data),
+        SampleFormat::U16 => fill::<u16>(data),
+        SampleFormat::U32 => fill::<u32>(data),
+        SampleFormat::U64 => fill::<u64>(data),
+        // Zero is silence for the signed and float formats
+        _ => data.bytes_mut().fill(0),
+    }
+}
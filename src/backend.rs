@@ -0,0 +1,183 @@
+//! Abstraction over the hosts, devices and streams of the audio backend,
+//! implemented by cpal and by an in-memory backend so the device
+//! selection, config negotiation and audio pipeline can be tested
+//! without sound hardware
+
+#[cfg(test)]
+pub mod mock;
+
+use crate::DeviceType;
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    BuildStreamError, DefaultStreamConfigError, DeviceNameError, DevicesError, PlayStreamError,
+    StreamConfig, StreamError, SupportedStreamConfig, SupportedStreamConfigRange,
+    SupportedStreamConfigsError,
+};
+
+/// Host of the audio devices
+pub trait AudioHost {
+    type Device: AudioDevice;
+
+    /// Name of the host shown with the devices (e.g. "ALSA")
+    fn host_name(&self) -> &'static str;
+
+    /// Default device of the `ty` when there is one
+    fn default_device(&self, ty: DeviceType) -> Option<Self::Device>;
+
+    /// All the devices of the `ty`
+    fn devices(&self, ty: DeviceType) -> Result<Vec<Self::Device>, DevicesError>;
+
+    /// Whether output devices can be opened as inputs to capture what is
+    /// played through them
+    fn captures_outputs(&self) -> bool;
+}
+
+/// Device streams of audio are opened on
+pub trait AudioDevice: Clone {
+    type Stream: AudioStream;
+
+    /// Name the device reports
+    fn device_name(&self) -> Result<String, DeviceNameError>;
+
+    /// Default config of the device when used as the `ty` of device
+    fn default_config(
+        &self,
+        ty: DeviceType,
+    ) -> Result<SupportedStreamConfig, DefaultStreamConfigError>;
+
+    /// Supported config ranges of the device when used as the `ty` of
+    /// device
+    fn config_ranges(
+        &self,
+        ty: DeviceType,
+    ) -> Result<Vec<SupportedStreamConfigRange>, SupportedStreamConfigsError>;
+
+    /// Builds an input stream with the `config` passing the captured
+    /// samples to `data` and the stream errors to `error`
+    fn open_input<D, E>(
+        &self,
+        config: &StreamConfig,
+        data: D,
+        error: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&[f32]) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static;
+
+    /// Builds an output stream with the `config` filling the samples to
+    /// play with `data` and passing the stream errors to `error`
+    fn open_output<D, E>(
+        &self,
+        config: &StreamConfig,
+        data: D,
+        error: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut [f32]) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static;
+}
+
+/// Stream of audio opened on an [AudioDevice], closed when dropped
+pub trait AudioStream {
+    /// Starts the stream calling its callbacks
+    fn start(&self) -> Result<(), PlayStreamError>;
+}
+
+impl AudioHost for cpal::Host {
+    type Device = cpal::Device;
+
+    fn host_name(&self) -> &'static str {
+        self.id().name()
+    }
+
+    fn default_device(&self, ty: DeviceType) -> Option<Self::Device> {
+        match ty {
+            DeviceType::Input => self.default_input_device(),
+            DeviceType::Output => self.default_output_device(),
+        }
+    }
+
+    fn devices(&self, ty: DeviceType) -> Result<Vec<Self::Device>, DevicesError> {
+        Ok(match ty {
+            DeviceType::Input => self.input_devices()?.collect(),
+            DeviceType::Output => self.output_devices()?.collect(),
+        })
+    }
+
+    /// Only WASAPI records what is played through an output device opened
+    /// as an input
+    #[cfg(target_os = "windows")]
+    fn captures_outputs(&self) -> bool {
+        self.id() == cpal::HostId::Wasapi
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    fn captures_outputs(&self) -> bool {
+        false
+    }
+}
+
+impl AudioDevice for cpal::Device {
+    type Stream = cpal::Stream;
+
+    fn device_name(&self) -> Result<String, DeviceNameError> {
+        self.name()
+    }
+
+    fn default_config(
+        &self,
+        ty: DeviceType,
+    ) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        match ty {
+            DeviceType::Input => self.default_input_config(),
+            DeviceType::Output => self.default_output_config(),
+        }
+    }
+
+    fn config_ranges(
+        &self,
+        ty: DeviceType,
+    ) -> Result<Vec<SupportedStreamConfigRange>, SupportedStreamConfigsError> {
+        Ok(match ty {
+            DeviceType::Input => self.supported_input_configs()?.collect(),
+            DeviceType::Output => self.supported_output_configs()?.collect(),
+        })
+    }
+
+    fn open_input<D, E>(
+        &self,
+        config: &StreamConfig,
+        mut data: D,
+        error: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&[f32]) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_input_stream(config, move |samples: &[f32], _| data(samples), error, None)
+    }
+
+    fn open_output<D, E>(
+        &self,
+        config: &StreamConfig,
+        mut data: D,
+        error: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut [f32]) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_output_stream(
+            config,
+            move |samples: &mut [f32], _| data(samples),
+            error,
+            None,
+        )
+    }
+}
+
+impl AudioStream for cpal::Stream {
+    fn start(&self) -> Result<(), PlayStreamError> {
+        self.play()
+    }
+}
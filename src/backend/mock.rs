@@ -0,0 +1,219 @@
+//! In-memory audio backend for the tests. Devices report fixed configs
+//! and their streams run the callbacks as soon as they are started, on
+//! the thread starting them, so the audio they pass is deterministic
+
+use super::{AudioDevice, AudioHost, AudioStream};
+use crate::DeviceType;
+use cpal::{
+    BuildStreamError, DefaultStreamConfigError, DeviceNameError, DevicesError, PlayStreamError,
+    SampleFormat, SampleRate, StreamConfig, StreamError, SupportedBufferSize,
+    SupportedStreamConfig, SupportedStreamConfigRange, SupportedStreamConfigsError,
+};
+use std::sync::{Arc, Mutex};
+
+/// Samples passed to each callback of the streams
+const CALLBACK_SAMPLES: usize = 256;
+
+/// Host of the mock devices
+#[derive(Default)]
+pub struct MockHost {
+    pub inputs: Vec<MockDevice>,
+    pub outputs: Vec<MockDevice>,
+    /// Index of the default input in the `inputs`
+    pub default_input: Option<usize>,
+    /// Index of the default output in the `outputs`
+    pub default_output: Option<usize>,
+    /// Whether the outputs are offered as loopback inputs
+    pub captures_outputs: bool,
+}
+
+impl AudioHost for MockHost {
+    type Device = MockDevice;
+
+    fn host_name(&self) -> &'static str {
+        "Mock"
+    }
+
+    fn default_device(&self, ty: DeviceType) -> Option<Self::Device> {
+        match ty {
+            DeviceType::Input => self.default_input.map(|index| self.inputs[index].clone()),
+            DeviceType::Output => self.default_output.map(|index| self.outputs[index].clone()),
+        }
+    }
+
+    fn devices(&self, ty: DeviceType) -> Result<Vec<Self::Device>, DevicesError> {
+        Ok(match ty {
+            DeviceType::Input => self.inputs.clone(),
+            DeviceType::Output => self.outputs.clone(),
+        })
+    }
+
+    fn captures_outputs(&self) -> bool {
+        self.captures_outputs
+    }
+}
+
+/// Mock device, used as the same configs whether opened as an input or
+/// an output
+#[derive(Clone)]
+pub struct MockDevice {
+    pub name: String,
+    /// Default config, [None] for drivers that don't report one
+    pub default_config: Option<SupportedStreamConfig>,
+    pub ranges: Vec<SupportedStreamConfigRange>,
+    /// Interleaved samples the input streams capture when started
+    pub capture: Arc<Vec<f32>>,
+    /// Number of samples the output streams play when started
+    pub playback_len: usize,
+    /// Interleaved samples played by the output streams
+    pub played: Arc<Mutex<Vec<f32>>>,
+    /// Whether building streams fails
+    pub fail_open: bool,
+}
+
+impl MockDevice {
+    /// Creates a device named `name` supporting the config `ranges`
+    /// without a default config
+    pub fn new(name: &str, ranges: Vec<SupportedStreamConfigRange>) -> Self {
+        Self {
+            name: name.to_string(),
+            default_config: None,
+            ranges,
+            capture: Arc::new(Vec::new()),
+            playback_len: 0,
+            played: Arc::new(Mutex::new(Vec::new())),
+            fail_open: false,
+        }
+    }
+
+    /// Sets the default config to the first range at the `sample_rate`
+    pub fn with_default_rate(mut self, sample_rate: u32) -> Self {
+        self.default_config = Some(self.ranges[0].with_sample_rate(SampleRate(sample_rate)));
+        self
+    }
+
+    /// Interleaved samples played by the output streams so far
+    pub fn played(&self) -> Vec<f32> {
+        self.played.lock().unwrap().clone()
+    }
+}
+
+/// Creates a config range of `channels` in the `sample_format` from the
+/// `min` to the `max` sample rate with buffers of 64 to 4096 frames
+pub fn range(
+    channels: u16,
+    min: u32,
+    max: u32,
+    sample_format: SampleFormat,
+) -> SupportedStreamConfigRange {
+    SupportedStreamConfigRange::new(
+        channels,
+        SampleRate(min),
+        SampleRate(max),
+        SupportedBufferSize::Range { min: 64, max: 4096 },
+        sample_format,
+    )
+}
+
+impl AudioDevice for MockDevice {
+    type Stream = MockStream;
+
+    fn device_name(&self) -> Result<String, DeviceNameError> {
+        Ok(self.name.clone())
+    }
+
+    fn default_config(
+        &self,
+        _ty: DeviceType,
+    ) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        self.default_config
+            .clone()
+            .ok_or(DefaultStreamConfigError::StreamTypeNotSupported)
+    }
+
+    fn config_ranges(
+        &self,
+        _ty: DeviceType,
+    ) -> Result<Vec<SupportedStreamConfigRange>, SupportedStreamConfigsError> {
+        Ok(self.ranges.clone())
+    }
+
+    fn open_input<D, E>(
+        &self,
+        _config: &StreamConfig,
+        data: D,
+        _error: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&[f32]) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.open(Callback::Input(Box::new(data)))
+    }
+
+    fn open_output<D, E>(
+        &self,
+        _config: &StreamConfig,
+        data: D,
+        _error: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut [f32]) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.open(Callback::Output(Box::new(data)))
+    }
+}
+
+impl MockDevice {
+    fn open(&self, callback: Callback) -> Result<MockStream, BuildStreamError> {
+        if self.fail_open {
+            return Err(BuildStreamError::DeviceNotAvailable);
+        }
+        Ok(MockStream {
+            device: self.clone(),
+            callback: Mutex::new(callback),
+        })
+    }
+}
+
+/// Callback passed the samples captured by an input stream
+type InputCallback = Box<dyn FnMut(&[f32]) + Send>;
+/// Callback filling the samples played by an output stream
+type OutputCallback = Box<dyn FnMut(&mut [f32]) + Send>;
+
+/// Callback of a mock stream
+enum Callback {
+    Input(InputCallback),
+    Output(OutputCallback),
+}
+
+/// Stream of a [MockDevice], passes all of its audio when started
+pub struct MockStream {
+    device: MockDevice,
+    callback: Mutex<Callback>,
+}
+
+impl AudioStream for MockStream {
+    fn start(&self) -> Result<(), PlayStreamError> {
+        match &mut *self.callback.lock().unwrap() {
+            Callback::Input(data) => {
+                for samples in self.device.capture.chunks(CALLBACK_SAMPLES) {
+                    data(samples);
+                }
+            }
+            Callback::Output(data) => {
+                let mut played = self.device.played.lock().unwrap();
+                let mut buffer = [0.0; CALLBACK_SAMPLES];
+                let mut remaining = self.device.playback_len;
+                while remaining > 0 {
+                    let samples = &mut buffer[..remaining.min(CALLBACK_SAMPLES)];
+                    data(samples);
+                    played.extend_from_slice(samples);
+                    remaining -= samples.len();
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+//! One third octave band analysis of the input for judging the tonal
+//! balance of a microphone or room
+
+use std::f64::consts::PI;
+
+/// Number of bands from 20 Hz to 20 kHz
+pub const BANDS: usize = 31;
+/// Quality factor of a band pass filter one third of an octave wide
+const BAND_Q: f64 = 4.318;
+/// Bands are only analysed below this fraction of the sample rate
+const MAX_FREQUENCY_RATIO: f64 = 0.45;
+
+/// Center frequency of the `band` in Hz using the base ten series from
+/// IEC 61260 (1 kHz is band 17)
+pub fn center_frequency(band: usize) -> f64 {
+    1000.0 * 10f64.powf((band as f64 - 17.0) / 10.0)
+}
+
+/// Band pass filter with a peak gain of 0 dB at the center frequency
+struct BandPass {
+    b0: f64,
+    a1: f64,
+    a2: f64,
+    /// Previous two inputs and outputs
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl BandPass {
+    fn new(center: f64, sample_rate: f64) -> Self {
+        let omega = 2.0 * PI * center / sample_rate;
+        let alpha = omega.sin() / (2.0 * BAND_Q);
+        let a0 = 1.0 + alpha;
+        Self {
+            b0: alpha / a0,
+            a1: -2.0 * omega.cos() / a0,
+            a2: (1.0 - alpha) / a0,
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        // The numerator is b0, 0, -b0
+        let output = self.b0 * (input - self.x[1]) - self.a1 * self.y[0] - self.a2 * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
+
+/// Bank of band pass filters measuring the energy of the input in each
+/// band, the channels are averaged before filtering
+pub struct BandFilter {
+    /// Filter for each band below the analysed limit, the bands above it
+    /// have no filter
+    filters: Vec<Option<BandPass>>,
+    /// Number of interleaved channels
+    channels: usize,
+    /// Energy of each band from the last processed samples
+    energies: [f64; BANDS],
+}
+
+impl BandFilter {
+    /// Creates the filters for audio at the `sample_rate` with the number
+    /// of interleaved `channels`
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let sample_rate = sample_rate as f64;
+        let filters = (0..BANDS)
+            .map(|band| {
+                let center = center_frequency(band);
+                (center < sample_rate * MAX_FREQUENCY_RATIO)
+                    .then(|| BandPass::new(center, sample_rate))
+            })
+            .collect();
+
+        Self {
+            filters,
+            channels: channels.max(1) as usize,
+            energies: [0.0; BANDS],
+        }
+    }
+
+    /// Filters the interleaved `samples` returning the sum of the squared
+    /// filtered samples of each band and the number of frames
+    pub fn process(&mut self, samples: &[f32]) -> (&[f64; BANDS], usize) {
+        self.energies = [0.0; BANDS];
+
+        let frames = samples.chunks_exact(self.channels);
+        let count = frames.len();
+        for frame in frames {
+            let value = frame.iter().sum::<f32>() as f64 / self.channels as f64;
+            for (filter, energy) in self.filters.iter_mut().zip(&mut self.energies) {
+                if let Some(filter) = filter {
+                    let output = filter.process(value);
+                    *energy += output * output;
+                }
+            }
+        }
+
+        (&self.energies, count)
+    }
+}
+
+/// Spectral tilt of the band `energies` from [BandFilter::process] in dB
+/// per octave, the slope of the line best fitting the band levels from
+/// 100 Hz to 10 kHz. [None] when fewer than two bands have energy
+pub fn tilt(energies: &[f64; BANDS]) -> Option<f64> {
+    // Bands 7 and 27 are centered on 100 Hz and 10 kHz
+    let points: Vec<(f64, f64)> = (7..=27)
+        .filter(|&band| energies[band] > 0.0)
+        .map(|band| {
+            let octaves = (center_frequency(band) / 1000.0).log2();
+            (octaves, 10.0 * energies[band].log10())
+        })
+        .collect();
+    if points.len() < 2 {
+        return None;
+    }
+
+    let count = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / count;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / count;
+    let covariance: f64 = points
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum();
+    let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    Some(covariance / variance)
+}
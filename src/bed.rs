@@ -0,0 +1,299 @@
+//! Reference tone, music bed, metronome click or tone burst mixed into
+//! the monitored output, so the level of the microphone can be judged
+//! against program material, the latency against a steady beat and the
+//! whole loop through the air checked
+
+use crate::{controls::Controls, meter::from_db, wav};
+use cpal::StreamConfig;
+use std::{f64::consts::TAU, fmt, io, path::PathBuf, str::FromStr, sync::Arc};
+
+/// Frequency of the reference tone when none is given
+const DEFAULT_TONE_HZ: f64 = 1000.0;
+/// Level of the bed when none is given in decibels
+pub const DEFAULT_LEVEL_DB: f32 = -20.0;
+
+/// Level of the metronome clicks in decibels
+const CLICK_LEVEL_DB: f32 = -12.0;
+/// Length of each metronome click
+const CLICK_LENGTH: f64 = 0.02;
+/// Time for a click to decay to about a third of its level
+const CLICK_DECAY: f64 = 0.003;
+/// Frequencies of the clicks on the first beat of the bar and the rest
+const ACCENT_HZ: f64 = 1500.0;
+const CLICK_HZ: f64 = 1000.0;
+/// Beats in each bar of the metronome
+const BEATS_PER_BAR: usize = 4;
+
+/// Level of the tone burst in decibels
+const BURST_LEVEL_DB: f32 = -12.0;
+/// Length of the tone burst
+const BURST_LENGTH: f64 = 0.25;
+/// Time the tone burst fades in and out over
+const BURST_FADE: f64 = 0.005;
+/// Frequency of the tone burst
+const BURST_HZ: f64 = 1000.0;
+
+/// Source of the bed
+#[derive(Debug, Clone, PartialEq)]
+pub enum BedSource {
+    /// Sine tone at the frequency in Hz
+    Tone(f64),
+    /// WAV file played on a loop
+    File(PathBuf),
+}
+
+/// Parses "tone" for a 1 kHz tone, "tone:<HZ>" for a tone at another
+/// frequency or anything else as the path of a WAV file
+impl FromStr for BedSource {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let lower = value.to_lowercase();
+        if lower == "tone" {
+            return Ok(BedSource::Tone(DEFAULT_TONE_HZ));
+        }
+        if let Some(frequency) = lower.strip_prefix("tone:") {
+            return match frequency.trim().parse::<f64>() {
+                Ok(frequency) if frequency.is_finite() && frequency > 0.0 => {
+                    Ok(BedSource::Tone(frequency))
+                }
+                _ => Err(format!("invalid tone frequency \"{frequency}\"")),
+            };
+        }
+        Ok(BedSource::File(value.into()))
+    }
+}
+
+impl fmt::Display for BedSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BedSource::Tone(frequency) => write!(f, "a {frequency} Hz tone"),
+            BedSource::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Signal of the bed at the sample rate of the output
+enum BedSignal {
+    /// Phase of the tone and the change in phase each frame
+    Tone { phase: f64, step: f64 },
+    /// Interleaved samples of the file with the channels of the output
+    /// and the index of the next sample
+    File { samples: Vec<f32>, position: usize },
+    /// Samples of the accented and normal clicks, the frames between
+    /// beats and the frame into the bar
+    Click {
+        accent: Vec<f32>,
+        click: Vec<f32>,
+        beat_frames: usize,
+        frame: usize,
+    },
+    /// Samples of the tone burst played when the `controls` ask for one,
+    /// the index of the next sample while playing
+    Burst {
+        samples: Vec<f32>,
+        position: Option<usize>,
+        controls: Arc<Controls>,
+    },
+}
+
+/// Bed being mixed into the output
+pub struct Bed {
+    signal: BedSignal,
+    /// Number of interleaved output channels
+    channels: usize,
+    /// Linear gain of the bed
+    gain: f32,
+}
+
+impl Bed {
+    /// Prepares the bed from the `source` at the `level_db` for the
+    /// output in the `config` format, the peak of a tone is at the level
+    /// and files are played with the level as their gain
+    pub fn open(source: &BedSource, level_db: f32, config: &StreamConfig) -> io::Result<Self> {
+        let sample_rate = config.sample_rate.0 as f64;
+        let channels = config.channels.max(1) as usize;
+
+        let signal = match source {
+            BedSource::Tone(frequency) => BedSignal::Tone {
+                phase: 0.0,
+                step: TAU * frequency / sample_rate,
+            },
+            BedSource::File(path) => {
+                let audio = wav::read(path).map_err(|err| {
+                    io::Error::new(err.kind(), format!("{}: {err}", path.display()))
+                })?;
+                let samples = resample(&audio, sample_rate, channels);
+                if samples.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{} has no audio", path.display()),
+                    ));
+                }
+                BedSignal::File {
+                    samples,
+                    position: 0,
+                }
+            }
+        };
+
+        Ok(Self {
+            signal,
+            channels,
+            gain: from_db(level_db),
+        })
+    }
+
+    /// Prepares a metronome clicking at the `bpm` for the output in the
+    /// `config` format, the first beat of each bar is accented
+    pub fn metronome(bpm: f64, config: &StreamConfig) -> Self {
+        let sample_rate = config.sample_rate.0 as f64;
+        let click = |frequency: f64| -> Vec<f32> {
+            let length = (CLICK_LENGTH * sample_rate) as usize;
+            (0..length)
+                .map(|frame| {
+                    let time = frame as f64 / sample_rate;
+                    ((TAU * frequency * time).sin() * (-time / CLICK_DECAY).exp()) as f32
+                })
+                .collect()
+        };
+
+        Self {
+            signal: BedSignal::Click {
+                accent: click(ACCENT_HZ),
+                click: click(CLICK_HZ),
+                beat_frames: ((60.0 / bpm) * sample_rate).max(1.0) as usize,
+                frame: 0,
+            },
+            channels: config.channels.max(1) as usize,
+            gain: from_db(CLICK_LEVEL_DB),
+        }
+    }
+
+    /// Prepares the tone burst played whenever the `controls` ask for
+    /// one, for the output in the `config` format
+    pub fn burst(controls: Arc<Controls>, config: &StreamConfig) -> Self {
+        let sample_rate = config.sample_rate.0 as f64;
+        let length = (BURST_LENGTH * sample_rate) as usize;
+        let fade = (BURST_FADE * sample_rate).max(1.0);
+        let samples = (0..length)
+            .map(|frame| {
+                let envelope = (frame as f64 / fade)
+                    .min((length - frame) as f64 / fade)
+                    .min(1.0);
+                let time = frame as f64 / sample_rate;
+                ((TAU * BURST_HZ * time).sin() * envelope) as f32
+            })
+            .collect();
+
+        Self {
+            signal: BedSignal::Burst {
+                samples,
+                position: None,
+                controls,
+            },
+            channels: config.channels.max(1) as usize,
+            gain: from_db(BURST_LEVEL_DB),
+        }
+    }
+
+    /// Adds the next samples of the bed to the interleaved `buffer`
+    /// scaled by the `scale` on top of the level of the bed
+    pub fn mix_into(&mut self, buffer: &mut [f32], scale: f32) {
+        let gain = self.gain * scale;
+        match &mut self.signal {
+            BedSignal::Tone { phase, step } => {
+                for frame in buffer.chunks_exact_mut(self.channels) {
+                    let value = phase.sin() as f32 * gain;
+                    *phase = (*phase + *step) % TAU;
+                    frame.iter_mut().for_each(|sample| *sample += value);
+                }
+            }
+            BedSignal::File { samples, position } => {
+                for sample in buffer.iter_mut() {
+                    *sample += samples[*position] * gain;
+                    *position = (*position + 1) % samples.len();
+                }
+            }
+            BedSignal::Click {
+                accent,
+                click,
+                beat_frames,
+                frame,
+            } => {
+                let bar_frames = *beat_frames * BEATS_PER_BAR;
+                for output in buffer.chunks_exact_mut(self.channels) {
+                    let beat_frame = *frame % *beat_frames;
+                    let sound = if *frame < *beat_frames {
+                        &accent
+                    } else {
+                        &click
+                    };
+                    if let Some(value) = sound.get(beat_frame) {
+                        output.iter_mut().for_each(|sample| *sample += value * gain);
+                    }
+                    *frame = (*frame + 1) % bar_frames;
+                }
+            }
+            BedSignal::Burst {
+                samples,
+                position,
+                controls,
+            } => {
+                // A burst asked for while playing starts over
+                if controls.take_burst() {
+                    *position = Some(0);
+                }
+                let Some(start) = *position else {
+                    return;
+                };
+
+                let frames = buffer.len() / self.channels;
+                for (output, value) in buffer
+                    .chunks_exact_mut(self.channels)
+                    .zip(samples.iter().skip(start))
+                {
+                    output.iter_mut().for_each(|sample| *sample += value * gain);
+                }
+                let next = start + frames;
+                *position = (next < samples.len()).then_some(next);
+            }
+        }
+    }
+}
+
+/// Converts the `audio` to the `sample_rate` and number of `channels`
+/// of the output using linear interpolation, audio with a different
+/// number of channels is mixed to mono and played on every channel
+pub fn resample(audio: &wav::WavAudio, sample_rate: f64, channels: usize) -> Vec<f32> {
+    let source_channels = audio.channels as usize;
+    let frames = audio.samples.len() / source_channels;
+    if frames == 0 {
+        return Vec::new();
+    }
+
+    let frame_at = |index: usize, channel: usize| {
+        let frame = &audio.samples[index * source_channels..(index + 1) * source_channels];
+        if source_channels == channels {
+            frame[channel]
+        } else {
+            frame.iter().sum::<f32>() / source_channels as f32
+        }
+    };
+
+    let ratio = audio.sample_rate as f64 / sample_rate;
+    let output_frames = (frames as f64 / ratio) as usize;
+    let mut samples = Vec::with_capacity(output_frames * channels);
+    for frame in 0..output_frames {
+        let position = frame as f64 * ratio;
+        let index = position as usize;
+        let fraction = (position - index as f64) as f32;
+        // The loop wraps around so the last frame blends into the first
+        let next = (index + 1) % frames;
+        for channel in 0..channels {
+            let current = frame_at(index, channel);
+            samples.push(current + (frame_at(next, channel) - current) * fraction);
+        }
+    }
+    samples
+}
@@ -0,0 +1,223 @@
+//! Throughput benchmark of the audio processing path and effects,
+//! reporting how many times faster than real time this machine can
+//! process audio
+
+use crate::{
+    ceiling::{Ceiling, DEFAULT_CEILING_DB},
+    controls::Controls,
+    create_pipeline,
+    meter::LevelMeter,
+    mix::Downmix,
+    processor::ProcessorSpec,
+    simd,
+    stats::StreamStats,
+};
+use cpal::{BufferSize, SampleRate, StreamConfig};
+use std::{
+    hint::black_box,
+    io,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Default number of seconds of audio processed by each case
+pub const DEFAULT_DURATION: f64 = 30.0;
+/// Number of frames processed per simulated callback
+const BLOCK_FRAMES: usize = 512;
+
+/// Pipeline configuration measured by the benchmark
+struct Case {
+    /// Description of the case
+    name: &'static str,
+    /// Input sample rate and channel count
+    input: (u32, u16),
+    /// Output sample rate and channel count
+    output: (u32, u16),
+}
+
+const CASES: &[Case] = &[
+    Case {
+        name: "passthrough 48 kHz stereo",
+        input: (48000, 2),
+        output: (48000, 2),
+    },
+    Case {
+        name: "resample 44.1 kHz -> 48 kHz stereo",
+        input: (44100, 2),
+        output: (48000, 2),
+    },
+    Case {
+        name: "downmix stereo -> mono 48 kHz",
+        input: (48000, 2),
+        output: (48000, 1),
+    },
+    Case {
+        name: "upmix mono -> stereo 48 kHz",
+        input: (48000, 1),
+        output: (48000, 2),
+    },
+    Case {
+        name: "downmix 5.1 -> stereo 48 kHz",
+        input: (48000, 6),
+        output: (48000, 2),
+    },
+    Case {
+        name: "resample 192 kHz -> 48 kHz stereo",
+        input: (192000, 2),
+        output: (48000, 2),
+    },
+];
+
+/// Runs every benchmark case over `duration` of audio printing the
+/// time taken and the real time factor
+pub fn run(duration: Duration) -> io::Result<()> {
+    println!(
+        "Processing {:.0}s of audio per case in blocks of {BLOCK_FRAMES} frames\n",
+        duration.as_secs_f64()
+    );
+
+    // Inverted with an offset so the whole factor is applied, and peaks
+    // at full scale so the ceiling is always limiting
+    let config = config((48000, 2));
+    let controls = Controls::default();
+    controls.set_inverted(true);
+    controls.set_offset_db(6.0);
+    let mut ceiling = Ceiling::new(DEFAULT_CEILING_DB, &config);
+    let mut high_pass = ProcessorSpec::HighPass(80.0).build(&config)?;
+    let mut gate = ProcessorSpec::Gate(-50.0).build(&config)?;
+    let effects: [(&str, Effect); 4] = [
+        (
+            "gain and invert 48 kHz stereo",
+            Box::new(|samples| simd::scale(samples, controls.factor())),
+        ),
+        (
+            "ceiling 48 kHz stereo",
+            Box::new(|samples| {
+                simd::scale(samples, 2.0);
+                ceiling.apply(samples);
+            }),
+        ),
+        (
+            "high pass 48 kHz stereo",
+            Box::new(|samples| high_pass.process(samples)),
+        ),
+        (
+            "gate 48 kHz stereo",
+            Box::new(|samples| gate.process(samples)),
+        ),
+    ];
+
+    let width = CASES
+        .iter()
+        .map(|case| case.name)
+        .chain(effects.iter().map(|(name, _)| *name))
+        .chain(["level meter 48 kHz stereo"])
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+
+    for case in CASES {
+        let elapsed = bench_pipeline(case, duration);
+        print_result(case.name, width, duration, elapsed);
+    }
+
+    for (name, effect) in effects {
+        let elapsed = bench_effect(duration, effect);
+        print_result(name, width, duration, elapsed);
+    }
+
+    let elapsed = bench_meter(duration);
+    print_result("level meter 48 kHz stereo", width, duration, elapsed);
+
+    Ok(())
+}
+
+fn print_result(name: &str, width: usize, duration: Duration, elapsed: Duration) {
+    let factor = duration.as_secs_f64() / elapsed.as_secs_f64().max(f64::EPSILON);
+    println!("{name:<width$}  {elapsed:>10.2?}  {factor:>10.0}x real time");
+}
+
+fn config((sample_rate, channels): (u32, u16)) -> StreamConfig {
+    StreamConfig {
+        channels,
+        sample_rate: SampleRate(sample_rate),
+        buffer_size: BufferSize::Default,
+    }
+}
+
+/// Generates a block of a 440 Hz sine wave at the `sample_rate`
+/// duplicated across the `channels`
+fn sine_block(sample_rate: u32, channels: u16, frames: usize) -> Vec<f32> {
+    (0..frames)
+        .flat_map(|frame| {
+            let value = (frame as f32 * 440.0 * std::f32::consts::TAU / sample_rate as f32).sin();
+            std::iter::repeat_n(value * 0.5, channels as usize)
+        })
+        .collect()
+}
+
+/// Pushes input blocks through the same pipeline the streams use and
+/// pulls the matching output blocks, like the stream callbacks would
+fn bench_pipeline(case: &Case, duration: Duration) -> Duration {
+    let input_config = config(case.input);
+    let output_config = config(case.output);
+    let stats = Arc::new(StreamStats::default());
+    let (mut producer, mut channel_converter, mut converter) =
+        create_pipeline(&input_config, &output_config, Downmix::Average, &stats);
+
+    let (input_rate, input_channels) = case.input;
+    let (output_rate, output_channels) = case.output;
+
+    // Input frames needed for each output block at the output rate, the
+    // remainder is carried over so the input never falls behind and the
+    // underrun concealment isn't measured in place of the resampler
+    let input_frames =
+        |blocks: usize| blocks * BLOCK_FRAMES * input_rate as usize / output_rate as usize;
+    let input = sine_block(input_rate, input_channels, input_frames(1) + 1);
+    let mut output = vec![0.0f32; BLOCK_FRAMES * output_channels as usize];
+    let mut scratch = Vec::new();
+
+    let blocks =
+        (duration.as_secs_f64() * output_rate as f64 / BLOCK_FRAMES as f64).ceil() as usize;
+
+    let start = Instant::now();
+    for block in 0..blocks {
+        let frames = input_frames(block + 1) - input_frames(block);
+        producer.push_slice(black_box(&input[..frames * input_channels as usize]));
+        channel_converter.fill(&mut converter, &mut scratch, &mut output);
+        black_box(&output);
+    }
+    start.elapsed()
+}
+
+/// Effect of the monitor chain applied to the samples in place
+type Effect<'a> = Box<dyn FnMut(&mut [f32]) + 'a>;
+
+/// Applies the `effect` to blocks of 48 kHz stereo audio like the worker
+/// would, each block starts from the same sine wave
+fn bench_effect(duration: Duration, mut effect: Effect) -> Duration {
+    let input = sine_block(48000, 2, BLOCK_FRAMES);
+    let mut samples = input.clone();
+    let blocks = (duration.as_secs_f64() * 48000.0 / BLOCK_FRAMES as f64).ceil() as usize;
+
+    let start = Instant::now();
+    for _ in 0..blocks {
+        samples.copy_from_slice(black_box(&input));
+        effect(&mut samples);
+        black_box(&samples);
+    }
+    start.elapsed()
+}
+
+fn bench_meter(duration: Duration) -> Duration {
+    let meter = LevelMeter::new(2);
+    let input = sine_block(48000, 2, BLOCK_FRAMES);
+    let blocks = (duration.as_secs_f64() * 48000.0 / BLOCK_FRAMES as f64).ceil() as usize;
+
+    let start = Instant::now();
+    for _ in 0..blocks {
+        meter.record(black_box(&input));
+    }
+    black_box(meter.take());
+    start.elapsed()
+}
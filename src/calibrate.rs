@@ -0,0 +1,99 @@
+//! Calibration of an input device against a reference sound of a known
+//! sound pressure level so the meters can show approximate dB SPL, along
+//! with the noise floor and the recommended input gain of the device
+
+use crate::{
+    args::Args,
+    config::{self, Config},
+    error::ChemicError,
+    log::info,
+    measure::select_device,
+    meter::Calibration,
+    positive_duration, sweep,
+    weighting::Weighting,
+    DeviceType,
+};
+use std::io;
+
+/// Level of the reference sound when none is provided, the level
+/// produced by most acoustic calibrators
+pub const DEFAULT_SPL: f32 = 94.0;
+/// Default number of seconds the reference sound and the noise floor
+/// are measured for
+const DEFAULT_DURATION: f64 = 5.0;
+/// Typical level of speech at a close microphone in dB SPL
+const SPEECH_SPL: f32 = 70.0;
+/// Level in dBFS speech should be captured at, leaving headroom
+const TARGET_DBFS: f32 = -18.0;
+
+/// Measures the input device from the `args` while it captures a
+/// reference sound at `spl` dB SPL and then the quiet room, storing the
+/// calibration for the device in the config file
+pub fn run(mut args: Args, spl: Option<f32>) -> Result<(), ChemicError> {
+    let config = Config::load(args.config.as_deref())?;
+    args.apply_config(&config)?;
+
+    let spl = spl.unwrap_or(DEFAULT_SPL);
+    let duration = positive_duration(args.duration.unwrap_or(DEFAULT_DURATION), "Duration")?;
+
+    let host = &cpal::default_host();
+    let input = select_device(host, &args, DeviceType::Input)?;
+
+    println!(
+        "Measuring \"{}\" for {:.1}s, play a {spl:.1} dB SPL reference sound into the microphone",
+        input.name,
+        duration.as_secs_f64()
+    );
+    println!("Use an acoustic calibrator or a tone measured with a sound level meter at the microphone\n");
+
+    // The reference is measured flat, calibrators play 1 kHz where the
+    // weightings are 0 dB anyway
+    let measurement = sweep::measure(&input, duration, Weighting::Z)?;
+    let Some(dbfs) = measurement.rms_db else {
+        println!(
+            "The reference sound was not detected, check that the microphone is picking it up"
+        );
+        return Ok(());
+    };
+
+    let calibration = Calibration { spl, dbfs };
+    println!("{spl:.1} dB SPL measured at {dbfs:.1} dBFS\n");
+
+    println!(
+        "Stop the reference sound, keep the room quiet and press Enter to measure the noise floor"
+    );
+    io::stdin().read_line(&mut String::new())?;
+    let weighting = args.weighting.unwrap_or_default();
+    let noise_floor = sweep::measure(&input, duration, weighting)?.rms_db;
+
+    // Gain that brings speech at the microphone to the target level
+    let speech_dbfs = SPEECH_SPL - calibration.spl + calibration.dbfs;
+    let recommended_gain = TARGET_DBFS - speech_dbfs;
+
+    let mut settings = config.device(&input.name).cloned().unwrap_or_default();
+    settings.calibration = Some(calibration);
+    settings.noise_floor_dbfs = noise_floor;
+    settings.recommended_gain_db = Some(recommended_gain);
+    let path = config::save_device(args.config.as_deref(), &input.name, &settings)?;
+
+    info!(
+        "calibrated \"{}\": {spl:.1} dB SPL at {dbfs:.1} dBFS, noise floor {noise_floor:?} dBFS",
+        input.name
+    );
+    match noise_floor {
+        Some(noise_floor) => println!(
+            "Noise floor: {noise_floor:.1} dBFS{suffix} ({:.1} dB{suffix} SPL)",
+            calibration.spl(noise_floor),
+            suffix = weighting.suffix()
+        ),
+        None => println!("Noise floor: below the meter range"),
+    }
+    println!("Recommended input gain change: {recommended_gain:+.1} dB, for speech at {TARGET_DBFS:.0} dBFS\n");
+    println!(
+        "Saved the calibration to {}, it is shown and the level meter uses dB SPL whenever \"{}\" is selected",
+        path.display(),
+        input.name
+    );
+
+    Ok(())
+}
@@ -0,0 +1,71 @@
+//! Safety ceiling on the monitored output, protecting the ears of
+//! headphone users from a misconfigured gain, volume or bed. Peaks above
+//! the ceiling are limited straight away and anything left above it is
+//! clipped, so the output never goes above the ceiling
+
+use crate::meter::from_db;
+use cpal::StreamConfig;
+use std::time::Duration;
+
+/// Default ceiling in dBFS
+pub const DEFAULT_CEILING_DB: f32 = -3.0;
+/// Time taken for the gain to recover after limiting a peak
+const RELEASE: Duration = Duration::from_millis(100);
+
+/// Limiter holding interleaved samples below the ceiling
+pub struct Ceiling {
+    /// Highest level of a sample
+    level: f32,
+    /// Gain currently applied by the limiter
+    gain: f32,
+    /// Fraction of the way back to unity gain recovered per frame
+    release: f32,
+    channels: usize,
+}
+
+impl Ceiling {
+    /// Creates a ceiling at `ceiling_db` dBFS for the output `config`
+    pub fn new(ceiling_db: f32, config: &StreamConfig) -> Self {
+        let frames = RELEASE.as_secs_f32() * config.sample_rate.0.max(1) as f32;
+        Self {
+            level: from_db(ceiling_db.min(0.0)),
+            gain: 1.0,
+            release: 1.0 / frames.max(1.0),
+            channels: config.channels.max(1) as usize,
+        }
+    }
+
+    /// Holds the interleaved `samples` below the ceiling, returns the
+    /// number of frames that were above it
+    pub fn apply(&mut self, samples: &mut [f32]) -> usize {
+        let mut limited = 0;
+        for frame in samples.chunks_mut(self.channels) {
+            let peak = frame
+                .iter()
+                .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+
+            if self.gain < 1.0 {
+                self.gain += (1.0 - self.gain) * self.release;
+                if self.gain > 0.9999 {
+                    self.gain = 1.0;
+                }
+            }
+            if peak > self.level {
+                limited += 1;
+                self.gain = self.gain.min(self.level / peak);
+            }
+            // Anything that isn't a number would be played as noise
+            if self.gain == 1.0 && peak <= self.level && !frame.iter().any(|s| s.is_nan()) {
+                continue;
+            }
+
+            for sample in frame {
+                *sample = match sample.is_nan() {
+                    true => 0.0,
+                    false => (*sample * self.gain).clamp(-self.level, self.level),
+                };
+            }
+        }
+        limited
+    }
+}
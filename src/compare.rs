@@ -0,0 +1,176 @@
+//! Comparison of two recordings of the same material, such as two
+//! microphones or a microphone before and after a driver update,
+//! reporting the differences in level, tonal balance and noise floor
+
+use crate::{
+    align::{align, overlap},
+    analyze::{noise_floor, rms},
+    bands::{self, BandFilter},
+    bed,
+    error::ChemicError,
+    log::info,
+    meter::to_db,
+    wav::{self, WavAudio},
+};
+use std::{io, path::Path};
+
+/// Audio from the start of each file correlated to align them
+const ALIGN_SECONDS: f64 = 30.0;
+/// Largest offset between the files that is searched for
+const MAX_OFFSET_SECONDS: f64 = 10.0;
+/// Correlation below which the files aren't considered the same take
+const MIN_CORRELATION: f64 = 0.3;
+
+/// Levels measured over the aligned part of a file
+struct Analysis {
+    rms_db: Option<f32>,
+    peak_db: Option<f32>,
+    /// Spectral tilt in dB per octave
+    tilt: Option<f64>,
+    /// RMS level of the quietest windows
+    noise_db: Option<f32>,
+}
+
+impl Analysis {
+    /// Measures the mono `samples` at the `sample_rate`
+    fn measure(samples: &[f32], sample_rate: u32) -> Self {
+        let mut filter = BandFilter::new(sample_rate, 1);
+        let (energies, _) = filter.process(samples);
+
+        Self {
+            rms_db: to_db(rms(samples)),
+            peak_db: to_db(
+                samples
+                    .iter()
+                    .fold(0.0, |peak, sample| sample.abs().max(peak)),
+            ),
+            tilt: bands::tilt(energies),
+            noise_db: to_db(noise_floor(samples, sample_rate)),
+        }
+    }
+}
+
+/// Aligns the files at the paths `first` and `second` and prints the
+/// differences between them
+pub fn run(first: &Path, second: &Path) -> Result<(), ChemicError> {
+    let open = |path: &Path| {
+        wav::read(path).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("Failed to read {}: {err}", path.display()),
+            )
+        })
+    };
+    let first_audio = open(first)?;
+    let second_audio = open(second)?;
+
+    // Both are mixed to mono at the sample rate of the first file
+    let sample_rate = first_audio.sample_rate;
+    let mono = |audio: &WavAudio| bed::resample(audio, sample_rate as f64, 1);
+    let first_samples = mono(&first_audio);
+    let second_samples = mono(&second_audio);
+    if second_audio.sample_rate != sample_rate {
+        println!(
+            "Resampled {} from {}Hz to {sample_rate}Hz",
+            second.display(),
+            second_audio.sample_rate
+        );
+    }
+
+    let seconds = |value: f64| (value * sample_rate as f64) as usize;
+    let head = |samples: &[f32]| samples[..samples.len().min(seconds(ALIGN_SECONDS))].to_vec();
+    let alignment = align(
+        &head(&first_samples),
+        &head(&second_samples),
+        seconds(MAX_OFFSET_SECONDS),
+    );
+
+    let lag = match alignment {
+        Some(alignment) => {
+            let offset_ms = alignment.lag as f64 * 1000.0 / sample_rate as f64;
+            info!(
+                "aligned {} and {} with an offset of {offset_ms:.1} ms, correlation {:.2}",
+                first.display(),
+                second.display(),
+                alignment.correlation
+            );
+            println!(
+                "Aligned with {} {:.1} ms {} (correlation {:.2})",
+                second.display(),
+                offset_ms.abs(),
+                if alignment.lag >= 0 {
+                    "later"
+                } else {
+                    "earlier"
+                },
+                alignment.correlation
+            );
+            if alignment.correlation.abs() < MIN_CORRELATION {
+                println!(
+                    "The files correlate poorly, they may not be recordings of the same sound"
+                );
+            }
+            if alignment.correlation < 0.0 {
+                println!("The files have opposite polarity");
+            }
+            alignment.lag
+        }
+        None => {
+            println!("One of the files is silent, comparing them without aligning");
+            0
+        }
+    };
+
+    let (first_samples, second_samples) = overlap(&first_samples, &second_samples, lag);
+    if first_samples.is_empty() {
+        println!("The files don't overlap once aligned");
+        return Ok(());
+    }
+    println!(
+        "Comparing {:.1}s of overlapping audio\n",
+        first_samples.len() as f64 / sample_rate as f64
+    );
+
+    let a = Analysis::measure(first_samples, sample_rate);
+    let b = Analysis::measure(second_samples, sample_rate);
+
+    println!("{:<16}{:>12}{:>12}{:>12}", "", "A", "B", "B - A");
+    print_row(
+        "RMS level",
+        a.rms_db.map(f64::from),
+        b.rms_db.map(f64::from),
+        "dBFS",
+    );
+    print_row(
+        "Peak level",
+        a.peak_db.map(f64::from),
+        b.peak_db.map(f64::from),
+        "dBFS",
+    );
+    print_row("Spectral tilt", a.tilt, b.tilt, "dB/oct");
+    print_row(
+        "Noise floor",
+        a.noise_db.map(f64::from),
+        b.noise_db.map(f64::from),
+        "dBFS",
+    );
+    println!("\nA: {}\nB: {}", first.display(), second.display());
+
+    Ok(())
+}
+
+/// Prints a row of the comparison table with the values `a` and `b` in
+/// the `unit` and their difference
+fn print_row(name: &str, a: Option<f64>, b: Option<f64>, unit: &str) {
+    let format = |value: Option<f64>| match value {
+        Some(value) => format!("{value:.1}"),
+        None => "-".to_string(),
+    };
+    let difference = a.zip(b).map(|(a, b)| b - a);
+    println!(
+        "{name:<16}{:>12}{:>12}{:>12} {unit}",
+        format(a),
+        format(b),
+        difference.map_or_else(|| "-".to_string(), |value| format!("{value:+.1}"))
+    );
+}
@@ -0,0 +1,267 @@
+//! Generation of shell completion scripts
+
+use crate::args::{FlagValue, COMMANDS, FLAGS};
+use std::{fmt::Write, io, str::FromStr};
+
+/// Shell to generate a completion script for
+#[derive(Clone, Copy)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+impl FromStr for Shell {
+    type Err = io::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            "powershell" | "pwsh" => Ok(Shell::Powershell),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unsupported shell \"{value}\""),
+            )),
+        }
+    }
+}
+
+/// Generates the completion script for the provided `shell`, device
+/// names are completed dynamically using `chemic devices`
+pub fn generate(shell: Shell) -> String {
+    match shell {
+        Shell::Bash => generate_bash(),
+        Shell::Zsh => generate_zsh(),
+        Shell::Fish => generate_fish(),
+        Shell::Powershell => generate_powershell(),
+    }
+}
+
+/// Shells that completions can be generated for
+const SHELLS: &str = "bash zsh fish powershell";
+
+fn generate_bash() -> String {
+    let mut flags = String::new();
+    let mut values = String::new();
+
+    for flag in FLAGS {
+        write!(flags, "-{} --{} ", flag.short, flag.long).unwrap();
+
+        let completion = match flag.value {
+            Some(FlagValue::Device(ty)) => format!(
+                "local IFS=$'\\n'; COMPREPLY=($(compgen -W \"$(chemic devices {ty} 2>/dev/null)\" -- \"$cur\"))"
+            ),
+            Some(FlagValue::Path) => "COMPREPLY=($(compgen -f -- \"$cur\"))".to_string(),
+            Some(FlagValue::Text) => "COMPREPLY=()".to_string(),
+            None => continue,
+        };
+
+        writeln!(
+            values,
+            "        -{}|--{})\n            {completion}\n            return\n            ;;",
+            flag.short, flag.long
+        )
+        .unwrap();
+    }
+
+    format!(
+        r#"_chemic() {{
+    local cur prev
+    cur="${{COMP_WORDS[COMP_CWORD]}}"
+    prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+
+    case "$prev" in
+        completions)
+            COMPREPLY=($(compgen -W "{SHELLS}" -- "$cur"))
+            return
+            ;;
+        devices)
+            COMPREPLY=($(compgen -W "input output" -- "$cur"))
+            return
+            ;;
+        configs|audit)
+            local IFS=$'\n'; COMPREPLY=($(compgen -W "$(chemic devices 2>/dev/null)" -- "$cur"))
+            return
+            ;;
+{values}    esac
+
+    if [[ $COMP_CWORD -eq 1 ]]; then
+        COMPREPLY=($(compgen -W "{commands} {flags}" -- "$cur"))
+    else
+        COMPREPLY=($(compgen -W "{flags}" -- "$cur"))
+    fi
+}}
+
+complete -F _chemic chemic
+"#,
+        commands = COMMANDS.join(" "),
+        flags = flags.trim_end(),
+    )
+}
+
+fn generate_zsh() -> String {
+    let mut arguments = String::new();
+
+    for flag in FLAGS {
+        let action = match flag.value {
+            Some(FlagValue::Device(ty)) => format!(":device:_chemic_devices {ty}"),
+            Some(FlagValue::Path) => ":path:_files".to_string(),
+            Some(FlagValue::Text) => ":value: ".to_string(),
+            None => String::new(),
+        };
+
+        writeln!(
+            arguments,
+            "        '(-{short} --{long})'{{-{short},--{long}}}'[{help}]{action}' \\",
+            short = flag.short,
+            long = flag.long,
+            help = flag.help,
+        )
+        .unwrap();
+    }
+
+    format!(
+        r#"#compdef chemic
+
+_chemic_devices() {{
+    local -a devices
+    devices=("${{(@f)$(chemic devices $1 2>/dev/null)}}")
+    compadd -a devices
+}}
+
+_chemic() {{
+    if (( CURRENT == 3 )); then
+        case "$words[2]" in
+            completions) compadd {SHELLS}; return ;;
+            devices) compadd input output; return ;;
+            configs|audit) _chemic_devices; return ;;
+        esac
+    fi
+
+    _arguments \
+{arguments}        '1::command:({commands})'
+}}
+
+compdef _chemic chemic
+"#,
+        commands = COMMANDS.join(" "),
+    )
+}
+
+fn generate_fish() -> String {
+    let mut script = String::from("complete -c chemic -f\n");
+
+    writeln!(
+        script,
+        "complete -c chemic -n '__fish_is_first_arg' -a '{}'",
+        COMMANDS.join(" ")
+    )
+    .unwrap();
+    writeln!(
+        script,
+        "complete -c chemic -n '__fish_seen_subcommand_from completions' -a '{SHELLS}'"
+    )
+    .unwrap();
+    writeln!(
+        script,
+        "complete -c chemic -n '__fish_seen_subcommand_from devices' -a 'input output'"
+    )
+    .unwrap();
+    writeln!(
+        script,
+        "complete -c chemic -n '__fish_seen_subcommand_from configs audit' -a '(chemic devices 2>/dev/null)'"
+    )
+    .unwrap();
+
+    for flag in FLAGS {
+        // Fish only supports single character short flags, longer
+        // ones are declared as old style options
+        let short = if flag.short.len() == 1 { "-s" } else { "-o" };
+
+        let value = match flag.value {
+            Some(FlagValue::Device(ty)) => {
+                format!(" -x -a '(chemic devices {ty} 2>/dev/null)'")
+            }
+            Some(FlagValue::Path) => " -r -F".to_string(),
+            Some(FlagValue::Text) => " -x".to_string(),
+            None => String::new(),
+        };
+
+        writeln!(
+            script,
+            "complete -c chemic {short} {} -l {} -d '{}'{value}",
+            flag.short, flag.long, flag.help
+        )
+        .unwrap();
+    }
+
+    script
+}
+
+fn generate_powershell() -> String {
+    let mut flags = String::new();
+    let mut values = String::new();
+
+    for flag in FLAGS {
+        write!(flags, "'-{}', '--{}', ", flag.short, flag.long).unwrap();
+
+        if let Some(FlagValue::Device(ty)) = flag.value {
+            writeln!(
+                values,
+                "        {{ $_ -in '-{}', '--{}' }} {{ $candidates = @(chemic devices {} 2>$null) }}",
+                flag.short,
+                flag.long,
+                ty
+            )
+            .unwrap();
+        }
+    }
+
+    let commands = COMMANDS
+        .iter()
+        .map(|command| format!("'{command}'"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let shells = SHELLS
+        .split(' ')
+        .map(|shell| format!("'{shell}'"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        r#"Register-ArgumentCompleter -Native -CommandName chemic -ScriptBlock {{
+    param($wordToComplete, $commandAst, $cursorPosition)
+
+    $elements = $commandAst.CommandElements | ForEach-Object {{ $_.ToString() }}
+    if ($wordToComplete) {{
+        $previous = $elements[-2]
+    }} else {{
+        $previous = $elements[-1]
+    }}
+
+    $candidates = switch ($previous) {{
+        'completions' {{ @({shells}) }}
+        'devices' {{ @('input', 'output') }}
+        'configs' {{ @(chemic devices 2>$null) }}
+        'audit' {{ @(chemic devices 2>$null) }}
+        default {{ @({commands}, {flags}) }}
+    }}
+
+    switch ($previous) {{
+{values}    }}
+
+    $candidates |
+        Where-Object {{ $_ -like "$wordToComplete*" }} |
+        ForEach-Object {{
+            $text = if ($_ -match '\s') {{ "'$_'" }} else {{ $_ }}
+            [System.Management.Automation.CompletionResult]::new($text, $_, 'ParameterValue', $_)
+        }}
+}}
+"#,
+        flags = flags.trim_end_matches([',', ' ']),
+    )
+}
@@ -0,0 +1,79 @@
+//! Concealment of underruns, when the audio runs out the last sample of
+//! each channel is held and faded towards silence instead of snapping to
+//! it, and the audio fades back in once it returns, so short underruns
+//! are heard as a dip rather than a click
+
+/// Time taken to fade out when the audio runs out and back in when it
+/// returns
+const FADE_SECONDS: f32 = 0.005;
+
+/// Fade applied to interleaved samples around the underruns
+pub struct Concealment {
+    /// Last real sample of each channel
+    last: Vec<f32>,
+    /// Channel of the next sample
+    channel: usize,
+    /// Gain of the fade, one while the audio plays normally
+    level: f32,
+    /// Change in the gain per frame
+    step: f32,
+}
+
+impl Concealment {
+    /// Creates the concealment for interleaved audio with the number of
+    /// `channels` at the `sample_rate`
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        Self {
+            last: vec![0.0; channels.max(1) as usize],
+            channel: 0,
+            level: 1.0,
+            step: 1.0 / (FADE_SECONDS * sample_rate.max(1) as f32).max(1.0),
+        }
+    }
+
+    /// Passes the real `samples` through, fading them back in after an
+    /// underrun
+    pub fn play_slice(&mut self, samples: &mut [f32]) {
+        if self.level < 1.0 {
+            samples
+                .iter_mut()
+                .for_each(|sample| *sample = self.play(*sample));
+            return;
+        }
+
+        // Only the last frame needs to be remembered
+        let channels = self.last.len();
+        let start = samples.len().saturating_sub(channels);
+        for (index, &sample) in samples.iter().enumerate().skip(start) {
+            self.last[(self.channel + index) % channels] = sample;
+        }
+        self.channel = (self.channel + samples.len()) % channels;
+    }
+
+    /// Passes the real `sample` through, fading it back in after an
+    /// underrun
+    pub fn play(&mut self, sample: f32) -> f32 {
+        self.last[self.channel] = sample;
+        let sample = sample * self.level;
+        self.advance(self.step);
+        sample
+    }
+
+    /// Creates the sample played in place of a missing one, the last real
+    /// sample of the channel faded towards silence
+    pub fn conceal(&mut self) -> f32 {
+        let sample = self.last[self.channel] * self.level;
+        self.advance(-self.step);
+        sample
+    }
+
+    /// Moves on to the next channel, changing the gain by the `step` at
+    /// the end of each frame
+    fn advance(&mut self, step: f32) {
+        self.channel += 1;
+        if self.channel == self.last.len() {
+            self.channel = 0;
+            self.level = (self.level + step).clamp(0.0, 1.0);
+        }
+    }
+}
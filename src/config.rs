@@ -0,0 +1,697 @@
+//! Loading of the config file and the named profiles within it
+
+use self::parser::{Section, Value};
+use crate::{
+    args::{parse_peak_level, parse_tempo},
+    bed::BedSource,
+    controls::{self, Action, KeyBindings, Volume},
+    meter::Calibration,
+    mix::Downmix,
+    processor::ProcessorSpec,
+    record::SegmentLimit,
+    theme::{ThemeColor, UiTheme},
+    wav::SampleFormat,
+    weighting::Weighting,
+    DeviceType,
+};
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+mod parser;
+
+/// Name of the profile that is used when no profile is specified
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Environment variable that can be used to override the config path
+const CONFIG_ENV: &str = "CHEMIC_CONFIG";
+
+/// Name of the file in the config directory remembering the devices last
+/// chosen from the device prompts, kept apart from the config file so
+/// choosing a device doesn't rewrite it
+const LAST_DEVICES_FILE: &str = "last-devices.toml";
+
+/// Loaded configuration file
+#[derive(Default)]
+pub struct Config {
+    /// Profiles declared in the config keyed by name
+    profiles: HashMap<String, Profile>,
+    /// Keys bound to the playback controls
+    pub keys: KeyBindings,
+    /// Colors and characters used by the prompts and meter
+    pub theme: UiTheme,
+    /// Settings remembered for devices keyed by device name
+    devices: HashMap<String, DeviceSettings>,
+    /// Names of devices keyed by the alias they can be referred to by
+    aliases: HashMap<String, String>,
+}
+
+/// Settings remembered for a device from a `[device."<name>"]` section
+/// of the config file
+#[derive(Default, Clone)]
+pub struct DeviceSettings {
+    /// Sound pressure level calibration of an input device
+    pub calibration: Option<Calibration>,
+    /// Level of the input device in a quiet room in dBFS
+    pub noise_floor_dbfs: Option<f32>,
+    /// Change to the interface input gain that brings speech to the
+    /// target level, shown when the device is selected
+    pub recommended_gain_db: Option<f32>,
+    /// Gain applied to the monitored audio of the device so microphones
+    /// of different sensitivity monitor at the same level
+    pub sensitivity_offset_db: Option<f32>,
+}
+
+/// Named set of settings from a `[profile.<name>]` section of
+/// the config file
+#[derive(Default, Clone)]
+pub struct Profile {
+    /// Name of the input device to use
+    pub input: Option<String>,
+    /// Name of the output device to use
+    pub output: Option<String>,
+    /// Names of the input devices to use in order of preference, the
+    /// first that is present is used
+    pub preferred_inputs: Vec<String>,
+    /// Names of the output devices to use in order of preference
+    pub preferred_outputs: Vec<String>,
+    /// Sample rate to use for both devices
+    pub sample_rate: Option<u32>,
+    /// Whether to delay the audio
+    pub delay: Option<bool>,
+    /// Whether to monitor as sidetone with the lowest latency
+    pub sidetone: Option<bool>,
+    /// Buffer size in frames requested from both devices
+    pub buffer_size: Option<u32>,
+    /// Milliseconds of audio the jitter buffer targets at least
+    pub jitter_buffer: Option<f64>,
+    /// Whether to show the input level as text lines
+    pub accessible: Option<bool>,
+    /// Whether to show the input level in one third octave bands
+    pub bands: Option<bool>,
+    /// Seconds between the text level lines
+    pub meter_interval: Option<f64>,
+    /// How channels are mixed into a mono output
+    pub downmix: Option<Downmix>,
+    /// Frequency weighting of the measured levels
+    pub weighting: Option<Weighting>,
+    /// Whether to invert the polarity of the monitored audio
+    pub invert: Option<bool>,
+    /// Gain in decibels applied to the captured input
+    pub gain: Option<f32>,
+    /// Volume of the monitored output
+    pub volume: Option<Volume>,
+    /// Stages processing the monitored output in order
+    pub processors: Vec<ProcessorSpec>,
+    /// Tone or file mixed into the monitored output
+    pub bed: Option<BedSource>,
+    /// Level of the bed in decibels
+    pub bed_level: Option<f32>,
+    /// Beats per minute of the metronome mixed into the output
+    pub metronome: Option<f64>,
+    /// Path of the WAV file the input is recorded to, which may use
+    /// the same placeholders as `--record`
+    pub record: Option<PathBuf>,
+    /// Whether the processed monitor audio is recorded as well
+    pub record_processed: Option<bool>,
+    /// Limit of the length of each recorded file
+    pub segment: Option<SegmentLimit>,
+    /// Level in dBFS below which the start and end of recordings are
+    /// trimmed as silence
+    pub trim_silence: Option<f32>,
+    /// Level in dBFS the peak of recordings is normalized to
+    pub normalize: Option<f32>,
+    /// Level in dBFS the monitored output is never played above
+    pub ceiling: Option<f32>,
+    /// Format the samples of recordings are saved in
+    pub bits: Option<SampleFormat>,
+    /// Whether dither is added when saving integer samples
+    pub dither: Option<bool>,
+}
+
+impl Config {
+    /// Loads the config file from the provided `path` or from the
+    /// default config location when no path is provided.
+    ///
+    /// A missing config file at the default location is treated as
+    /// an empty config
+    pub fn load(path: Option<&Path>) -> io::Result<Self> {
+        let Some((path, required)) = locate(path) else {
+            return Ok(Self::default());
+        };
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(value) => value,
+            Err(err) if !required && err.kind() == io::ErrorKind::NotFound => {
+                return Ok(Self::default())
+            }
+            Err(err) => {
+                return Err(io::Error::new(
+                    err.kind(),
+                    format!("Failed to read config {}: {}", path.display(), err),
+                ))
+            }
+        };
+
+        Self::parse(&contents).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid config {}: {}", path.display(), err),
+            )
+        })
+    }
+
+    /// Parses the config from the provided file `contents`
+    fn parse(contents: &str) -> Result<Self, String> {
+        let document = parser::parse(contents).map_err(|err| err.to_string())?;
+        let mut profiles = HashMap::new();
+        let mut keys = KeyBindings::default();
+        let mut theme = UiTheme::default();
+        let mut devices = HashMap::new();
+        let mut aliases = HashMap::new();
+
+        for section in &document.sections {
+            match section.path.as_slice() {
+                // Root section has no settings yet
+                [] => {
+                    if let Some((key, _)) = section.entries.first() {
+                        return Err(format!("unknown key \"{key}\""));
+                    }
+                }
+                [kind, name] if kind == "profile" => {
+                    let profile = Profile::from_section(section)
+                        .map_err(|err| format!("[profile.{name}]: {err}"))?;
+                    profiles.insert(name.clone(), profile);
+                }
+                [kind] if kind == "keys" => {
+                    parse_keys(section, &mut keys).map_err(|err| format!("[keys]: {err}"))?;
+                }
+                [kind] if kind == "theme" => {
+                    theme = parse_theme(section).map_err(|err| format!("[theme]: {err}"))?;
+                }
+                [kind] if kind == "aliases" => {
+                    for (alias, value) in &section.entries {
+                        let device = expect_string(alias, value)
+                            .map_err(|err| format!("[aliases]: {err}"))?;
+                        aliases.insert(alias.clone(), device);
+                    }
+                }
+                [kind, name] if kind == "device" => {
+                    let settings = DeviceSettings::from_section(section)
+                        .map_err(|err| format!("[device.\"{name}\"]: {err}"))?;
+                    devices.insert(name.clone(), settings);
+                }
+                path => return Err(format!("unknown section [{}]", path.join("."))),
+            }
+        }
+
+        Ok(Self {
+            profiles,
+            keys,
+            theme,
+            devices,
+            aliases,
+        })
+    }
+
+    /// Name of the device the alias `name` refers to, [None] when it
+    /// isn't an alias
+    pub fn alias(&self, name: &str) -> Option<&str> {
+        self.aliases.get(name).map(String::as_str)
+    }
+
+    /// Settings remembered for the device with the provided `name`
+    pub fn device(&self, name: &str) -> Option<&DeviceSettings> {
+        self.devices.get(name)
+    }
+
+    /// Finds the profile to use, when a `name` is provided the named
+    /// profile must exist, otherwise the "default" profile is used
+    /// if one is present
+    pub fn profile(&self, name: Option<&str>) -> io::Result<Option<&Profile>> {
+        match name {
+            Some(name) => self.profiles.get(name).map(Some).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Unknown profile \"{name}\""),
+                )
+            }),
+            None => Ok(self.profiles.get(DEFAULT_PROFILE)),
+        }
+    }
+}
+
+impl Profile {
+    /// Creates a profile from the entries of a config `section`
+    fn from_section(section: &Section) -> Result<Self, String> {
+        let mut profile = Profile::default();
+
+        for (key, value) in &section.entries {
+            match key.as_str() {
+                "input" => profile.input = Some(expect_string(key, value)?),
+                "output" => profile.output = Some(expect_string(key, value)?),
+                "preferred_inputs" => profile.preferred_inputs = expect_strings(key, value)?,
+                "preferred_outputs" => profile.preferred_outputs = expect_strings(key, value)?,
+                "sample_rate" => profile.sample_rate = Some(expect_u32(key, value)?),
+                "delay" => profile.delay = Some(expect_bool(key, value)?),
+                "sidetone" => profile.sidetone = Some(expect_bool(key, value)?),
+                "buffer_size" => profile.buffer_size = Some(expect_u32(key, value)?),
+                "jitter_buffer" => profile.jitter_buffer = Some(expect_number(key, value)?),
+                "accessible" => profile.accessible = Some(expect_bool(key, value)?),
+                "bands" => profile.bands = Some(expect_bool(key, value)?),
+                "meter_interval" => profile.meter_interval = Some(expect_number(key, value)?),
+                "downmix" => profile.downmix = Some(expect_string(key, value)?.parse()?),
+                "weighting" => profile.weighting = Some(expect_string(key, value)?.parse()?),
+                "invert" => profile.invert = Some(expect_bool(key, value)?),
+                "gain" => profile.gain = Some(expect_number(key, value)? as f32),
+                "volume" => {
+                    profile.volume = Some(match value {
+                        Value::String(value) => value.parse()?,
+                        value => expect_number(key, value)?.to_string().parse()?,
+                    })
+                }
+                "bits" => {
+                    profile.bits = Some(match value {
+                        Value::String(value) => value.parse()?,
+                        value => expect_number(key, value)?.to_string().parse()?,
+                    })
+                }
+                "dither" => profile.dither = Some(expect_bool(key, value)?),
+                "processors" => {
+                    profile.processors = expect_strings(key, value)?
+                        .iter()
+                        .map(|value| value.parse())
+                        .collect::<Result<_, _>>()?
+                }
+                "bed" => profile.bed = Some(expect_string(key, value)?.parse()?),
+                "bed_level" => profile.bed_level = Some(expect_number(key, value)? as f32),
+                "metronome" => {
+                    profile.metronome = Some(parse_tempo(&expect_number(key, value)?.to_string())?)
+                }
+                "record" => profile.record = Some(expect_string(key, value)?.into()),
+                "record_processed" => profile.record_processed = Some(expect_bool(key, value)?),
+                "segment" => {
+                    profile.segment = Some(match value {
+                        Value::String(value) => value.parse()?,
+                        value => expect_number(key, value)?.to_string().parse()?,
+                    })
+                }
+                "normalize" => {
+                    profile.normalize = Some(match value {
+                        Value::String(value) => parse_peak_level(value)?,
+                        value => parse_peak_level(&expect_number(key, value)?.to_string())?,
+                    })
+                }
+                "ceiling" => {
+                    profile.ceiling = Some(match value {
+                        Value::String(value) => parse_peak_level(value)?,
+                        value => parse_peak_level(&expect_number(key, value)?.to_string())?,
+                    })
+                }
+                "trim_silence" => profile.trim_silence = Some(expect_number(key, value)? as f32),
+                _ => return Err(format!("unknown key \"{key}\"")),
+            }
+        }
+
+        Ok(profile)
+    }
+
+    /// Entries of the devices and input gain of the profile that are
+    /// set, the settings chosen by `chemic setup`
+    fn entries(&self) -> Vec<(&'static str, Value)> {
+        let devices = [("input", &self.input), ("output", &self.output)]
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, Value::String(value.clone()?))));
+        let gain = self
+            .gain
+            .map(|gain| ("gain", Value::Float((gain as f64 * 10.0).round() / 10.0)));
+        devices.chain(gain).collect()
+    }
+}
+
+impl DeviceSettings {
+    /// Creates the device settings from the entries of a config `section`
+    fn from_section(section: &Section) -> Result<Self, String> {
+        let mut settings = DeviceSettings::default();
+        let mut spl = None;
+        let mut dbfs = None;
+
+        for (key, value) in &section.entries {
+            let number = || expect_number(key, value).map(|value| value as f32);
+            match key.as_str() {
+                "calibration_spl" => spl = Some(number()?),
+                "calibration_dbfs" => dbfs = Some(number()?),
+                "noise_floor_dbfs" => settings.noise_floor_dbfs = Some(number()?),
+                "recommended_gain_db" => settings.recommended_gain_db = Some(number()?),
+                "sensitivity_offset_db" => settings.sensitivity_offset_db = Some(number()?),
+                _ => return Err(format!("unknown key \"{key}\"")),
+            }
+        }
+
+        settings.calibration = match (spl, dbfs) {
+            (Some(spl), Some(dbfs)) => Some(Calibration { spl, dbfs }),
+            (None, None) => None,
+            _ => {
+                return Err(
+                    "\"calibration_spl\" and \"calibration_dbfs\" must be set together".to_string(),
+                )
+            }
+        };
+
+        Ok(settings)
+    }
+
+    /// Entries of the settings that are set, as written to the config
+    fn entries(&self) -> Vec<(&'static str, Value)> {
+        // Round to a tenth of a decibel so the file stays readable
+        let round = |value: f32| Value::Float((value as f64 * 10.0).round() / 10.0);
+        let calibration = self.calibration.into_iter().flat_map(|calibration| {
+            [
+                ("calibration_spl", calibration.spl),
+                ("calibration_dbfs", calibration.dbfs),
+            ]
+        });
+        let others = [
+            ("noise_floor_dbfs", self.noise_floor_dbfs),
+            ("recommended_gain_db", self.recommended_gain_db),
+            ("sensitivity_offset_db", self.sensitivity_offset_db),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| value.map(|value| (key, value)));
+
+        calibration
+            .chain(others)
+            .map(|(key, value)| (key, round(value)))
+            .collect()
+    }
+}
+
+/// Stores the `settings` of the device named `name` in the config file at
+/// the provided `path` or the default location, keeping the rest of the
+/// file and the settings that aren't set as they are. Returns the path of
+/// the updated file
+pub fn save_device(
+    path: Option<&Path>,
+    name: &str,
+    settings: &DeviceSettings,
+) -> io::Result<PathBuf> {
+    save_section(path, &["device", name], &settings.entries())
+}
+
+/// Stores the devices and input gain of the `profile` as the profile
+/// named `name` in the config file at the provided `path` or the default
+/// location, keeping the rest of the file and the other settings of the
+/// profile as they are. Returns the path of the updated file
+pub fn save_profile(path: Option<&Path>, name: &str, profile: &Profile) -> io::Result<PathBuf> {
+    save_section(path, &["profile", name], &profile.entries())
+}
+
+/// Sets the `entries` of the `section` in the config file at the provided
+/// `path` or the default location, returning the path of the file
+fn save_section(
+    path: Option<&Path>,
+    section: &[&str],
+    entries: &[(&str, Value)],
+) -> io::Result<PathBuf> {
+    let (path, _) = locate(path).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "No config location available, provide one with --config",
+        )
+    })?;
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(value) => value,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => {
+            return Err(io::Error::new(
+                err.kind(),
+                format!("Failed to read config {}: {}", path.display(), err),
+            ))
+        }
+    };
+
+    let section: Vec<String> = section.iter().map(|part| part.to_string()).collect();
+    let contents = parser::set_entries(&contents, &section, entries);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, contents).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!("Failed to write config {}: {}", path.display(), err),
+        )
+    })?;
+
+    Ok(path)
+}
+
+/// Name of the `ty` device last chosen from the device prompt as
+/// remembered in the config `dir`, [None] when no device has been
+/// chosen before
+pub fn last_device(dir: &Path, ty: DeviceType) -> Option<String> {
+    let path = dir.join(LAST_DEVICES_FILE);
+    let contents = fs::read_to_string(path).ok()?;
+    let document = parser::parse(&contents).ok()?;
+    let section = document
+        .sections
+        .iter()
+        .find(|section| section.path == ["last"])?;
+    match section.get(&ty.to_string())? {
+        Value::String(device) => Some(device.clone()),
+        _ => None,
+    }
+}
+
+/// Remembers the device `name` in the config `dir` as the last `ty`
+/// device chosen from the device prompt
+pub fn save_last_device(dir: &Path, ty: DeviceType, name: &str) -> io::Result<()> {
+    let path = dir.join(LAST_DEVICES_FILE);
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(value) => value,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+    let key = ty.to_string();
+    let contents = parser::set_entries(
+        &contents,
+        &["last".to_string()],
+        &[(key.as_str(), Value::String(name.to_string()))],
+    );
+
+    fs::create_dir_all(dir)?;
+    fs::write(&path, contents)
+}
+
+/// Finds the config file path from the provided `path`, the environment
+/// or the default location, along with whether the file must exist
+fn locate(path: Option<&Path>) -> Option<(PathBuf, bool)> {
+    match path {
+        Some(path) => Some((path.to_path_buf(), true)),
+        None => match env::var_os(CONFIG_ENV) {
+            Some(path) => Some((PathBuf::from(path), true)),
+            None => default_path().map(|path| (path, false)),
+        },
+    }
+}
+
+/// Binds the keys listed in the `[keys]` config `section`, each entry
+/// replaces the default keys of its action
+fn parse_keys(section: &Section, keys: &mut KeyBindings) -> Result<(), String> {
+    for (key, value) in &section.entries {
+        let action =
+            Action::from_config_key(key).ok_or_else(|| format!("unknown action \"{key}\""))?;
+
+        let names = match value {
+            Value::String(name) => vec![name.clone()],
+            Value::Array(values) => values
+                .iter()
+                .map(|value| expect_string(key, value))
+                .collect::<Result<_, _>>()?,
+            value => return Err(type_error(key, "string or array of strings", value)),
+        };
+
+        let bound = names
+            .iter()
+            .map(|name| controls::parse_key(name))
+            .collect::<Result<_, _>>()?;
+        keys.bind(action, bound);
+    }
+
+    keys.validate()
+}
+
+/// Parses the theme settings from the `[theme]` config `section`
+fn parse_theme(section: &Section) -> Result<UiTheme, String> {
+    let mut theme = UiTheme::default();
+
+    for (key, value) in &section.entries {
+        match key.as_str() {
+            "preset" => theme.preset = expect_string(key, value)?.parse()?,
+            "accent" => theme.accent = Some(expect_color(key, value)?),
+            "prompt_prefix" => theme.prompt_prefix = Some(expect_string(key, value)?),
+            "active_item_prefix" => theme.active_item_prefix = Some(expect_string(key, value)?),
+            "meter_fill" => theme.meter_fill = Some(expect_char(key, value)?),
+            "meter_empty" => theme.meter_empty = Some(expect_char(key, value)?),
+            "meter_low" => theme.meter_low = Some(expect_color(key, value)?),
+            "meter_mid" => theme.meter_mid = Some(expect_color(key, value)?),
+            "meter_high" => theme.meter_high = Some(expect_color(key, value)?),
+            "meter_clip" => theme.meter_clip = Some(expect_color(key, value)?),
+            _ => return Err(format!("unknown key \"{key}\"")),
+        }
+    }
+
+    Ok(theme)
+}
+
+/// Determines the default location of the config file for
+/// the current platform
+fn default_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.toml"))
+}
+
+/// Directory holding the config file and the other files chemic loads,
+/// [None] when the home directory isn't known
+pub fn config_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+
+    base.map(|base| base.join("chemic"))
+}
+
+fn expect_string(key: &str, value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(value) => Ok(value.clone()),
+        value => Err(type_error(key, "string", value)),
+    }
+}
+
+fn expect_strings(key: &str, value: &Value) -> Result<Vec<String>, String> {
+    match value {
+        Value::Array(values) => values
+            .iter()
+            .map(|value| expect_string(key, value))
+            .collect(),
+        value => Err(type_error(key, "array of strings", value)),
+    }
+}
+
+fn expect_bool(key: &str, value: &Value) -> Result<bool, String> {
+    match value {
+        Value::Boolean(value) => Ok(*value),
+        value => Err(type_error(key, "boolean", value)),
+    }
+}
+
+fn expect_u32(key: &str, value: &Value) -> Result<u32, String> {
+    match value {
+        Value::Integer(value) => u32::try_from(*value)
+            .map_err(|_| format!("\"{key}\" must be between 0 and {}", u32::MAX)),
+        value => Err(type_error(key, "integer", value)),
+    }
+}
+
+fn expect_number(key: &str, value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Integer(value) => Ok(*value as f64),
+        Value::Float(value) if value.is_finite() => Ok(*value),
+        Value::Float(_) => Err(format!("\"{key}\" must be a finite number")),
+        value => Err(type_error(key, "number", value)),
+    }
+}
+
+fn expect_char(key: &str, value: &Value) -> Result<char, String> {
+    let value = expect_string(key, value)?;
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(value), None) => Ok(value),
+        _ => Err(format!("\"{key}\" must be a single character")),
+    }
+}
+
+/// Colors are either a color name or a 256 color palette index
+fn expect_color(key: &str, value: &Value) -> Result<ThemeColor, String> {
+    match value {
+        Value::String(value) => value.parse(),
+        Value::Integer(value) => u8::try_from(*value)
+            .map(ThemeColor::indexed)
+            .map_err(|_| format!("\"{key}\" must be between 0 and 255")),
+        value => Err(type_error(key, "color name or integer", value)),
+    }
+}
+
+fn type_error(key: &str, expected: &str, value: &Value) -> String {
+    format!(
+        "\"{key}\" must be a {expected} but found a {}",
+        value.type_name()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_must_be_finite() {
+        for value in ["inf", "-inf", "nan"] {
+            let err = Config::parse(&format!("[profile.default]\ngain = {value}"))
+                .err()
+                .unwrap();
+            assert_eq!(err, "[profile.default]: \"gain\" must be a finite number");
+        }
+
+        let config = Config::parse("[profile.default]\ngain = -3.5").unwrap();
+        let profile = config.profile(None).unwrap().unwrap();
+        assert_eq!(profile.gain, Some(-3.5));
+    }
+
+    #[test]
+    fn profiles_hold_the_monitor_and_recording_settings() {
+        let config = Config::parse(
+            r#"
+            [profile.practice]
+            processors = ["highpass", "gate:-40"]
+            bed = "tone:440"
+            bed_level = -30
+            metronome = 90
+            record = "takes/{date}-{seq}.wav"
+            record_processed = true
+            segment = "30m"
+            "#,
+        )
+        .unwrap();
+        let profile = config.profile(Some("practice")).unwrap().unwrap();
+        assert_eq!(
+            profile.processors,
+            [ProcessorSpec::HighPass(80.0), ProcessorSpec::Gate(-40.0)]
+        );
+        assert_eq!(profile.bed, Some(BedSource::Tone(440.0)));
+        assert_eq!(profile.bed_level, Some(-30.0));
+        assert_eq!(profile.metronome, Some(90.0));
+        assert_eq!(
+            profile.record.as_deref(),
+            Some(Path::new("takes/{date}-{seq}.wav"))
+        );
+        assert_eq!(profile.record_processed, Some(true));
+        assert_eq!(
+            profile.segment,
+            Some(SegmentLimit::Duration(std::time::Duration::from_secs(1800)))
+        );
+
+        let err = Config::parse("[profile.default]\nmetronome = 1000")
+            .err()
+            .unwrap();
+        assert!(err.contains("must be between 20 and 400 BPM"), "{err}");
+    }
+}
@@ -0,0 +1,604 @@
+//! Minimal parser for the subset of TOML used by the config file
+//!
+//! Supports `[section.headers]` (with quoted segments), `key = value`
+//! entries, `#` comments, and string, integer, float, boolean and
+//! single line array values. Entries can also be written back into
+//! a file keeping the rest of its contents and comments intact.
+
+use std::fmt::{self, Display};
+
+/// Value assigned to a key in the config file
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    /// Name of the value type for use in error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "float",
+            Value::Boolean(_) => "boolean",
+            Value::Array(_) => "array",
+        }
+    }
+}
+
+/// Formats the value the same way it would be written in the file
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::String(value) => write_string(f, value),
+            Value::Integer(value) => write!(f, "{value}"),
+            // Floats always keep a decimal point so they read back as floats
+            Value::Float(value) if value.fract() == 0.0 && value.is_finite() => {
+                write!(f, "{value:.1}")
+            }
+            Value::Float(value) => write!(f, "{value}"),
+            Value::Boolean(value) => write!(f, "{value}"),
+            Value::Array(values) => {
+                f.write_str("[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                f.write_str("]")
+            }
+        }
+    }
+}
+
+/// Writes the `value` as a double quoted string with the escape
+/// sequences understood by the parser
+fn write_string(f: &mut impl fmt::Write, value: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in value.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\t' => f.write_str("\\t")?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// Formats a section header for the `path`, quoting the segments that
+/// can't be written as bare keys (e.g. `[device."USB Microphone"]`)
+pub fn format_header(path: &[String]) -> String {
+    let mut header = String::from("[");
+    for (index, segment) in path.iter().enumerate() {
+        if index > 0 {
+            header.push('.');
+        }
+        let bare = !segment.is_empty()
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if bare {
+            header.push_str(segment);
+        } else {
+            // Writing to a string can't fail
+            _ = write_string(&mut header, segment);
+        }
+    }
+    header.push(']');
+    header
+}
+
+/// Section of the config file started by a `[header]`, entries
+/// before the first header belong to a section with an empty path
+#[derive(Debug, Default)]
+pub struct Section {
+    /// Dotted path segments of the section header
+    pub path: Vec<String>,
+    /// Key value entries in the order they were declared
+    pub entries: Vec<(String, Value)>,
+}
+
+impl Section {
+    /// Finds the value for the provided `key` within this section
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.entries
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+    }
+}
+
+/// Parsed config document
+#[derive(Debug, Default)]
+pub struct Document {
+    /// All the sections in the document, the first of which is
+    /// always the root section
+    pub sections: Vec<Section>,
+}
+
+/// Error produced when the config file contents are malformed
+#[derive(Debug)]
+pub struct ParseError {
+    /// Line number the error occurred on (1-based)
+    pub line: usize,
+    /// Description of the error
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses the provided config file `input`
+pub fn parse(input: &str) -> Result<Document, ParseError> {
+    let mut document = Document {
+        sections: vec![Section::default()],
+    };
+
+    for (index, line) in input.lines().enumerate() {
+        let error = |message: String| ParseError {
+            line: index + 1,
+            message,
+        };
+
+        let mut cursor = Cursor::new(line);
+        cursor.skip_whitespace();
+
+        if cursor.is_done() {
+            continue;
+        }
+
+        if cursor.eat('[') {
+            let path = cursor.parse_key_path(']').map_err(error)?;
+            if !cursor.eat(']') {
+                return Err(error("expected ']' to close section header".to_string()));
+            }
+            cursor.expect_end().map_err(error)?;
+
+            if document.sections.iter().any(|section| section.path == path) {
+                return Err(error(format!("duplicate section [{}]", path.join("."))));
+            }
+
+            document.sections.push(Section {
+                path,
+                entries: Vec::new(),
+            });
+            continue;
+        }
+
+        let key = cursor.parse_key().map_err(error)?;
+        cursor.skip_whitespace();
+        if !cursor.eat('=') {
+            return Err(error(format!("expected '=' after key \"{key}\"")));
+        }
+        cursor.skip_whitespace();
+        let value = cursor.parse_value().map_err(error)?;
+        cursor.expect_end().map_err(error)?;
+
+        let section = document
+            .sections
+            .last_mut()
+            // The root section is always present
+            .expect("Missing root section");
+
+        if section.get(&key).is_some() {
+            return Err(error(format!("duplicate key \"{key}\"")));
+        }
+
+        section.entries.push((key, value));
+    }
+
+    Ok(document)
+}
+
+/// Sets the `entries` of the section at `path` within the config file
+/// `input`, replacing the lines of keys that are already set and adding
+/// the others at the end of the section. The section is added to the
+/// end of the file when it isn't present
+pub fn set_entries(input: &str, path: &[String], entries: &[(&str, Value)]) -> String {
+    let lines: Vec<&str> = input.lines().collect();
+    let mut current: Vec<String> = Vec::new();
+    let mut written = vec![false; entries.len()];
+    // Index after the last entry of the section for the new entries
+    let mut insert_at = None;
+    let mut output: Vec<String> = Vec::with_capacity(lines.len() + entries.len() + 2);
+
+    for line in lines {
+        let mut cursor = Cursor::new(line);
+        cursor.skip_whitespace();
+
+        if cursor.eat('[') {
+            if let Ok(header) = cursor.parse_key_path(']') {
+                current = header;
+            }
+            output.push(line.to_string());
+            if current == path {
+                insert_at = Some(output.len());
+            }
+            continue;
+        }
+
+        if current == path && !cursor.is_done() {
+            let index = cursor
+                .parse_key()
+                .ok()
+                .and_then(|key| entries.iter().position(|(name, _)| *name == key));
+            match index {
+                Some(index) => {
+                    let (name, value) = &entries[index];
+                    output.push(format!("{} = {value}", format_key(name)));
+                    written[index] = true;
+                }
+                None => output.push(line.to_string()),
+            }
+            insert_at = Some(output.len());
+            continue;
+        }
+
+        output.push(line.to_string());
+    }
+
+    let missing: Vec<String> = entries
+        .iter()
+        .zip(&written)
+        .filter(|(_, written)| !**written)
+        .map(|((name, value), _)| format!("{} = {value}", format_key(name)))
+        .collect();
+
+    match insert_at {
+        Some(index) => {
+            output.splice(index..index, missing);
+        }
+        None => {
+            if output.last().is_some_and(|line| !line.trim().is_empty()) {
+                output.push(String::new());
+            }
+            output.push(format_header(path));
+            output.extend(missing);
+        }
+    }
+
+    let mut contents = output.join("\n");
+    contents.push('\n');
+    contents
+}
+
+/// Formats a key, quoting it when it can't be written bare
+fn format_key(key: &str) -> String {
+    let header = format_header(&[key.to_string()]);
+    header[1..header.len() - 1].to_string()
+}
+
+/// Cursor over the characters of a single line
+struct Cursor<'a> {
+    /// Remaining portion of the line
+    rest: &'a str,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(line: &'a str) -> Self {
+        Self { rest: line }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest.chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let next = self.peek()?;
+        self.rest = &self.rest[next.len_utf8()..];
+        Some(next)
+    }
+
+    /// Consumes the next character if it matches `expected`
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    /// Whether the rest of the line is empty or a comment
+    fn is_done(&self) -> bool {
+        self.rest.is_empty() || self.rest.starts_with('#')
+    }
+
+    fn expect_end(&mut self) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.is_done() {
+            Ok(())
+        } else {
+            Err(format!("unexpected trailing content \"{}\"", self.rest))
+        }
+    }
+
+    /// Parses a dotted key path up until the `end` character
+    fn parse_key_path(&mut self, end: char) -> Result<Vec<String>, String> {
+        let mut path = Vec::new();
+        loop {
+            self.skip_whitespace();
+            path.push(self.parse_key()?);
+            self.skip_whitespace();
+            if self.peek() == Some(end) {
+                return Ok(path);
+            }
+            if !self.eat('.') {
+                return Err(format!("expected '.' or '{end}' in section header"));
+            }
+        }
+    }
+
+    /// Parses a bare or quoted key
+    fn parse_key(&mut self) -> Result<String, String> {
+        if self.peek() == Some('"') {
+            return self.parse_string();
+        }
+
+        let length = self
+            .rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(self.rest.len());
+
+        if length == 0 {
+            return Err("expected a key".to_string());
+        }
+
+        let (key, rest) = self.rest.split_at(length);
+        self.rest = rest;
+        Ok(key.to_string())
+    }
+
+    fn parse_value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') => self.parse_string().map(Value::String),
+            Some('[') => self.parse_array(),
+            Some(_) => self.parse_literal(),
+            None => Err("expected a value".to_string()),
+        }
+    }
+
+    /// Parses a double quoted string with basic escape sequences
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.bump();
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(value),
+                Some('\\') => value.push(match self.bump() {
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some(other) => return Err(format!("unknown escape sequence \"\\{other}\"")),
+                    None => return Err("unterminated string".to_string()),
+                }),
+                Some(other) => value.push(other),
+                None => return Err("unterminated string".to_string()),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, String> {
+        self.bump();
+        let mut values = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.eat(']') {
+                return Ok(Value::Array(values));
+            }
+            values.push(self.parse_value()?);
+            self.skip_whitespace();
+            if !self.eat(',') && self.peek() != Some(']') {
+                return Err("expected ',' or ']' in array".to_string());
+            }
+        }
+    }
+
+    /// Parses a boolean or numeric literal
+    fn parse_literal(&mut self) -> Result<Value, String> {
+        let length = self
+            .rest
+            .find(|c: char| c.is_whitespace() || matches!(c, ',' | ']' | '#'))
+            .unwrap_or(self.rest.len());
+        let (literal, rest) = self.rest.split_at(length);
+        self.rest = rest;
+
+        let value = match literal {
+            "true" => Value::Boolean(true),
+            "false" => Value::Boolean(false),
+            _ => {
+                let number = literal.replace('_', "");
+                if let Ok(value) = number.parse::<i64>() {
+                    Value::Integer(value)
+                } else if let Ok(value) = number.parse::<f64>() {
+                    Value::Float(value)
+                } else {
+                    return Err(format!("invalid value \"{literal}\""));
+                }
+            }
+        };
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(segments: &[&str]) -> Vec<String> {
+        segments.iter().map(|segment| segment.to_string()).collect()
+    }
+
+    fn error(input: &str) -> String {
+        parse(input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parses_sections_and_values() {
+        let document = parse(
+            "# comment\n\
+             gain = -3.5\n\
+             \n\
+             [profile.default]\n\
+             input = \"USB Mic\" # trailing comment\n\
+             sample_rate = 48_000\n\
+             sidetone = true\n\
+             preferred = [\"a\", \"b\",]\n\
+             [device.\"USB Microphone\"]\n\
+             \"quoted key\" = 1e3\n",
+        )
+        .unwrap();
+
+        let sections = &document.sections;
+        assert_eq!(sections.len(), 3);
+        assert!(sections[0].path.is_empty());
+        assert_eq!(sections[0].get("gain"), Some(&Value::Float(-3.5)));
+        assert_eq!(sections[1].path, path(&["profile", "default"]));
+        assert_eq!(
+            sections[1].entries,
+            vec![
+                ("input".to_string(), Value::String("USB Mic".to_string())),
+                ("sample_rate".to_string(), Value::Integer(48000)),
+                ("sidetone".to_string(), Value::Boolean(true)),
+                (
+                    "preferred".to_string(),
+                    Value::Array(vec![
+                        Value::String("a".to_string()),
+                        Value::String("b".to_string())
+                    ])
+                ),
+            ]
+        );
+        assert_eq!(sections[2].path, path(&["device", "USB Microphone"]));
+        assert_eq!(sections[2].get("quoted key"), Some(&Value::Float(1000.0)));
+    }
+
+    #[test]
+    fn values_round_trip() {
+        let values = [
+            Value::String("say \"hi\"\\\n\ttab # not a comment".to_string()),
+            Value::Integer(-42),
+            Value::Float(2.0),
+            Value::Float(-0.25),
+            Value::Boolean(false),
+            Value::Array(vec![
+                Value::Integer(1),
+                Value::Array(vec![Value::String("]".to_string())]),
+                Value::Array(Vec::new()),
+            ]),
+        ];
+        for value in values {
+            let document = parse(&format!("key = {value}")).unwrap();
+            assert_eq!(document.sections[0].get("key"), Some(&value));
+        }
+    }
+
+    #[test]
+    fn headers_quote_segments() {
+        let header = format_header(&path(&["device", "USB Mic \"2\"", "bare_key-1", ""]));
+        assert_eq!(header, r#"[device."USB Mic \"2\"".bare_key-1.""]"#);
+        let document = parse(&header).unwrap();
+        assert_eq!(
+            document.sections[1].path,
+            path(&["device", "USB Mic \"2\"", "bare_key-1", ""])
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(error("a = 1\na = 2"), "line 2: duplicate key \"a\"");
+        assert_eq!(error("[p]\n[q]\n[p]"), "line 3: duplicate section [p]");
+        // The same key in another section isn't a duplicate
+        assert!(parse("a = 1\n[p]\na = 2").is_ok());
+        assert_eq!(error("[p"), "line 1: expected '.' or ']' in section header");
+        assert_eq!(error("a"), "line 1: expected '=' after key \"a\"");
+        assert_eq!(error("a = "), "line 1: expected a value");
+        assert_eq!(error("a = \"open"), "line 1: unterminated string");
+        assert_eq!(
+            error(r#"a = "\x""#),
+            "line 1: unknown escape sequence \"\\x\""
+        );
+        assert_eq!(error("a = [1 2]"), "line 1: expected ',' or ']' in array");
+        assert_eq!(error("a = yes"), "line 1: invalid value \"yes\"");
+        assert_eq!(
+            error("a = 1 2"),
+            "line 1: unexpected trailing content \"2\""
+        );
+    }
+
+    #[test]
+    fn set_entries_replaces_existing_keys() {
+        let input = "# profiles\n[profile.default]\ninput = \"Old\" # old\ngain = 1.0\n\n[other]\ninput = \"Kept\"\n";
+        let output = set_entries(
+            input,
+            &path(&["profile", "default"]),
+            &[("input", Value::String("New".to_string()))],
+        );
+        assert_eq!(
+            output,
+            "# profiles\n[profile.default]\ninput = \"New\"\ngain = 1.0\n\n[other]\ninput = \"Kept\"\n"
+        );
+    }
+
+    #[test]
+    fn set_entries_adds_to_existing_sections() {
+        let input =
+            "[last]\n# chosen from the prompt\ninput = \"Mic\"\n\n# next section\n[aliases]\n";
+        let output = set_entries(
+            input,
+            &path(&["last"]),
+            &[
+                ("input", Value::String("Webcam".to_string())),
+                ("output", Value::String("Headphones".to_string())),
+            ],
+        );
+        assert_eq!(
+            output,
+            "[last]\n# chosen from the prompt\ninput = \"Webcam\"\noutput = \"Headphones\"\n\n# next section\n[aliases]\n"
+        );
+    }
+
+    #[test]
+    fn set_entries_adds_new_sections() {
+        let input = "# chemic config\ntheme = \"dark\" # comment\n";
+        let device = path(&["device", "USB Microphone"]);
+        let output = set_entries(
+            input,
+            &device,
+            &[
+                ("calibration db", Value::Float(94.0)),
+                ("gain", Value::Integer(6)),
+            ],
+        );
+        assert_eq!(
+            output,
+            "# chemic config\ntheme = \"dark\" # comment\n\n[device.\"USB Microphone\"]\n\"calibration db\" = 94.0\ngain = 6\n"
+        );
+
+        let document = parse(&output).unwrap();
+        assert_eq!(document.sections[1].path, device);
+        assert_eq!(
+            document.sections[1].get("calibration db"),
+            Some(&Value::Float(94.0))
+        );
+        assert_eq!(set_entries("", &path(&["last"]), &[]), "[last]\n");
+    }
+}
@@ -0,0 +1,383 @@
+//! Playback controls that are triggered by key presses while
+//! monitoring, along with the key bindings that trigger them
+
+use crate::meter::from_db;
+use dialoguer::console::Key;
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+};
+
+/// Lowest volume of the output in decibels
+const MIN_VOLUME_DB: f32 = -30.0;
+/// Highest volume of the output in decibels
+const MAX_VOLUME_DB: f32 = 12.0;
+/// Amount the volume is changed by each key press in decibels
+const VOLUME_STEP_DB: f32 = 1.0;
+
+/// Action triggered by a key press
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Stop monitoring
+    Stop,
+    /// Toggle muting the output
+    Mute,
+    /// Toggle pausing both streams
+    Pause,
+    /// Increase the output volume
+    VolumeUp,
+    /// Decrease the output volume
+    VolumeDown,
+    /// Drop a marker in the recording
+    Marker,
+    /// Play a short tone burst through the output
+    ToneBurst,
+    /// Increase the input volume in the OS mixer
+    OsGainUp,
+    /// Decrease the input volume in the OS mixer
+    OsGainDown,
+}
+
+impl Action {
+    /// All of the actions in the order they are described
+    pub const ALL: [Action; 9] = [
+        Action::Stop,
+        Action::Mute,
+        Action::Pause,
+        Action::VolumeUp,
+        Action::VolumeDown,
+        Action::Marker,
+        Action::ToneBurst,
+        Action::OsGainUp,
+        Action::OsGainDown,
+    ];
+
+    /// Name of the action used by the `[keys]` config section
+    pub fn config_key(self) -> &'static str {
+        match self {
+            Action::Stop => "stop",
+            Action::Mute => "mute",
+            Action::Pause => "pause",
+            Action::VolumeUp => "volume_up",
+            Action::VolumeDown => "volume_down",
+            Action::Marker => "marker",
+            Action::ToneBurst => "tone_burst",
+            Action::OsGainUp => "os_gain_up",
+            Action::OsGainDown => "os_gain_down",
+        }
+    }
+
+    /// Finds the action with the provided config `name`, the volume
+    /// actions are also found by their earlier gain names
+    pub fn from_config_key(name: &str) -> Option<Action> {
+        match name {
+            "gain_up" => return Some(Action::VolumeUp),
+            "gain_down" => return Some(Action::VolumeDown),
+            _ => {}
+        }
+
+        Action::ALL
+            .into_iter()
+            .find(|action| action.config_key() == name)
+    }
+
+    fn default_keys(self) -> Vec<Key> {
+        match self {
+            Action::Stop => vec![Key::Escape, Key::Backspace, Key::Del],
+            Action::Mute => vec![Key::Char('m')],
+            Action::Pause => vec![Key::Char(' ')],
+            Action::VolumeUp => vec![Key::Char('+'), Key::ArrowUp],
+            Action::VolumeDown => vec![Key::Char('-'), Key::ArrowDown],
+            Action::Marker => vec![Key::Enter],
+            Action::ToneBurst => vec![Key::Char('t')],
+            Action::OsGainUp => vec![Key::Char(']')],
+            Action::OsGainDown => vec![Key::Char('[')],
+        }
+    }
+}
+
+/// Keys bound to each of the actions
+#[derive(Clone)]
+pub struct KeyBindings {
+    /// Keys and the action they trigger
+    bindings: Vec<(Key, Action)>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let bindings = Action::ALL
+            .into_iter()
+            .flat_map(|action| {
+                action
+                    .default_keys()
+                    .into_iter()
+                    .map(move |key| (key, action))
+            })
+            .collect();
+
+        Self { bindings }
+    }
+}
+
+impl KeyBindings {
+    /// Replaces the keys bound to the `action`
+    pub fn bind(&mut self, action: Action, keys: Vec<Key>) {
+        self.bindings.retain(|(_, bound)| *bound != action);
+        self.bindings
+            .extend(keys.into_iter().map(|key| (key, action)));
+    }
+
+    /// Ensures none of the keys are bound to more than one action
+    pub fn validate(&self) -> Result<(), String> {
+        for (index, (key, action)) in self.bindings.iter().enumerate() {
+            let conflict = self.bindings[index + 1..]
+                .iter()
+                .find(|(other_key, other)| other_key == key && other != action);
+
+            if let Some((_, other)) = conflict {
+                return Err(format!(
+                    "\"{}\" is bound to both {} and {}",
+                    key_name(key),
+                    action.config_key(),
+                    other.config_key()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finds the action bound to the provided `key`, Ctrl+C always
+    /// stops so monitoring can't become impossible to exit
+    pub fn action(&self, key: &Key) -> Option<Action> {
+        if let Key::CtrlC = key {
+            return Some(Action::Stop);
+        }
+
+        self.bindings
+            .iter()
+            .find(|(bound, _)| bound == key)
+            .map(|(_, action)| *action)
+    }
+
+    /// Names of the keys bound to the `action` joined with "/"
+    /// (e.g. "Escape/Backspace")
+    pub fn describe(&self, action: Action) -> String {
+        let names: Vec<String> = self
+            .bindings
+            .iter()
+            .filter(|(_, bound)| *bound == action)
+            .map(|(key, _)| key_name(key))
+            .collect();
+
+        if names.is_empty() {
+            return "Ctrl+C".to_string();
+        }
+
+        names.join("/")
+    }
+}
+
+/// Parses the name of a key from the config, either a single character
+/// or one of the named keys (e.g. "Escape", "Space", "Up")
+pub fn parse_key(name: &str) -> Result<Key, String> {
+    let mut chars = name.chars();
+    if let (Some(value), None) = (chars.next(), chars.next()) {
+        return Ok(Key::Char(value));
+    }
+
+    let key = match name.to_lowercase().as_str() {
+        "escape" | "esc" => Key::Escape,
+        "backspace" => Key::Backspace,
+        "delete" | "del" => Key::Del,
+        "enter" | "return" => Key::Enter,
+        "tab" => Key::Tab,
+        "space" => Key::Char(' '),
+        "up" => Key::ArrowUp,
+        "down" => Key::ArrowDown,
+        "left" => Key::ArrowLeft,
+        "right" => Key::ArrowRight,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "insert" => Key::Insert,
+        _ => return Err(format!("unknown key \"{name}\"")),
+    };
+
+    Ok(key)
+}
+
+/// Name of the `key` as shown in the hints
+fn key_name(key: &Key) -> String {
+    match key {
+        Key::Char(' ') => "Space".to_string(),
+        Key::Char(value) => value.to_string(),
+        Key::Escape => "Escape".to_string(),
+        Key::Backspace => "Backspace".to_string(),
+        Key::Del => "Delete".to_string(),
+        Key::Enter => "Enter".to_string(),
+        Key::Tab => "Tab".to_string(),
+        Key::ArrowUp => "Up".to_string(),
+        Key::ArrowDown => "Down".to_string(),
+        Key::ArrowLeft => "Left".to_string(),
+        Key::ArrowRight => "Right".to_string(),
+        Key::Home => "Home".to_string(),
+        Key::End => "End".to_string(),
+        Key::PageUp => "PageUp".to_string(),
+        Key::PageDown => "PageDown".to_string(),
+        Key::Insert => "Insert".to_string(),
+        key => format!("{key:?}"),
+    }
+}
+
+/// Volume of the monitored output
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Volume {
+    /// Percentage of the full volume, 100% leaves the level unchanged
+    /// and 0% is silent
+    Percent(f32),
+    /// Gain in decibels
+    Decibels(f32),
+}
+
+impl Volume {
+    /// Gain of the volume in decibels, [None] when silent
+    pub fn db(self) -> Option<f32> {
+        match self {
+            Volume::Percent(percent) if percent <= 0.0 => None,
+            Volume::Percent(percent) => Some(20.0 * (percent / 100.0).log10()),
+            Volume::Decibels(db) => Some(db),
+        }
+    }
+}
+
+/// Parses a percentage from 0 to 100 (e.g. "50" or "50%") or a gain in
+/// decibels (e.g. "-6dB")
+impl FromStr for Volume {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let lower = value.trim().to_lowercase();
+        let invalid = || format!("invalid volume \"{value}\" (e.g. 50, 50% or -6dB)");
+
+        if let Some(db) = lower.strip_suffix("db") {
+            let db: f32 = db.trim().parse().map_err(|_| invalid())?;
+            return db
+                .is_finite()
+                .then_some(Volume::Decibels(db))
+                .ok_or_else(invalid);
+        }
+
+        let percent: f32 = lower
+            .strip_suffix('%')
+            .unwrap_or(&lower)
+            .trim()
+            .parse()
+            .map_err(|_| invalid())?;
+        (0.0..=100.0)
+            .contains(&percent)
+            .then_some(Volume::Percent(percent))
+            .ok_or_else(invalid)
+    }
+}
+
+/// State of the playback controls shared with the output callback
+#[derive(Default)]
+pub struct Controls {
+    /// Whether the output is muted
+    muted: AtomicBool,
+    /// Bits of the volume of the output in decibels
+    volume_db: AtomicU32,
+    /// Whether the polarity of the output is inverted
+    inverted: AtomicBool,
+    /// Bits of the gain in decibels applied on top of the volume to
+    /// compensate for the sensitivity of the input device
+    offset_db: AtomicU32,
+    /// Whether a tone burst should be played
+    burst: AtomicBool,
+}
+
+impl Controls {
+    /// Toggles muting the output, returns whether the output is now muted
+    pub fn toggle_mute(&self) -> bool {
+        !self.muted.fetch_xor(true, Ordering::Relaxed)
+    }
+
+    /// Sets the `volume` of the output, a silent volume mutes the output
+    /// at the lowest volume
+    pub fn set_volume(&self, volume: Volume) {
+        let db = volume.db();
+        self.muted.store(db.is_none(), Ordering::Relaxed);
+        let db = db
+            .unwrap_or(MIN_VOLUME_DB)
+            .clamp(MIN_VOLUME_DB, MAX_VOLUME_DB);
+        self.volume_db.store(db.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Changes the volume by a step `up` or down, returns the new volume
+    /// in decibels
+    pub fn step_volume(&self, up: bool) -> f32 {
+        let step = if up { VOLUME_STEP_DB } else { -VOLUME_STEP_DB };
+        let update = |bits: u32| {
+            let db = (f32::from_bits(bits) + step).clamp(MIN_VOLUME_DB, MAX_VOLUME_DB);
+            Some(db.to_bits())
+        };
+
+        let previous = self
+            .volume_db
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, update)
+            .unwrap_or_default();
+        f32::from_bits(update(previous).unwrap_or_default())
+    }
+
+    /// Sets whether the polarity of the output is `inverted`
+    pub fn set_inverted(&self, inverted: bool) {
+        self.inverted.store(inverted, Ordering::Relaxed);
+    }
+
+    /// Sets the gain `offset_db` applied on top of the volume
+    pub fn set_offset_db(&self, offset_db: f32) {
+        self.offset_db.store(offset_db.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Asks for a tone burst to be played through the output
+    pub fn request_burst(&self) {
+        self.burst.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether a tone burst was asked for since the last call
+    pub fn take_burst(&self) -> bool {
+        self.burst.swap(false, Ordering::Relaxed)
+    }
+
+    /// Linear factor of the volume alone, zero when the output is muted
+    pub fn volume_factor(&self) -> f32 {
+        if self.muted.load(Ordering::Relaxed) {
+            return 0.0;
+        }
+
+        from_db(f32::from_bits(self.volume_db.load(Ordering::Relaxed)))
+    }
+
+    /// Linear factor the output samples are multiplied by
+    pub fn factor(&self) -> f32 {
+        if self.muted.load(Ordering::Relaxed) {
+            return 0.0;
+        }
+
+        let sign = if self.inverted.load(Ordering::Relaxed) {
+            -1.0
+        } else {
+            1.0
+        };
+
+        let gain_db = f32::from_bits(self.volume_db.load(Ordering::Relaxed))
+            + f32::from_bits(self.offset_db.load(Ordering::Relaxed));
+        if gain_db == 0.0 {
+            return sign;
+        }
+
+        sign * 10f32.powf(gain_db / 20.0)
+    }
+}
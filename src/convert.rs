@@ -0,0 +1,114 @@
+//! Conversion of WAV files to another sample rate or number of channels
+//! with the same resampler and channel mixer used while monitoring
+
+use crate::{
+    args::Args,
+    error::ChemicError,
+    log::info,
+    mix::MixMatrix,
+    wav::{self, BroadcastInfo, Finishing, WavAudio, WavWriter},
+};
+use dasp_interpolate::linear::Linear;
+use dasp_signal::{interpolate::Converter, Signal};
+use std::{io, path::Path, time::SystemTime};
+
+/// Converts the WAV file at the `input` path to the `--sample-rate` and
+/// `--channels` from the `args`, writing it to the `output` path
+pub fn run(args: Args, input: &Path, output: &Path) -> Result<(), ChemicError> {
+    let audio = wav::read(input).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!("Failed to read {}: {err}", input.display()),
+        )
+    })?;
+
+    let sample_rate = args.sample_rate.unwrap_or(audio.sample_rate);
+    let channels = args.channels.unwrap_or(audio.channels);
+    let samples = resample(
+        &mix(&audio, channels, &args),
+        channels,
+        audio.sample_rate,
+        sample_rate,
+    );
+
+    let info = BroadcastInfo {
+        description: format!("Converted from {}", input.display()),
+        device: String::new(),
+        started: SystemTime::now(),
+    };
+    let finishing = Finishing {
+        format: args.bits.unwrap_or_default(),
+        dither: args.dither.unwrap_or_default(),
+        ..Finishing::default()
+    };
+    let mut writer = WavWriter::create(output, sample_rate, channels, info)?.finishing(finishing);
+    writer.write(&samples)?;
+    writer.finish()?;
+
+    info!(
+        "converted {} ({}Hz {}ch) to {} ({sample_rate}Hz {channels}ch)",
+        input.display(),
+        audio.sample_rate,
+        audio.channels,
+        output.display()
+    );
+    println!(
+        "Converted {} from {}Hz {}ch to {sample_rate}Hz {channels}ch, saved to {}",
+        input.display(),
+        audio.sample_rate,
+        audio.channels,
+        output.display()
+    );
+    Ok(())
+}
+
+/// Mixes the interleaved samples of the `audio` to the number of
+/// `channels`, mono uses the `--downmix` from the `args` when provided
+fn mix(audio: &WavAudio, channels: u16, args: &Args) -> Vec<f32> {
+    let inputs = audio.channels.max(1) as usize;
+    let outputs = channels as usize;
+    if inputs == outputs {
+        return audio.samples.clone();
+    }
+
+    if let (1, Some(downmix)) = (outputs, args.downmix) {
+        return audio
+            .samples
+            .chunks_exact(inputs)
+            .map(|frame| downmix.apply(frame))
+            .collect();
+    }
+
+    let matrix = MixMatrix::default_for(inputs, outputs);
+    let mut mixed = vec![0.0; audio.samples.len() / inputs * outputs];
+    matrix.apply_frames(&audio.samples, &mut mixed);
+    mixed
+}
+
+/// Converts each channel of the interleaved `samples` from the `from`
+/// sample rate to the `to` sample rate using linear interpolation
+fn resample(samples: &[f32], channels: u16, from: u32, to: u32) -> Vec<f32> {
+    if from == to {
+        return samples.to_vec();
+    }
+
+    let channels = channels as usize;
+    let frames = samples.len() / channels;
+    let output_frames = (frames as f64 * to as f64 / from as f64) as usize;
+    let mut output = vec![0.0; output_frames * channels];
+    for channel in 0..channels {
+        let source =
+            dasp_signal::from_iter(samples.iter().skip(channel).step_by(channels).copied());
+        let converter =
+            Converter::from_hz_to_hz(source, Linear::new(0.0, 0.0), from as f64, to as f64);
+        for (sample, value) in output
+            .iter_mut()
+            .skip(channel)
+            .step_by(channels)
+            .zip(converter.until_exhausted())
+        {
+            *sample = value;
+        }
+    }
+    output
+}
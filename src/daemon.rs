@@ -0,0 +1,169 @@
+//! Background watchdog that keeps the configured input device open and
+//! reports silence and device failures through the log and event stream
+
+use crate::{
+    args::Args,
+    config::Config,
+    error::ChemicError,
+    events::{self, Event},
+    get_default_device,
+    log::{error, info},
+    meter::LevelMeter,
+    negotiate::negotiate_input,
+    positive_duration, requested_device, DeviceType, NamedDevice,
+};
+use cpal::{
+    traits::{DeviceTrait, StreamTrait},
+    Stream, StreamConfig, StreamError,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Time between the level checks
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Time to wait before trying to reopen a failed device
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+/// Default level in dBFS below which the input is considered silent
+pub const DEFAULT_SILENCE_THRESHOLD: f32 = -60.0;
+/// Default number of seconds of silence before it is reported
+pub const DEFAULT_SILENCE_TIMEOUT: f64 = 10.0;
+
+/// Open input stream being watched
+struct Watched {
+    /// Stream kept alive while watching
+    _stream: Stream,
+    /// Set by the stream error callback when the stream fails
+    failed: Arc<AtomicBool>,
+}
+
+/// Runs the watchdog until the process is terminated, never prompts
+/// for devices so it can run without a terminal
+pub fn run(mut args: Args) -> Result<(), ChemicError> {
+    let config = Config::load(args.config.as_deref())?;
+    args.apply_config(&config)?;
+
+    let threshold = args.silence_threshold.unwrap_or(DEFAULT_SILENCE_THRESHOLD);
+    let timeout = positive_duration(
+        args.silence_timeout.unwrap_or(DEFAULT_SILENCE_TIMEOUT),
+        "Silence timeout",
+    )?;
+
+    let host = cpal::default_host();
+    let meter = Arc::new(LevelMeter::default());
+
+    let mut watched: Option<Watched> = None;
+    // When the input became silent, [None] while there is signal
+    let mut silent_since: Option<Instant> = None;
+    let mut silence_reported = false;
+
+    info!("daemon started, silence below {threshold} dBFS for {timeout:?} is reported");
+
+    loop {
+        // Open the device when it isn't open or has failed
+        if watched
+            .as_ref()
+            .is_none_or(|watched| watched.failed.load(Ordering::Relaxed))
+        {
+            if watched.take().is_some() {
+                events::emit(Event::DeviceLost {
+                    device: DeviceType::Input,
+                    message: "input stream failed".to_string(),
+                });
+            }
+
+            match open(&host, &args, &meter) {
+                Ok(opened) => {
+                    watched = Some(opened);
+                    silent_since = None;
+                    silence_reported = false;
+                }
+                Err(err) => {
+                    error!("failed to open input device: {err}");
+                    thread::sleep(RETRY_INTERVAL);
+                    continue;
+                }
+            }
+        }
+
+        thread::sleep(CHECK_INTERVAL);
+
+        let level = meter.take();
+        let silent = level.rms_db().is_none_or(|db| db < threshold);
+
+        match (silent, silent_since) {
+            (true, None) => silent_since = Some(Instant::now()),
+            (true, Some(since)) if !silence_reported && since.elapsed() >= timeout => {
+                silence_reported = true;
+                let seconds = since.elapsed().as_secs();
+                error!("input has been silent for {seconds}s");
+                events::emit(Event::Silence { seconds });
+            }
+            (false, Some(_)) => {
+                if silence_reported {
+                    info!("input signal restored");
+                    events::emit(Event::SignalRestored);
+                }
+                silent_since = None;
+                silence_reported = false;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Finds and opens the configured input device recording its levels
+/// into the `meter`
+fn open(host: &cpal::Host, args: &Args, meter: &Arc<LevelMeter>) -> Result<Watched, ChemicError> {
+    let device: NamedDevice = match requested_device(host, args, DeviceType::Input, false)? {
+        Some(device) => device,
+        None => get_default_device(host, DeviceType::Input)
+            .ok_or(ChemicError::NoDeviceRequested(DeviceType::Input))?,
+    };
+
+    let config: StreamConfig = negotiate_input(&device, args.sample_rate)?.into();
+    let failed = Arc::new(AtomicBool::new(false));
+
+    let stream = device
+        .device
+        .build_input_stream(
+            &config,
+            {
+                let meter = meter.clone();
+                move |data: &[f32], _| meter.record(data)
+            },
+            {
+                let failed = failed.clone();
+                move |err: StreamError| {
+                    error!("input stream error: {err} ({err:?})");
+                    failed.store(true, Ordering::Relaxed);
+                }
+            },
+            None,
+        )
+        .map_err(|source| ChemicError::BuildStream {
+            ty: DeviceType::Input,
+            device: device.name.clone(),
+            source,
+        })?;
+    stream.play()?;
+
+    info!(
+        "watching input device \"{}\" at {}Hz {}ch",
+        device.name, config.sample_rate.0, config.channels
+    );
+    events::emit(Event::Watching {
+        input: &device.name,
+        input_config: &config,
+    });
+
+    Ok(Watched {
+        _stream: stream,
+        failed,
+    })
+}
@@ -0,0 +1,176 @@
+//! Errors that can occur while selecting devices and streaming
+
+use crate::DeviceType;
+use cpal::{
+    BuildStreamError, DevicesError, PauseStreamError, PlayStreamError, SupportedStreamConfigsError,
+};
+use std::io;
+use thiserror::Error;
+
+/// Error reported by chemic, shown to the user when it stops the program
+#[derive(Debug, Error)]
+pub enum ChemicError {
+    /// Error from reading files or the terminal
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    /// The host was unable to list the devices
+    #[error("Unable to load the {ty} devices: {source}")]
+    LoadDevices {
+        ty: DeviceType,
+        source: DevicesError,
+    },
+
+    /// The host has no devices of the type
+    #[error("No {0} devices available")]
+    NoDevices(DeviceType),
+
+    /// No device matched the requested name
+    #[error("No {ty} device named \"{name}\"")]
+    DeviceNotFound { ty: DeviceType, name: String },
+
+    /// Neither an input nor an output device matched the requested name
+    #[error("No device named \"{0}\"")]
+    NoDeviceNamed(String),
+
+    /// No device was requested and there is no default device to use in
+    /// its place without prompting
+    #[error("No {0} device was provided and there is no default {0} device, provide one with --{0} or in a profile")]
+    NoDeviceRequested(DeviceType),
+
+    /// No device name matched the requested pattern
+    #[error("No {ty} device matches \"{pattern}\"")]
+    NoDeviceMatches { ty: DeviceType, pattern: String },
+
+    /// The device was unable to list its supported configs
+    #[error("Unable to load the supported {ty} configs for {device}: {source}")]
+    LoadConfigs {
+        ty: DeviceType,
+        device: String,
+        source: SupportedStreamConfigsError,
+    },
+
+    /// The device didn't report any supported configs
+    #[error("No supported {ty} configs for {device}")]
+    NoConfigs { ty: DeviceType, device: String },
+
+    /// The device doesn't support the requested sample rate
+    #[error("{device} does not support a sample rate of {sample_rate}Hz")]
+    UnsupportedSampleRate { device: String, sample_rate: u32 },
+
+    /// None of the supported configs of the device can be used
+    #[error("No usable config for {device}")]
+    NoUsableConfig { device: String },
+
+    /// The stream for the device couldn't be created, the device may
+    /// be busy or have been unplugged since it was selected
+    #[error("Failed to open the {ty} device {device}: {source}")]
+    BuildStream {
+        ty: DeviceType,
+        device: String,
+        source: BuildStreamError,
+    },
+
+    /// The streams couldn't be started
+    #[error("Failed to start the streams: {0}")]
+    PlayStream(#[from] PlayStreamError),
+
+    /// The streams couldn't be paused
+    #[error("Failed to pause the streams: {0}")]
+    PauseStream(#[from] PauseStreamError),
+
+    /// Reading the device selection failed
+    #[error("Failed to select a device: {0}")]
+    Prompt(#[source] io::Error),
+
+    /// A check finished but its result didn't meet the threshold for
+    /// passing
+    #[error("{0}")]
+    CheckFailed(String),
+}
+
+/// Exit codes chemic stops with, documented in the README so scripts can
+/// tell the failures apart
+pub mod exit_code {
+    /// Any other error such as invalid arguments or failing to read a file
+    pub const OTHER: u8 = 1;
+    /// No device was available or matched the requested device
+    pub const NO_DEVICE: u8 = 2;
+    /// A device couldn't be configured, opened or started
+    pub const DEVICE_FAILED: u8 = 3;
+    /// A check ran but didn't pass
+    pub const CHECK_FAILED: u8 = 4;
+    /// The user cancelled a device prompt
+    pub const ABORTED: u8 = 5;
+}
+
+impl ChemicError {
+    /// Whether choosing different devices may avoid the error, these
+    /// errors re-prompt for the devices when running interactively
+    pub fn recoverable(&self) -> bool {
+        matches!(
+            self,
+            ChemicError::DeviceNotFound { .. }
+                | ChemicError::NoDeviceMatches { .. }
+                | ChemicError::LoadConfigs { .. }
+                | ChemicError::NoConfigs { .. }
+                | ChemicError::UnsupportedSampleRate { .. }
+                | ChemicError::NoUsableConfig { .. }
+                | ChemicError::BuildStream { .. }
+                | ChemicError::PlayStream(_)
+        )
+    }
+
+    /// Code the program exits with when it stops with this error
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ChemicError::LoadDevices { .. }
+            | ChemicError::NoDevices(_)
+            | ChemicError::NoDeviceRequested(_)
+            | ChemicError::DeviceNotFound { .. }
+            | ChemicError::NoDeviceNamed(_)
+            | ChemicError::NoDeviceMatches { .. } => exit_code::NO_DEVICE,
+            ChemicError::LoadConfigs { .. }
+            | ChemicError::NoConfigs { .. }
+            | ChemicError::UnsupportedSampleRate { .. }
+            | ChemicError::NoUsableConfig { .. }
+            | ChemicError::BuildStream { .. }
+            | ChemicError::PlayStream(_)
+            | ChemicError::PauseStream(_) => exit_code::DEVICE_FAILED,
+            ChemicError::CheckFailed(_) => exit_code::CHECK_FAILED,
+            // Ctrl+C and closing the input end the prompt without an answer
+            ChemicError::Prompt(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::Interrupted | io::ErrorKind::UnexpectedEof
+                ) =>
+            {
+                exit_code::ABORTED
+            }
+            ChemicError::Prompt(_) | ChemicError::Io(_) => exit_code::OTHER,
+        }
+    }
+
+    /// Type of the device that caused the error when the error is
+    /// specific to one of the devices
+    pub fn device_type(&self) -> Option<DeviceType> {
+        match self {
+            ChemicError::DeviceNotFound { ty, .. }
+            | ChemicError::NoDeviceMatches { ty, .. }
+            | ChemicError::LoadConfigs { ty, .. }
+            | ChemicError::NoConfigs { ty, .. }
+            | ChemicError::BuildStream { ty, .. } => Some(*ty),
+            _ => None,
+        }
+    }
+}
+
+/// Allows the errors to be used by the commands that report io errors
+impl From<ChemicError> for io::Error {
+    fn from(err: ChemicError) -> Self {
+        match err {
+            ChemicError::Io(err) => err,
+            err => io::Error::other(err),
+        }
+    }
+}
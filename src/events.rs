@@ -0,0 +1,312 @@
+//! Machine readable events written to stdout as newline delimited
+//! JSON, allowing other programs to use chemic as a backend process
+
+use crate::{
+    glitch::GlitchKind,
+    log::{escape_json, format_timestamp},
+    meter::LevelMeter,
+    stats::StreamStats,
+    DeviceType,
+};
+use cpal::StreamConfig;
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+/// Whether events are written to stdout
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Time between the level events
+const LEVEL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Format the events are written in
+#[derive(Clone, Copy)]
+pub enum EventFormat {
+    /// Newline delimited JSON objects
+    Json,
+}
+
+impl FromStr for EventFormat {
+    type Err = io::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "json" => Ok(EventFormat::Json),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown event format \"{value}\""),
+            )),
+        }
+    }
+}
+
+/// Event reported while monitoring
+pub enum Event<'a> {
+    /// Monitoring started using the named devices and configs
+    Started {
+        input: &'a str,
+        input_config: &'a StreamConfig,
+        output: &'a str,
+        output_config: &'a StreamConfig,
+    },
+    /// Daemon started watching the named input device
+    Watching {
+        input: &'a str,
+        input_config: &'a StreamConfig,
+    },
+    /// Input levels over the last interval in dBFS, [None] for silence,
+    /// and the stereo correlation when available
+    Level {
+        rms_db: Option<f32>,
+        peak_db: Option<f32>,
+        correlation: Option<f32>,
+    },
+    /// Input reached full scale during the last interval
+    Clip { peak: f32 },
+    /// Output samples were filled with silence during the last interval
+    Underrun { samples: u64 },
+    /// Samples didn't fit in the recording buffers and are missing from
+    /// the recordings of the take
+    RecordingOverflow { samples: u64 },
+    /// Dropout or sample jump detected in the input
+    Glitch {
+        kind: GlitchKind,
+        at_seconds: f64,
+        length_ms: f64,
+    },
+    /// A device was added to the device list
+    DeviceAdded { device: DeviceType, name: &'a str },
+    /// A device was removed from the device list
+    DeviceRemoved { device: DeviceType, name: &'a str },
+    /// A device became unavailable
+    DeviceLost { device: DeviceType, message: String },
+    /// Input has been silent for the provided number of seconds
+    Silence { seconds: u64 },
+    /// Input has a signal again after being reported as silent
+    SignalRestored,
+    /// Monitoring stopped
+    Stopped { stats: &'a StreamStats },
+}
+
+impl Event<'_> {
+    fn name(&self) -> &'static str {
+        match self {
+            Event::Started { .. } => "started",
+            Event::Watching { .. } => "watching",
+            Event::Level { .. } => "level",
+            Event::Clip { .. } => "clip",
+            Event::Underrun { .. } => "underrun",
+            Event::RecordingOverflow { .. } => "recording_overflow",
+            Event::Glitch { .. } => "glitch",
+            Event::DeviceAdded { .. } => "device_added",
+            Event::DeviceRemoved { .. } => "device_removed",
+            Event::DeviceLost { .. } => "device_lost",
+            Event::Silence { .. } => "silence",
+            Event::SignalRestored => "signal_restored",
+            Event::Stopped { .. } => "stopped",
+        }
+    }
+
+    /// Writes the fields specific to the event as JSON object members
+    fn write_fields(&self, out: &mut String) {
+        match self {
+            Event::Started {
+                input,
+                input_config,
+                output,
+                output_config,
+            } => {
+                _ = write!(
+                    out,
+                    r#","input":{{"name":"{}","sample_rate":{},"channels":{}}},"output":{{"name":"{}","sample_rate":{},"channels":{}}}"#,
+                    escape_json(input),
+                    input_config.sample_rate.0,
+                    input_config.channels,
+                    escape_json(output),
+                    output_config.sample_rate.0,
+                    output_config.channels
+                );
+            }
+            Event::Watching {
+                input,
+                input_config,
+            } => {
+                _ = write!(
+                    out,
+                    r#","input":{{"name":"{}","sample_rate":{},"channels":{}}}"#,
+                    escape_json(input),
+                    input_config.sample_rate.0,
+                    input_config.channels
+                );
+            }
+            Event::Level {
+                rms_db,
+                peak_db,
+                correlation,
+            } => {
+                _ = write!(
+                    out,
+                    r#","rms_db":{},"peak_db":{},"correlation":{}"#,
+                    json_number(*rms_db),
+                    json_number(*peak_db),
+                    match correlation {
+                        Some(correlation) => format!("{correlation:.2}"),
+                        None => "null".to_string(),
+                    }
+                );
+            }
+            Event::Clip { peak } => {
+                _ = write!(out, r#","peak":{peak:.3}"#);
+            }
+            Event::Underrun { samples } | Event::RecordingOverflow { samples } => {
+                _ = write!(out, r#","samples":{samples}"#);
+            }
+            Event::Glitch {
+                kind,
+                at_seconds,
+                length_ms,
+            } => {
+                _ = write!(
+                    out,
+                    r#","kind":"{kind}","at_seconds":{at_seconds:.3},"length_ms":{length_ms:.1}"#
+                );
+            }
+            Event::DeviceAdded { device, name } | Event::DeviceRemoved { device, name } => {
+                _ = write!(
+                    out,
+                    r#","device":"{device}","name":"{}""#,
+                    escape_json(name)
+                );
+            }
+            Event::DeviceLost { device, message } => {
+                _ = write!(
+                    out,
+                    r#","device":"{device}","message":"{}""#,
+                    escape_json(message)
+                );
+            }
+            Event::Silence { seconds } => {
+                _ = write!(out, r#","seconds":{seconds}"#);
+            }
+            Event::SignalRestored => {}
+            Event::Stopped { stats } => {
+                let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+                let latency_ms = stats
+                    .latency()
+                    .map(|latency| latency.total().as_secs_f32() * 1000.0);
+                _ = write!(
+                    out,
+                    r#","input_samples":{},"dropped_samples":{},"output_samples":{},"underrun_samples":{},"dropouts":{},"sample_jumps":{},"latency_ms":{}"#,
+                    load(&stats.input_samples),
+                    load(&stats.dropped_samples),
+                    load(&stats.output_samples),
+                    load(&stats.underrun_samples),
+                    load(&stats.dropouts),
+                    load(&stats.sample_jumps),
+                    json_number(latency_ms)
+                );
+            }
+        }
+    }
+}
+
+/// Formats an optional number as JSON, [None] becomes null
+fn json_number(value: Option<f32>) -> String {
+    match value {
+        Some(value) => format!("{value:.1}"),
+        None => "null".to_string(),
+    }
+}
+
+/// Enables writing events to stdout in the provided `format`
+pub fn enable(format: EventFormat) {
+    match format {
+        EventFormat::Json => ENABLED.store(true, Ordering::Relaxed),
+    }
+}
+
+/// Whether events are being written
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Writes the `event` to stdout when events are enabled
+pub fn emit(event: Event<'_>) {
+    if !enabled() {
+        return;
+    }
+
+    let mut line = format!(
+        r#"{{"event":"{}","timestamp":"{}""#,
+        event.name(),
+        format_timestamp(SystemTime::now())
+    );
+    event.write_fields(&mut line);
+    line.push('}');
+
+    // Flushed after every event so readers receive them immediately
+    let mut stdout = io::stdout().lock();
+    _ = writeln!(stdout, "{line}");
+    _ = stdout.flush();
+}
+
+/// Background thread reporting the level, clip and underrun events
+pub struct EventReporter {
+    /// Whether the reporter should stop
+    stop: Arc<AtomicBool>,
+    /// Thread reporting the events
+    handle: JoinHandle<()>,
+}
+
+impl EventReporter {
+    /// Starts reporting the levels of the `meter` and the underruns
+    /// recorded in the `stats`
+    pub fn start(meter: Arc<LevelMeter>, stats: Arc<StreamStats>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                let mut underruns = stats.underrun_samples.load(Ordering::Relaxed);
+                meter.take();
+
+                while !stop.load(Ordering::Relaxed) {
+                    thread::sleep(LEVEL_INTERVAL);
+
+                    let level = meter.take();
+                    emit(Event::Level {
+                        rms_db: level.rms_db(),
+                        peak_db: level.peak_db(),
+                        correlation: level.correlation,
+                    });
+                    if level.clipped() {
+                        emit(Event::Clip { peak: level.peak });
+                    }
+
+                    let total = stats.underrun_samples.load(Ordering::Relaxed);
+                    if total > underruns {
+                        emit(Event::Underrun {
+                            samples: total - underruns,
+                        });
+                    }
+                    underruns = total;
+                }
+            }
+        });
+
+        Self { stop, handle }
+    }
+
+    /// Stops reporting events
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        _ = self.handle.join();
+    }
+}
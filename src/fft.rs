@@ -0,0 +1,57 @@
+//! Radix 2 fast Fourier transform used to correlate and analyse whole
+//! recordings
+
+use std::f64::consts::TAU;
+
+/// Transforms the complex samples with the real parts `re` and the
+/// imaginary parts `im` in place, the inverse transform is scaled by one
+/// over the length. The length must be a power of two
+pub fn fft(re: &mut [f64], im: &mut [f64], inverse: bool) {
+    let length = re.len();
+    debug_assert!(length.is_power_of_two() && im.len() == length);
+    if length < 2 {
+        return;
+    }
+
+    // Reorder the samples into bit reversed order
+    let bits = length.trailing_zeros();
+    for index in 0..length {
+        let reversed = index.reverse_bits() >> (usize::BITS - bits);
+        if reversed > index {
+            re.swap(index, reversed);
+            im.swap(index, reversed);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut size = 2;
+    while size <= length {
+        let angle = sign * TAU / size as f64;
+        let (step_im, step_re) = angle.sin_cos();
+        for start in (0..length).step_by(size) {
+            let (mut twiddle_re, mut twiddle_im) = (1.0, 0.0);
+            for offset in 0..size / 2 {
+                let even = start + offset;
+                let odd = even + size / 2;
+                let odd_re = re[odd] * twiddle_re - im[odd] * twiddle_im;
+                let odd_im = re[odd] * twiddle_im + im[odd] * twiddle_re;
+                re[odd] = re[even] - odd_re;
+                im[odd] = im[even] - odd_im;
+                re[even] += odd_re;
+                im[even] += odd_im;
+
+                let next_re = twiddle_re * step_re - twiddle_im * step_im;
+                twiddle_im = twiddle_re * step_im + twiddle_im * step_re;
+                twiddle_re = next_re;
+            }
+        }
+        size *= 2;
+    }
+
+    if inverse {
+        let scale = 1.0 / length as f64;
+        re.iter_mut()
+            .chain(im.iter_mut())
+            .for_each(|value| *value *= scale);
+    }
+}
@@ -0,0 +1,322 @@
+//! Select prompt that filters the available items as the user types
+//!
+//! Dialoguer provides a similar prompt behind its "fuzzy-select" feature
+//! but this implementation is self contained to avoid the extra matcher
+//! dependency
+
+use crate::lang::{tr, Message};
+use dialoguer::{
+    console::{Key, Term},
+    theme::Theme,
+};
+use std::io;
+
+/// Select prompt that narrows down the items using a fuzzy search
+/// query typed by the user
+pub struct FuzzySelect<'a> {
+    /// Theme used to render the prompt
+    theme: &'a dyn Theme,
+    /// Prompt text shown above the items
+    prompt: String,
+    /// Items to select from
+    items: Vec<String>,
+    /// Extra details shown alongside each item, these are not
+    /// searched and aren't included in the report
+    details: Vec<String>,
+    /// Index of the item selected by default
+    default: usize,
+    /// Whether to report the selection after the prompt has finished
+    report: bool,
+    /// Whether to use a line based prompt that doesn't redraw the screen
+    plain: bool,
+}
+
+impl<'a> FuzzySelect<'a> {
+    /// Creates a new prompt using the provided `theme`
+    pub fn with_theme(theme: &'a dyn Theme) -> Self {
+        Self {
+            theme,
+            prompt: String::new(),
+            items: Vec::new(),
+            details: Vec::new(),
+            default: 0,
+            report: false,
+            plain: false,
+        }
+    }
+
+    pub fn with_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.prompt = prompt.into();
+        self
+    }
+
+    pub fn default(mut self, default: usize) -> Self {
+        self.default = default;
+        self
+    }
+
+    pub fn report(mut self, report: bool) -> Self {
+        self.report = report;
+        self
+    }
+
+    pub fn items<T: ToString>(mut self, items: &[T]) -> Self {
+        self.items.extend(items.iter().map(ToString::to_string));
+        self
+    }
+
+    /// Uses a plain line based prompt that lists numbered items and
+    /// reads the selection from stdin, for terminals that don't
+    /// support redrawing
+    pub fn plain(mut self, plain: bool) -> Self {
+        self.plain = plain;
+        self
+    }
+
+    /// Sets the details shown alongside the item at the same index
+    pub fn details<T: ToString>(mut self, details: &[T]) -> Self {
+        self.details = details.iter().map(ToString::to_string).collect();
+        self
+    }
+
+    /// Shows the prompt on stderr returning the index of the
+    /// selected item
+    pub fn interact(self) -> io::Result<usize> {
+        if self.plain {
+            return self.interact_plain();
+        }
+
+        let term = Term::stderr();
+
+        if !term.is_term() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "not a terminal",
+            ));
+        }
+
+        term.hide_cursor()?;
+        let result = self.interact_on(&term);
+        term.show_cursor()?;
+        result
+    }
+
+    fn interact_on(&self, term: &Term) -> io::Result<usize> {
+        let mut query = String::new();
+        // Position of the selection within the matches
+        let mut selected: usize = 0;
+        // First match that is visible on screen
+        let mut offset: usize = 0;
+        // Number of lines written by the last render
+        let mut rendered: usize = 0;
+
+        // Leave space for the prompt line and a line for the cursor
+        let visible_rows = (term.size().0 as usize).max(3) - 2;
+
+        // The default item starts selected when there is no query
+        let mut matches = self.matches(&query);
+        if let Some(position) = matches.iter().position(|&index| index == self.default) {
+            selected = position;
+        }
+
+        loop {
+            // Keep the selection in view
+            if selected < offset {
+                offset = selected;
+            } else if selected >= offset + visible_rows {
+                offset = selected + 1 - visible_rows;
+            }
+
+            term.clear_last_lines(rendered)?;
+
+            let mut prompt = String::new();
+            self.theme
+                .format_input_prompt(&mut prompt, &self.prompt, None)
+                .map_err(io::Error::other)?;
+            term.write_line(&format!("{prompt}{query}"))?;
+            rendered = 1;
+
+            for (position, &index) in matches.iter().enumerate().skip(offset).take(visible_rows) {
+                let text = self.item_text(index);
+
+                let mut item = String::new();
+                self.theme
+                    .format_select_prompt_item(&mut item, &text, position == selected)
+                    .map_err(io::Error::other)?;
+                term.write_line(&item)?;
+                rendered += 1;
+            }
+
+            term.flush()?;
+
+            match term.read_key()? {
+                Key::ArrowUp | Key::BackTab if !matches.is_empty() => {
+                    selected = selected.checked_sub(1).unwrap_or(matches.len() - 1);
+                }
+                Key::ArrowDown | Key::Tab if !matches.is_empty() => {
+                    selected = (selected + 1) % matches.len();
+                }
+                Key::Enter if !matches.is_empty() => {
+                    let index = matches[selected];
+
+                    term.clear_last_lines(rendered)?;
+                    self.report_selection(term, index)?;
+                    return Ok(index);
+                }
+                Key::Backspace => {
+                    query.pop();
+                    matches = self.matches(&query);
+                    selected = 0;
+                }
+                // Escape clears the current query
+                Key::Escape => {
+                    query.clear();
+                    matches = self.matches(&query);
+                    selected = 0;
+                }
+                Key::Char(value) if !value.is_control() => {
+                    query.push(value);
+                    matches = self.matches(&query);
+                    selected = 0;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Line based version of the prompt, entering a number selects the
+    /// item with that number while any other text filters the items
+    fn interact_plain(&self) -> io::Result<usize> {
+        let term = Term::stderr();
+        let mut matches = self.matches("");
+
+        loop {
+            for (position, &index) in matches.iter().enumerate() {
+                term.write_line(&format!("{:>3}) {}", position + 1, self.item_text(index)))?;
+            }
+
+            let mut prompt = String::new();
+            self.theme
+                .format_input_prompt(&mut prompt, &self.prompt, None)
+                .map_err(io::Error::other)?;
+            term.write_str(&prompt)?;
+            term.flush()?;
+
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "no selection provided",
+                ));
+            }
+            let line = line.trim();
+
+            let selected = if line.is_empty() {
+                // Empty input selects the default item
+                if matches.contains(&self.default) {
+                    Some(self.default)
+                } else {
+                    matches.first().copied()
+                }
+            } else {
+                line.parse::<usize>()
+                    .ok()
+                    .and_then(|number| number.checked_sub(1))
+                    .and_then(|position| matches.get(position).copied())
+            };
+
+            if let Some(index) = selected {
+                self.report_selection(&term, index)?;
+                return Ok(index);
+            }
+
+            matches = self.matches(line);
+            if matches.is_empty() {
+                term.write_line(tr(Message::NoMatchingItems))?;
+                matches = self.matches("");
+            }
+        }
+    }
+
+    /// Writes the selected item to the `term` if reporting is enabled
+    fn report_selection(&self, term: &Term, index: usize) -> io::Result<()> {
+        if !self.report {
+            return Ok(());
+        }
+
+        let mut report = String::new();
+        self.theme
+            .format_input_prompt_selection(&mut report, &self.prompt, &self.items[index])
+            .map_err(io::Error::other)?;
+        term.write_line(&report)
+    }
+
+    /// Text shown for the item at `index` including its details
+    fn item_text(&self, index: usize) -> String {
+        match self.details.get(index) {
+            Some(details) => format!("{} — {details}", self.items[index]),
+            None => self.items[index].clone(),
+        }
+    }
+
+    /// Finds the indexes of the items matching the `query` ordered
+    /// from the best match to the worst
+    fn matches(&self, query: &str) -> Vec<usize> {
+        let mut matches: Vec<(usize, i64)> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| fuzzy_score(item, query).map(|score| (index, score)))
+            .collect();
+
+        // Stable sort keeps the original order for equal scores
+        matches.sort_by(|(_, a), (_, b)| b.cmp(a));
+        matches.into_iter().map(|(index, _)| index).collect()
+    }
+}
+
+/// Scores how well the `query` matches the `text`. All of the query
+/// characters must appear in order within the text, consecutive
+/// matches and matches at the start of words score higher while gaps
+/// between matches reduce the score. Matching is case insensitive
+fn fuzzy_score(text: &str, query: &str) -> Option<i64> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let query = query.to_lowercase();
+    let mut query = query.chars().peekable();
+
+    let mut score: i64 = 0;
+    let mut last_match: Option<usize> = None;
+
+    for (index, &value) in text.iter().enumerate() {
+        let Some(&expected) = query.peek() else {
+            break;
+        };
+
+        if value != expected {
+            continue;
+        }
+
+        query.next();
+        score += 1;
+
+        // Start of the text or start of a word
+        if index == 0 || !text[index - 1].is_alphanumeric() {
+            score += 3;
+        }
+
+        match last_match {
+            Some(last) if last + 1 == index => score += 5,
+            Some(last) => score -= (index - last - 1) as i64,
+            None => {}
+        }
+
+        last_match = Some(index);
+    }
+
+    // Not all of the query characters were found
+    if query.peek().is_some() {
+        return None;
+    }
+
+    Some(score)
+}
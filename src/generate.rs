@@ -0,0 +1,206 @@
+//! Generation of calibrated test signals written to WAV files, for
+//! playing on other devices or sharing with remote parties
+
+use crate::{
+    args::Args,
+    error::ChemicError,
+    log::info,
+    meter::from_db,
+    rt60::Noise,
+    wav::{BroadcastInfo, Finishing, WavWriter},
+};
+use std::{
+    f64::consts::TAU,
+    fmt, io,
+    path::Path,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+/// Default length of the generated signal
+const DEFAULT_DURATION: f64 = 10.0;
+/// Default sample rate of the generated file
+const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+/// Default level of the signal in dBFS, the peak of tones and sweeps and
+/// the RMS of noise
+const DEFAULT_LEVEL_DB: f32 = -20.0;
+/// Frequency of a tone when none is provided
+const DEFAULT_TONE_HZ: f64 = 1000.0;
+/// Frequencies the sweep starts and ends at, the end is kept below the
+/// Nyquist frequency of lower sample rates
+const SWEEP_START_HZ: f64 = 20.0;
+const SWEEP_END_HZ: f64 = 20_000.0;
+/// Time the signal fades in and out over so it starts and ends without
+/// a click
+const FADE: Duration = Duration::from_millis(10);
+
+/// Test signal written by `chemic generate`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TestSignal {
+    /// Logarithmic sine sweep from 20 Hz to 20 kHz
+    Sweep,
+    /// Sine tone at the frequency in Hz
+    Tone(f64),
+    /// White noise
+    Noise,
+}
+
+/// Parses "sweep", "noise", "tone" for a 1 kHz tone or "tone:<HZ>" for a
+/// tone at another frequency
+impl FromStr for TestSignal {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let lower = value.to_lowercase();
+        match lower.as_str() {
+            "sweep" => return Ok(TestSignal::Sweep),
+            "noise" => return Ok(TestSignal::Noise),
+            "tone" => return Ok(TestSignal::Tone(DEFAULT_TONE_HZ)),
+            _ => {}
+        }
+        if let Some(frequency) = lower.strip_prefix("tone:") {
+            return match frequency.trim().parse::<f64>() {
+                Ok(frequency) if frequency.is_finite() && frequency > 0.0 => {
+                    Ok(TestSignal::Tone(frequency))
+                }
+                _ => Err(format!("invalid tone frequency \"{frequency}\"")),
+            };
+        }
+        Err(format!(
+            "unknown test signal \"{value}\" (expected sweep, tone, tone:<HZ> or noise)"
+        ))
+    }
+}
+
+impl fmt::Display for TestSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TestSignal::Sweep => write!(f, "{SWEEP_START_HZ} Hz to {SWEEP_END_HZ} Hz sweep"),
+            TestSignal::Tone(frequency) => write!(f, "{frequency} Hz tone"),
+            TestSignal::Noise => f.write_str("white noise"),
+        }
+    }
+}
+
+/// Writes the `--signal` from the `args` to the `--out` path for the
+/// `--duration` at the `--level`, the `--sample-rate` and `--channels`
+/// default to 48 kHz mono
+pub fn run(args: &Args) -> Result<(), ChemicError> {
+    let missing = |flag: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("generate needs {flag}"),
+        )
+    };
+    let signal = args.signal.ok_or_else(|| missing("--signal"))?;
+    let path = args.out.as_deref().ok_or_else(|| missing("--out"))?;
+    let seconds = args.duration.unwrap_or(DEFAULT_DURATION);
+    if !seconds.is_finite() || seconds <= 0.0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Duration must be a positive number of seconds",
+        )
+        .into());
+    }
+    let sample_rate = args.sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE);
+    let channels = args.channels.unwrap_or(1);
+    let level_db = args.level.unwrap_or(DEFAULT_LEVEL_DB);
+
+    let frames = (seconds * sample_rate as f64) as usize;
+    let mono = generate(signal, frames, sample_rate, from_db(level_db));
+    let samples: Vec<f32> = mono
+        .iter()
+        .flat_map(|&sample| std::iter::repeat_n(sample, channels as usize))
+        .collect();
+
+    let level = match signal {
+        TestSignal::Noise => "RMS",
+        _ => "peak",
+    };
+    write(
+        path,
+        &samples,
+        sample_rate,
+        channels,
+        format!("Test signal: {signal} at {level_db:.1} dBFS {level}"),
+        args,
+    )?;
+
+    info!(
+        "generated a {seconds}s {signal} at {level_db:.1} dBFS to {}",
+        path.display()
+    );
+    println!(
+        "Saved a {seconds}s {signal} at {level_db:.1} dBFS {level} ({sample_rate}Hz {channels}ch) to {}",
+        path.display()
+    );
+    Ok(())
+}
+
+/// Creates `frames` of the mono `signal` at the `sample_rate`, `level` is
+/// the linear peak of tones and sweeps and the RMS of noise
+fn generate(signal: TestSignal, frames: usize, sample_rate: u32, level: f32) -> Vec<f32> {
+    let rate = sample_rate as f64;
+    let mut samples: Vec<f32> = match signal {
+        TestSignal::Tone(frequency) => (0..frames)
+            .map(|frame| (TAU * frequency * frame as f64 / rate).sin() as f32 * level)
+            .collect(),
+        TestSignal::Sweep => {
+            // Exponential sweep, the frequency rises by the same ratio
+            // each second
+            let start = SWEEP_START_HZ;
+            let end = SWEEP_END_HZ.min(rate * 0.45);
+            let length = frames as f64 / rate;
+            let rate_of_rise = (end / start).ln() / length;
+            (0..frames)
+                .map(|frame| {
+                    let time = frame as f64 / rate;
+                    let phase = TAU * start * ((time * rate_of_rise).exp() - 1.0) / rate_of_rise;
+                    phase.sin() as f32 * level
+                })
+                .collect()
+        }
+        TestSignal::Noise => {
+            // Uniform noise has an RMS of 1 / sqrt(3), peaks that would
+            // clip are held at full scale
+            let mut noise = Noise::default();
+            let scale = level * 3.0f32.sqrt();
+            (0..frames)
+                .map(|_| (noise.next() * scale).clamp(-1.0, 1.0))
+                .collect()
+        }
+    };
+
+    let fade = ((FADE.as_secs_f64() * rate) as usize).min(frames / 2);
+    for index in 0..fade {
+        let gain = index as f32 / fade as f32;
+        samples[index] *= gain;
+        samples[frames - 1 - index] *= gain;
+    }
+    samples
+}
+
+/// Writes the interleaved `samples` to the `path` with the bit depth and
+/// dither from the `args`
+fn write(
+    path: &Path,
+    samples: &[f32],
+    sample_rate: u32,
+    channels: u16,
+    description: String,
+    args: &Args,
+) -> io::Result<()> {
+    let info = BroadcastInfo {
+        description,
+        device: String::new(),
+        started: SystemTime::now(),
+    };
+    let finishing = Finishing {
+        format: args.bits.unwrap_or_default(),
+        dither: args.dither.unwrap_or_default(),
+        ..Finishing::default()
+    };
+    let mut writer = WavWriter::create(path, sample_rate, channels, info)?.finishing(finishing);
+    writer.write(samples)?;
+    writer.finish()
+}
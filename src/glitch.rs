@@ -0,0 +1,257 @@
+//! Detection of dropouts and glitches in the captured audio, short runs
+//! of zeros in the middle of a signal and sudden jumps between samples
+//! that the signal doesn't explain
+
+use crate::{
+    events::{self, Event},
+    log::info,
+};
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Shortest run of zeros counted as a dropout
+const MIN_DROPOUT: Duration = Duration::from_millis(2);
+/// Longest run of zeros counted as a dropout, longer runs are the input
+/// being muted or going silent
+const MAX_DROPOUT: Duration = Duration::from_millis(500);
+/// Level the signal must have before a run of zeros for the run to count
+/// as a dropout, so signals fading out to digital silence aren't
+const SIGNAL_LEVEL: f32 = 0.001;
+/// Smallest change between two samples counted as a jump
+const MIN_JUMP: f32 = 0.5;
+/// How many times larger than the typical change between samples a
+/// change has to be to count as a jump
+const JUMP_RATIO: f32 = 8.0;
+/// Smoothing applied per frame to the typical change and level
+const SMOOTHING: f32 = 0.005;
+/// Frames at the start before jumps are detected, while the typical
+/// change settles
+const SETTLE_FRAMES: u64 = (4.0 / SMOOTHING) as u64;
+/// Jumps closer together than this are counted as one
+const JUMP_HOLDOFF: Duration = Duration::from_millis(5);
+/// Number of detected glitches waiting to be logged before more are
+/// only counted
+const PENDING_GLITCHES: usize = 64;
+/// Time between logging the detected glitches
+const LOG_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Kind of a detected glitch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlitchKind {
+    /// Run of zeros in the middle of the signal
+    Dropout,
+    /// Sudden jump between two samples
+    Jump,
+}
+
+impl fmt::Display for GlitchKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GlitchKind::Dropout => "dropout",
+            GlitchKind::Jump => "jump",
+        })
+    }
+}
+
+/// Glitch found in the captured audio
+#[derive(Debug, Clone, Copy)]
+pub struct Glitch {
+    pub kind: GlitchKind,
+    /// Frame of the stream the glitch started at
+    pub frame: u64,
+    /// Number of frames the glitch lasted for, one for jumps
+    pub frames: u64,
+}
+
+/// Signal state tracked for each channel
+#[derive(Default, Clone, Copy)]
+struct ChannelState {
+    /// Previous sample
+    previous: f32,
+    /// Smoothed change between samples
+    change: f32,
+}
+
+/// Detects glitches in interleaved samples across the calls to
+/// [GlitchDetector::process]
+pub struct GlitchDetector {
+    channels: Vec<ChannelState>,
+    /// Index of the next frame
+    frame: u64,
+    /// Smoothed level of the signal
+    level: f32,
+    /// Length of the current run of zero frames
+    zeros: u64,
+    /// Whether there was signal before the current run of zeros
+    signal_before_zeros: bool,
+    /// Frame of the latest jump
+    last_jump: Option<u64>,
+    /// Range of run lengths in frames that count as a dropout
+    min_dropout: u64,
+    max_dropout: u64,
+    /// Frames after a jump before another is counted
+    jump_holdoff: u64,
+}
+
+impl GlitchDetector {
+    /// Creates a detector for audio at the `sample_rate` with the number
+    /// of interleaved `channels`
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let frames = |duration: Duration| (duration.as_secs_f64() * sample_rate as f64) as u64;
+        Self {
+            channels: vec![ChannelState::default(); channels.max(1) as usize],
+            frame: 0,
+            level: 0.0,
+            zeros: 0,
+            signal_before_zeros: false,
+            last_jump: None,
+            min_dropout: frames(MIN_DROPOUT).max(1),
+            max_dropout: frames(MAX_DROPOUT),
+            jump_holdoff: frames(JUMP_HOLDOFF),
+        }
+    }
+
+    /// Checks the interleaved `samples` calling `found` for each glitch
+    pub fn process(&mut self, samples: &[f32], mut found: impl FnMut(Glitch)) {
+        for frame in samples.chunks_exact(self.channels.len()) {
+            let zero = frame.iter().all(|&sample| sample == 0.0);
+            // The edges of a run of zeros are part of the dropout rather
+            // than jumps of their own
+            let after_zeros = self.zeros > 0;
+
+            if zero {
+                if self.zeros == 0 {
+                    self.signal_before_zeros = self.level >= SIGNAL_LEVEL;
+                }
+                self.zeros += 1;
+            } else {
+                if self.signal_before_zeros
+                    && (self.min_dropout..=self.max_dropout).contains(&self.zeros)
+                {
+                    found(Glitch {
+                        kind: GlitchKind::Dropout,
+                        frame: self.frame - self.zeros,
+                        frames: self.zeros,
+                    });
+                }
+                self.zeros = 0;
+            }
+
+            let mut jumped = false;
+            let mut peak = 0.0f32;
+            for (&sample, state) in frame.iter().zip(&mut self.channels) {
+                let change = (sample - state.previous).abs();
+                jumped |= change >= MIN_JUMP && change > state.change * JUMP_RATIO;
+                state.change += (change - state.change) * SMOOTHING;
+                state.previous = sample;
+                peak = peak.max(sample.abs());
+            }
+            self.level += (peak - self.level) * SMOOTHING;
+
+            if jumped && !zero && !after_zeros && self.frame >= SETTLE_FRAMES {
+                let held = self
+                    .last_jump
+                    .is_some_and(|last| self.frame - last <= self.jump_holdoff);
+                if !held {
+                    found(Glitch {
+                        kind: GlitchKind::Jump,
+                        frame: self.frame,
+                        frames: 1,
+                    });
+                }
+                self.last_jump = Some(self.frame);
+            }
+
+            self.frame += 1;
+        }
+    }
+}
+
+/// Creates the buffer the input callback passes the detected glitches
+/// through to the [GlitchLog]
+pub fn channel() -> (HeapProducer<Glitch>, HeapConsumer<Glitch>) {
+    HeapRb::new(PENDING_GLITCHES).split()
+}
+
+/// Background thread logging the glitches detected by the input callback
+/// with the time into the stream they happened at
+pub struct GlitchLog {
+    /// Whether the thread should stop
+    stop: Arc<AtomicBool>,
+    /// Thread logging the glitches
+    handle: Option<JoinHandle<()>>,
+}
+
+impl GlitchLog {
+    /// Starts logging the glitches from the `consumer` for a stream at the
+    /// `sample_rate`
+    pub fn start(mut consumer: HeapConsumer<Glitch>, sample_rate: u32) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                let seconds = |frames: u64| frames as f64 / sample_rate.max(1) as f64;
+                while !stop.load(Ordering::Relaxed) {
+                    thread::sleep(LOG_INTERVAL);
+
+                    for glitch in consumer.pop_iter() {
+                        let at = seconds(glitch.frame);
+                        match glitch.kind {
+                            GlitchKind::Dropout => info!(
+                                "dropout of {:.1} ms at {}",
+                                seconds(glitch.frames) * 1000.0,
+                                StreamTime(at)
+                            ),
+                            GlitchKind::Jump => info!("sample jump at {}", StreamTime(at)),
+                        }
+                        events::emit(Event::Glitch {
+                            kind: glitch.kind,
+                            at_seconds: at,
+                            length_ms: seconds(glitch.frames) * 1000.0,
+                        });
+                    }
+                }
+            }
+        });
+
+        Self {
+            stop,
+            handle: Some(handle),
+        }
+    }
+}
+
+/// Logging stops when the log is dropped along with the streams
+impl Drop for GlitchLog {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            _ = handle.join();
+        }
+    }
+}
+
+/// Formats seconds into the stream as minutes, seconds and milliseconds
+/// (e.g. "12:03.512")
+struct StreamTime(f64);
+
+impl fmt::Display for StreamTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = (self.0 * 1000.0) as u64;
+        write!(
+            f,
+            "{}:{:02}.{:03}",
+            millis / 60_000,
+            millis / 1000 % 60,
+            millis % 1000
+        )
+    }
+}
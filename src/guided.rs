@@ -0,0 +1,101 @@
+//! Guided test spoken through the output device, identifying the left
+//! and right channels and then recording the microphone and playing it
+//! back, so it can be followed without reading the terminal
+
+use crate::{
+    args::Args,
+    config::Config,
+    error::ChemicError,
+    log::info,
+    measure::play_and_record,
+    negotiate::negotiate,
+    prompt_device,
+    prompts::{self, Prompt},
+    requested_device, use_plain_output, DeviceType,
+};
+use cpal::StreamConfig;
+use std::time::Duration;
+
+/// Length of the recording made after the speak now prompt
+const RECORD_LENGTH: Duration = Duration::from_secs(5);
+
+/// Plays the channel prompts, records the input after asking the user
+/// to speak and plays the recording back
+pub fn run(mut args: Args) -> Result<(), ChemicError> {
+    let plain = use_plain_output(args.plain);
+    let config = Config::load(args.config.as_deref())?;
+    args.apply_config(&config)?;
+
+    let host = &cpal::default_host();
+    let input = match requested_device(host, &args, DeviceType::Input, true)? {
+        Some(device) => device,
+        None => prompt_device(
+            host,
+            "Select the microphone",
+            DeviceType::Input,
+            &config.theme,
+            plain,
+        )?,
+    };
+    let output = match requested_device(host, &args, DeviceType::Output, true)? {
+        Some(device) => device,
+        None => prompt_device(
+            host,
+            "Select the speakers or headphones",
+            DeviceType::Output,
+            &config.theme,
+            plain,
+        )?,
+    };
+
+    let (input_config, output_config) = negotiate(&input, &output, args.sample_rate)?;
+    let input_config: StreamConfig = input_config.into();
+    let output_config: StreamConfig = output_config.into();
+    let output_rate = output_config.sample_rate.0;
+
+    let say = |prompt: Prompt, channel: Option<usize>| {
+        println!("{}", prompt.text());
+        prompts::play(
+            &output,
+            &output_config,
+            prompt.samples(output_rate),
+            channel,
+        )
+    };
+
+    // Channels past the first two are only told apart on the terminal
+    if output_config.channels >= 2 {
+        say(Prompt::LeftChannel, Some(0))?;
+        say(Prompt::RightChannel, Some(1))?;
+    } else {
+        println!("\"{}\" has a single channel", output.name);
+    }
+
+    // The prompt is played as the recording starts and cut off after
+    let speak_now = Prompt::SpeakNow.samples(output_rate);
+    let prompt_length = Duration::from_secs_f64(speak_now.len() as f64 / output_rate as f64);
+    println!("{}", Prompt::SpeakNow.text());
+    let mut speak_now = speak_now.into_iter();
+    let recording = play_and_record(
+        &input,
+        &input_config,
+        &output,
+        &output_config,
+        prompt_length + RECORD_LENGTH,
+        move || speak_now.next().unwrap_or_default(),
+    )?;
+    let skipped = (prompt_length.as_secs_f64() * input_config.sample_rate.0 as f64) as usize;
+    let recording = recording.get(skipped..).unwrap_or_default().to_vec();
+    info!(
+        "guided test recorded {:.1} s",
+        recording.len() as f64 / input_config.sample_rate.0 as f64
+    );
+
+    say(Prompt::RecordingFinished, None)?;
+
+    println!("Playing back the recording");
+    let playback = prompts::convert(recording, input_config.sample_rate.0, &output_config);
+    prompts::play(&output, &output_config, playback, None)?;
+
+    Ok(())
+}
@@ -0,0 +1,188 @@
+//! Harmonic analysis that plays a test tone through the output device and
+//! reports the levels of the harmonics in the audio captured by the input
+//! device, a quick distortion fingerprint of the signal chain
+
+use crate::{
+    args::Args,
+    config::Config,
+    error::ChemicError,
+    log::{debug, info, span},
+    measure::{play_and_record, select_device},
+    meter::to_db,
+    negotiate::negotiate,
+    DeviceType, NamedDevice,
+};
+use cpal::StreamConfig;
+use std::{
+    f64::consts::{PI, TAU},
+    time::Duration,
+};
+
+/// Frequency of the test tone when none is provided
+pub const DEFAULT_FREQUENCY: f32 = 1000.0;
+/// Peak level of the test tone, low enough to not clip the input when
+/// the output is looped back
+const TONE_LEVEL: f32 = 0.5;
+/// Length of the test tone
+const TONE_LENGTH: Duration = Duration::from_secs(2);
+/// Audio skipped at the start of the recording while the streams start
+/// and the tone reaches the input
+const SETTLE: Duration = Duration::from_millis(500);
+/// Length of the recording that is analysed
+const ANALYSIS_LENGTH: Duration = Duration::from_secs(1);
+/// Highest harmonic reported
+const HIGHEST_HARMONIC: usize = 5;
+
+/// Plays the test tone at `frequency` through the output device from the
+/// `args` while recording the input device, printing the harmonic levels
+pub fn run(mut args: Args, frequency: Option<f32>) -> Result<(), ChemicError> {
+    let config = Config::load(args.config.as_deref())?;
+    args.apply_config(&config)?;
+
+    let host = &cpal::default_host();
+    let input = select_device(host, &args, DeviceType::Input)?;
+    let output = select_device(host, &args, DeviceType::Output)?;
+
+    let (input_config, output_config) = negotiate(&input, &output, args.sample_rate)?;
+    let input_config: StreamConfig = input_config.into();
+    let output_config: StreamConfig = output_config.into();
+
+    analyse(
+        &input,
+        &input_config,
+        &output,
+        &output_config,
+        frequency.unwrap_or(DEFAULT_FREQUENCY) as f64,
+    )
+}
+
+/// Plays the test tone at `frequency` through the `output` while
+/// recording the `input`, printing the harmonic levels
+pub fn analyse(
+    input: &NamedDevice,
+    input_config: &StreamConfig,
+    output: &NamedDevice,
+    output_config: &StreamConfig,
+    frequency: f64,
+) -> Result<(), ChemicError> {
+    let _span = span!("harmonics");
+
+    let nyquist = input_config.sample_rate.0.min(output_config.sample_rate.0) as f64 / 2.0;
+    if frequency <= 0.0 || frequency * 2.0 >= nyquist {
+        println!(
+            "The tone must be below {:.0} Hz for the second harmonic to be captured",
+            nyquist / 2.0
+        );
+        return Ok(());
+    }
+
+    println!(
+        "Playing a {frequency:.0} Hz tone through \"{}\" and recording \"{}\"\n",
+        output.name, input.name
+    );
+
+    let step = TAU * frequency / output_config.sample_rate.0 as f64;
+    let mut phase = 0.0f64;
+    let signal = move || {
+        let value = phase.sin() as f32 * TONE_LEVEL;
+        phase = (phase + step) % TAU;
+        value
+    };
+
+    let recording = play_and_record(
+        input,
+        input_config,
+        output,
+        output_config,
+        TONE_LENGTH,
+        signal,
+    )?;
+
+    let sample_rate = input_config.sample_rate.0 as f64;
+    let start = (SETTLE.as_secs_f64() * sample_rate) as usize;
+    let length = (ANALYSIS_LENGTH.as_secs_f64() * sample_rate) as usize;
+    let Some(segment) = recording.get(start..start + length) else {
+        println!("Not enough audio was recorded for the analysis");
+        return Ok(());
+    };
+
+    debug!(
+        "analysing {} of {} samples at {}Hz for harmonics of {frequency}Hz",
+        segment.len(),
+        recording.len(),
+        input_config.sample_rate.0
+    );
+
+    let window = hann(segment);
+    let Some(fundamental) = to_db(amplitude(&window, frequency, sample_rate) as f32) else {
+        info!("harmonic analysis: tone not detected");
+        println!("The test tone was not detected, connect the output to the input or move the microphone closer to the speaker");
+        return Ok(());
+    };
+
+    info!("harmonic analysis: fundamental {frequency}Hz at {fundamental:.1} dBFS");
+    println!("Fundamental   {frequency:>7.0} Hz  {fundamental:>7.1} dBFS");
+
+    for harmonic in 2..=HIGHEST_HARMONIC {
+        let harmonic_frequency = frequency * harmonic as f64;
+        if harmonic_frequency >= nyquist {
+            break;
+        }
+
+        let level = amplitude(&window, harmonic_frequency, sample_rate) as f32;
+        let relative = match to_db(level) {
+            Some(db) => format!("{:>7.1} dB", db - fundamental),
+            None => "below the floor".to_string(),
+        };
+        info!("harmonic analysis: harmonic {harmonic} {relative}");
+        println!(
+            "{:<13} {harmonic_frequency:>7.0} Hz  {relative}",
+            ordinal(harmonic)
+        );
+    }
+
+    println!("\nHarmonics are relative to the fundamental, lower is cleaner");
+
+    Ok(())
+}
+
+/// Name of the `harmonic` (e.g. "2nd harmonic")
+fn ordinal(harmonic: usize) -> String {
+    let suffix = match harmonic {
+        2 => "nd",
+        3 => "rd",
+        _ => "th",
+    };
+    format!("{harmonic}{suffix} harmonic")
+}
+
+/// Applies a Hann window to the `samples` so the energy of the tone
+/// doesn't leak into the harmonics
+pub fn hann(samples: &[f32]) -> Vec<f64> {
+    let length = samples.len().max(2) as f64;
+    samples
+        .iter()
+        .enumerate()
+        .map(|(index, &sample)| {
+            let weight = 0.5 - 0.5 * (TAU * index as f64 / (length - 1.0)).cos();
+            sample as f64 * weight
+        })
+        .collect()
+}
+
+/// Peak amplitude of the `frequency` within the windowed `samples` using
+/// the Goertzel algorithm
+pub fn amplitude(samples: &[f64], frequency: f64, sample_rate: f64) -> f64 {
+    let omega = 2.0 * PI * frequency / sample_rate;
+    let coefficient = 2.0 * omega.cos();
+    let (mut previous, mut before) = (0.0, 0.0);
+    for &sample in samples {
+        let current = sample + coefficient * previous - before;
+        before = previous;
+        previous = current;
+    }
+
+    let power = previous * previous + before * before - coefficient * previous * before;
+    // The Hann window halves the amplitude of a tone
+    power.max(0.0).sqrt() * 2.0 / (samples.len().max(1) as f64 * 0.5)
+}
@@ -0,0 +1,172 @@
+//! Speech intelligibility check that records the user reading a test
+//! sentence and runs it through a whisper.cpp speech to text model,
+//! reporting how much of the sentence was recognized as an objective
+//! check of the microphone chain
+
+use crate::{
+    args::Args,
+    bed,
+    config::Config,
+    error::ChemicError,
+    log::{debug, info},
+    measure::{record, select_device},
+    negotiate::negotiate_input,
+    positive_duration,
+    wav::{BroadcastInfo, WavAudio, WavWriter},
+    DeviceType,
+};
+use cpal::StreamConfig;
+use std::{env, io, path::Path, process, time::SystemTime};
+
+/// Sentence the user is asked to read, one of the Harvard sentences
+/// used for speech intelligibility testing
+const TEST_SENTENCE: &str = "The birch canoe slid on the smooth planks";
+/// Default number of seconds the sentence is recorded for
+const DEFAULT_DURATION: f64 = 5.0;
+/// Sample rate whisper.cpp models take their audio at
+const MODEL_SAMPLE_RATE: u32 = 16_000;
+/// Environment variable overriding the whisper.cpp program that is run
+const WHISPER_ENV: &str = "CHEMIC_WHISPER";
+/// Name of the whisper.cpp program looked up on the path
+const WHISPER_PROGRAM: &str = "whisper-cli";
+/// Share of the words that must be recognized for the check to pass
+const PASS_RATIO: f64 = 0.8;
+
+/// Records the input device from the `args` while the user reads the
+/// test sentence and transcribes it with the whisper.cpp `model`
+pub fn run(mut args: Args, model: &Path) -> Result<(), ChemicError> {
+    let config = Config::load(args.config.as_deref())?;
+    args.apply_config(&config)?;
+
+    if !model.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No speech to text model at {}", model.display()),
+        )
+        .into());
+    }
+
+    let duration = positive_duration(args.duration.unwrap_or(DEFAULT_DURATION), "Duration")?;
+    let host = &cpal::default_host();
+    let input = select_device(host, &args, DeviceType::Input)?;
+    let input_config: StreamConfig = negotiate_input(&input, args.sample_rate)?.into();
+
+    println!(
+        "Press Enter and read the following sentence into \"{}\":\n",
+        input.name
+    );
+    println!("    {TEST_SENTENCE}\n");
+    io::stdin().read_line(&mut String::new())?;
+    println!("Recording for {:.1}s...", duration.as_secs_f64());
+
+    let recording = record(&[(&input, &input_config)], duration)?.remove(0);
+    let audio = WavAudio {
+        sample_rate: input_config.sample_rate.0,
+        channels: 1,
+        samples: recording,
+    };
+    let speech = bed::resample(&audio, MODEL_SAMPLE_RATE as f64, 1);
+
+    let path = env::temp_dir().join(format!("chemic-intelligibility-{}.wav", process::id()));
+    let info = BroadcastInfo {
+        description: TEST_SENTENCE.to_string(),
+        device: input.name.clone(),
+        started: SystemTime::now(),
+    };
+    let mut writer = WavWriter::create(&path, MODEL_SAMPLE_RATE, 1, info)?;
+    writer.write(&speech)?;
+    writer.finish()?;
+
+    println!("Transcribing...");
+    let transcript = transcribe(model, &path);
+    _ = std::fs::remove_file(&path);
+    let transcript = transcript?;
+    debug!("transcript: {transcript:?}");
+
+    let expected = words(TEST_SENTENCE);
+    let recognized = matching_words(&expected, &words(&transcript));
+    let ratio = recognized as f64 / expected.len() as f64;
+    info!(
+        "intelligibility of \"{}\": {recognized} of {} words",
+        input.name,
+        expected.len()
+    );
+
+    println!("\nHeard: {}", transcript.trim());
+    println!(
+        "Recognized {recognized} of {} words ({:.0}%)",
+        expected.len(),
+        ratio * 100.0
+    );
+    if ratio < PASS_RATIO {
+        return Err(ChemicError::CheckFailed(
+            "The sentence was not recognized, check the microphone level, distance and background noise"
+                .to_string(),
+        ));
+    }
+    println!("The sentence was recognized, speech is coming through clearly");
+
+    Ok(())
+}
+
+/// Runs the whisper.cpp program over the WAV file at the `path`,
+/// returning the transcript it prints
+fn transcribe(model: &Path, path: &Path) -> io::Result<String> {
+    let program = env::var_os(WHISPER_ENV).unwrap_or_else(|| WHISPER_PROGRAM.into());
+    let output = process::Command::new(&program)
+        .arg("--model")
+        .arg(model)
+        .arg("--file")
+        .arg(path)
+        .args(["--language", "en", "--no-timestamps", "--no-prints"])
+        .output()
+        .map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!(
+                    "Failed to run {}, install whisper.cpp or set {WHISPER_ENV} to its path: {err}",
+                    program.to_string_lossy()
+                ),
+            )
+        })?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "Speech to text failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Lowercase words of the `text` without punctuation
+fn words(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|char| char.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Number of the `expected` words heard in order, the longest common
+/// subsequence so extra or missed words only count once
+fn matching_words(expected: &[String], heard: &[String]) -> usize {
+    let mut lengths = vec![0usize; heard.len() + 1];
+    for word in expected {
+        let mut diagonal = 0;
+        for (index, heard_word) in heard.iter().enumerate() {
+            let above = lengths[index + 1];
+            lengths[index + 1] = if word == heard_word {
+                diagonal + 1
+            } else {
+                above.max(lengths[index])
+            };
+            diagonal = above;
+        }
+    }
+    lengths[heard.len()]
+}
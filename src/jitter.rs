@@ -0,0 +1,121 @@
+//! Jitter buffer between the worker and the output callback. The worker
+//! keeps the output buffer filled to a target occupancy that the output
+//! callback raises when the audio arrives unevenly and lowers again once
+//! it arrives evenly, so devices delivering audio in bursts such as
+//! Bluetooth and USB headsets play smoothly without holding more audio
+//! than they need
+
+use cpal::StreamConfig;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Default occupancy the target starts from and never falls below
+pub const DEFAULT_TARGET: Duration = Duration::from_millis(20);
+/// Largest occupancy the target grows to
+pub const MAX_TARGET: Duration = Duration::from_millis(500);
+/// Audio played between the adjustments of the target
+const WINDOW: Duration = Duration::from_secs(1);
+/// Target as a multiple of the furthest the occupancy fell during a
+/// window, the headroom kept above the observed jitter
+const HEADROOM: f64 = 1.5;
+/// Fraction of the difference the target falls by each window when less
+/// is needed, the target rises straight away
+const SHRINK: usize = 4;
+
+/// Number of interleaved samples the worker fills the output buffer up
+/// to, shared between the worker and the output callback
+pub struct JitterTarget(AtomicUsize);
+
+impl JitterTarget {
+    pub fn samples(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Adapts the [JitterTarget] to the occupancy of the output buffer seen
+/// by the output callback
+pub struct JitterBuffer {
+    target: Arc<JitterTarget>,
+    /// Smallest and largest target in interleaved samples
+    min: usize,
+    max: usize,
+    /// Samples played between the adjustments
+    window: usize,
+    /// Samples played during the current window
+    played: usize,
+    /// Fewest samples left in the buffer after a callback during the
+    /// window
+    lowest: usize,
+    /// Most samples played by a callback during the window
+    longest: usize,
+    /// Whether the buffer ran out during the window
+    ran_out: bool,
+}
+
+impl JitterBuffer {
+    /// Creates a jitter buffer for the output `config` that targets the
+    /// `target` occupancy until more is needed
+    pub fn new(target: Duration, config: &StreamConfig) -> Self {
+        let samples = |duration: Duration| {
+            (duration.as_secs_f64() * config.sample_rate.0 as f64) as usize
+                * config.channels.max(1) as usize
+        };
+        let max = samples(MAX_TARGET);
+        let min = samples(target).clamp(1, max);
+
+        Self {
+            target: Arc::new(JitterTarget(AtomicUsize::new(min))),
+            min,
+            max,
+            window: samples(WINDOW),
+            played: 0,
+            lowest: usize::MAX,
+            longest: 0,
+            ran_out: false,
+        }
+    }
+
+    /// Target shared with the worker
+    pub fn target(&self) -> Arc<JitterTarget> {
+        self.target.clone()
+    }
+
+    /// Records a callback that `played` samples and left `remaining`
+    /// samples in the buffer, having `ran_out` when it played silence.
+    /// The target is adjusted at the end of each window
+    pub fn observe(&mut self, played: usize, remaining: usize, ran_out: bool) {
+        self.played += played;
+        self.lowest = self.lowest.min(remaining);
+        self.longest = self.longest.max(played);
+        self.ran_out |= ran_out;
+        if self.played < self.window {
+            return;
+        }
+
+        // The occupancy falls as the callbacks take the audio and while
+        // none arrives, running out means the target was too small
+        let target = self.target.samples();
+        let fall = target.saturating_sub(self.lowest);
+        let mut wanted = (fall as f64 * HEADROOM) as usize;
+        if self.ran_out {
+            wanted = wanted.max(target + self.longest);
+        }
+        let next = match wanted > target {
+            true => wanted,
+            false => target - (target - wanted) / SHRINK,
+        };
+        self.target
+            .0
+            .store(next.clamp(self.min, self.max), Ordering::Relaxed);
+
+        self.played = 0;
+        self.lowest = usize::MAX;
+        self.longest = 0;
+        self.ran_out = false;
+    }
+}
@@ -0,0 +1,522 @@
+//! Catalog of the user facing messages in each supported language
+
+use std::{
+    env, io,
+    str::FromStr,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// Language used for the messages
+static LANGUAGE: AtomicU8 = AtomicU8::new(Language::English as u8);
+
+/// Supported languages
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    English = 0,
+    Spanish = 1,
+    German = 2,
+}
+
+impl Language {
+    /// Detects the language from the locale environment variables,
+    /// defaulting to English for unsupported locales
+    pub fn detect() -> Self {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(Language::English)
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Language::Spanish,
+            2 => Language::German,
+            _ => Language::English,
+        }
+    }
+}
+
+impl FromStr for Language {
+    type Err = io::Error;
+
+    /// Parses a language code or locale (e.g. "es" or "de_DE.UTF-8")
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let code = value
+            .split(['_', '-', '.'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+
+        match code.as_str() {
+            "en" | "c" | "posix" => Ok(Language::English),
+            "es" => Ok(Language::Spanish),
+            "de" => Ok(Language::German),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unsupported language \"{value}\" (supported: en, es, de)"),
+            )),
+        }
+    }
+}
+
+/// Sets the language used for messages
+pub fn set_language(language: Language) {
+    LANGUAGE.store(language as u8, Ordering::Relaxed);
+}
+
+/// User facing messages
+#[derive(Clone, Copy)]
+pub enum Message {
+    Tagline,
+    UsingProfile,
+    SelectInputDevice,
+    SelectOutputDevice,
+    InputDevice,
+    OutputDevice,
+    Name,
+    Channels,
+    SampleRate,
+    Resampling,
+    On,
+    Off,
+    Warning,
+    Input,
+    Output,
+    LinearResampler,
+    MonoToStereo,
+    StereoToMono,
+    LeftToMono,
+    RightToMono,
+    PowerSumToMono,
+    ChannelsMixed,
+    QualityMayDiffer,
+    Playing,
+    Controls,
+    Stop,
+    Mute,
+    Pause,
+    VolumeUp,
+    VolumeDown,
+    OsGainUp,
+    OsGainDown,
+    Muted,
+    Unmuted,
+    Paused,
+    Resumed,
+    Volume,
+    NoMatchingItems,
+    Level,
+    Minus,
+    Silent,
+    Clipping,
+    NoClipping,
+    Error,
+    SelectAnotherDevice,
+    Correlation,
+    Plus,
+    Latency,
+    Calibration,
+    NoiseFloor,
+    RecommendedGain,
+    SensitivityOffset,
+    OsGain,
+    OsGainLow,
+    OsGainBoosted,
+    BluetoothHeadset,
+    SidetoneConverted,
+    Glitches,
+    Dropouts,
+    SampleJumps,
+    Recording,
+    AddMarker,
+    Marker,
+    NotRecording,
+    ToneBurst,
+    TonePlayed,
+    BufferGrown,
+    RecordingOverflow,
+    SetupWelcome,
+    SelectMicrophone,
+    SelectHeadphones,
+    SettingGain,
+    SpeakNormally,
+    SpeakLouder,
+    NothingHeard,
+    Speech,
+    Peak,
+    SpeechClipped,
+    HighGain,
+    RetryGain,
+    Gain,
+    SaveProfile,
+    YesNo,
+    NothingSaved,
+    ProfileSaved,
+    StartMonitoring,
+    ChangeOsGain,
+    RaiseInputVolume,
+    LowerBoost,
+}
+
+/// Translates the `message` into the current language
+pub fn tr(message: Message) -> &'static str {
+    let language = Language::from_u8(LANGUAGE.load(Ordering::Relaxed));
+
+    match language {
+        Language::English => english(message),
+        Language::Spanish => spanish(message),
+        Language::German => german(message),
+    }
+}
+
+fn english(message: Message) -> &'static str {
+    match message {
+        Message::Tagline => "Microphone testing tool",
+        Message::UsingProfile => "Using profile",
+        Message::SelectInputDevice => "Select input device to test",
+        Message::SelectOutputDevice => "Select output device to play to",
+        Message::InputDevice => "Input Device",
+        Message::OutputDevice => "Output Device",
+        Message::Name => "Name",
+        Message::Channels => "Channels",
+        Message::SampleRate => "Sample Rate",
+        Message::Resampling => "Resampling",
+        Message::On => "On",
+        Message::Off => "Off",
+        Message::Warning => "Warning",
+        Message::Input => "Input",
+        Message::Output => "Output",
+        Message::LinearResampler => "linear resampler active",
+        Message::MonoToStereo => "mono duplicated to both channels",
+        Message::StereoToMono => "stereo averaged to mono",
+        Message::LeftToMono => "left channel only as mono",
+        Message::RightToMono => "right channel only as mono",
+        Message::PowerSumToMono => "channels power summed to mono",
+        Message::ChannelsMixed => "channels mixed with the default layout",
+        Message::QualityMayDiffer => "Quality and latency may differ from the device itself",
+        Message::Playing => "Playing microphone through output device...",
+        Message::Controls => "Controls",
+        Message::Stop => "stop",
+        Message::Mute => "mute",
+        Message::Pause => "pause",
+        Message::VolumeUp => "volume up",
+        Message::VolumeDown => "volume down",
+        Message::OsGainUp => "OS gain up",
+        Message::OsGainDown => "OS gain down",
+        Message::Muted => "Output muted",
+        Message::Unmuted => "Output unmuted",
+        Message::Paused => "Paused",
+        Message::Resumed => "Resumed",
+        Message::Volume => "Volume",
+        Message::NoMatchingItems => "No matching items",
+        Message::Level => "Level",
+        Message::Minus => "minus",
+        Message::Silent => "silent",
+        Message::Clipping => "clipping",
+        Message::NoClipping => "no clipping",
+        Message::Error => "Error",
+        Message::SelectAnotherDevice => "Select another device to try again",
+        Message::Correlation => "correlation",
+        Message::Plus => "plus",
+        Message::Latency => "Latency",
+        Message::BufferGrown => {
+            "The output kept running out of audio, the streams were restarted with a larger buffer"
+        }
+        Message::RecordingOverflow => "The recording could not be written fast enough, samples are missing from the recordings",
+        Message::Calibration => "Calibration",
+        Message::NoiseFloor => "Noise floor",
+        Message::RecommendedGain => "Recommended gain",
+        Message::SensitivityOffset => "Sensitivity offset",
+        Message::OsGain => "OS input gain",
+        Message::OsGainLow => "The input volume in the system sound settings is critically low or muted, a quiet microphone may only need it raised",
+        Message::OsGainBoosted => "The microphone boost in the system mixer is above +20 dB, which amplifies the hiss of the input along with the voice. Lower the boost and raise the input volume instead",
+        Message::Glitches => "Glitches",
+        Message::Dropouts => "dropouts",
+        Message::SampleJumps => "sample jumps",
+        Message::Recording => "Recording",
+        Message::AddMarker => "add marker",
+        Message::Marker => "Marker",
+        Message::NotRecording => "Not recording, markers need --record",
+        Message::ToneBurst => "tone burst",
+        Message::TonePlayed => "Tone burst played, it should return through the microphone",
+        Message::SidetoneConverted => "Sidetone needs both devices to use the same sample rate and channels, the audio is converted which adds latency",
+        Message::BluetoothHeadset => {
+            "is a Bluetooth headset in the hands-free profile (HFP/HSP), audio is limited to narrowband mono by the Bluetooth codec rather than the microphone itself. Switch the headset to A2DP or use a wired microphone to hear it at full quality"
+        }
+        Message::SetupWelcome => "Welcome to chemic, this sets up the devices to monitor with and the input gain and saves them to a profile in the config file",
+        Message::SelectMicrophone => "Select your microphone",
+        Message::SelectHeadphones => "Select your headphones or speakers",
+        Message::SettingGain => "Setting the input gain of",
+        Message::SpeakNormally => "Speak normally, as you would while monitoring",
+        Message::SpeakLouder => "Now a bit louder, as loud as you expect to get",
+        Message::NothingHeard => "Nothing was heard from the microphone, check that it is connected and unmuted",
+        Message::Speech => "Speech",
+        Message::Peak => "peak",
+        Message::SpeechClipped => "The loud speech clipped, lower the gain of the interface or microphone",
+        Message::HighGain => "A gain this high also boosts the noise, raise the gain of the interface or microphone and try again if you can",
+        Message::RetryGain => "Press Enter to try again or type \"s\" to skip setting the gain",
+        Message::Gain => "Gain",
+        Message::SaveProfile => "Save these settings as the profile",
+        Message::YesNo => "[Y/n]",
+        Message::NothingSaved => "Nothing was saved",
+        Message::ProfileSaved => "Saved the profile",
+        Message::StartMonitoring => "Start monitoring with it by running",
+        Message::ChangeOsGain => "Change the input volume in the OS mixer instead of applying the gain digitally",
+        Message::RaiseInputVolume => {
+            if cfg!(target_os = "linux") {
+                "Raise the input volume of the microphone in the sound settings, pavucontrol or alsamixer (F4 shows the capture controls) and check the capture switch isn't off"
+            } else if cfg!(target_os = "windows") {
+                "Raise the input volume in Settings > System > Sound > Input, or in the Levels tab of the microphone properties in the Sound control panel"
+            } else if cfg!(target_os = "macos") {
+                "Raise the input volume in System Settings > Sound > Input, or in the Audio MIDI Setup app for interfaces"
+            } else {
+                "Raise the input volume of the microphone in the system sound settings"
+            }
+        }
+        Message::LowerBoost => {
+            if cfg!(target_os = "linux") {
+                "Lower the Mic Boost control in alsamixer (F4 shows the capture controls) and raise the capture volume instead, the boost amplifies the noise of the input along with the voice"
+            } else if cfg!(target_os = "windows") {
+                "Lower the Microphone Boost in the Levels tab of the microphone properties in the Sound control panel and raise the volume instead, the boost amplifies the noise of the input along with the voice"
+            } else {
+                "Lower the microphone boost of the device and raise the input volume instead, the boost amplifies the noise of the input along with the voice"
+            }
+        }
+    }
+}
+
+fn spanish(message: Message) -> &'static str {
+    match message {
+        Message::Tagline => "Herramienta para probar micrófonos",
+        Message::UsingProfile => "Usando el perfil",
+        Message::SelectInputDevice => "Selecciona el dispositivo de entrada a probar",
+        Message::SelectOutputDevice => "Selecciona el dispositivo de salida",
+        Message::InputDevice => "Dispositivo de entrada",
+        Message::OutputDevice => "Dispositivo de salida",
+        Message::Name => "Nombre",
+        Message::Channels => "Canales",
+        Message::SampleRate => "Frecuencia de muestreo",
+        Message::Resampling => "Remuestreo",
+        Message::On => "Activado",
+        Message::Off => "Desactivado",
+        Message::Warning => "Aviso",
+        Message::Input => "Entrada",
+        Message::Output => "Salida",
+        Message::LinearResampler => "remuestreo lineal activo",
+        Message::MonoToStereo => "mono duplicado en ambos canales",
+        Message::StereoToMono => "estéreo promediado a mono",
+        Message::LeftToMono => "solo el canal izquierdo como mono",
+        Message::RightToMono => "solo el canal derecho como mono",
+        Message::PowerSumToMono => "canales sumados por potencia a mono",
+        Message::ChannelsMixed => "canales mezclados con la disposición predeterminada",
+        Message::QualityMayDiffer => {
+            "La calidad y la latencia pueden diferir de las del dispositivo"
+        }
+        Message::Playing => "Reproduciendo el micrófono en el dispositivo de salida...",
+        Message::Controls => "Controles",
+        Message::Stop => "detener",
+        Message::Mute => "silenciar",
+        Message::Pause => "pausar",
+        Message::VolumeUp => "subir volumen",
+        Message::VolumeDown => "bajar volumen",
+        Message::OsGainUp => "subir ganancia del sistema",
+        Message::OsGainDown => "bajar ganancia del sistema",
+        Message::Muted => "Salida silenciada",
+        Message::Unmuted => "Salida activada",
+        Message::Paused => "En pausa",
+        Message::Resumed => "Reanudado",
+        Message::Volume => "Volumen",
+        Message::NoMatchingItems => "No hay coincidencias",
+        Message::Level => "Nivel",
+        Message::Minus => "menos",
+        Message::Silent => "silencio",
+        Message::Clipping => "saturación",
+        Message::NoClipping => "sin saturación",
+        Message::Error => "Error",
+        Message::SelectAnotherDevice => "Selecciona otro dispositivo para intentarlo de nuevo",
+        Message::Correlation => "correlación",
+        Message::Plus => "más",
+        Message::Latency => "Latencia",
+        Message::BufferGrown => {
+            "La salida se quedaba sin audio, los flujos se reiniciaron con un búfer más grande"
+        }
+        Message::RecordingOverflow => "La grabación no se pudo escribir lo bastante rápido, faltan muestras en las grabaciones",
+        Message::Calibration => "Calibración",
+        Message::NoiseFloor => "Ruido de fondo",
+        Message::RecommendedGain => "Ganancia recomendada",
+        Message::SensitivityOffset => "Ajuste de sensibilidad",
+        Message::OsGain => "Ganancia de entrada del sistema",
+        Message::OsGainLow => "El volumen de entrada en la configuración de sonido del sistema es muy bajo o está silenciado, puede que un micrófono flojo solo necesite subirlo",
+        Message::OsGainBoosted => "El realce del micrófono en el mezclador del sistema supera los +20 dB, lo que amplifica el siseo de la entrada junto con la voz. Baja el realce y sube el volumen de entrada en su lugar",
+        Message::Glitches => "Fallos",
+        Message::Dropouts => "cortes",
+        Message::SampleJumps => "saltos de muestra",
+        Message::Recording => "Grabando",
+        Message::AddMarker => "añadir marcador",
+        Message::Marker => "Marcador",
+        Message::NotRecording => "No se está grabando, los marcadores necesitan --record",
+        Message::ToneBurst => "ráfaga de tono",
+        Message::TonePlayed => "Ráfaga de tono reproducida, debería volver por el micrófono",
+        Message::SidetoneConverted => "El sidetone necesita que ambos dispositivos usen la misma frecuencia de muestreo y canales, el audio se convierte y añade latencia",
+        Message::BluetoothHeadset => {
+            "es un dispositivo Bluetooth en el perfil manos libres (HFP/HSP), el audio está limitado a mono de banda estrecha por el códec Bluetooth y no por el micrófono. Cambia el dispositivo a A2DP o usa un micrófono con cable para oírlo con toda su calidad"
+        }
+        Message::SetupWelcome => "Bienvenido a chemic, esto configura los dispositivos con los que monitorizar y la ganancia de entrada y los guarda en un perfil del archivo de configuración",
+        Message::SelectMicrophone => "Selecciona tu micrófono",
+        Message::SelectHeadphones => "Selecciona tus auriculares o altavoces",
+        Message::SettingGain => "Ajustando la ganancia de entrada de",
+        Message::SpeakNormally => "Habla con normalidad, como lo harías al monitorizar",
+        Message::SpeakLouder => "Ahora un poco más alto, tan alto como esperas llegar a hablar",
+        Message::NothingHeard => "No se oyó nada del micrófono, comprueba que está conectado y no silenciado",
+        Message::Speech => "Voz",
+        Message::Peak => "pico",
+        Message::SpeechClipped => "La voz alta saturó, baja la ganancia de la interfaz o del micrófono",
+        Message::HighGain => "Una ganancia tan alta también amplifica el ruido, sube la ganancia de la interfaz o del micrófono y vuelve a intentarlo si puedes",
+        Message::RetryGain => "Pulsa Enter para intentarlo de nuevo o escribe \"s\" para saltar el ajuste de la ganancia",
+        Message::Gain => "Ganancia",
+        Message::SaveProfile => "¿Guardar estos ajustes como el perfil",
+        Message::YesNo => "[S/n]",
+        Message::NothingSaved => "No se guardó nada",
+        Message::ProfileSaved => "Perfil guardado",
+        Message::StartMonitoring => "Empieza a monitorizar con él ejecutando",
+        Message::ChangeOsGain => "¿Cambiar el volumen de entrada en el mezclador del sistema en lugar de aplicar la ganancia digitalmente",
+        Message::RaiseInputVolume => {
+            if cfg!(target_os = "linux") {
+                "Sube el volumen de entrada del micrófono en la configuración de sonido, pavucontrol o alsamixer (F4 muestra los controles de captura) y comprueba que el interruptor de captura no esté desactivado"
+            } else if cfg!(target_os = "windows") {
+                "Sube el volumen de entrada en Configuración > Sistema > Sonido > Entrada, o en la pestaña Niveles de las propiedades del micrófono en el Panel de control de sonido"
+            } else if cfg!(target_os = "macos") {
+                "Sube el volumen de entrada en Ajustes del Sistema > Sonido > Entrada, o en la app Configuración de Audio MIDI para las interfaces"
+            } else {
+                "Sube el volumen de entrada del micrófono en la configuración de sonido del sistema"
+            }
+        }
+        Message::LowerBoost => {
+            if cfg!(target_os = "linux") {
+                "Baja el control Mic Boost en alsamixer (F4 muestra los controles de captura) y sube el volumen de captura en su lugar, el realce amplifica el ruido de la entrada junto con la voz"
+            } else if cfg!(target_os = "windows") {
+                "Baja el Aumento de micrófono en la pestaña Niveles de las propiedades del micrófono en el Panel de control de sonido y sube el volumen en su lugar, el realce amplifica el ruido de la entrada junto con la voz"
+            } else {
+                "Baja el realce del micrófono del dispositivo y sube el volumen de entrada en su lugar, el realce amplifica el ruido de la entrada junto con la voz"
+            }
+        }
+    }
+}
+
+fn german(message: Message) -> &'static str {
+    match message {
+        Message::Tagline => "Werkzeug zum Testen von Mikrofonen",
+        Message::UsingProfile => "Verwende Profil",
+        Message::SelectInputDevice => "Zu testendes Eingabegerät auswählen",
+        Message::SelectOutputDevice => "Ausgabegerät für die Wiedergabe auswählen",
+        Message::InputDevice => "Eingabegerät",
+        Message::OutputDevice => "Ausgabegerät",
+        Message::Name => "Name",
+        Message::Channels => "Kanäle",
+        Message::SampleRate => "Abtastrate",
+        Message::Resampling => "Resampling",
+        Message::On => "An",
+        Message::Off => "Aus",
+        Message::Warning => "Warnung",
+        Message::Input => "Eingang",
+        Message::Output => "Ausgang",
+        Message::LinearResampler => "lineares Resampling aktiv",
+        Message::MonoToStereo => "Mono auf beide Kanäle dupliziert",
+        Message::StereoToMono => "Stereo zu Mono gemittelt",
+        Message::LeftToMono => "nur der linke Kanal als Mono",
+        Message::RightToMono => "nur der rechte Kanal als Mono",
+        Message::PowerSumToMono => "Kanäle leistungssummiert zu Mono",
+        Message::ChannelsMixed => "Kanäle mit der Standardanordnung gemischt",
+        Message::QualityMayDiffer => "Qualität und Latenz können vom Gerät selbst abweichen",
+        Message::Playing => "Mikrofon wird über das Ausgabegerät wiedergegeben...",
+        Message::Controls => "Steuerung",
+        Message::Stop => "beenden",
+        Message::Mute => "stummschalten",
+        Message::Pause => "pausieren",
+        Message::VolumeUp => "lauter",
+        Message::VolumeDown => "leiser",
+        Message::OsGainUp => "Systempegel lauter",
+        Message::OsGainDown => "Systempegel leiser",
+        Message::Muted => "Ausgabe stummgeschaltet",
+        Message::Unmuted => "Ausgabe aktiviert",
+        Message::Paused => "Pausiert",
+        Message::Resumed => "Fortgesetzt",
+        Message::Volume => "Lautstärke",
+        Message::NoMatchingItems => "Keine Treffer",
+        Message::Level => "Pegel",
+        Message::Minus => "minus",
+        Message::Silent => "still",
+        Message::Clipping => "Übersteuerung",
+        Message::NoClipping => "keine Übersteuerung",
+        Message::Error => "Fehler",
+        Message::SelectAnotherDevice => "Wähle ein anderes Gerät aus, um es erneut zu versuchen",
+        Message::Correlation => "Korrelation",
+        Message::Plus => "plus",
+        Message::Latency => "Latenz",
+        Message::BufferGrown => {
+            "Der Ausgabe ging wiederholt das Audio aus, die Streams wurden mit einem größeren Puffer neu gestartet"
+        }
+        Message::RecordingOverflow => "Die Aufnahme konnte nicht schnell genug geschrieben werden, in den Aufnahmen fehlen Samples",
+        Message::Calibration => "Kalibrierung",
+        Message::NoiseFloor => "Grundrauschen",
+        Message::RecommendedGain => "Empfohlene Verstärkung",
+        Message::SensitivityOffset => "Empfindlichkeitsausgleich",
+        Message::OsGain => "Systemeingangspegel",
+        Message::OsGainLow => "Die Eingangslautstärke in den Toneinstellungen des Systems ist sehr niedrig oder stummgeschaltet, ein leises Mikrofon muss eventuell nur lauter gestellt werden",
+        Message::OsGainBoosted => "Die Mikrofonverstärkung im Systemmixer liegt über +20 dB und verstärkt das Rauschen des Eingangs zusammen mit der Stimme. Verringere die Verstärkung und erhöhe stattdessen die Eingangslautstärke",
+        Message::Glitches => "Störungen",
+        Message::Dropouts => "Aussetzer",
+        Message::SampleJumps => "Sprünge",
+        Message::Recording => "Aufnahme",
+        Message::AddMarker => "Marker setzen",
+        Message::Marker => "Marker",
+        Message::NotRecording => "Keine Aufnahme, Marker benötigen --record",
+        Message::ToneBurst => "Tonimpuls",
+        Message::TonePlayed => "Tonimpuls abgespielt, er sollte über das Mikrofon zurückkommen",
+        Message::SidetoneConverted => "Sidetone erfordert dieselbe Abtastrate und Kanalanzahl auf beiden Geräten, das Audio wird umgewandelt, was die Latenz erhöht",
+        Message::BluetoothHeadset => {
+            "ist ein Bluetooth-Headset im Freisprechprofil (HFP/HSP), der Ton wird durch den Bluetooth-Codec und nicht durch das Mikrofon auf Schmalband-Mono begrenzt. Schalte das Headset auf A2DP um oder verwende ein kabelgebundenes Mikrofon für die volle Qualität"
+        }
+        Message::SetupWelcome => "Willkommen bei chemic, hier werden die Geräte zum Abhören und die Eingangsverstärkung eingerichtet und in einem Profil der Konfigurationsdatei gespeichert",
+        Message::SelectMicrophone => "Wähle dein Mikrofon aus",
+        Message::SelectHeadphones => "Wähle deine Kopfhörer oder Lautsprecher aus",
+        Message::SettingGain => "Eingangsverstärkung einstellen für",
+        Message::SpeakNormally => "Sprich ganz normal, wie du es beim Abhören tun würdest",
+        Message::SpeakLouder => "Jetzt etwas lauter, so laut, wie du voraussichtlich wirst",
+        Message::NothingHeard => "Vom Mikrofon war nichts zu hören, prüfe, ob es angeschlossen und nicht stummgeschaltet ist",
+        Message::Speech => "Sprache",
+        Message::Peak => "Spitze",
+        Message::SpeechClipped => "Die laute Sprache hat übersteuert, verringere die Verstärkung des Interfaces oder Mikrofons",
+        Message::HighGain => "So viel Verstärkung hebt auch das Rauschen an, erhöhe wenn möglich die Verstärkung des Interfaces oder Mikrofons und versuche es erneut",
+        Message::RetryGain => "Drücke Enter, um es erneut zu versuchen, oder gib \"s\" ein, um die Verstärkung zu überspringen",
+        Message::Gain => "Verstärkung",
+        Message::SaveProfile => "Diese Einstellungen speichern als Profil",
+        Message::YesNo => "[J/n]",
+        Message::NothingSaved => "Es wurde nichts gespeichert",
+        Message::ProfileSaved => "Profil gespeichert",
+        Message::StartMonitoring => "Zum Abhören damit ausführen",
+        Message::ChangeOsGain => "Die Eingangslautstärke im Systemmixer ändern, statt die Verstärkung digital anzuwenden",
+        Message::RaiseInputVolume => {
+            if cfg!(target_os = "linux") {
+                "Erhöhe die Eingangslautstärke des Mikrofons in den Toneinstellungen, in pavucontrol oder alsamixer (F4 zeigt die Aufnahmeregler) und prüfe, dass der Aufnahmeschalter nicht aus ist"
+            } else if cfg!(target_os = "windows") {
+                "Erhöhe die Eingangslautstärke unter Einstellungen > System > Sound > Eingabe oder auf der Registerkarte Pegel der Mikrofoneigenschaften in der Sound-Systemsteuerung"
+            } else if cfg!(target_os = "macos") {
+                "Erhöhe die Eingangslautstärke unter Systemeinstellungen > Ton > Eingang oder für Interfaces im Programm Audio-MIDI-Setup"
+            } else {
+                "Erhöhe die Eingangslautstärke des Mikrofons in den Toneinstellungen des Systems"
+            }
+        }
+        Message::LowerBoost => {
+            if cfg!(target_os = "linux") {
+                "Verringere den Regler Mic Boost in alsamixer (F4 zeigt die Aufnahmeregler) und erhöhe stattdessen die Aufnahmelautstärke, die Verstärkung hebt das Rauschen des Eingangs zusammen mit der Stimme an"
+            } else if cfg!(target_os = "windows") {
+                "Verringere die Mikrofonverstärkung auf der Registerkarte Pegel der Mikrofoneigenschaften in der Sound-Systemsteuerung und erhöhe stattdessen die Lautstärke, die Verstärkung hebt das Rauschen des Eingangs zusammen mit der Stimme an"
+            } else {
+                "Verringere die Mikrofonverstärkung des Geräts und erhöhe stattdessen die Eingangslautstärke, die Verstärkung hebt das Rauschen des Eingangs zusammen mit der Stimme an"
+            }
+        }
+    }
+}
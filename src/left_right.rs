@@ -0,0 +1,124 @@
+//! Check of the left and right channels of headphones, playing a tone on
+//! one side then the other and asking which side it was heard from, so
+//! headphones worn the wrong way round or a swapped cable are noticed
+
+use crate::{
+    args::Args, config::Config, error::ChemicError, fuzzy_select::FuzzySelect, log::info,
+    meter::from_db, negotiate::negotiate_output, prompt_device, prompts, requested_device,
+    speaker_test::tone, use_plain_output, DeviceType,
+};
+use cpal::StreamConfig;
+use dialoguer::theme::{SimpleTheme, Theme};
+use std::{io, time::Duration};
+
+/// Length of the tone played on each side
+const TONE_LENGTH: Duration = Duration::from_millis(1500);
+/// Frequency of the tone
+const TONE_HZ: f64 = 1000.0;
+/// Default level of the tone in dBFS
+const DEFAULT_LEVEL_DB: f32 = -20.0;
+
+/// Side a tone was played on or heard from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+    Both,
+    Neither,
+}
+
+impl Side {
+    /// Answers offered after each tone
+    const ANSWERS: [Side; 4] = [Side::Left, Side::Right, Side::Both, Side::Neither];
+
+    fn name(self) -> &'static str {
+        match self {
+            Side::Left => "Left",
+            Side::Right => "Right",
+            Side::Both => "Both sides",
+            Side::Neither => "Neither side",
+        }
+    }
+}
+
+/// Plays a tone on the left then the right channel of the output from the
+/// `args` at the `--level`, asking which side each was heard from
+pub fn run(mut args: Args) -> Result<(), ChemicError> {
+    let plain = use_plain_output(args.plain);
+    let config = Config::load(args.config.as_deref())?;
+    args.apply_config(&config)?;
+
+    let prompt_theme = config.theme.prompt_theme();
+    let theme: &dyn Theme = if plain { &SimpleTheme } else { &prompt_theme };
+    let level = from_db(args.level.unwrap_or(DEFAULT_LEVEL_DB));
+
+    let host = &cpal::default_host();
+    let output = match requested_device(host, &args, DeviceType::Output, true)? {
+        Some(device) => device,
+        None => prompt_device(
+            host,
+            "Select the headphones",
+            DeviceType::Output,
+            &config.theme,
+            plain,
+        )?,
+    };
+
+    let output_config: StreamConfig = negotiate_output(&output, args.sample_rate)?.into();
+    if output_config.channels < 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("\"{}\" has a single channel", output.name),
+        )
+        .into());
+    }
+
+    let sample_rate = output_config.sample_rate.0;
+    let frames = (TONE_LENGTH.as_secs_f64() * sample_rate as f64) as usize;
+    let names: Vec<&str> = Side::ANSWERS.iter().map(|side| side.name()).collect();
+
+    println!("Put on the headphones, a tone is played on one side and then the other\n");
+    let mut answers = Vec::with_capacity(2);
+    for (side, channel) in [(Side::Left, 0), (Side::Right, 1)] {
+        prompts::play(
+            &output,
+            &output_config,
+            tone(TONE_HZ, frames, sample_rate, level),
+            Some(channel),
+        )?;
+        let index = FuzzySelect::with_theme(theme)
+            .with_prompt("Which side did you hear the tone from?")
+            .plain(plain)
+            .items(&names)
+            .default(0)
+            .interact()
+            .map_err(ChemicError::Prompt)?;
+        answers.push((side, Side::ANSWERS[index]));
+    }
+
+    info!("left and right check of \"{}\": {answers:?}", output.name);
+    println!();
+    match answers.as_slice() {
+        [(_, Side::Left), (_, Side::Right)] => {
+            println!("Left and right are the right way round")
+        }
+        [(_, Side::Right), (_, Side::Left)] => {
+            println!("The left and right channels are swapped");
+            println!("Check the headphones aren't worn backwards, then the cable and the");
+            println!("channel routing of the device");
+        }
+        answers => {
+            for &(played, heard) in answers {
+                if played != heard {
+                    println!(
+                        "The {} tone was heard from {}",
+                        played.name().to_lowercase(),
+                        heard.name().to_lowercase()
+                    );
+                }
+            }
+            println!("Check the connection of the headphones and the balance of the device");
+        }
+    }
+    Ok(())
+}
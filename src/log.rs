@@ -0,0 +1,312 @@
+//! Logging of events and debug information. Messages are written to
+//! stderr when enabled through the `-v` flags and to a log file when
+//! one is provided through `--log-file`
+//!
+//! chemic is only built as a binary so there is no subscriber to
+//! register, other tools follow the messages and timed spans through
+//! the log file written with `--log-format json`
+
+use std::{
+    fmt::{self, Write as _},
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Highest level of messages written to stderr, 0 disables stderr logging
+static STDERR_LEVEL: AtomicU8 = AtomicU8::new(0);
+/// Highest level of messages written to the log file, 0 when there is
+/// no log file
+static FILE_LEVEL: AtomicU8 = AtomicU8::new(0);
+/// Log file messages are written to
+static LOG_FILE: OnceLock<Mutex<LogFile>> = OnceLock::new();
+
+/// Level of a log message
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Failures such as stream errors
+    Error = 1,
+    /// Notable events such as device changes and results
+    Info = 2,
+    /// Decisions and events useful for debugging (-v)
+    Debug = 3,
+    /// Detailed high frequency information (-vv)
+    Trace = 4,
+}
+
+impl Level {
+    fn name(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+}
+
+/// Format of the lines written to the log file
+#[derive(Clone, Copy, Default)]
+pub enum LogFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+    /// Newline delimited JSON objects
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = io::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown log format \"{value}\""),
+            )),
+        }
+    }
+}
+
+/// Log file and the format to write it in
+struct LogFile {
+    writer: BufWriter<File>,
+    format: LogFormat,
+}
+
+/// Sets the verbosity of the stderr logging, 0 disables logging, 1
+/// enables debug messages and 2 or above enables trace messages
+pub fn set_verbosity(verbosity: u8) {
+    let level = match verbosity {
+        0 => 0,
+        1 => Level::Debug as u8,
+        _ => Level::Trace as u8,
+    };
+    STDERR_LEVEL.store(level, Ordering::Relaxed);
+
+    // The log file always records debug messages shown on stderr
+    if LOG_FILE.get().is_some() {
+        FILE_LEVEL.fetch_max(level, Ordering::Relaxed);
+    }
+}
+
+/// Opens the log file at `path` that all info and error messages (along
+/// with any enabled debug messages) are appended to
+pub fn open_file(path: &Path, format: LogFormat) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let log_file = LogFile {
+        writer: BufWriter::new(file),
+        format,
+    };
+
+    if LOG_FILE.set(Mutex::new(log_file)).is_err() {
+        return Err(io::Error::other("Log file already opened"));
+    }
+
+    FILE_LEVEL.store(
+        STDERR_LEVEL.load(Ordering::Relaxed).max(Level::Info as u8),
+        Ordering::Relaxed,
+    );
+    Ok(())
+}
+
+/// Whether messages at the provided `level` will be written
+pub fn enabled(level: Level) -> bool {
+    let level = level as u8;
+    level <= STDERR_LEVEL.load(Ordering::Relaxed) || level <= FILE_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Whether messages at the provided `level` are written to stderr
+pub fn stderr_enabled(level: Level) -> bool {
+    level as u8 <= STDERR_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Writes a log message, use the logging macros instead of
+/// calling this directly
+pub fn write(level: Level, target: &str, args: fmt::Arguments<'_>) {
+    if stderr_enabled(level) {
+        eprintln!("[{}] {target}: {args}", level.name());
+    }
+
+    if level as u8 > FILE_LEVEL.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let Some(log_file) = LOG_FILE.get() else {
+        return;
+    };
+
+    // Logging must never take down the program
+    let Ok(mut log_file) = log_file.lock() else {
+        return;
+    };
+
+    let timestamp = format_timestamp(SystemTime::now());
+    let line = match log_file.format {
+        LogFormat::Text => format!("{timestamp} [{}] {target}: {args}", level.name()),
+        LogFormat::Json => format!(
+            r#"{{"timestamp":"{timestamp}","level":"{}","target":"{}","message":"{}"}}"#,
+            level.name(),
+            escape_json(target),
+            escape_json(&args.to_string())
+        ),
+    };
+
+    // Flushed after every line so an unexpected exit still leaves the trail
+    _ = writeln!(log_file.writer, "{line}");
+    _ = log_file.writer.flush();
+}
+
+/// Timed section of work, logs how long the section took when dropped.
+/// Created using the [span] macro
+pub struct Span {
+    /// Level the span is logged at
+    level: Level,
+    /// Module the span was created in
+    target: &'static str,
+    /// Name of the span
+    name: &'static str,
+    /// When the span was entered, [None] when the level is disabled
+    start: Option<Instant>,
+}
+
+impl Span {
+    pub fn enter(level: Level, target: &'static str, name: &'static str) -> Self {
+        let start = enabled(level).then(|| {
+            if enabled(Level::Trace) {
+                write(Level::Trace, target, format_args!("enter {name}"));
+            }
+            Instant::now()
+        });
+
+        Self {
+            level,
+            target,
+            name,
+            start,
+        }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            write(
+                self.level,
+                self.target,
+                format_args!("{} took {:.2?}", self.name, start.elapsed()),
+            );
+        }
+    }
+}
+
+/// Escapes the provided `value` for use within a JSON string
+pub fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Formats the provided `time` as an RFC 3339 UTC timestamp with
+/// millisecond precision (e.g. "2024-01-31T09:15:00.123Z")
+pub fn format_timestamp(time: SystemTime) -> String {
+    let duration = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = duration.as_secs();
+    let days = (seconds / 86_400) as i64;
+    let seconds_of_day = seconds % 86_400;
+
+    // Convert the days since the epoch to a civil date
+    // (https://howardhinnant.github.io/date_algorithms.html#civil_from_days)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        seconds_of_day / 3600,
+        (seconds_of_day / 60) % 60,
+        seconds_of_day % 60,
+        duration.subsec_millis()
+    )
+}
+
+/// Logs an error message
+macro_rules! error {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Error) {
+            $crate::log::write($crate::log::Level::Error, module_path!(), format_args!($($arg)*));
+        }
+    };
+}
+
+/// Logs an info message
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Info) {
+            $crate::log::write($crate::log::Level::Info, module_path!(), format_args!($($arg)*));
+        }
+    };
+}
+
+/// Logs a debug message
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Debug) {
+            $crate::log::write($crate::log::Level::Debug, module_path!(), format_args!($($arg)*));
+        }
+    };
+}
+
+/// Logs a trace message
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Trace) {
+            $crate::log::write($crate::log::Level::Trace, module_path!(), format_args!($($arg)*));
+        }
+    };
+}
+
+/// Enters a [Span] at the debug level that lasts until the returned
+/// guard is dropped
+macro_rules! span {
+    ($name:expr) => {
+        $crate::log::Span::enter($crate::log::Level::Debug, module_path!(), $name)
+    };
+}
+
+pub(crate) use debug;
+pub(crate) use error;
+pub(crate) use info;
+pub(crate) use span;
+pub(crate) use trace;
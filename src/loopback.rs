@@ -0,0 +1,402 @@
+//! Guided setup of a loopback connection from the output to the input,
+//! checking the signal gets through before offering the measurements
+//! that need a loopback such as latency and frequency response
+
+use crate::{
+    args::Args,
+    bands::{self, BANDS},
+    config::Config,
+    error::ChemicError,
+    fuzzy_select::FuzzySelect,
+    harmonics::{self, amplitude, hann},
+    log::{debug, info, span},
+    measure::play_and_record,
+    meter::to_db,
+    negotiate::negotiate,
+    polarity::{self, pulse_shape},
+    prompt_device, requested_device, use_plain_output, DeviceType, NamedDevice,
+};
+use cpal::StreamConfig;
+use dialoguer::theme::{SimpleTheme, Theme};
+use std::{f64::consts::TAU, io, time::Duration};
+
+/// Frequency of the tone used to check the connection
+const CHECK_FREQUENCY: f64 = 1000.0;
+/// Peak level of the test signals
+const TEST_LEVEL: f32 = 0.5;
+/// Length of the tone used to check the connection
+const CHECK_LENGTH: Duration = Duration::from_secs(1);
+/// Level below which the loopback is considered disconnected
+const MIN_SIGNAL_DB: f32 = -50.0;
+/// Silence before the latency pulse so the streams have started
+const LATENCY_LEAD: Duration = Duration::from_millis(300);
+/// Longest round trip latency that can be measured
+const MAX_LATENCY: Duration = Duration::from_secs(1);
+/// Length of each tone of the frequency response
+const RESPONSE_TONE: Duration = Duration::from_millis(250);
+/// Part at the start of each tone skipped while the tone settles
+const RESPONSE_SETTLE: Duration = Duration::from_millis(80);
+/// Part of each tone analysed
+const RESPONSE_WINDOW: Duration = Duration::from_millis(150);
+
+/// Measurement offered once the loopback is working
+#[derive(Clone, Copy)]
+enum Measurement {
+    Latency,
+    Distortion,
+    FrequencyResponse,
+    Polarity,
+    Done,
+}
+
+impl Measurement {
+    const ALL: [Measurement; 5] = [
+        Measurement::Latency,
+        Measurement::Distortion,
+        Measurement::FrequencyResponse,
+        Measurement::Polarity,
+        Measurement::Done,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Measurement::Latency => "Round trip latency",
+            Measurement::Distortion => "Distortion (harmonics of a 1 kHz tone)",
+            Measurement::FrequencyResponse => "Frequency response",
+            Measurement::Polarity => "Polarity",
+            Measurement::Done => "Done",
+        }
+    }
+}
+
+/// Walks through connecting the output to the input, checks the signal
+/// and then runs the chosen loopback measurements
+pub fn run(mut args: Args) -> Result<(), ChemicError> {
+    let plain = use_plain_output(args.plain);
+    let config = Config::load(args.config.as_deref())?;
+    args.apply_config(&config)?;
+
+    let prompt_theme = config.theme.prompt_theme();
+    let theme: &dyn Theme = if plain { &SimpleTheme } else { &prompt_theme };
+
+    println!("Loopback setup\n");
+    println!("1. Connect an output of the interface to one of its inputs with a cable,");
+    println!("   or use a virtual loopback device that records what is played");
+    println!("2. Turn off direct monitoring and any effects on the input");
+    println!("3. Set the input gain low, the test signals are played at -6 dBFS\n");
+
+    let host = &cpal::default_host();
+    let mut input = requested_device(host, &args, DeviceType::Input, true)?;
+    let mut output = requested_device(host, &args, DeviceType::Output, true)?;
+
+    let (input, input_config, output, output_config) = loop {
+        let input_device = match input.take() {
+            Some(device) => device,
+            None => prompt_device(
+                host,
+                "Select the loopback input",
+                DeviceType::Input,
+                &config.theme,
+                plain,
+            )?,
+        };
+        let output_device = match output.take() {
+            Some(device) => device,
+            None => prompt_device(
+                host,
+                "Select the loopback output",
+                DeviceType::Output,
+                &config.theme,
+                plain,
+            )?,
+        };
+
+        let (input_config, output_config) =
+            negotiate(&input_device, &output_device, args.sample_rate)?;
+        let input_config: StreamConfig = input_config.into();
+        let output_config: StreamConfig = output_config.into();
+
+        println!(
+            "\nChecking the signal from \"{}\" to \"{}\"...",
+            output_device.name, input_device.name
+        );
+        if check_signal(&input_device, &input_config, &output_device, &output_config)? {
+            break (input_device, input_config, output_device, output_config);
+        }
+
+        println!("Press Enter to check again or type \"q\" to stop");
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line)? == 0 || line.trim().eq_ignore_ascii_case("q") {
+            return Ok(());
+        }
+    };
+
+    let names: Vec<&str> = Measurement::ALL
+        .iter()
+        .map(|measurement| measurement.name())
+        .collect();
+    let mut latency = None;
+
+    loop {
+        println!();
+        let index = FuzzySelect::with_theme(theme)
+            .with_prompt("Choose a measurement")
+            .plain(plain)
+            .items(&names)
+            .default(0)
+            .interact()
+            .map_err(ChemicError::Prompt)?;
+        println!();
+
+        match Measurement::ALL[index] {
+            Measurement::Latency => {
+                latency = measure_latency(&input, &input_config, &output, &output_config)?;
+            }
+            Measurement::Distortion => harmonics::analyse(
+                &input,
+                &input_config,
+                &output,
+                &output_config,
+                harmonics::DEFAULT_FREQUENCY as f64,
+            )?,
+            Measurement::FrequencyResponse => {
+                // The tones are found in the recording using the latency
+                if latency.is_none() {
+                    latency = measure_latency(&input, &input_config, &output, &output_config)?;
+                }
+                match latency {
+                    Some(latency) => {
+                        frequency_response(&input, &input_config, &output, &output_config, latency)?
+                    }
+                    None => println!("The frequency response needs the latency to be measured"),
+                }
+            }
+            Measurement::Polarity => {
+                polarity::test(&input, &input_config, &output, &output_config)?
+            }
+            Measurement::Done => return Ok(()),
+        }
+    }
+}
+
+/// Creates a sine tone signal for the stream `config` at the `frequency`
+/// returned for each frame index, silent while it returns [None]
+fn tone(
+    config: &StreamConfig,
+    mut frequency: impl FnMut(usize) -> Option<f64> + Send + 'static,
+) -> impl FnMut() -> f32 + Send + 'static {
+    let sample_rate = config.sample_rate.0 as f64;
+    let mut phase = 0.0f64;
+    let mut frame = 0usize;
+    move || {
+        let value = match frequency(frame) {
+            Some(frequency) => {
+                phase = (phase + TAU * frequency / sample_rate) % TAU;
+                phase.sin() as f32 * TEST_LEVEL
+            }
+            None => 0.0,
+        };
+        frame += 1;
+        value
+    }
+}
+
+/// Plays a tone checking that it is captured by the input at a usable
+/// level, returns whether the loopback works
+fn check_signal(
+    input: &NamedDevice,
+    input_config: &StreamConfig,
+    output: &NamedDevice,
+    output_config: &StreamConfig,
+) -> Result<bool, ChemicError> {
+    let _span = span!("loopback_check_signal");
+
+    let signal = tone(output_config, |_| Some(CHECK_FREQUENCY));
+    let recording = play_and_record(
+        input,
+        input_config,
+        output,
+        output_config,
+        CHECK_LENGTH,
+        signal,
+    )?;
+
+    // The second half is well after the streams started
+    let segment = &recording[recording.len() / 2..];
+    let window = hann(segment);
+    let level =
+        to_db(amplitude(&window, CHECK_FREQUENCY, input_config.sample_rate.0 as f64) as f32);
+    let peak = segment
+        .iter()
+        .map(|sample| sample.abs())
+        .fold(0.0f32, f32::max);
+
+    debug!("loopback check level {level:?} dBFS, peak {peak}");
+
+    match level {
+        Some(level) if level >= MIN_SIGNAL_DB => {
+            info!("loopback check passed at {level:.1} dBFS");
+            println!("Signal received at {level:.1} dBFS");
+            if peak >= 1.0 {
+                println!("The input is clipping, lower the input gain for accurate measurements");
+            }
+            Ok(true)
+        }
+        level => {
+            info!("loopback check failed at {level:?} dBFS");
+            println!(
+                "The test tone was not received, check the cable and that the input isn't muted"
+            );
+            Ok(false)
+        }
+    }
+}
+
+/// Plays a single pulse and finds it in the recording, printing and
+/// returning the time it took to arrive at the input
+fn measure_latency(
+    input: &NamedDevice,
+    input_config: &StreamConfig,
+    output: &NamedDevice,
+    output_config: &StreamConfig,
+) -> Result<Option<Duration>, ChemicError> {
+    let _span = span!("loopback_latency");
+
+    let pulse = pulse_shape(output_config.sample_rate.0);
+    let lead = (LATENCY_LEAD.as_secs_f64() * output_config.sample_rate.0 as f64) as usize;
+    let mut frame = 0usize;
+    let signal = move || {
+        let value = frame
+            .checked_sub(lead)
+            .and_then(|offset| pulse.get(offset))
+            .map_or(0.0, |value| value * TEST_LEVEL);
+        frame += 1;
+        value
+    };
+
+    let recording = play_and_record(
+        input,
+        input_config,
+        output,
+        output_config,
+        LATENCY_LEAD + MAX_LATENCY,
+        signal,
+    )?;
+
+    // The strongest match of the pulse shape is where it arrived
+    let pulse = pulse_shape(input_config.sample_rate.0);
+    let arrival = recording
+        .windows(pulse.len())
+        .map(|window| {
+            window
+                .iter()
+                .zip(&pulse)
+                .map(|(sample, weight)| sample * weight)
+                .sum::<f32>()
+                .abs()
+        })
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    let sample_rate = input_config.sample_rate.0 as f64;
+    let latency = arrival
+        .map(|(index, _)| Duration::from_secs_f64(index as f64 / sample_rate))
+        .and_then(|arrival| arrival.checked_sub(LATENCY_LEAD));
+
+    match latency {
+        Some(latency) => {
+            info!("loopback round trip latency {latency:?}");
+            println!(
+                "Round trip latency: {:.1} ms from the output to the input, including the stream buffers",
+                latency.as_secs_f64() * 1000.0
+            );
+        }
+        None => {
+            info!("loopback latency pulse not found");
+            println!("The latency pulse was not found in the recording");
+        }
+    }
+
+    Ok(latency)
+}
+
+/// Plays a tone at the center of each one third octave band and prints
+/// the level each was captured at relative to 1 kHz
+fn frequency_response(
+    input: &NamedDevice,
+    input_config: &StreamConfig,
+    output: &NamedDevice,
+    output_config: &StreamConfig,
+    latency: Duration,
+) -> Result<(), ChemicError> {
+    let _span = span!("loopback_frequency_response");
+
+    let sample_rate = input_config.sample_rate.0.min(output_config.sample_rate.0) as f64;
+    let frequencies: Vec<f64> = (0..BANDS)
+        .map(bands::center_frequency)
+        .filter(|frequency| *frequency < sample_rate * 0.45)
+        .collect();
+
+    println!(
+        "Playing {} tones from {:.0} Hz to {:.0} Hz",
+        frequencies.len(),
+        frequencies.first().copied().unwrap_or_default(),
+        frequencies.last().copied().unwrap_or_default()
+    );
+
+    let tone_frames = (RESPONSE_TONE.as_secs_f64() * output_config.sample_rate.0 as f64) as usize;
+    let signal = tone(output_config, {
+        let frequencies = frequencies.clone();
+        move |frame| frequencies.get(frame / tone_frames.max(1)).copied()
+    });
+
+    let recording = play_and_record(
+        input,
+        input_config,
+        output,
+        output_config,
+        RESPONSE_TONE * frequencies.len() as u32 + latency,
+        signal,
+    )?;
+
+    let input_rate = input_config.sample_rate.0 as f64;
+    let to_frames = |duration: Duration| (duration.as_secs_f64() * input_rate) as usize;
+    let levels: Vec<Option<f32>> = frequencies
+        .iter()
+        .enumerate()
+        .map(|(index, &frequency)| {
+            let start = to_frames(latency + RESPONSE_TONE * index as u32 + RESPONSE_SETTLE);
+            let segment = recording.get(start..start + to_frames(RESPONSE_WINDOW))?;
+            to_db(amplitude(&hann(segment), frequency, input_rate) as f32)
+        })
+        .collect();
+
+    let reference = frequencies
+        .iter()
+        .position(|frequency| (*frequency - 1000.0).abs() < 1.0)
+        .and_then(|index| levels[index]);
+    let Some(reference) = reference else {
+        println!("The 1 kHz tone was not received, the response can't be shown");
+        return Ok(());
+    };
+
+    println!("\nLevels relative to 1 kHz\n");
+    for (frequency, level) in frequencies.iter().zip(&levels) {
+        let label = if *frequency >= 1000.0 {
+            format!("{:.1} kHz", frequency / 1000.0)
+        } else {
+            format!("{frequency:.0} Hz")
+        };
+        match level {
+            Some(level) => {
+                let relative = level - reference;
+                // One character for each decibel below the reference
+                let bar = "#".repeat((40.0 + relative.clamp(-40.0, 6.0)).round() as usize);
+                println!("{label:>9}  {relative:>6.1} dB  {bar}");
+            }
+            None => println!("{label:>9}  not received"),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,128 @@
+//! Integrated loudness in LUFS following ITU-R BS.1770, the K-weighted
+//! level of the audio gated to ignore silence and quiet passages
+
+use std::f64::consts::PI;
+
+/// Length of the gating blocks in seconds
+const BLOCK_SECONDS: f64 = 0.4;
+/// Blocks overlap by three quarters of their length
+const BLOCK_STEPS: usize = 4;
+/// Blocks quieter than this absolute level aren't counted
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+/// Blocks this much quieter than the ungated loudness aren't counted
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+/// Second order filter section in direct form one
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
+
+/// K-weighting filter of one channel, a high shelf modelling the head
+/// followed by a high pass
+struct KWeighting {
+    shelf: Biquad,
+    high_pass: Biquad,
+}
+
+impl KWeighting {
+    /// Creates the filter for the `sample_rate`, the coefficients are
+    /// derived for any rate from the 48 kHz filter of the standard
+    fn new(sample_rate: f64) -> Self {
+        let k = (PI * 1681.974450955533 / sample_rate).tan();
+        let q = 0.7071752369554196;
+        let vh = 10f64.powf(3.999843853973347 / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        };
+
+        let k = (PI * 38.13547087602444 / sample_rate).tan();
+        let q = 0.5003270373238773;
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        };
+
+        Self { shelf, high_pass }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        self.high_pass.process(self.shelf.process(input))
+    }
+}
+
+/// Integrated loudness of the interleaved `samples` at the `sample_rate`
+/// with the number of `channels` in LUFS, [None] when the audio is
+/// shorter than a block or quieter than the absolute gate. Every channel
+/// is weighted equally, which matches the standard for mono and stereo
+pub fn integrated(samples: &[f32], sample_rate: u32, channels: u16) -> Option<f64> {
+    let channels = channels.max(1) as usize;
+    let mut filters: Vec<KWeighting> = (0..channels)
+        .map(|_| KWeighting::new(sample_rate as f64))
+        .collect();
+
+    // Sum of the squared weighted samples of each step, a block is the
+    // sum of the steps it covers
+    let step = ((BLOCK_SECONDS * sample_rate as f64) as usize / BLOCK_STEPS).max(1);
+    let steps: Vec<f64> = samples
+        .chunks(step * channels)
+        .filter(|chunk| chunk.len() == step * channels)
+        .map(|chunk| {
+            chunk
+                .chunks_exact(channels)
+                .map(|frame| {
+                    frame
+                        .iter()
+                        .zip(&mut filters)
+                        .map(|(&sample, filter)| filter.process(sample as f64).powi(2))
+                        .sum::<f64>()
+                })
+                .sum()
+        })
+        .collect();
+
+    let block_frames = (step * BLOCK_STEPS) as f64;
+    let blocks: Vec<f64> = steps
+        .windows(BLOCK_STEPS)
+        .map(|window| window.iter().sum::<f64>() / block_frames)
+        .collect();
+
+    let loudness = |power: f64| -0.691 + 10.0 * power.log10();
+    let gated_mean = |threshold: f64| {
+        let gated: Vec<f64> = blocks
+            .iter()
+            .copied()
+            .filter(|&power| loudness(power) > threshold)
+            .collect();
+        (!gated.is_empty()).then(|| gated.iter().sum::<f64>() / gated.len() as f64)
+    };
+
+    let ungated = gated_mean(ABSOLUTE_GATE_LUFS)?;
+    let power = gated_mean(loudness(ungated) + RELATIVE_GATE_LU)?;
+    Some(loudness(power))
+}
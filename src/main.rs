@@ -1,21 +1,332 @@
+use analysis::{Analysis, AnalysisTap, Analyzer};
+use args::{Args, Command};
+use backend::{AudioDevice, AudioHost};
+use bands::BandFilter;
+use bed::Bed;
+use ceiling::Ceiling;
+use conceal::Concealment;
+use config::{Config, DeviceSettings};
+use controls::{Action, Controls, KeyBindings};
 use cpal::{
-    traits::{DeviceTrait, HostTrait, StreamTrait},
-    BufferSize, Device, Devices, DevicesError, Host, InputCallbackInfo, OutputCallbackInfo, Sample,
-    SampleRate, StreamConfig, StreamError, SupportedBufferSize,
+    traits::{DeviceTrait, StreamTrait},
+    BufferSize, DefaultStreamConfigError, Device, Host, InputCallbackInfo, OutputCallbackInfo,
+    Sample, SampleRate, Stream, StreamConfig, StreamError, SupportedBufferSize,
+    SupportedStreamConfig, SupportedStreamConfigRange, SupportedStreamConfigsError,
 };
 use dasp_interpolate::linear::Linear;
 use dasp_signal::{interpolate::Converter, Signal};
 use dialoguer::{
-    console::{Key, Term},
-    theme::ColorfulTheme,
-    Select,
+    console::{self, Term},
+    theme::{SimpleTheme, Theme},
 };
+use error::ChemicError;
+use events::{Event, EventReporter};
+use fuzzy_select::FuzzySelect;
+use glitch::GlitchLog;
+use jitter::JitterBuffer;
+use lang::{tr, Language, Message};
+use log::{debug, error, info, span};
+use meter::{LevelMeter, MeterDisplay, MeterMode, MeterScale};
+use mix::{Downmix, MixMatrix, Mixer};
+use negotiate::negotiate;
+use os_gain::OsGain;
+use pattern::DevicePattern;
+use record::{Recorder, Track, TrackProducer};
 use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
-use std::{env::args, io};
+use stats::StreamStats;
+use std::{
+    env, fmt,
+    io::{self, IsTerminal},
+    path::Path,
+    process::ExitCode,
+    sync::{atomic::Ordering, mpsc, Arc},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+use theme::UiTheme;
+use wav::{BroadcastInfo, Finishing, Trim};
+use worker::{Effects, Worker};
+
+mod align;
+#[cfg(target_os = "linux")]
+mod alsa_device;
+mod analysis;
+mod analyze;
+mod args;
+mod audit;
+mod backend;
+mod bands;
+mod bed;
+mod bench;
+mod calibrate;
+mod ceiling;
+mod compare;
+mod completions;
+mod conceal;
+mod config;
+mod controls;
+mod convert;
+mod daemon;
+mod error;
+mod events;
+mod fft;
+mod fuzzy_select;
+mod generate;
+mod glitch;
+mod guided;
+mod harmonics;
+#[cfg(feature = "stt")]
+mod intelligibility;
+mod jitter;
+mod lang;
+mod left_right;
+mod log;
+mod loopback;
+mod loudness;
+mod measure;
+mod meter;
+mod mix;
+mod negotiate;
+mod null_test;
+mod os_gain;
+mod pattern;
+mod polarity;
+mod processor;
+mod prompts;
+mod push_record;
+mod record;
+mod reference;
+mod rt60;
+mod setup;
+mod shared;
+mod simd;
+mod soak;
+mod speaker_test;
+mod stats;
+mod stress;
+mod sweep;
+mod theme;
+mod tune;
+mod watch;
+mod wav;
+mod weighting;
+mod worker;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-fn main() -> io::Result<()> {
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            error!("{err}");
+            // The stderr log already shows the error when enabled
+            if !log::stderr_enabled(log::Level::Error) {
+                eprintln!("Error: {err}");
+            }
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
+
+/// Parses the arguments and runs the selected command
+fn run() -> Result<(), ChemicError> {
+    let args = Args::parse()?;
+    log::set_verbosity(args.verbose);
+    lang::set_language(args.lang.unwrap_or_else(Language::detect));
+    if let Some(path) = &args.log_file {
+        log::open_file(path, args.log_format).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("Failed to open log file {}: {err}", path.display()),
+            )
+        })?;
+    }
+
+    if let Some(format) = args.events {
+        events::enable(format);
+    }
+
+    if args.non_interactive && args.command.asks_questions() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "This command asks questions while it runs and can't be used with --non-interactive",
+        )
+        .into());
+    }
+
+    match args.command {
+        Command::Monitor => monitor(args),
+        Command::Completions(shell) => {
+            print!("{}", completions::generate(shell));
+            Ok(())
+        }
+        Command::Devices(ty) => {
+            let host = cpal::default_host();
+            let types = match ty {
+                Some(ty) => vec![ty],
+                None => vec![DeviceType::Input, DeviceType::Output],
+            };
+
+            for ty in types {
+                for device in selectable_devices(&host, ty)? {
+                    println!("{}", device.name);
+                }
+            }
+
+            Ok(())
+        }
+        Command::Configs(ref name) => {
+            let config = Config::load(args.config.as_deref())?;
+            print_configs(&cpal::default_host(), config.alias(name).unwrap_or(name))
+        }
+        Command::Daemon => daemon::run(args),
+        Command::Polarity => polarity::run(args),
+        Command::Rt60 => rt60::run(args),
+        Command::Calibrate(spl) => calibrate::run(args, spl),
+        Command::Harmonics(frequency) => harmonics::run(args, frequency),
+        Command::Loopback => loopback::run(args),
+        Command::Soak => soak::run(args),
+        Command::Tune => tune::run(args, false),
+        Command::LatencyReport => tune::run(args, true),
+        Command::Generate => generate::run(&args),
+        Command::SpeakerTest => speaker_test::run(args),
+        Command::LeftRight => left_right::run(args),
+        Command::Setup => setup::run(args),
+        Command::Audit(ref name) => {
+            let config = Config::load(args.config.as_deref())?;
+            audit::run(&cpal::default_host(), config.alias(name).unwrap_or(name))
+        }
+        Command::Stress => stress::run(args),
+        Command::SharedAccess => shared::run(args),
+        Command::PushToRecord => push_record::run(args),
+        Command::Guided => guided::run(args),
+        Command::Compare(first, second) => compare::run(&first, &second),
+        Command::Analyze(path) => analyze::run(&path),
+        Command::Convert(ref input, ref output) => {
+            let (input, output) = (input.clone(), output.clone());
+            convert::run(args, &input, &output)
+        }
+        Command::NullTest(ref second) => {
+            let second = second.clone();
+            null_test::run(args, second)
+        }
+        Command::Reference(ref path) => {
+            let path = path.clone();
+            reference::run(args, &path)
+        }
+        #[cfg(feature = "stt")]
+        Command::Intelligibility(ref model) => {
+            let model = model.clone();
+            intelligibility::run(args, &model)
+        }
+        #[cfg(not(feature = "stt"))]
+        Command::Intelligibility(model) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "Unable to use the model {}, chemic was built without the stt feature",
+                model.display()
+            ),
+        )
+        .into()),
+        Command::Watch => watch::run(&cpal::default_host()),
+        Command::Bench => Ok(positive_duration(
+            args.duration.unwrap_or(bench::DEFAULT_DURATION),
+            "Duration",
+        )
+        .and_then(bench::run)?),
+        Command::SweepInputs => {
+            let duration =
+                positive_duration(args.duration.unwrap_or(sweep::DEFAULT_DURATION), "Duration")?;
+            sweep::run(
+                &cpal::default_host(),
+                duration,
+                args.weighting.unwrap_or_default(),
+            )
+        }
+    }
+}
+
+/// Prints every supported input and output stream config range for
+/// the device with the provided `name`
+fn print_configs(host: &Host, name: &str) -> Result<(), ChemicError> {
+    let mut found = false;
+
+    for ty in [DeviceType::Input, DeviceType::Output] {
+        let Ok(device) = find_device(host, ty, name) else {
+            continue;
+        };
+        found = true;
+
+        let (default_config, configs) = device.supported_configs(ty);
+
+        println!("== == == == {} configs: {} == == == ==", ty, device.name);
+
+        match default_config {
+            Ok(config) => println!(
+                "Default: {}, {}ch, {}Hz, buffer {}\n",
+                config.sample_format(),
+                config.channels(),
+                config.sample_rate().0,
+                format_buffer_size(config.buffer_size())
+            ),
+            Err(err) => println!("Default: unavailable ({err})\n"),
+        }
+
+        match configs {
+            Ok(configs) if configs.is_empty() => println!("No supported configs"),
+            Ok(configs) => {
+                println!(
+                    "{:<8}{:<10}{:<22}Buffer Size",
+                    "Format", "Channels", "Sample Rate"
+                );
+                for config in configs {
+                    let sample_rate = format!(
+                        "{} - {}Hz",
+                        config.min_sample_rate().0,
+                        config.max_sample_rate().0
+                    );
+                    println!(
+                        "{:<8}{:<10}{:<22}{}",
+                        config.sample_format().to_string(),
+                        config.channels(),
+                        sample_rate,
+                        format_buffer_size(config.buffer_size())
+                    );
+                }
+            }
+            Err(err) => println!("Unable to load supported configs: {err}"),
+        }
+
+        println!();
+    }
+
+    if !found {
+        return Err(ChemicError::NoDeviceNamed(name.to_string()));
+    }
+
+    Ok(())
+}
+
+/// Formats the range of a supported buffer size
+fn format_buffer_size(buffer_size: &SupportedBufferSize) -> String {
+    match buffer_size {
+        SupportedBufferSize::Range { min, max } => format!("{min} - {max}"),
+        SupportedBufferSize::Unknown => "unknown".to_string(),
+    }
+}
+
+/// Determines whether plain output without ANSI styling should be used,
+/// either because it was requested, the `NO_COLOR` environment variable
+/// is set, or the output isn't an interactive terminal
+fn use_plain_output(requested: bool) -> bool {
+    requested
+        || env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+        || env::var("TERM").is_ok_and(|term| term == "dumb")
+        || !io::stdout().is_terminal()
+}
+
+/// Prints the CheMic banner and version
+fn print_banner() {
     println!(
         r#"
                                                            
@@ -24,67 +335,304 @@ fn main() -> io::Result<()> {
 |   ---|     |  -__||       |  ||  __|  | |  
 |______|__|__|_____||__|_|__|__||____|  |_| 
                                         
-CheMic - Microphone testing tool (v{VERSION})
-"#
+CheMic - {} (v{VERSION})
+"#,
+        tr(Message::Tagline)
     );
+}
+
+/// Plays the selected input device through the selected output device
+fn monitor(mut args: Args) -> Result<(), ChemicError> {
+    // Disable styling when it would end up as escape sequences in the output
+    let plain = use_plain_output(args.plain);
+    if plain {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+
+    let config = Config::load(args.config.as_deref())?;
+
+    // Fill in the missing arguments from the selected profile
+    args.apply_config(&config)?;
+
+    // Events take over stdout so the human readable output is hidden
+    if events::enabled() {
+        args.quiet = true;
+    }
+
+    let meter_mode = meter_mode(&args, &config.theme, plain)?;
+
+    if !args.quiet {
+        print_banner();
+
+        if let Some(profile) = &args.profile {
+            println!("{}: {profile}\n", tr(Message::UsingProfile));
+        }
+    }
 
     let host = cpal::default_host();
+    debug!(
+        "using host {} (available: {:?})",
+        host.id().name(),
+        cpal::available_hosts()
+    );
+
+    // Devices can only be chosen again when someone can answer the prompt
+    let interactive = io::stdin().is_terminal() && !events::enabled() && !args.non_interactive;
 
-    let mut input_device: Option<NamedDevice> = None;
-    let mut output_device: Option<NamedDevice> = None;
+    let mut input_device = requested_device(&host, &args, DeviceType::Input, interactive)?;
+    let mut output_device = requested_device(&host, &args, DeviceType::Output, interactive)?;
 
-    // Whether to use the default device
-    let mut is_default = false;
+    loop {
+        // Prompt for the devices that are still missing
+        let input = match input_device.take() {
+            Some(device) => device,
+            None => prompt_device(
+                &host,
+                tr(Message::SelectInputDevice),
+                DeviceType::Input,
+                &config.theme,
+                plain,
+            )?,
+        };
+        let output = match output_device.take() {
+            Some(device) => device,
+            None => prompt_device(
+                &host,
+                tr(Message::SelectOutputDevice),
+                DeviceType::Output,
+                &config.theme,
+                plain,
+            )?,
+        };
 
-    // Whether to delay the audio
-    let mut is_delayed = false;
+        match monitor_devices(
+            &args,
+            &config,
+            input.clone(),
+            output.clone(),
+            meter_mode.clone(),
+        ) {
+            Err(err) if interactive && err.recoverable() => {
+                report_device_error(&err);
 
-    for arg in args().skip(1) {
-        let arg = arg.to_lowercase();
-        if matches!(arg.as_str(), "default" | "--default" | "d" | "-d") {
-            is_default = true;
-        } else if matches!(arg.as_str(), "delay" | "--delay" | "dly" | "-dly") {
-            is_delayed = true;
+                // Only the device that failed is chosen again, both are
+                // when the failure can't be attributed to one of them
+                match err.device_type() {
+                    Some(DeviceType::Input) => output_device = Some(output),
+                    Some(DeviceType::Output) => input_device = Some(input),
+                    None => {}
+                }
+            }
+            result => return result,
         }
     }
+}
+
+/// Finds the device of the provided `ty` requested by the `args`, either
+/// by name or as the default device. [None] when the device should be
+/// prompted for, which also happens for unknown names when `interactive`
+fn requested_device<H: AudioHost>(
+    host: &H,
+    args: &Args,
+    ty: DeviceType,
+    interactive: bool,
+) -> Result<Option<NamedDevice<H::Device>>, ChemicError> {
+    let (name, pattern, preferred) = match ty {
+        DeviceType::Input => (
+            args.input.as_deref(),
+            args.input_regex.as_ref(),
+            &args.preferred_inputs,
+        ),
+        DeviceType::Output => (
+            args.output.as_deref(),
+            args.output_regex.as_ref(),
+            &args.preferred_outputs,
+        ),
+    };
+
+    let found = match (name, pattern) {
+        (Some(name), _) => find_device(host, ty, name),
+        (None, Some(pattern)) => match_device(host, ty, pattern),
+        (None, None) => {
+            let device = if args.default {
+                get_default_device(host, ty)
+            } else if args.last {
+                config::config_dir().and_then(|dir| find_last_device(host, &dir, ty))
+            } else {
+                // The first preferred device that is present is used, the
+                // device is prompted for as usual when none are
+                let device = preferred.iter().find_map(|name| {
+                    find_device(host, ty, name)
+                        .inspect_err(|err| debug!("preferred {ty} device is unavailable: {err}"))
+                        .ok()
+                });
+                if let Some(device) = &device {
+                    info!("using the preferred {ty} device \"{}\"", device.name);
+                }
+                device
+            };
 
-    // Set the default input devices
-    if is_default {
-        input_device = host
-            .default_input_device()
-            // Create a named device
-            .map(NamedDevice::from_default);
-        output_device = host
-            .default_output_device()
-            // Create a named device
-            .map(NamedDevice::from_default);
+            // Without prompting the default device is used instead
+            return match device {
+                Some(device) => Ok(Some(device)),
+                None if args.non_interactive => get_default_device(host, ty)
+                    .map(Some)
+                    .ok_or(ChemicError::NoDeviceRequested(ty)),
+                None => Ok(None),
+            };
+        }
+    };
+
+    match found {
+        Ok(device) => Ok(Some(device)),
+        Err(err) if interactive && !args.non_interactive => {
+            report_device_error(&err);
+            Ok(None)
+        }
+        Err(err) => Err(err),
     }
+}
 
-    // Create the named devices and prompt for them if they are missing
-    let input_device: NamedDevice = input_device
-        // Prompt input device if none specified
-        .unwrap_or_else(|| {
-            prompt_device(&host, "Select input device to test", DeviceType::Input)
-                .expect("Failed to select input device")
-        });
+/// Explains a device error before the device is prompted for again
+fn report_device_error(err: &ChemicError) {
+    error!("{err}");
+    eprintln!("{}: {err}", tr(Message::Error));
+    eprintln!("{}\n", tr(Message::SelectAnotherDevice));
+}
 
-    let output_device: NamedDevice = output_device
-        // Prompt for an output device if none specified
-        .unwrap_or_else(|| {
-            prompt_device(&host, "Select output device to play to", DeviceType::Output)
-                .expect("Failed to select output device")
-        });
+/// Monitors the `input_device` through the `output_device` until stopped
+fn monitor_devices(
+    args: &Args,
+    config: &Config,
+    input_device: NamedDevice,
+    output_device: NamedDevice,
+    meter_mode: Option<MeterMode>,
+) -> Result<(), ChemicError> {
+    info!("input device: {}", input_device.name);
+    info!("output device: {}", output_device.name);
+
+    let (input_config, output_config) = stream_configs(&input_device, &output_device, args)?;
+
+    if let Some(percent) = args.set_os_gain {
+        let gain = os_gain::set_input_gain(&input_device.name, percent)?;
+        info!("set the OS input gain of {} to {gain}", input_device.name);
+    }
+
+    if !args.quiet {
+        let os_gain = os_gain::input_gain(&input_device.name);
+
+        // Print the device information
+        print_device_info(
+            tr(Message::InputDevice),
+            &input_device,
+            &input_config,
+            config.device(&input_device.name),
+            os_gain.as_ref(),
+        );
+        print_device_info(
+            tr(Message::OutputDevice),
+            &output_device,
+            &output_config,
+            None,
+            None,
+        );
+
+        let resampling = if input_config.sample_rate == output_config.sample_rate {
+            tr(Message::Off).to_string()
+        } else {
+            format!(
+                "{} ({}Hz -> {}Hz)",
+                tr(Message::On),
+                input_config.sample_rate.0,
+                output_config.sample_rate.0
+            )
+        };
+        println!("{}: {resampling}\n", pad_label(Message::Resampling));
+
+        if let Some(warning) = conversion_warning(
+            &input_config,
+            &output_config,
+            args.downmix.unwrap_or_default(),
+        ) {
+            println!("{}: {warning}\n", tr(Message::Warning));
+        }
+
+        for (device, config) in [
+            (&input_device, &input_config),
+            (&output_device, &output_config),
+        ] {
+            if is_headset_profile(&device.name, config) {
+                info!("{} is using a bluetooth headset profile", device.name);
+                println!(
+                    "{}: \"{}\" {}\n",
+                    tr(Message::Warning),
+                    device.name,
+                    tr(Message::BluetoothHeadset)
+                );
+            }
+        }
+
+        if let Some(gain) = &os_gain {
+            for (warn, message) in [
+                (gain.is_low(), Message::OsGainLow),
+                (gain.is_boosted(), Message::OsGainBoosted),
+            ] {
+                if warn {
+                    info!("OS input gain of {}: {gain}", input_device.name);
+                    println!("{}: {} ({gain})\n", tr(Message::Warning), tr(message));
+                }
+            }
+        }
+    }
+
+    if args.sidetone.unwrap_or_default() && !is_sidetone_direct(args, &input_config, &output_config)
+    {
+        info!("sidetone needs converting between the devices");
+        if !args.quiet {
+            println!(
+                "{}: {}\n",
+                tr(Message::Warning),
+                tr(Message::SidetoneConverted)
+            );
+        }
+    }
+
+    start_streams(
+        input_device,
+        &input_config,
+        output_device,
+        &output_config,
+        args,
+        meter_mode,
+        config,
+    )
+}
 
-    // Obtain the supported device configs
-    let supported_input_config = input_device
-        .device
-        .default_input_config()
-        .expect("No supported input configs");
+/// Negotiates the best stream configs supported by both the `input` and
+/// `output` devices, using the sample rate and delay from the `args`
+fn stream_configs(
+    input: &NamedDevice,
+    output: &NamedDevice,
+    args: &Args,
+) -> Result<(StreamConfig, StreamConfig), ChemicError> {
+    if args.sidetone.unwrap_or_default() && args.delay.unwrap_or_default() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Sidetone monitoring can't be delayed",
+        )
+        .into());
+    }
+    if args.delay.unwrap_or_default() && args.buffer_size.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The buffer size can't be set when the audio is delayed",
+        )
+        .into());
+    }
 
-    let supported_output_config = output_device
-        .device
-        .default_output_config()
-        .expect("No supported output configs");
+    let (supported_input_config, supported_output_config) =
+        negotiate(input, output, args.sample_rate)?;
 
     let input_buffer_size = supported_input_config.buffer_size();
     let output_buffer_size = supported_output_config.buffer_size();
@@ -94,79 +642,478 @@ CheMic - Microphone testing tool (v{VERSION})
     let mut output_config: StreamConfig = supported_output_config.config();
 
     // Determine the buffer type to use
-    input_config.buffer_size =
-        get_buffer_size(input_buffer_size, input_config.sample_rate, is_delayed);
-    output_config.buffer_size =
-        get_buffer_size(output_buffer_size, output_config.sample_rate, is_delayed);
-
-    // Print the device information
-    println!("== == == == Input Device == == == ==");
-    println!("Name       : {}", input_device.name);
-    println!("Channels   : {}", input_config.channels);
-    println!("Sample Rate: {}Hz", input_config.sample_rate.0);
-    println!("== == == == == === === == == == == ==\n\n");
+    input_config.buffer_size = get_buffer_size(
+        input_buffer_size,
+        input_config.sample_rate,
+        args.delay.unwrap_or_default(),
+        args.buffer_size,
+    );
+    output_config.buffer_size = get_buffer_size(
+        output_buffer_size,
+        output_config.sample_rate,
+        args.delay.unwrap_or_default(),
+        args.buffer_size,
+    );
+
+    Ok((input_config, output_config))
+}
+
+/// Determines how the input level should be shown, bar meters can't be
+/// used by screen readers or in plain output so text lines are used
+/// instead. No meter is shown in quiet mode
+fn meter_mode(args: &Args, theme: &UiTheme, plain: bool) -> io::Result<Option<MeterMode>> {
+    if args.quiet {
+        return Ok(None);
+    }
+
+    if !args.accessible.unwrap_or_default() && !plain {
+        let theme = theme.meter_theme();
+        return Ok(Some(if args.bands.unwrap_or_default() {
+            MeterMode::Bands(theme)
+        } else {
+            MeterMode::Bar(theme)
+        }));
+    }
+
+    let interval = match args.meter_interval {
+        Some(seconds) => positive_duration(seconds, "Meter interval")?,
+        None => meter::DEFAULT_TEXT_INTERVAL,
+    };
+
+    Ok(Some(MeterMode::Text(interval)))
+}
+
+/// Converts the `seconds` provided for the setting called `name`
+/// into a duration, the duration must be greater than zero
+fn positive_duration(seconds: f64, name: &str) -> io::Result<Duration> {
+    Duration::try_from_secs_f64(seconds)
+        .ok()
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{name} must be a positive number of seconds"),
+            )
+        })
+}
+
+/// Creates a warning explaining the sample rate and channel conversions
+/// that will be applied between the `input` and `output` configs, returns
+/// [None] when no conversion is needed
+fn conversion_warning(
+    input: &StreamConfig,
+    output: &StreamConfig,
+    downmix: Downmix,
+) -> Option<String> {
+    let resampling = input.sample_rate != output.sample_rate;
+    let converting = input.channels != output.channels;
+
+    if !resampling && !converting {
+        return None;
+    }
+
+    let mut conversions = Vec::new();
+    if resampling {
+        conversions.push(tr(Message::LinearResampler));
+    }
+    if converting {
+        conversions.push(tr(match (input.channels, output.channels) {
+            (1, 2) => Message::MonoToStereo,
+            (_, 1) if downmix == Downmix::Left => Message::LeftToMono,
+            (_, 1) if downmix == Downmix::Right => Message::RightToMono,
+            (_, 1) if downmix == Downmix::PowerSum => Message::PowerSumToMono,
+            (2, 1) => Message::StereoToMono,
+            _ => Message::ChannelsMixed,
+        }));
+    }
+
+    Some(format!(
+        "{} {} {} -> {} {} {}, {}. {}",
+        tr(Message::Input),
+        format_sample_rate(input.sample_rate),
+        format_channels(input.channels),
+        tr(Message::Output),
+        format_sample_rate(output.sample_rate),
+        format_channels(output.channels),
+        conversions.join(", "),
+        tr(Message::QualityMayDiffer)
+    ))
+}
+
+/// Name fragments of Bluetooth devices across the hosts, PulseAudio and
+/// PipeWire use "bluez", BlueALSA names its PCMs "bluealsa" and Windows
+/// names the headset endpoints "Hands-Free"
+const BLUETOOTH_NAMES: [&str; 5] = ["bluez", "bluealsa", "bluetooth", "hands-free", "handsfree"];
+/// Name fragments only used by the headset profiles
+const HEADSET_PROFILE_NAMES: [&str; 4] = [
+    "hands-free",
+    "handsfree",
+    "headset-head-unit",
+    "profile=sco",
+];
+/// Highest sample rate of the headset profiles, 8 kHz with CVSD and
+/// 16 kHz with mSBC
+const HEADSET_PROFILE_RATE: u32 = 16000;
+
+/// Whether the device named `name` using the `config` appears to be a
+/// Bluetooth headset in the hands-free or headset profile rather than
+/// A2DP, either the name says so or the device only runs as narrowband
+/// mono
+fn is_headset_profile(name: &str, config: &StreamConfig) -> bool {
+    let name = name.to_lowercase();
+    if HEADSET_PROFILE_NAMES.iter().any(|part| name.contains(part)) {
+        return true;
+    }
+
+    BLUETOOTH_NAMES.iter().any(|part| name.contains(part))
+        && config.channels == 1
+        && config.sample_rate.0 <= HEADSET_PROFILE_RATE
+}
+
+/// Prints the `title`, name, channels and sample rate of a `device`
+/// using the provided `config`, along with the calibration from the
+/// remembered `settings` of the device
+fn print_device_info(
+    title: &str,
+    device: &NamedDevice,
+    config: &StreamConfig,
+    settings: Option<&DeviceSettings>,
+    os_gain: Option<&OsGain>,
+) {
+    println!("== == == == {title} == == == ==");
+    println!("{}: {}", pad_label(Message::Name), device.name);
+    println!("{}: {}", pad_label(Message::Channels), config.channels);
+    println!(
+        "{}: {}Hz",
+        pad_label(Message::SampleRate),
+        config.sample_rate.0
+    );
+
+    if let Some(settings) = settings {
+        if let Some(calibration) = settings.calibration {
+            println!(
+                "{}: {:.1} dB SPL @ {:.1} dBFS",
+                pad_label(Message::Calibration),
+                calibration.spl,
+                calibration.dbfs
+            );
+        }
+        if let Some(noise_floor) = settings.noise_floor_dbfs {
+            let spl = settings
+                .calibration
+                .map(|calibration| format!(" ({:.1} dB SPL)", calibration.spl(noise_floor)))
+                .unwrap_or_default();
+            println!(
+                "{}: {noise_floor:.1} dBFS{spl}",
+                pad_label(Message::NoiseFloor)
+            );
+        }
+        if let Some(gain) = settings.recommended_gain_db {
+            println!("{}: {gain:+.1} dB", pad_label(Message::RecommendedGain));
+        }
+        if let Some(offset) = settings.sensitivity_offset_db {
+            println!("{}: {offset:+.1} dB", pad_label(Message::SensitivityOffset));
+        }
+    }
+
+    if let Some(gain) = os_gain {
+        let muted = if gain.muted { " [muted]" } else { "" };
+        println!("{}: {gain}{muted}", pad_label(Message::OsGain));
+    }
 
-    println!("== == == == Output Device == == == ==");
-    println!("Name       : {}", output_device.name);
-    println!("Channels   : {}", output_config.channels);
-    println!("Sample Rate: {}Hz", output_config.sample_rate.0);
     println!("== == == == == === === == == == == ==\n\n");
+}
 
-    start_streams(
-        input_device.device,
-        &input_config,
-        output_device.device,
-        &output_config,
-    )
+/// Translates the `label` padding it to the width of the longest
+/// device info label so the values line up
+fn pad_label(label: Message) -> String {
+    let width = [
+        Message::Name,
+        Message::Channels,
+        Message::SampleRate,
+        Message::Resampling,
+        Message::Calibration,
+        Message::NoiseFloor,
+        Message::RecommendedGain,
+        Message::SensitivityOffset,
+        Message::OsGain,
+    ]
+    .into_iter()
+    .map(|label| tr(label).chars().count())
+    .max()
+    .unwrap_or_default();
+
+    format!("{:<width$}", tr(label))
 }
 
+/// Formats a channel count as a layout name (e.g. "mono")
+fn format_channels(channels: u16) -> String {
+    match channels {
+        1 => "mono".to_string(),
+        2 => "stereo".to_string(),
+        channels => format!("{channels}ch"),
+    }
+}
+
+/// Chooses the buffer size of a stream, the `requested` size in frames is
+/// kept within the `supported` range
 fn get_buffer_size(
     supported: &SupportedBufferSize,
     sample_rate: SampleRate,
     is_delayed: bool,
+    requested: Option<u32>,
 ) -> BufferSize {
     /// The time to delay in seconds
     const DELAY_SECONDS: u32 = 2;
 
     match supported {
         SupportedBufferSize::Range { min, max } => {
-            if is_delayed {
+            if let Some(frames) = requested {
+                BufferSize::Fixed(frames.clamp(*min, *max))
+            } else if is_delayed {
                 BufferSize::Fixed((sample_rate.0.saturating_mul(DELAY_SECONDS)).min(*max))
             } else {
                 BufferSize::Fixed(*min)
             }
         }
         // Unable to determine limitations
-        SupportedBufferSize::Unknown => BufferSize::Default,
+        SupportedBufferSize::Unknown => match requested {
+            Some(frames) => BufferSize::Fixed(frames.max(1)),
+            None => BufferSize::Default,
+        },
     }
 }
 
+/// Nanoseconds of audio held by `samples` interleaved samples of the
+/// stream `config`
+fn samples_to_ns(samples: usize, config: &StreamConfig) -> u64 {
+    let rate = config.sample_rate.0 as u64 * config.channels.max(1) as u64;
+    samples as u64 * 1_000_000_000 / rate.max(1)
+}
+
+/// Buffers the input callback passes the captured audio on through to
+/// the background threads
+struct CaptureTaps {
+    /// Untouched captured samples for the [Analyzer]
+    analysis: AnalysisTap,
+    /// Input samples for the recording, after the input gain
+    recording: Option<TrackProducer>,
+}
+
 /// Create a input stream callback that pushes the callback data onto
-/// the provided `producer`
+/// the provided `producer` after applying the `gain`. The captured
+/// samples are passed on through the `taps` to be analysed and recorded,
+/// the heavier analysis is left to the [Analyzer] thread
 fn create_producer_callback(
     mut producer: HeapProducer<f32>,
+    config: &StreamConfig,
+    stats: Arc<StreamStats>,
+    gain: f32,
+    mut taps: CaptureTaps,
 ) -> impl FnMut(&[f32], &InputCallbackInfo) {
-    move |data, _| {
+    let config = config.clone();
+    // Room for the gained samples, only grows when a callback is larger
+    // than any before it
+    let mut gained: Vec<f32> = Vec::with_capacity(8192);
+    move |data, info| {
+        let start = Instant::now();
+
+        // Glitches are found in the untouched capture, everything after
+        // sees the gained signal
+        taps.analysis.push(data);
+        let data = if gain == 1.0 {
+            data
+        } else {
+            gained.clear();
+            gained.extend(data.iter().map(|sample| sample * gain));
+            &gained
+        };
+        if let Some(recording) = &mut taps.recording {
+            recording.push(data);
+        }
+
         // Write the data to the producer
-        producer.push_slice(data);
+        let pushed = producer.push_slice(data);
+
+        let timestamp = info.timestamp();
+        if let Some(delay) = timestamp.callback.duration_since(&timestamp.capture) {
+            stats
+                .capture_delay_ns
+                .store(delay.as_nanos() as u64, Ordering::Relaxed);
+        }
+        stats
+            .input_buffered_ns
+            .store(samples_to_ns(producer.len(), &config), Ordering::Relaxed);
+
+        stats.input_callbacks.fetch_add(1, Ordering::Relaxed);
+        stats
+            .input_samples
+            .fetch_add(data.len() as u64, Ordering::Relaxed);
+        if pushed < data.len() {
+            stats
+                .dropped_samples
+                .fetch_add((data.len() - pushed) as u64, Ordering::Relaxed);
+        }
+
+        stats
+            .input_callback_max_ns
+            .fetch_max(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Source of the samples played by the output stream
+enum SampleConverter {
+    /// Sample rates match so samples are taken directly from the consumer
+    Direct(ConsumerSignal),
+    /// Samples are interpolated to the output sample rate
+    Resampled(Converter<ConsumerSignal, Linear<f32>>),
+}
+
+impl SampleConverter {
+    /// Number of input samples waiting to be converted
+    fn input_available(&self) -> usize {
+        match self {
+            SampleConverter::Direct(signal) => signal.consumer.len(),
+            SampleConverter::Resampled(converter) => converter.source().consumer.len(),
+        }
     }
 }
 
-/// Type alias for the sample converter
-type SampleConverter = Converter<ConsumerSignal, Linear<f32>>;
+impl Signal for SampleConverter {
+    type Frame = f32;
+
+    fn next(&mut self) -> Self::Frame {
+        match self {
+            SampleConverter::Direct(signal) => signal.next(),
+            SampleConverter::Resampled(converter) => converter.next(),
+        }
+    }
+}
 
-/// Creates an output stream callback that stores the output from the
-/// provided `converter` onto the callback output buffer
-fn create_converter_callback(
-    mut channel_converter: ChannelConverter,
-    mut converter: SampleConverter,
+/// Creates an output stream callback that copies the samples prepared
+/// by the worker from the `source` onto the callback output buffer, or
+/// the captured samples themselves for `sidetone` monitoring
+fn create_output_callback(
+    mut source: ConsumerSignal,
+    config: &StreamConfig,
+    stats: Arc<StreamStats>,
+    mut sidetone: Option<Sidetone>,
+    mut jitter: Option<JitterBuffer>,
+    mut ceiling: Ceiling,
 ) -> impl FnMut(&mut [f32], &OutputCallbackInfo) {
-    move |data, _| {
-        // Fill the output data with the values from the converter
-        data.fill_with(|| channel_converter.next(&mut converter));
+    let config = config.clone();
+    let window = config.sample_rate.0 as usize * config.channels.max(1) as usize / 2;
+    move |data, info| {
+        let start = Instant::now();
+
+        let ran_out = source.pop_into(data);
+        if let Some(sidetone) = &mut sidetone {
+            sidetone.process(&mut source, data, window);
+        }
+        // Nothing is played above the ceiling, whatever came before it
+        let limited = ceiling.apply(data);
+        if limited > 0 {
+            stats
+                .limited_frames
+                .fetch_add(limited as u64, Ordering::Relaxed);
+        }
+        if let Some(jitter) = &mut jitter {
+            jitter.observe(data.len(), source.consumer.len(), ran_out);
+            stats.jitter_target_ns.store(
+                samples_to_ns(jitter.target().samples(), &config),
+                Ordering::Relaxed,
+            );
+        }
+
+        let timestamp = info.timestamp();
+        if let Some(delay) = timestamp.playback.duration_since(&timestamp.callback) {
+            stats
+                .playback_delay_ns
+                .store(delay.as_nanos() as u64, Ordering::Relaxed);
+        }
+        stats.output_buffered_ns.store(
+            samples_to_ns(source.consumer.len(), &config),
+            Ordering::Relaxed,
+        );
+
+        stats.output_callbacks.fetch_add(1, Ordering::Relaxed);
+        stats
+            .output_samples
+            .fetch_add(data.len() as u64, Ordering::Relaxed);
+        stats
+            .output_callback_max_ns
+            .fetch_max(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
     }
 }
 
+/// State of sidetone monitoring, where the output callback plays the
+/// captured samples without going through the worker
+struct Sidetone {
+    /// Mute, volume and polarity applied to the samples
+    controls: Arc<Controls>,
+    /// Tone burst and metronome mixed into the samples
+    beds: Vec<Bed>,
+    /// Processed samples for the recording
+    recording: Option<TrackProducer>,
+    /// Fewest samples left waiting after a callback during the window
+    lowest: usize,
+    /// Samples played during the window
+    played: usize,
+}
+
+impl Sidetone {
+    fn new(controls: Arc<Controls>, beds: Vec<Bed>, recording: Option<TrackProducer>) -> Self {
+        Self {
+            controls,
+            beds,
+            recording,
+            lowest: usize::MAX,
+            played: 0,
+        }
+    }
+
+    /// Applies the controls and mixes the beds into the `output` taken
+    /// from the `source`, keeping the latency from building up. When
+    /// more than a callback of samples was always left waiting during a
+    /// `window` of samples the excess is skipped, as the input clock
+    /// running ahead would otherwise slowly add latency
+    fn process(&mut self, source: &mut ConsumerSignal, output: &mut [f32], window: usize) {
+        let factor = self.controls.factor();
+        if factor != 1.0 {
+            simd::scale(output, factor);
+        }
+        for bed in &mut self.beds {
+            bed.mix_into(output, self.controls.volume_factor());
+        }
+        if let Some(recording) = &mut self.recording {
+            recording.push(output);
+        }
+
+        self.lowest = self.lowest.min(source.consumer.len());
+        self.played += output.len();
+        if self.played >= window {
+            let excess = self.lowest.saturating_sub(output.len());
+            if excess > 0 {
+                source.consumer.skip(excess);
+                source
+                    .stats
+                    .dropped_samples
+                    .fetch_add(excess as u64, Ordering::Relaxed);
+            }
+            self.lowest = usize::MAX;
+            self.played = 0;
+        }
+    }
+}
+
+/// Whether the `args` ask for sidetone monitoring and the `input` and
+/// `output` configs allow the captured samples to be played as they are
+fn is_sidetone_direct(args: &Args, input: &StreamConfig, output: &StreamConfig) -> bool {
+    args.sidetone.unwrap_or_default()
+        && input.sample_rate == output.sample_rate
+        && input.channels == output.channels
+}
+
 pub enum ChannelConverter {
     /// Direct passthrough for channels of the same width
     Passthrough,
@@ -176,9 +1123,79 @@ pub enum ChannelConverter {
     /// Single channel to dual channel by duplicating the value for
     /// both channels
     MonoToStereo(Option<f32>),
+    /// Multiple channels to a single channel using a [Downmix] other
+    /// than averaging, the frame holds the samples of one input frame
+    ToMono { downmix: Downmix, frame: Vec<f32> },
+    /// Any other channel counts mixed using a matrix of gains
+    Mix(Mixer),
 }
 
 impl ChannelConverter {
+    /// Number of input samples consumed for each output sample
+    fn input_per_output(&self) -> f64 {
+        match self {
+            ChannelConverter::Passthrough => 1.0,
+            ChannelConverter::StereoToMono => 2.0,
+            ChannelConverter::MonoToStereo(_) => 0.5,
+            ChannelConverter::ToMono { frame, .. } => frame.len() as f64,
+            ChannelConverter::Mix(mixer) => {
+                mixer.matrix().inputs() as f64 / mixer.matrix().outputs() as f64
+            }
+        }
+    }
+
+    /// Fills the `output` with converted samples, whole blocks are
+    /// converted at once when the sample rates match otherwise each
+    /// sample is taken from the resampler
+    fn fill(
+        &mut self,
+        converter: &mut SampleConverter,
+        scratch: &mut Vec<f32>,
+        output: &mut [f32],
+    ) {
+        let SampleConverter::Direct(signal) = converter else {
+            output.fill_with(|| self.next(converter));
+            return;
+        };
+
+        match self {
+            ChannelConverter::Passthrough => {
+                signal.pop_into(output);
+            }
+            ChannelConverter::StereoToMono => {
+                scratch.resize(output.len() * 2, 0.0);
+                signal.pop_into(scratch);
+                simd::downmix_stereo(scratch, output);
+            }
+            // Blocks are only upmixed when whole frames are written, a
+            // sample stored between callbacks has to be written first
+            ChannelConverter::MonoToStereo(None) if output.len().is_multiple_of(2) => {
+                scratch.resize(output.len() / 2, 0.0);
+                signal.pop_into(scratch);
+                simd::upmix_mono(scratch, output);
+            }
+            ChannelConverter::ToMono { downmix, frame } => {
+                scratch.resize(output.len() * frame.len(), 0.0);
+                signal.pop_into(scratch);
+                for (frame, out) in scratch.chunks_exact(frame.len()).zip(output) {
+                    *out = downmix.apply(frame);
+                }
+            }
+            ChannelConverter::Mix(mixer)
+                if !mixer.has_pending()
+                    && output.len().is_multiple_of(mixer.matrix().outputs()) =>
+            {
+                let matrix = mixer.matrix();
+                scratch.resize(output.len() / matrix.outputs() * matrix.inputs(), 0.0);
+                signal.pop_into(scratch);
+                matrix.apply_frames(scratch, output);
+            }
+            ChannelConverter::MonoToStereo(_) | ChannelConverter::Mix(_) => {
+                output.fill_with(|| self.next(converter))
+            }
+        }
+    }
+
     fn next(&mut self, converter: &mut SampleConverter) -> f32 {
         match self {
             ChannelConverter::Passthrough => converter.next(),
@@ -199,201 +1216,1542 @@ impl ChannelConverter {
                         next
                     })
             }
+            ChannelConverter::ToMono { downmix, frame } => {
+                frame.fill_with(|| converter.next());
+                downmix.apply(frame)
+            }
+            ChannelConverter::Mix(mixer) => mixer.next(|| converter.next()),
         }
     }
 }
 
-fn start_streams(
-    input: Device,
+/// Creates the ring buffer that the input samples are pushed into and
+/// the converters that produce the output samples from it
+fn create_pipeline(
     input_config: &StreamConfig,
-    output: Device,
     output_config: &StreamConfig,
-) -> io::Result<()> {
+    downmix: Downmix,
+    stats: &Arc<StreamStats>,
+) -> (HeapProducer<f32>, ChannelConverter, SampleConverter) {
     // Create the ring buffer for the input data
     let ring: HeapRb<f32> = HeapRb::new(input_config.sample_rate.0 as usize * 2);
     let (producer, consumer) = ring.split();
 
     // Wrap the consumer for use as a signal
-    let source = ConsumerSignal(consumer);
-
-    // We need to interpolate to the target sample rate
-    let converter = Converter::from_hz_to_hz(
-        source,
-        Linear::new(Sample::EQUILIBRIUM, Sample::EQUILIBRIUM),
-        input_config.sample_rate.0 as f64,
-        output_config.sample_rate.0 as f64,
-    );
+    let source = ConsumerSignal {
+        consumer,
+        stats: stats.clone(),
+        concealment: Concealment::new(input_config.channels, input_config.sample_rate.0),
+    };
+
+    // We need to interpolate to the target sample rate if they differ
+    let converter = if input_config.sample_rate == output_config.sample_rate {
+        SampleConverter::Direct(source)
+    } else {
+        SampleConverter::Resampled(Converter::from_hz_to_hz(
+            source,
+            Linear::new(Sample::EQUILIBRIUM, Sample::EQUILIBRIUM),
+            input_config.sample_rate.0 as f64,
+            output_config.sample_rate.0 as f64,
+        ))
+    };
 
     let channel_converter: ChannelConverter = match (input_config.channels, output_config.channels)
     {
+        (inputs, outputs) if inputs == outputs => ChannelConverter::Passthrough,
         (1, 2) => ChannelConverter::MonoToStereo(None),
+        (inputs, 1) if downmix != Downmix::Average => ChannelConverter::ToMono {
+            downmix,
+            frame: vec![0.0; inputs as usize],
+        },
         (2, 1) => ChannelConverter::StereoToMono,
-        _ => ChannelConverter::Passthrough,
+        (inputs, outputs) => ChannelConverter::Mix(Mixer::new(MixMatrix::default_for(
+            inputs as usize,
+            outputs as usize,
+        ))),
     };
 
-    // Small closure for handling stream errors
-    let handle_error = |error: StreamError| eprint!("Error while streaming: {}", error);
+    (producer, channel_converter, converter)
+}
+
+/// Streams monitoring the input through the output and the state shared
+/// with their callbacks
+struct Streams {
+    input_stream: Stream,
+    output_stream: Stream,
+    /// Converts the audio between the streams until dropped, [None] for
+    /// sidetone monitoring
+    worker: Option<Worker>,
+    /// Counters updated by the stream callbacks
+    stats: Arc<StreamStats>,
+    /// Levels of the input shown by the meter
+    meter: Arc<LevelMeter>,
+    /// Mute and gain state changed by the key bindings
+    controls: Arc<Controls>,
+    /// Measures the levels and detects the glitches of the input until
+    /// dropped
+    analyzer: Analyzer,
+    /// Logs the glitches detected in the input until dropped
+    glitch_log: GlitchLog,
+    /// Writes the recording requested by the `--record` flag
+    recorder: Option<Recorder>,
+}
+
+/// Builds and plays the streams monitoring the `input` through the
+/// `output` using the options from the `args` and the remembered
+/// `settings` of the input device, the levels of the bands are recorded
+/// when there is a `band_filter`
+fn open_streams(
+    input: &NamedDevice,
+    input_config: &StreamConfig,
+    output: &NamedDevice,
+    output_config: &StreamConfig,
+    args: &Args,
+    settings: &DeviceSettings,
+    band_filter: Option<BandFilter>,
+) -> Result<Streams, ChemicError> {
+    let stats = Arc::new(StreamStats::default());
+    let meter = Arc::new(LevelMeter::new(input_config.channels));
+    let controls = Arc::new(Controls::default());
+    controls.set_inverted(args.invert.unwrap_or_default());
+    if let Some(offset_db) = settings.sensitivity_offset_db {
+        controls.set_offset_db(offset_db);
+    }
+    if let Some(volume) = args.volume {
+        controls.set_volume(volume);
+    }
+    if let Some(gain) = args.gain {
+        info!("applying {gain:+.1} dB of gain to the input");
+    }
+
+    let (producer, channel_converter, converter) = create_pipeline(
+        input_config,
+        output_config,
+        args.downmix.unwrap_or_default(),
+        &stats,
+    );
+
+    // The capture is recorded, along with the processed monitor
+    // signal when asked for
+    let mut tracks = Vec::new();
+    let mut raw_recording = None;
+    let mut processed_recording = None;
+    if let Some(template) = &args.record {
+        let started = SystemTime::now();
+        let path = &record::expand_path(template, &input.name, started);
+        let info = |description: &str| BroadcastInfo {
+            description: format!("{description} of \"{}\"", input.name),
+            device: input.name.clone(),
+            started,
+        };
+        let finishing = Finishing {
+            trim: args.trim_silence.map(|threshold_db| Trim {
+                threshold_db,
+                start: true,
+                end: true,
+            }),
+            normalize: args.normalize,
+            format: args.bits.unwrap_or_default(),
+            dither: args.dither.unwrap_or_default(),
+        };
+        if let Some(level) = args.normalize {
+            info!("normalizing the peak of the recordings to {level:.1} dBFS");
+        }
+        if let Some(threshold_db) = args.trim_silence {
+            info!("trimming the silence below {threshold_db:.1} dBFS from the recordings");
+        }
+
+        let (producer, track) = Track::create(
+            path,
+            input_config.sample_rate.0,
+            input_config.channels,
+            info("Input"),
+            args.segment,
+            finishing,
+        )?;
+        raw_recording = Some(TrackProducer::new(producer, stats.clone()));
+        tracks.push(track);
+        info!("recording the input to {}", path.display());
+
+        if args.record_processed.unwrap_or_default() {
+            let path = record::processed_path(path);
+            let (producer, track) = Track::create(
+                &path,
+                output_config.sample_rate.0,
+                output_config.channels,
+                info("Processed monitor audio"),
+                args.segment,
+                finishing,
+            )?;
+            processed_recording = Some(TrackProducer::new(producer, stats.clone()));
+            tracks.push(track);
+            info!("recording the processed audio to {}", path.display());
+        }
+    }
+    let recorder =
+        (!tracks.is_empty()).then(|| Recorder::start(tracks, stats.clone(), input_config));
+
+    // The tone burst is always ready for its key, the metronome clicks
+    // when asked for
+    let mut beds = vec![Bed::burst(controls.clone(), output_config)];
+    if let Some(bpm) = args.metronome {
+        info!("metronome clicking at {bpm} BPM");
+        beds.push(Bed::metronome(bpm, output_config));
+    }
+
+    // Sidetone takes the captured samples straight to the output
+    // callback, skipping the worker and the bed. The tone burst and the
+    // metronome are still played for judging the latency against
+    let (worker, output_source, sidetone, jitter) = match converter {
+        SampleConverter::Direct(source)
+            if is_sidetone_direct(args, input_config, output_config) =>
+        {
+            info!("monitoring as sidetone without the worker");
+            if args.bed.is_some() {
+                info!("the bed isn't mixed into sidetone monitoring");
+            }
+            if !args.processors.is_empty() {
+                info!("the processors aren't applied to sidetone monitoring");
+            }
+            let sidetone = Sidetone::new(controls.clone(), beds, processed_recording);
+            (None, source, Some(sidetone), None)
+        }
+        converter => {
+            if let Some(source) = &args.bed {
+                let level = args.bed_level.unwrap_or(bed::DEFAULT_LEVEL_DB);
+                info!("mixing {source} into the output at {level:+.1} dB");
+                beds.push(Bed::open(source, level, output_config)?);
+            }
+
+            let target = match args.jitter_buffer {
+                Some(ms) => Duration::try_from_secs_f64(ms / 1000.0)
+                    .ok()
+                    .filter(|target| !target.is_zero())
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "Jitter buffer must be a positive number of milliseconds",
+                        )
+                    })?,
+                None => jitter::DEFAULT_TARGET,
+            };
+            let jitter = JitterBuffer::new(target, output_config);
+
+            let processors = args
+                .processors
+                .iter()
+                .map(|spec| {
+                    info!("processing the output with {spec}");
+                    spec.build(output_config)
+                })
+                .collect::<io::Result<_>>()?;
+
+            // Conversion runs on the worker, the output callback only copies
+            let effects = Effects {
+                processors,
+                controls: controls.clone(),
+                beds,
+                recording: processed_recording,
+            };
+            let (worker, output_consumer) = Worker::start(
+                channel_converter,
+                converter,
+                input_config,
+                output_config,
+                effects,
+                jitter.target(),
+            );
+            let output_source = ConsumerSignal {
+                consumer: output_consumer,
+                stats: stats.clone(),
+                concealment: Concealment::new(output_config.channels, output_config.sample_rate.0),
+            };
+            (Some(worker), output_source, None, Some(jitter))
+        }
+    };
+
+    let ceiling_db = args.ceiling.unwrap_or(ceiling::DEFAULT_CEILING_DB);
+    info!("output ceiling at {ceiling_db:.1} dBFS");
+    let ceiling = Ceiling::new(ceiling_db, output_config);
+
+    let (glitches, glitch_consumer) = glitch::channel();
+    let glitch_log = GlitchLog::start(glitch_consumer, input_config.sample_rate.0);
+    let (analyzer, analysis) = Analyzer::start(
+        input_config,
+        stats.clone(),
+        meter.clone(),
+        Analysis {
+            gain: meter::from_db(args.gain.unwrap_or_default()),
+            weighting: args.weighting.unwrap_or_default(),
+            band_filter,
+        },
+        glitches,
+    );
+
+    // Small closure for creating the stream error handlers
+    let handle_error = |ty: DeviceType| {
+        let stats = stats.clone();
+        move |error: StreamError| {
+            error!("{ty} stream error: {error} ({error:?})");
+            stats.stream_errors.fetch_add(1, Ordering::Relaxed);
+            if let StreamError::DeviceNotAvailable = error {
+                events::emit(Event::DeviceLost {
+                    device: ty,
+                    message: error.to_string(),
+                });
+            }
+            eprint!("Error while streaming: {}", error)
+        }
+    };
+
+    debug!(
+        "building output stream: {}ch {}Hz buffer {:?}",
+        output_config.channels, output_config.sample_rate.0, output_config.buffer_size
+    );
+    debug!(
+        "building input stream: {}ch {}Hz buffer {:?}",
+        input_config.channels, input_config.sample_rate.0, input_config.buffer_size
+    );
 
     // Build the streams
-    let output_stream = output
-        .build_output_stream(
+    let output_stream = {
+        let _span = span!("build_output_stream");
+        output
+            .device
+            .build_output_stream(
+                output_config,
+                create_output_callback(
+                    output_source,
+                    output_config,
+                    stats.clone(),
+                    sidetone,
+                    jitter,
+                    ceiling,
+                ),
+                handle_error(DeviceType::Output),
+                None,
+            )
+            .map_err(|source| ChemicError::BuildStream {
+                ty: DeviceType::Output,
+                device: output.name.clone(),
+                source,
+            })?
+    };
+
+    let input_stream = {
+        let _span = span!("build_input_stream");
+        input
+            .device
+            .build_input_stream(
+                input_config,
+                create_producer_callback(
+                    producer,
+                    input_config,
+                    stats.clone(),
+                    meter::from_db(args.gain.unwrap_or_default()),
+                    CaptureTaps {
+                        analysis,
+                        recording: raw_recording,
+                    },
+                ),
+                handle_error(DeviceType::Input),
+                None,
+            )
+            .map_err(|source| ChemicError::BuildStream {
+                ty: DeviceType::Input,
+                device: input.name.clone(),
+                source,
+            })?
+    };
+
+    // Play the streams
+    output_stream.play()?;
+    input_stream.play()?;
+
+    // Periodically log the callback stats
+    if log::enabled(log::Level::Debug) {
+        let stats = stats.clone();
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(5));
+            debug!("{}", stats.summary());
+        });
+    }
+
+    Ok(Streams {
+        input_stream,
+        output_stream,
+        worker,
+        stats,
+        meter,
+        controls,
+        analyzer,
+        glitch_log,
+        recorder,
+    })
+}
+
+/// Outcome of monitoring until [handle_keys] returns
+enum Outcome {
+    /// The stop key was pressed
+    Stopped,
+    /// The output kept running out of audio, the streams are rebuilt with
+    /// a larger buffer
+    Starved,
+}
+
+/// Seconds with underruns out of the last [STARVED_WINDOW] seconds before
+/// the streams are rebuilt with a larger buffer
+const STARVED_SECONDS: u32 = 3;
+/// Number of the latest seconds checked for underruns
+const STARVED_WINDOW: u32 = 10;
+/// Largest buffer in frames the streams grow to
+const MAX_GROWN_BUFFER: u32 = 8192;
+
+fn start_streams(
+    input: NamedDevice,
+    input_config: &StreamConfig,
+    output: NamedDevice,
+    output_config: &StreamConfig,
+    args: &Args,
+    meter_mode: Option<MeterMode>,
+    config: &Config,
+) -> Result<(), ChemicError> {
+    let quiet = args.quiet;
+    let keys = &config.keys;
+    // Remembered settings of the input device are applied automatically
+    let settings = config.device(&input.name).cloned().unwrap_or_default();
+    let mut configs = (input_config.clone(), output_config.clone());
+    let mut key_actions = None;
+
+    loop {
+        let (input_config, output_config) = (&configs.0, &configs.1);
+        // Bands are only analysed when they are displayed
+        let band_filter = matches!(meter_mode, Some(MeterMode::Bands(_)))
+            .then(|| BandFilter::new(input_config.sample_rate.0, input_config.channels));
+
+        let Streams {
+            input_stream,
+            output_stream,
+            worker,
+            stats,
+            meter,
+            controls,
+            analyzer,
+            glitch_log,
+            recorder,
+        } = open_streams(
+            &input,
+            input_config,
+            &output,
             output_config,
-            create_converter_callback(channel_converter, converter),
-            handle_error,
-            None,
-        )
-        .map_err(io::Error::other)?;
+            args,
+            &settings,
+            band_filter,
+        )?;
+
+        // Keys are read once the streams first start, so failing to open
+        // them leaves the terminal free for choosing other devices
+        let first = key_actions.is_none();
+        let actions = key_actions.get_or_insert_with(|| read_actions(keys.clone()));
+
+        if !quiet && first {
+            println!("{}", tr(Message::Playing));
+            if let Some(recorder) = &recorder {
+                let paths: Vec<String> = recorder
+                    .paths()
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect();
+                println!("{}: {}", tr(Message::Recording), paths.join(", "));
+            }
+            println!("{}", describe_controls(keys, recorder.is_some()));
+        }
 
-    let input_stream = input
-        .build_input_stream(
+        info!("started monitoring");
+        events::emit(Event::Started {
+            input: &input.name,
             input_config,
-            create_producer_callback(producer),
-            handle_error,
-            None,
-        )
-        .map_err(io::Error::other)?;
+            output: &output.name,
+            output_config,
+        });
 
-    // Play the streams
-    output_stream.play().map_err(io::Error::other)?;
-    input_stream.play().map_err(io::Error::other)?;
+        let scale = MeterScale {
+            calibration: settings.calibration,
+            weighting: args.weighting.unwrap_or_default(),
+        };
+        let display = meter_mode
+            .clone()
+            .map(|mode| MeterDisplay::start(meter.clone(), stats.clone(), mode, scale));
+        let reporter = events::enabled().then(|| EventReporter::start(meter, stats.clone()));
 
-    println!("Playing microphone through output device...");
-    println!("Press the ESCAPE or BACKSPACE key to stop..");
+        // Rebuilding the streams would restart the recording, so the
+        // buffers only grow when nothing is recorded
+        let larger = match recorder {
+            Some(_) => None,
+            None => larger_configs(&input, &output, args, output_config)?,
+        };
 
-    // Wait for the stop key
-    while !stop_key_pressed() {}
+        // Handle the control keys until the stop key is pressed
+        let result = handle_keys(
+            actions,
+            &controls,
+            (&input_stream, &output_stream),
+            &input,
+            recorder.as_ref(),
+            larger.as_ref().map(|_| &*stats),
+            quiet,
+        );
 
-    Ok(())
+        drop(worker);
+
+        if let Some(display) = display {
+            display.stop();
+        }
+        if let Some(reporter) = reporter {
+            reporter.stop();
+        }
+
+        drop(analyzer);
+        drop(glitch_log);
+        // The recording is finished even when monitoring failed
+        let recorded = recorder.map(Recorder::finish).transpose();
+
+        info!("stopped monitoring, {}", stats.summary());
+        let overflow = stats.recording_overflow.load(Ordering::Relaxed);
+        if overflow > 0 {
+            error!("{overflow} samples didn't fit in the recording buffers and are missing from the recordings");
+            events::emit(Event::RecordingOverflow { samples: overflow });
+            if !quiet {
+                println!(
+                    "{}: {} ({overflow})",
+                    tr(Message::Warning),
+                    tr(Message::RecordingOverflow)
+                );
+            }
+        }
+        events::emit(Event::Stopped { stats: &stats });
+
+        if let (Ok(Outcome::Starved), Some(larger)) = (&result, larger) {
+            let frames = match larger.1.buffer_size {
+                BufferSize::Fixed(frames) => frames,
+                BufferSize::Default => 0,
+            };
+            let buffer_ms = frames as f64 / larger.1.sample_rate.0.max(1) as f64 * 1000.0;
+            info!("output kept running out of audio, rebuilding the streams with a buffer of {frames} frames");
+            if !quiet {
+                println!(
+                    "{}: {} ({buffer_ms:.1} ms)",
+                    tr(Message::Warning),
+                    tr(Message::BufferGrown)
+                );
+            }
+            configs = larger;
+            continue;
+        }
+
+        if !quiet {
+            if let Some(latency) = stats.latency() {
+                println!("{}: {latency}", tr(Message::Latency));
+            }
+            println!(
+                "{}: {} {}, {} {}",
+                tr(Message::Glitches),
+                stats.dropouts.load(Ordering::Relaxed),
+                tr(Message::Dropouts),
+                stats.sample_jumps.load(Ordering::Relaxed),
+                tr(Message::SampleJumps)
+            );
+        }
+
+        result?;
+        recorded?;
+        return Ok(());
+    }
 }
 
-/// Reads a input from the terminal, returns whether the
-/// provided input matches a stop key
-fn stop_key_pressed() -> bool {
-    let key = Term::stderr().read_key().expect("Failed to read stop key");
-    matches!(key, Key::Escape | Key::Backspace | Key::Del | Key::CtrlC)
+/// Stream configs with twice the buffer of the `current` output config,
+/// [None] when the buffer can't grow any further
+fn larger_configs(
+    input: &NamedDevice,
+    output: &NamedDevice,
+    args: &Args,
+    current: &StreamConfig,
+) -> Result<Option<(StreamConfig, StreamConfig)>, ChemicError> {
+    // Delayed audio already uses the largest buffers
+    if args.delay.unwrap_or_default() {
+        return Ok(None);
+    }
+    let BufferSize::Fixed(frames) = current.buffer_size else {
+        return Ok(None);
+    };
+
+    let mut args = args.clone();
+    args.buffer_size = Some(frames.saturating_mul(2).min(MAX_GROWN_BUFFER));
+    let configs = stream_configs(input, output, &args)?;
+    Ok((configs.1.buffer_size != current.buffer_size).then_some(configs))
+}
+
+/// Describes the keys bound to each of the controls
+/// (e.g. "Controls: Escape/Backspace stop, m mute"), markers are only
+/// described when `recording`
+fn describe_controls(keys: &KeyBindings, recording: bool) -> String {
+    let controls: Vec<String> = Action::ALL
+        .into_iter()
+        .filter(|action| recording || *action != Action::Marker)
+        .map(|action| {
+            let name = match action {
+                Action::Stop => Message::Stop,
+                Action::Mute => Message::Mute,
+                Action::Pause => Message::Pause,
+                Action::VolumeUp => Message::VolumeUp,
+                Action::VolumeDown => Message::VolumeDown,
+                Action::Marker => Message::AddMarker,
+                Action::ToneBurst => Message::ToneBurst,
+                Action::OsGainUp => Message::OsGainUp,
+                Action::OsGainDown => Message::OsGainDown,
+            };
+            format!("{} {}", keys.describe(action), tr(name))
+        })
+        .collect();
+
+    format!("{}: {}", tr(Message::Controls), controls.join(", "))
+}
+
+/// Reads keys from the terminal on a thread of its own, sending the bound
+/// actions until a stop key is pressed. When stdin isn't a terminal (e.g.
+/// when run by another program) actions are instead read from stdin as
+/// lines containing the action names, monitoring stops at the end of stdin
+fn read_actions(keys: KeyBindings) -> mpsc::Receiver<io::Result<Action>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let term = Term::stderr();
+        let interactive = io::stdin().is_terminal();
+
+        let next_action = || -> io::Result<Option<Action>> {
+            if interactive {
+                return Ok(keys.action(&term.read_key()?));
+            }
+
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line)? == 0 {
+                return Ok(Some(Action::Stop));
+            }
+            Ok(Action::from_config_key(line.trim()))
+        };
+
+        loop {
+            let action = match next_action() {
+                Ok(Some(action)) => Ok(action),
+                Ok(None) => continue,
+                Err(err) => Err(err),
+            };
+            let stop = !matches!(action, Ok(action) if action != Action::Stop);
+            if sender.send(action).is_err() || stop {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Performs the `actions` read from the keys on the streams of the
+/// `input` device until a stop key is pressed. Given the `stats` of
+/// streams that can grow their buffers, returns early once the output
+/// keeps running out of audio
+fn handle_keys(
+    actions: &mpsc::Receiver<io::Result<Action>>,
+    controls: &Controls,
+    (input_stream, output_stream): (&Stream, &Stream),
+    input: &NamedDevice,
+    recorder: Option<&Recorder>,
+    stats: Option<&StreamStats>,
+    quiet: bool,
+) -> Result<Outcome, ChemicError> {
+    const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+    let term = Term::stderr();
+    let mut paused = false;
+    // Whether each of the latest seconds had underruns, the latest in the
+    // lowest bit
+    let mut starved: u32 = 0;
+    let mut underruns: Option<u64> = None;
+    let mut last_check = Instant::now();
+
+    // Status messages replace the meter line which is redrawn after
+    let status = |message: &str| -> io::Result<()> {
+        if quiet {
+            return Ok(());
+        }
+        term.clear_line()?;
+        term.write_line(message)
+    };
+
+    loop {
+        if let Some(stats) = stats {
+            if last_check.elapsed() >= CHECK_INTERVAL {
+                last_check = Instant::now();
+                let current = stats.underrun_samples.load(Ordering::Relaxed);
+                // The first second after starting or resuming is skipped
+                // while the streams fill up
+                let previous = underruns.replace(current);
+                if paused {
+                    underruns = None;
+                } else if let Some(previous) = previous {
+                    starved = (starved << 1 | u32::from(current > previous))
+                        & ((1 << STARVED_WINDOW) - 1);
+                    if starved.count_ones() >= STARVED_SECONDS {
+                        return Ok(Outcome::Starved);
+                    }
+                }
+            }
+        }
+
+        let action = match actions.recv_timeout(CHECK_INTERVAL) {
+            Ok(action) => action?,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(Outcome::Stopped),
+        };
+
+        match action {
+            Action::Stop => return Ok(Outcome::Stopped),
+            Action::Mute => {
+                let muted = controls.toggle_mute();
+                info!("output muted: {muted}");
+                status(tr(if muted {
+                    Message::Muted
+                } else {
+                    Message::Unmuted
+                }))?;
+            }
+            Action::Pause => {
+                paused = !paused;
+                if paused {
+                    input_stream.pause()?;
+                    output_stream.pause()?;
+                } else {
+                    output_stream.play()?;
+                    input_stream.play()?;
+                }
+                info!("streams paused: {paused}");
+                status(tr(if paused {
+                    Message::Paused
+                } else {
+                    Message::Resumed
+                }))?;
+            }
+            Action::VolumeUp | Action::VolumeDown => {
+                let volume = controls.step_volume(action == Action::VolumeUp);
+                info!("output volume: {volume:+.1} dB");
+                status(&format!("{}: {volume:+.1} dB", tr(Message::Volume)))?;
+            }
+            Action::OsGainUp | Action::OsGainDown => {
+                match os_gain::step_input_gain(&input.name, action == Action::OsGainUp) {
+                    Ok(gain) => {
+                        info!("OS input gain: {gain}");
+                        status(&format!("{}: {gain}", tr(Message::OsGain)))?;
+                    }
+                    Err(err) => {
+                        error!("failed to change the OS input gain: {err}");
+                        status(&format!("{}: {err}", tr(Message::Error)))?;
+                    }
+                }
+            }
+            Action::ToneBurst => {
+                controls.request_burst();
+                info!("tone burst played");
+                status(tr(Message::TonePlayed))?;
+            }
+            Action::Marker => {
+                let Some(recorder) = recorder else {
+                    status(tr(Message::NotRecording))?;
+                    continue;
+                };
+                let marker = recorder.mark();
+                let position = marker.position.as_secs_f64();
+                info!("marker {} at {position:.3}s", marker.number);
+                status(&format!(
+                    "{} {}: {:.0}:{:06.3}",
+                    tr(Message::Marker),
+                    marker.number,
+                    (position / 60.0).floor(),
+                    position % 60.0
+                ))?;
+            }
+        }
+    }
 }
 
 /// [Signal] implementation for producing frames from a [HeapConsumer]
 /// allowing it to be used as a signal to convert values from
 /// the consumer between Hz values.
 ///
-/// Will produce silence when the consumer has no values to produce
-struct ConsumerSignal(HeapConsumer<f32>);
+/// Will fade to silence when the consumer has no values to produce
+struct ConsumerSignal {
+    /// Consumer to take the values from
+    consumer: HeapConsumer<f32>,
+    /// Stats to record underruns in
+    stats: Arc<StreamStats>,
+    /// Fades around the underruns
+    concealment: Concealment,
+}
+
+impl ConsumerSignal {
+    /// Fills the `output` with the next values from the consumer, any
+    /// values that aren't available are concealed by fading to silence.
+    /// Returns whether the consumer ran out
+    fn pop_into(&mut self, output: &mut [f32]) -> bool {
+        let popped = self.consumer.pop_slice(output);
+        self.concealment.play_slice(&mut output[..popped]);
+        if popped < output.len() {
+            output[popped..].fill_with(|| self.concealment.conceal());
+            self.stats
+                .underrun_samples
+                .fetch_add((output.len() - popped) as u64, Ordering::Relaxed);
+        }
+        popped < output.len()
+    }
+}
 
 impl Signal for ConsumerSignal {
     type Frame = f32;
 
     fn next(&mut self) -> Self::Frame {
-        self.0
-            .pop()
-            // Use silence if no more values are available
-            .unwrap_or(Sample::EQUILIBRIUM)
+        match self.consumer.pop() {
+            Some(sample) => self.concealment.play(sample),
+            // Fade to silence if no more values are available
+            None => {
+                self.stats.underrun_samples.fetch_add(1, Ordering::Relaxed);
+                self.concealment.conceal()
+            }
+        }
     }
 }
 
 /// [Device] with an additional name that has already been
 /// determined, might be a generic name like "Default" or "Unknown"
-struct NamedDevice {
+#[derive(Clone)]
+struct NamedDevice<D = Device> {
     /// The device itself
-    device: Device,
+    device: D,
     /// The name of the device
     name: String,
+    /// Whether the device is an output device captured as an input,
+    /// recording what is played through it
+    loopback: bool,
+    /// Whether the device is the entry for the default device, named
+    /// "Default (...)"
+    default: bool,
 }
 
-impl NamedDevice {
+impl<D: AudioDevice> NamedDevice<D> {
     /// Creates a new named device from the provided device, wraps
     /// the device name with "Default" to indicate its a default
     /// device
-    fn from_default(device: Device) -> Self {
+    fn from_default(device: D) -> Self {
         let mut device = NamedDevice::from(device);
         device.name = format!("Default ({})", device.name);
+        device.default = true;
         device
     }
+
+    /// Name the device is found again by with [find_device], the entry
+    /// for the default device is "default" so it follows the default
+    /// device rather than its name at the time
+    fn lookup_name(&self) -> &str {
+        match self.default {
+            true => "default",
+            false => &self.name,
+        }
+    }
+
+    /// Creates the loopback capture of the provided output `device`
+    fn loopback(device: NamedDevice<D>) -> Self {
+        Self {
+            name: format!("{} (loopback)", device.name),
+            loopback: true,
+            ..device
+        }
+    }
+
+    /// Type of device the configs are loaded as when the device is used
+    /// as the provided `ty`, a loopback capture uses the configs of the
+    /// output it captures
+    fn config_type(&self, ty: DeviceType) -> DeviceType {
+        match self.loopback {
+            true => DeviceType::Output,
+            false => ty,
+        }
+    }
+
+    /// Obtains the default config and all of the supported config
+    /// ranges of the device when used as the provided `ty` of device
+    fn supported_configs(
+        &self,
+        ty: DeviceType,
+    ) -> (
+        Result<SupportedStreamConfig, DefaultStreamConfigError>,
+        Result<Vec<SupportedStreamConfigRange>, SupportedStreamConfigsError>,
+    ) {
+        let ty = self.config_type(ty);
+        (
+            self.device.default_config(ty),
+            self.device.config_ranges(ty),
+        )
+    }
+
+    /// Describes the capabilities of the device when used as the
+    /// provided `ty` of device on the host named `host_name`
+    /// (e.g. "48 kHz, 2ch, ALSA")
+    fn describe(&self, ty: DeviceType, host_name: &str) -> String {
+        match self.device.default_config(self.config_type(ty)) {
+            Ok(config) => format!(
+                "{}, {}ch, {host_name}",
+                format_sample_rate(config.sample_rate()),
+                config.channels()
+            ),
+            Err(_) => format!("unknown config, {host_name}"),
+        }
+    }
 }
 
-impl From<Device> for NamedDevice {
-    fn from(device: Device) -> Self {
+/// Formats the `sample_rate` in kHz (e.g. "44.1 kHz")
+fn format_sample_rate(sample_rate: SampleRate) -> String {
+    format!("{} kHz", sample_rate.0 as f64 / 1000.)
+}
+
+impl<D: AudioDevice> From<D> for NamedDevice<D> {
+    fn from(device: D) -> Self {
         let name = device
-            .name()
+            .device_name()
             // Default "Unknown" name when name cannot be determined
             .unwrap_or_else(|_| "Unknown".to_string());
-        Self { device, name }
+        Self {
+            device,
+            name,
+            loopback: false,
+            default: false,
+        }
     }
 }
 
 /// Type of a [Device]
-#[derive(Clone, Copy)]
-enum DeviceType {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DeviceType {
     /// Input device
     Input,
     /// Output device
     Output,
 }
 
+impl fmt::Display for DeviceType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DeviceType::Input => "input",
+            DeviceType::Output => "output",
+        })
+    }
+}
+
 /// Finds the default device for the provided `ty` on the `host`
 /// will return [None] if it was unable to find one
-fn get_default_device(host: &Host, ty: DeviceType) -> Option<NamedDevice> {
-    // Type bounds for the default device fn
-    type DefaultDeviceFn = fn(&Host) -> Option<Device>;
+fn get_default_device<H: AudioHost>(host: &H, ty: DeviceType) -> Option<NamedDevice<H::Device>> {
+    host.default_device(ty).map(NamedDevice::from_default)
+}
 
-    let default_device: DefaultDeviceFn = match ty {
-        DeviceType::Input => Host::default_input_device,
-        DeviceType::Output => Host::default_output_device,
-    };
+/// Finds all devices that match the provided `ty` on the `host`
+fn list_devices<H: AudioHost>(
+    host: &H,
+    ty: DeviceType,
+) -> Result<Vec<NamedDevice<H::Device>>, ChemicError> {
+    let _span = span!("list_devices");
+
+    let devices = host
+        .devices(ty)
+        .map_err(|source| ChemicError::LoadDevices { ty, source })?;
+    Ok(devices.into_iter().map(NamedDevice::from).collect())
+}
+
+/// Finds the loopback captures of the output devices on the `host`, only
+/// WASAPI records what is played through an output device opened as an
+/// input
+fn loopback_devices<H: AudioHost>(host: &H) -> Result<Vec<NamedDevice<H::Device>>, ChemicError> {
+    if !host.captures_outputs() {
+        return Ok(Vec::new());
+    }
+
+    let devices = list_devices(host, DeviceType::Output)?;
+    Ok(devices.into_iter().map(NamedDevice::loopback).collect())
+}
 
-    default_device(host).map(NamedDevice::from_default)
+/// Finds all devices that can be selected as the provided `ty` on the
+/// `host`, the inputs include the loopback captures of the outputs
+fn selectable_devices<H: AudioHost>(
+    host: &H,
+    ty: DeviceType,
+) -> Result<Vec<NamedDevice<H::Device>>, ChemicError> {
+    let mut devices = list_devices(host, ty)?;
+    if ty == DeviceType::Input {
+        devices.extend(loopback_devices(host)?);
+    }
+    Ok(devices)
 }
 
 /// Finds all devices that match the provided `ty` on the `host`
 /// includes a duplicate of the default device
-fn get_devices(host: &Host, ty: DeviceType) -> Vec<NamedDevice> {
-    // Type alias for the filtered device iterator
-    type DevicesFiltered = std::iter::Filter<Devices, fn(&Device) -> bool>;
-    // Type bounds for the devices fn
-    type DevicesFn = fn(&Host) -> Result<DevicesFiltered, DevicesError>;
-
-    // Determine the function for getting the devices of the provided type
-    let devices_fn: DevicesFn = match ty {
-        DeviceType::Input => Host::input_devices,
-        DeviceType::Output => Host::output_devices,
-    };
+fn get_devices<H: AudioHost>(
+    host: &H,
+    ty: DeviceType,
+) -> Result<Vec<NamedDevice<H::Device>>, ChemicError> {
+    let devices = selectable_devices(host, ty)?;
 
     // Include the default device as the first device
-    get_default_device(host, ty)
+    Ok(get_default_device(host, ty)
         .into_iter()
         // Include all other devices (Duplicate of default device)
-        .chain(
-            devices_fn(host)
-                .expect("Unable to load devices")
-                .map(NamedDevice::from),
-        )
-        .collect()
+        .chain(devices)
+        .collect())
+}
+
+/// Finds the device matching the provided `ty` on the `host` with
+/// the provided `name`. Exact name matches are preferred over case
+/// insensitive matches, the name "default" refers to the default device
+fn find_device<H: AudioHost>(
+    host: &H,
+    ty: DeviceType,
+    name: &str,
+) -> Result<NamedDevice<H::Device>, ChemicError> {
+    let not_found = || ChemicError::DeviceNotFound {
+        ty,
+        name: name.to_string(),
+    };
+
+    if name.eq_ignore_ascii_case("default") {
+        return get_default_device(host, ty).ok_or_else(not_found);
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Some(identifier) = name.strip_prefix(alsa_device::PREFIX) {
+        let listed = alsa_device::listed_name(identifier);
+        debug!("looking up ALSA device \"{identifier}\" as \"{listed}\"");
+        return list_devices(host, ty)?
+            .into_iter()
+            .find(|device| device.name == listed)
+            .ok_or_else(not_found);
+    }
+
+    let mut devices = selectable_devices(host, ty)?;
+
+    let index = devices
+        .iter()
+        .position(|device| device.name == name)
+        .or_else(|| {
+            devices
+                .iter()
+                .position(|device| device.name.eq_ignore_ascii_case(name))
+        })
+        .ok_or_else(not_found)?;
+
+    let device = devices.swap_remove(index);
+    debug!("matched {ty} device \"{name}\" to \"{}\"", device.name);
+    Ok(device)
+}
+
+/// Finds the `ty` device on the `host` last chosen from the device
+/// prompt as remembered in the config `dir`, [None] when no device was
+/// chosen before or it is unavailable
+fn find_last_device<H: AudioHost>(
+    host: &H,
+    dir: &Path,
+    ty: DeviceType,
+) -> Option<NamedDevice<H::Device>> {
+    let name = config::last_device(dir, ty)?;
+    find_device(host, ty, &name)
+        .inspect_err(|err| debug!("last {ty} device is unavailable: {err}"))
+        .ok()
+}
+
+/// Finds the first `ty` device on the `host` whose name matches the
+/// `pattern`
+fn match_device<H: AudioHost>(
+    host: &H,
+    ty: DeviceType,
+    pattern: &DevicePattern,
+) -> Result<NamedDevice<H::Device>, ChemicError> {
+    let mut devices = selectable_devices(host, ty)?;
+    let index = devices
+        .iter()
+        .position(|device| pattern.is_match(&device.name))
+        .ok_or_else(|| ChemicError::NoDeviceMatches {
+            ty,
+            pattern: pattern.to_string(),
+        })?;
+
+    let device = devices.swap_remove(index);
+    debug!(
+        "matched {ty} device pattern \"{pattern}\" to \"{}\"",
+        device.name
+    );
+    Ok(device)
 }
 
 /// Prompts the user for a device using the provided `prompt` shows
-/// only devices matching the provided `ty` on the `host`
-fn prompt_device(host: &Host, prompt: &str, ty: DeviceType) -> io::Result<NamedDevice> {
+/// only devices matching the provided `ty` on the `host` styled using
+/// the `theme`, `plain` uses an uncolored line based prompt instead
+fn prompt_device<H: AudioHost>(
+    host: &H,
+    prompt: &str,
+    ty: DeviceType,
+    theme: &UiTheme,
+    plain: bool,
+) -> Result<NamedDevice<H::Device>, ChemicError> {
     // Get all available devices
-    let mut devices: Vec<NamedDevice> = get_devices(host, ty);
+    let mut devices = get_devices(host, ty)?;
 
     // Handle no devices
     if devices.is_empty() {
-        return Err(io::Error::other("No devices available"));
+        return Err(ChemicError::NoDevices(ty));
+    }
+
+    // The device chosen last time is offered first
+    let config_dir = config::config_dir();
+    if let Some(last) = config_dir
+        .as_deref()
+        .and_then(|dir| config::last_device(dir, ty))
+    {
+        if let Some(index) = devices
+            .iter()
+            .position(|device| device.lookup_name() == last)
+        {
+            let device = devices.remove(index);
+            devices.insert(0, device);
+        }
     }
 
-    // Collect the device names
+    // Collect the device names and their capabilities
     let device_names: Vec<&str> = devices.iter().map(|device| device.name.as_str()).collect();
+    let host_name = host.host_name();
+    let device_details: Vec<String> = devices
+        .iter()
+        .map(|device| device.describe(ty, host_name))
+        .collect();
 
-    // Create the selection prompt
-    let theme = ColorfulTheme::default();
-    let index = Select::with_theme(&theme)
+    // Create the selection prompt, typing filters the devices
+    let prompt_theme = theme.prompt_theme();
+    let theme: &dyn Theme = if plain { &SimpleTheme } else { &prompt_theme };
+    let index = FuzzySelect::with_theme(theme)
         .with_prompt(prompt)
+        .plain(plain)
         .default(0)
         .report(true)
         .items(&device_names)
+        .details(&device_details)
         .interact()
-        .map_err(io::Error::other)?;
+        .map_err(ChemicError::Prompt)?;
     let device = devices.remove(index);
 
+    match config_dir {
+        Some(dir) => {
+            if let Err(err) = config::save_last_device(&dir, ty, device.lookup_name()) {
+                debug!("failed to remember the last {ty} device: {err}");
+            }
+        }
+        None => debug!("no config directory to remember the last {ty} device in"),
+    }
+
     Ok(device)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use backend::{
+        mock::{range, MockDevice, MockHost},
+        AudioStream,
+    };
+    use cpal::SampleFormat;
+
+    fn device(name: &str) -> MockDevice {
+        MockDevice::new(name, vec![range(2, 44100, 48000, SampleFormat::F32)])
+            .with_default_rate(48000)
+    }
+
+    fn host() -> MockHost {
+        MockHost {
+            inputs: vec![device("USB Microphone"), device("Webcam")],
+            outputs: vec![device("Headphones")],
+            default_input: Some(1),
+            default_output: Some(0),
+            ..MockHost::default()
+        }
+    }
+
+    #[test]
+    fn find_device_prefers_exact_names() {
+        let mut host = host();
+        host.inputs.push(device("usb microphone"));
+
+        let found = find_device(&host, DeviceType::Input, "usb microphone").unwrap();
+        assert_eq!(found.name, "usb microphone");
+        let found = find_device(&host, DeviceType::Input, "USB MICROPHONE").unwrap();
+        assert_eq!(found.name, "USB Microphone");
+    }
+
+    #[test]
+    fn find_device_default_and_missing() {
+        let host = host();
+
+        let found = find_device(&host, DeviceType::Input, "default").unwrap();
+        assert_eq!(found.name, "Default (Webcam)");
+        assert!(matches!(
+            find_device(&host, DeviceType::Output, "Speakers"),
+            Err(ChemicError::DeviceNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn last_device_finds_the_picked_default_entry() {
+        let dir = std::env::temp_dir().join(format!("chemic-last-{}", std::process::id()));
+
+        let mut host = host();
+        let picked = get_devices(&host, DeviceType::Input).unwrap().remove(0);
+        assert_eq!(picked.name, "Default (Webcam)");
+        config::save_last_device(&dir, DeviceType::Input, picked.lookup_name()).unwrap();
+
+        let found = find_last_device(&host, &dir, DeviceType::Input);
+        assert_eq!(found.unwrap().name, "Default (Webcam)");
+
+        // The saved entry follows the default device when it changes
+        host.default_input = Some(0);
+        let found = find_last_device(&host, &dir, DeviceType::Input);
+        assert_eq!(found.unwrap().name, "Default (USB Microphone)");
+        assert!(find_last_device(&host, &dir, DeviceType::Output).is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn match_device_uses_the_first_match() {
+        let host = host();
+        let pattern: DevicePattern = "cam|mic".parse().unwrap();
+
+        let found = match_device(&host, DeviceType::Input, &pattern).unwrap();
+        assert_eq!(found.name, "USB Microphone");
+        let pattern: DevicePattern = "^web".parse().unwrap();
+        let found = match_device(&host, DeviceType::Input, &pattern).unwrap();
+        assert_eq!(found.name, "Webcam");
+    }
+
+    #[test]
+    fn loopback_inputs_follow_the_host() {
+        let mut host = host();
+        let names = |host: &MockHost| -> Vec<String> {
+            selectable_devices(host, DeviceType::Input)
+                .unwrap()
+                .into_iter()
+                .map(|device| device.name)
+                .collect()
+        };
+        assert_eq!(names(&host), ["USB Microphone", "Webcam"]);
+
+        host.captures_outputs = true;
+        assert_eq!(
+            names(&host),
+            ["USB Microphone", "Webcam", "Headphones (loopback)"]
+        );
+        let loopback = find_device(&host, DeviceType::Input, "Headphones (loopback)").unwrap();
+        assert_eq!(loopback.config_type(DeviceType::Input), DeviceType::Output);
+    }
+
+    #[test]
+    fn requested_device_uses_the_first_present_preferred_device() {
+        let host = host();
+        let args = Args {
+            preferred_inputs: vec!["Studio Mic".to_string(), "usb microphone".to_string()],
+            ..Args::default()
+        };
+
+        let found = requested_device(&host, &args, DeviceType::Input, false).unwrap();
+        assert_eq!(found.unwrap().name, "USB Microphone");
+    }
+
+    #[test]
+    fn requested_device_prefers_the_default_flag_over_preferred_devices() {
+        let host = host();
+        let args = Args {
+            default: true,
+            preferred_inputs: vec!["USB Microphone".to_string()],
+            ..Args::default()
+        };
+
+        let found = requested_device(&host, &args, DeviceType::Input, false).unwrap();
+        assert_eq!(found.unwrap().name, "Default (Webcam)");
+    }
+
+    #[test]
+    fn requested_device_without_prompting_uses_the_default() {
+        let mut host = host();
+        let args = Args::default();
+        assert!(requested_device(&host, &args, DeviceType::Input, true)
+            .unwrap()
+            .is_none());
+
+        let args = Args {
+            non_interactive: true,
+            ..Args::default()
+        };
+        let found = requested_device(&host, &args, DeviceType::Input, true).unwrap();
+        assert_eq!(found.unwrap().name, "Default (Webcam)");
+
+        host.default_input = None;
+        assert!(matches!(
+            requested_device(&host, &args, DeviceType::Input, true),
+            Err(ChemicError::NoDeviceRequested(DeviceType::Input))
+        ));
+    }
+
+    #[test]
+    fn requested_device_reports_unknown_names() {
+        let host = host();
+        let args = Args {
+            input: Some("Studio Mic".to_string()),
+            ..Args::default()
+        };
+
+        // Prompted for again when interactive
+        assert!(requested_device(&host, &args, DeviceType::Input, true)
+            .unwrap()
+            .is_none());
+        assert!(requested_device(&host, &args, DeviceType::Input, false).is_err());
+    }
+
+    /// Passes the `captured` samples from a mock input with the
+    /// `input_config` through the pipeline to a mock output with the
+    /// `output_config`, returning the `played` samples
+    fn run_pipeline(
+        captured: Vec<f32>,
+        input_config: &StreamConfig,
+        output_config: &StreamConfig,
+        played: usize,
+    ) -> Vec<f32> {
+        let stats = Arc::new(StreamStats::default());
+        let (mut producer, mut channel_converter, mut converter) =
+            create_pipeline(input_config, output_config, Downmix::Average, &stats);
+
+        let mut input = device("Input");
+        input.capture = Arc::new(captured);
+        let input_stream = input
+            .open_input(
+                input_config,
+                move |data| {
+                    producer.push_slice(data);
+                },
+                |_| {},
+            )
+            .unwrap();
+        input_stream.start().unwrap();
+
+        let mut output = device("Output");
+        output.playback_len = played;
+        let mut scratch = Vec::new();
+        let output_stream = output
+            .open_output(
+                output_config,
+                move |data| channel_converter.fill(&mut converter, &mut scratch, data),
+                |_| {},
+            )
+            .unwrap();
+        output_stream.start().unwrap();
+        output.played()
+    }
+
+    fn stream_config(channels: u16, sample_rate: u32) -> StreamConfig {
+        StreamConfig {
+            channels,
+            sample_rate: SampleRate(sample_rate),
+            buffer_size: BufferSize::Default,
+        }
+    }
+
+    #[test]
+    fn pipeline_converts_channels() {
+        let captured: Vec<f32> = (0..1000).map(|index| index as f32 / 1000.0).collect();
+
+        let played = run_pipeline(
+            captured.clone(),
+            &stream_config(1, 48000),
+            &stream_config(2, 48000),
+            2000,
+        );
+        assert_eq!(played.len(), 2000);
+        for (frame, sample) in played.chunks_exact(2).zip(&captured) {
+            assert_eq!(frame, [*sample, *sample]);
+        }
+
+        let played = run_pipeline(
+            captured.clone(),
+            &stream_config(2, 48000),
+            &stream_config(1, 48000),
+            500,
+        );
+        for (sample, frame) in played.iter().zip(captured.chunks_exact(2)) {
+            assert!((sample - (frame[0] + frame[1]) / 2.0).abs() < 1e-6);
+        }
+    }
+
+    /// Number of random cases each property is checked with
+    const CASES: u64 = 200;
+
+    /// Xorshift generator for the property tests, seeded per case so a
+    /// failing case can be reproduced from its seed
+    struct Rng(u64);
+
+    impl Rng {
+        fn new(seed: u64) -> Self {
+            Self(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+        }
+
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        /// Random number from 0 up to the `max`
+        fn below(&mut self, max: usize) -> usize {
+            (self.next() % max as u64) as usize
+        }
+
+        /// Random sample from -1 to 1
+        fn sample(&mut self) -> f32 {
+            (self.next() >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+        }
+    }
+
+    /// Converts random samples of `inputs` channels into `outputs`
+    /// channels, filling output blocks of random lengths so the stored
+    /// state of the converter carries across blocks. Returns the input
+    /// and output samples
+    fn convert_random(
+        rng: &mut Rng,
+        inputs: u16,
+        outputs: u16,
+        downmix: Downmix,
+    ) -> (Vec<f32>, Vec<f32>) {
+        let stats = Arc::new(StreamStats::default());
+        let (mut producer, mut channel_converter, mut converter) = create_pipeline(
+            &stream_config(inputs, 48000),
+            &stream_config(outputs, 48000),
+            downmix,
+            &stats,
+        );
+
+        let frames = 1 + rng.below(1000);
+        let input: Vec<f32> = (0..frames * inputs as usize)
+            .map(|_| rng.sample())
+            .collect();
+        producer.push_slice(&input);
+
+        let mut output = vec![0.0; frames * outputs as usize];
+        let mut scratch = Vec::new();
+        let mut filled = 0;
+        while filled < output.len() {
+            let length = (1 + rng.below(64)).min(output.len() - filled);
+            channel_converter.fill(
+                &mut converter,
+                &mut scratch,
+                &mut output[filled..filled + length],
+            );
+            filled += length;
+        }
+        (input, output)
+    }
+
+    #[test]
+    fn mono_to_stereo_outputs_each_sample_twice_in_order() {
+        for seed in 0..CASES {
+            let (input, output) = convert_random(&mut Rng::new(seed), 1, 2, Downmix::Average);
+            let expected: Vec<f32> = input.iter().flat_map(|&sample| [sample; 2]).collect();
+            assert_eq!(output, expected, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn stereo_to_mono_stays_between_its_inputs() {
+        for seed in 0..CASES {
+            let (input, output) = convert_random(&mut Rng::new(seed), 2, 1, Downmix::Average);
+            for (sample, frame) in output.iter().zip(input.chunks_exact(2)) {
+                let (low, high) = (frame[0].min(frame[1]), frame[0].max(frame[1]));
+                assert!(
+                    low <= *sample && *sample <= high,
+                    "seed {seed}: {sample} from {frame:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn passthrough_keeps_every_sample() {
+        for seed in 0..CASES {
+            let mut rng = Rng::new(seed);
+            let channels = 1 + rng.below(8) as u16;
+            let (input, output) = convert_random(&mut rng, channels, channels, Downmix::Average);
+            assert_eq!(output, input, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn to_mono_downmixes_each_frame() {
+        for seed in 0..CASES {
+            let mut rng = Rng::new(seed);
+            let channels = 2 + rng.below(7) as u16;
+            let (input, output) = convert_random(&mut rng, channels, 1, Downmix::Left);
+            let left: Vec<f32> = input.iter().step_by(channels as usize).copied().collect();
+            assert_eq!(output, left, "seed {seed}");
+
+            let (input, output) = convert_random(&mut rng, channels, 1, Downmix::PowerSum);
+            for (sample, frame) in output.iter().zip(input.chunks_exact(channels as usize)) {
+                let loudest = frame
+                    .iter()
+                    .fold(0.0f32, |max, sample| max.max(sample.abs()));
+                assert!(
+                    sample.abs() <= loudest + 1e-6,
+                    "seed {seed}: {sample} from {frame:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mixing_matches_frame_by_frame() {
+        for seed in 0..CASES {
+            let mut rng = Rng::new(seed);
+            let inputs = 1 + rng.below(8) as u16;
+            let outputs = 3 + rng.below(6) as u16;
+            let (input, output) = convert_random(&mut rng, inputs, outputs, Downmix::Average);
+
+            let matrix = MixMatrix::default_for(inputs as usize, outputs as usize);
+            let mut expected = vec![0.0; output.len()];
+            matrix.apply_frames(&input, &mut expected);
+            assert_eq!(output, expected, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn pipeline_resamples_and_conceals_underruns() {
+        let captured = vec![0.5; 1000];
+
+        let played = run_pipeline(
+            captured,
+            &stream_config(1, 24000),
+            &stream_config(1, 48000),
+            4000,
+        );
+        // Twice as many samples are played at twice the rate, until the
+        // input runs out and fades to silence
+        assert!(played[10..1990]
+            .iter()
+            .all(|sample| (sample - 0.5).abs() < 1e-3));
+        assert!(played[3000..].iter().all(|sample| *sample == 0.0));
+    }
+}
@@ -0,0 +1,217 @@
+//! Shared parts of the measurements that play a test signal through
+//! the output device while recording the input device
+
+use crate::{
+    args::Args,
+    backend::{AudioDevice, AudioHost, AudioStream},
+    error::ChemicError,
+    get_default_device,
+    log::{error, span},
+    requested_device, DeviceType, NamedDevice,
+};
+use cpal::{
+    traits::{DeviceTrait, StreamTrait},
+    StreamConfig,
+};
+use ringbuf::HeapRb;
+use std::{thread, time::Duration};
+
+/// Extra time recorded after the signal for the output latency
+const TAIL: Duration = Duration::from_millis(500);
+
+/// Finds the `ty` device requested by the `args` or the default device,
+/// measurements never prompt so that they measure the devices that were
+/// asked for
+pub fn select_device<H: AudioHost>(
+    host: &H,
+    args: &Args,
+    ty: DeviceType,
+) -> Result<NamedDevice<H::Device>, ChemicError> {
+    match requested_device(host, args, ty, false)? {
+        Some(device) => Ok(device),
+        None => get_default_device(host, ty).ok_or(ChemicError::NoDevices(ty)),
+    }
+}
+
+/// Plays the samples from the `signal` on every channel of the `output`
+/// for the `duration`, returning the first channel of the audio recorded
+/// from the `input` at the same time
+pub fn play_and_record<D: AudioDevice>(
+    input: &NamedDevice<D>,
+    input_config: &StreamConfig,
+    output: &NamedDevice<D>,
+    output_config: &StreamConfig,
+    duration: Duration,
+    mut signal: impl FnMut() -> f32 + Send + 'static,
+) -> Result<Vec<f32>, ChemicError> {
+    let _span = span!("play_and_record");
+
+    let duration = duration + TAIL;
+    let samples = (duration.as_secs_f64() * input_config.sample_rate.0 as f64) as usize;
+
+    let ring: HeapRb<f32> = HeapRb::new(samples * 2);
+    let (mut producer, mut consumer) = ring.split();
+
+    let input_channels = input_config.channels.max(1) as usize;
+    let input_stream = input
+        .device
+        .open_input(
+            input_config,
+            move |data| {
+                for frame in data.chunks_exact(input_channels) {
+                    _ = producer.push(frame[0]);
+                }
+            },
+            |err| error!("input stream error: {err} ({err:?})"),
+        )
+        .map_err(|source| ChemicError::BuildStream {
+            ty: DeviceType::Input,
+            device: input.name.clone(),
+            source,
+        })?;
+
+    let output_channels = output_config.channels.max(1) as usize;
+    let output_stream = output
+        .device
+        .open_output(
+            output_config,
+            move |data| {
+                for frame in data.chunks_exact_mut(output_channels) {
+                    frame.fill(signal());
+                }
+            },
+            |err| error!("output stream error: {err} ({err:?})"),
+        )
+        .map_err(|source| ChemicError::BuildStream {
+            ty: DeviceType::Output,
+            device: output.name.clone(),
+            source,
+        })?;
+
+    input_stream.start()?;
+    output_stream.start()?;
+
+    thread::sleep(duration);
+
+    drop(output_stream);
+    drop(input_stream);
+
+    let mut recording = vec![0.0; consumer.len()];
+    let recorded = consumer.pop_slice(&mut recording);
+    recording.truncate(recorded);
+
+    Ok(recording)
+}
+
+/// Records each of the `inputs` at the same time for the `duration`,
+/// returning the recording of each input mixed to mono
+pub fn record(
+    inputs: &[(&NamedDevice, &StreamConfig)],
+    duration: Duration,
+) -> Result<Vec<Vec<f32>>, ChemicError> {
+    let mut streams = Vec::with_capacity(inputs.len());
+    let mut consumers = Vec::with_capacity(inputs.len());
+    for (input, config) in inputs {
+        let samples = (duration.as_secs_f64() * config.sample_rate.0 as f64) as usize;
+        let ring: HeapRb<f32> = HeapRb::new(samples * 2);
+        let (mut producer, consumer) = ring.split();
+
+        let channels = config.channels.max(1) as usize;
+        let stream = input
+            .device
+            .build_input_stream(
+                config,
+                move |data: &[f32], _| {
+                    for frame in data.chunks_exact(channels) {
+                        _ = producer.push(frame.iter().sum::<f32>() / channels as f32);
+                    }
+                },
+                |err| error!("input stream error: {err} ({err:?})"),
+                None,
+            )
+            .map_err(|source| ChemicError::BuildStream {
+                ty: DeviceType::Input,
+                device: input.name.clone(),
+                source,
+            })?;
+        streams.push(stream);
+        consumers.push(consumer);
+    }
+
+    for stream in &streams {
+        stream.play()?;
+    }
+    thread::sleep(duration);
+    drop(streams);
+
+    Ok(consumers
+        .into_iter()
+        .map(|mut consumer| consumer.pop_iter().collect())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::mock::{range, MockDevice};
+    use cpal::{BufferSize, SampleFormat, SampleRate};
+    use std::sync::Arc;
+
+    #[test]
+    fn play_and_record_keeps_the_first_input_channel() {
+        let mut input = MockDevice::new("Input", vec![range(2, 48000, 48000, SampleFormat::F32)]);
+        input.capture = Arc::new((0..600).map(|index| index as f32).collect());
+        let mut output = MockDevice::new("Output", vec![range(2, 48000, 48000, SampleFormat::F32)]);
+        output.playback_len = 8;
+        let config = StreamConfig {
+            channels: 2,
+            sample_rate: SampleRate(48000),
+            buffer_size: BufferSize::Default,
+        };
+
+        let mut next = 0.0;
+        let recording = play_and_record(
+            &NamedDevice::from(input),
+            &config,
+            &NamedDevice::from(output.clone()),
+            &config,
+            Duration::ZERO,
+            move || {
+                next += 1.0;
+                next
+            },
+        )
+        .unwrap();
+
+        let first_channel: Vec<f32> = (0..300).map(|frame| (frame * 2) as f32).collect();
+        assert_eq!(recording, first_channel);
+        assert_eq!(output.played(), [1.0, 1.0, 2.0, 2.0, 3.0, 3.0, 4.0, 4.0]);
+    }
+
+    #[test]
+    fn play_and_record_reports_failed_streams() {
+        let mut input = MockDevice::new("Input", vec![range(1, 48000, 48000, SampleFormat::F32)]);
+        input.fail_open = true;
+        let output = MockDevice::new("Output", vec![range(1, 48000, 48000, SampleFormat::F32)]);
+        let config = StreamConfig {
+            channels: 1,
+            sample_rate: SampleRate(48000),
+            buffer_size: BufferSize::Default,
+        };
+
+        assert!(matches!(
+            play_and_record(
+                &NamedDevice::from(input),
+                &config,
+                &NamedDevice::from(output),
+                &config,
+                Duration::ZERO,
+                || 0.0,
+            ),
+            Err(ChemicError::BuildStream {
+                ty: DeviceType::Input,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,493 @@
+//! Input level metering, levels are recorded by the input callback and
+//! shown either as a redrawn bar or as periodic text lines that work
+//! with screen readers
+
+use crate::{
+    bands::{self, BANDS},
+    lang::{tr, Message},
+    simd,
+    stats::{Latency, StreamStats},
+    theme::MeterTheme,
+    weighting::Weighting,
+};
+use dialoguer::console::Term;
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Lowest level shown by the meters, anything quieter is silence
+const FLOOR_DB: f32 = -60.0;
+/// Number of characters used by the bar meter
+const BAR_WIDTH: usize = 30;
+/// How often the bar meter is redrawn
+const BAR_REFRESH: Duration = Duration::from_millis(50);
+/// Default time between the text meter lines
+pub const DEFAULT_TEXT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Levels of the input samples since the meter was last read, only
+/// relaxed atomic operations are performed from the input callback
+#[derive(Default)]
+pub struct LevelMeter {
+    /// Bits of the highest absolute sample value
+    peak: AtomicU32,
+    /// Bits of the sum of the squared sample values
+    sum_squares: AtomicU64,
+    /// Number of samples included in the sum
+    samples: AtomicU64,
+    /// Number of interleaved channels, the correlation between the
+    /// channels is only measured for stereo
+    channels: usize,
+    /// Bits of the sums of the left times right, squared left and
+    /// squared right samples for the correlation
+    sum_left_right: AtomicU64,
+    sum_left_left: AtomicU64,
+    sum_right_right: AtomicU64,
+    /// Bits of the sums of the squared samples of each one third octave
+    /// band, only recorded for the band display
+    band_sums: [AtomicU64; BANDS],
+    /// Number of frames included in the band sums
+    band_frames: AtomicU64,
+}
+
+/// Adds `value` to the f64 stored in the bits of the `sum`
+fn add_f64(sum: &AtomicU64, value: f64) {
+    _ = sum.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+        Some((f64::from_bits(bits) + value).to_bits())
+    });
+}
+
+/// Takes the f64 stored in the bits of the `sum` resetting it to zero
+fn take_f64(sum: &AtomicU64) -> f64 {
+    f64::from_bits(sum.swap(0, Ordering::Relaxed))
+}
+
+impl LevelMeter {
+    /// Creates a meter for samples with the provided number of
+    /// interleaved `channels`
+    pub fn new(channels: u16) -> Self {
+        Self {
+            channels: channels as usize,
+            ..Default::default()
+        }
+    }
+
+    /// Records the levels of the provided `samples`
+    pub fn record(&self, samples: &[f32]) {
+        if samples.is_empty() {
+            return;
+        }
+
+        let (peak, sum_squares) = simd::levels(samples);
+
+        // The bits of positive floats are ordered the same as the floats
+        self.peak.fetch_max(peak.to_bits(), Ordering::Relaxed);
+        add_f64(&self.sum_squares, sum_squares);
+        self.samples
+            .fetch_add(samples.len() as u64, Ordering::Relaxed);
+
+        if self.channels == 2 {
+            let (mut left_right, mut left_left, mut right_right) = (0.0f64, 0.0f64, 0.0f64);
+            for frame in samples.chunks_exact(2) {
+                let (left, right) = (frame[0] as f64, frame[1] as f64);
+                left_right += left * right;
+                left_left += left * left;
+                right_right += right * right;
+            }
+            add_f64(&self.sum_left_right, left_right);
+            add_f64(&self.sum_left_left, left_left);
+            add_f64(&self.sum_right_right, right_right);
+        }
+    }
+
+    /// Records the band `energies` measured over the provided number
+    /// of `frames`
+    pub fn record_bands(&self, energies: &[f64; BANDS], frames: usize) {
+        for (sum, &energy) in self.band_sums.iter().zip(energies) {
+            add_f64(sum, energy);
+        }
+        self.band_frames.fetch_add(frames as u64, Ordering::Relaxed);
+    }
+
+    /// Takes the RMS level in dBFS of each band recorded since the last
+    /// call, [None] for silent bands and bands that weren't analysed
+    pub fn take_bands(&self) -> [Option<f32>; BANDS] {
+        let frames = self.band_frames.swap(0, Ordering::Relaxed).max(1) as f64;
+        let mut levels = [None; BANDS];
+        for (level, sum) in levels.iter_mut().zip(&self.band_sums) {
+            *level = to_db((take_f64(sum) / frames).sqrt() as f32);
+        }
+        levels
+    }
+
+    /// Takes the levels recorded since the last call
+    pub fn take(&self) -> Level {
+        let peak = f32::from_bits(self.peak.swap(0, Ordering::Relaxed));
+        let sum_squares = take_f64(&self.sum_squares);
+        let samples = self.samples.swap(0, Ordering::Relaxed);
+
+        let rms = if samples == 0 {
+            0.0
+        } else {
+            (sum_squares / samples as f64).sqrt() as f32
+        };
+
+        let left_right = take_f64(&self.sum_left_right);
+        let energy = take_f64(&self.sum_left_left) * take_f64(&self.sum_right_right);
+        // Undefined while either channel is silent
+        let correlation = (self.channels == 2 && energy > f64::EPSILON)
+            .then(|| (left_right / energy.sqrt()).clamp(-1.0, 1.0) as f32);
+
+        Level {
+            rms,
+            peak,
+            correlation,
+        }
+    }
+}
+
+/// Levels over a period of time
+#[derive(Clone, Copy)]
+pub struct Level {
+    /// Root mean square of the samples
+    pub rms: f32,
+    /// Highest absolute sample value
+    pub peak: f32,
+    /// Correlation between the left and right channels from -1 (out of
+    /// phase) to +1 (identical), [None] unless both stereo channels
+    /// have a signal
+    pub correlation: Option<f32>,
+}
+
+impl Level {
+    /// RMS level in decibels relative to full scale, [None] when
+    /// below the meter floor
+    pub fn rms_db(&self) -> Option<f32> {
+        to_db(self.rms)
+    }
+
+    /// Peak level in decibels relative to full scale, [None] when
+    /// below the meter floor
+    pub fn peak_db(&self) -> Option<f32> {
+        to_db(self.peak)
+    }
+
+    /// Whether any of the samples reached full scale
+    pub fn clipped(&self) -> bool {
+        self.peak >= 1.0
+    }
+}
+
+/// Relation between the digital level of an input device and the sound
+/// pressure level, measured with a calibrator or a reference meter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    /// Sound pressure level in dB SPL of the reference sound
+    pub spl: f32,
+    /// Level in dBFS the input device captured the reference sound at
+    pub dbfs: f32,
+}
+
+impl Calibration {
+    /// Approximate sound pressure level of a `dbfs` input level
+    pub fn spl(&self, dbfs: f32) -> f32 {
+        dbfs - self.dbfs + self.spl
+    }
+}
+
+/// How the decibel values of the meter are presented
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MeterScale {
+    /// Calibration for showing dB SPL instead of dBFS
+    pub calibration: Option<Calibration>,
+    /// Frequency weighting the levels were measured with
+    pub weighting: Weighting,
+}
+
+impl MeterScale {
+    /// Converts the `dbfs` level to the value shown on the scale
+    pub fn value(&self, dbfs: f32) -> f32 {
+        self.calibration
+            .map_or(dbfs, |calibration| calibration.spl(dbfs))
+    }
+
+    /// Unit of the values on the scale (e.g. "dB", "dB(A) SPL")
+    pub fn unit(&self) -> String {
+        let spl = if self.calibration.is_some() {
+            " SPL"
+        } else {
+            ""
+        };
+        format!("dB{}{spl}", self.weighting.suffix())
+    }
+}
+
+/// Converts a linear `value` to decibels, [None] when below the floor
+pub fn to_db(value: f32) -> Option<f32> {
+    let db = 20.0 * value.log10();
+    (db >= FLOOR_DB).then_some(db)
+}
+
+/// Converts a level in decibels `db` to a linear factor
+pub fn from_db(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// How the input level is shown while monitoring
+#[derive(Clone)]
+pub enum MeterMode {
+    /// Bar redrawn in place on a single line using the characters
+    /// and styles from the theme
+    Bar(MeterTheme),
+    /// Line of text written at the provided interval, for screen
+    /// readers and terminals that can't redraw
+    Text(Duration),
+    /// One third octave bands redrawn in place on a single line next to
+    /// the overall level, using the colors from the theme
+    Bands(MeterTheme),
+}
+
+/// Meter display running on a background thread
+pub struct MeterDisplay {
+    /// Whether the display should stop
+    stop: Arc<AtomicBool>,
+    /// Thread rendering the display
+    handle: JoinHandle<()>,
+}
+
+impl MeterDisplay {
+    /// Starts showing the levels of the `meter` and the latency from the
+    /// `stats` on stderr using the `scale` for the values
+    pub fn start(
+        meter: Arc<LevelMeter>,
+        stats: Arc<StreamStats>,
+        mode: MeterMode,
+        scale: MeterScale,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                // Display errors only stop the meter, not the monitoring
+                _ = run(&meter, &stats, &mode, scale, &stop);
+            }
+        });
+
+        Self { stop, handle }
+    }
+
+    /// Stops the display, clearing the bar meter
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        _ = self.handle.join();
+    }
+}
+
+fn run(
+    meter: &LevelMeter,
+    stats: &StreamStats,
+    mode: &MeterMode,
+    scale: MeterScale,
+    stop: &AtomicBool,
+) -> io::Result<()> {
+    let term = Term::stderr();
+    let interval = match mode {
+        MeterMode::Bar(_) | MeterMode::Bands(_) => BAR_REFRESH,
+        MeterMode::Text(interval) => *interval,
+    };
+
+    // Discard the levels recorded before the display started
+    meter.take();
+    // The text meter announces the latency once instead of every line
+    let mut latency_announced = false;
+
+    while !stop.load(Ordering::Relaxed) {
+        sleep_unless_stopped(interval, stop);
+
+        let level = meter.take();
+        let latency = stats.latency();
+        match mode {
+            MeterMode::Bar(theme) => {
+                term.clear_line()?;
+                term.write_str(&format_bar(level, latency, scale, theme))?;
+            }
+            MeterMode::Text(_) => {
+                if let (Some(latency), false) = (latency, latency_announced) {
+                    term.write_line(&format!("{}: {latency}", tr(Message::Latency)))?;
+                    latency_announced = true;
+                }
+                term.write_line(&format_text(level, scale))?;
+            }
+            MeterMode::Bands(theme) => {
+                term.clear_line()?;
+                term.write_str(&format_bands(&meter.take_bands(), level, scale, theme))?;
+            }
+        }
+    }
+
+    if let MeterMode::Bar(_) | MeterMode::Bands(_) = mode {
+        term.clear_line()?;
+    }
+
+    Ok(())
+}
+
+/// Sleeps for the `duration` in small steps so that a stop
+/// request isn't delayed by a long interval
+fn sleep_unless_stopped(duration: Duration, stop: &AtomicBool) {
+    let step = BAR_REFRESH.min(duration);
+    let mut slept = Duration::ZERO;
+    while slept < duration && !stop.load(Ordering::Relaxed) {
+        thread::sleep(step);
+        slept += step;
+    }
+}
+
+/// Formats the `level` as a bar with the RMS level in decibels on the
+/// `scale` and the total `latency` (e.g. "[#########.....] -18.0 dB  14 ms")
+fn format_bar(
+    level: Level,
+    latency: Option<Latency>,
+    scale: MeterScale,
+    theme: &MeterTheme,
+) -> String {
+    let db = level.rms_db();
+    let filled = db.map_or(0, |db| {
+        (((db - FLOOR_DB) / -FLOOR_DB) * BAR_WIDTH as f32).round() as usize
+    });
+    let filled = filled.min(BAR_WIDTH);
+
+    let bar = theme.fill.to_string().repeat(filled);
+    let bar = match db {
+        Some(db) if db > -6.0 => theme.high.apply_to(bar),
+        Some(db) if db > -18.0 => theme.mid.apply_to(bar),
+        _ => theme.low.apply_to(bar),
+    };
+
+    let value = match db {
+        Some(db) => format!("{:>6.1} {}", scale.value(db), scale.unit()),
+        None => format!("{:>9}", tr(Message::Silent)),
+    };
+
+    // Correlation below zero means the channels will cancel when summed
+    let correlation = match level.correlation {
+        Some(correlation) if correlation < 0.0 => {
+            format!("  {}", theme.clip.apply_to(format!("{correlation:+.2}")))
+        }
+        Some(correlation) => format!("  {correlation:+.2}"),
+        None => String::new(),
+    };
+
+    let clip = if level.clipped() {
+        format!(
+            " {}",
+            theme.clip.apply_to(tr(Message::Clipping).to_uppercase())
+        )
+    } else {
+        String::new()
+    };
+
+    let latency = match latency {
+        Some(latency) => format!("  {:.0} ms", latency.total().as_secs_f64() * 1000.0),
+        None => String::new(),
+    };
+
+    format!(
+        "[{bar}{}] {value}{correlation}{latency}{clip}",
+        theme.empty.to_string().repeat(BAR_WIDTH - filled)
+    )
+}
+
+/// Characters for the band levels from quietest to loudest
+const BAND_CHARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Formats the `bands` as a column for each band next to the overall
+/// RMS `level` on the `scale` (e.g. "20 Hz ▁▂▄▆▇▆▅▄▃▂ 20 kHz  -18.0 dB")
+fn format_bands(
+    bands: &[Option<f32>; BANDS],
+    level: Level,
+    scale: MeterScale,
+    theme: &MeterTheme,
+) -> String {
+    let columns: String = bands
+        .iter()
+        .map(|band| match band {
+            Some(db) => {
+                let index = ((db - FLOOR_DB) / -FLOOR_DB * BAND_CHARS.len() as f32) as usize;
+                let column = BAND_CHARS[index.min(BAND_CHARS.len() - 1)].to_string();
+                match db {
+                    db if *db > -6.0 => theme.high.apply_to(column).to_string(),
+                    db if *db > -18.0 => theme.mid.apply_to(column).to_string(),
+                    _ => theme.low.apply_to(column).to_string(),
+                }
+            }
+            None => " ".to_string(),
+        })
+        .collect();
+
+    let value = match level.rms_db() {
+        Some(db) => format!("{:>6.1} {}", scale.value(db), scale.unit()),
+        None => format!("{:>9}", tr(Message::Silent)),
+    };
+
+    let clip = if level.clipped() {
+        format!(
+            " {}",
+            theme.clip.apply_to(tr(Message::Clipping).to_uppercase())
+        )
+    } else {
+        String::new()
+    };
+
+    format!(
+        "{:.0} Hz {columns} {:.0} kHz  {value}{clip}",
+        bands::center_frequency(0),
+        bands::center_frequency(BANDS - 1) / 1000.0
+    )
+}
+
+/// Formats the `level` as words that read well through a screen
+/// reader (e.g. "Level: minus 18 dB, correlation plus 0.9, no clipping")
+fn format_text(level: Level, scale: MeterScale) -> String {
+    let value = match level.rms_db().map(|db| scale.value(db)) {
+        Some(db) if db.round() < 0.0 => {
+            format!(
+                "{} {} {}",
+                tr(Message::Minus),
+                -db.round() as i32,
+                scale.unit()
+            )
+        }
+        Some(db) => format!("{} {}", db.round() as i32, scale.unit()),
+        None => tr(Message::Silent).to_string(),
+    };
+
+    let clipping = if level.clipped() {
+        tr(Message::Clipping)
+    } else {
+        tr(Message::NoClipping)
+    };
+
+    let correlation = match level.correlation {
+        Some(correlation) => {
+            let sign = if correlation < 0.0 {
+                tr(Message::Minus)
+            } else {
+                tr(Message::Plus)
+            };
+            format!(
+                ", {} {sign} {:.1}",
+                tr(Message::Correlation),
+                correlation.abs()
+            )
+        }
+        None => String::new(),
+    };
+
+    format!("{}: {value}{correlation}, {clipping}", tr(Message::Level))
+}
@@ -0,0 +1,450 @@
+//! Mixing between any number of input and output channels using a
+//! matrix of gains, with default matrices for the common layouts
+
+use std::{f32::consts::FRAC_1_SQRT_2, str::FromStr};
+
+/// How multiple input channels are combined into a mono output
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Downmix {
+    /// Average of the channels, out of phase channels cancel out
+    #[default]
+    Average,
+    /// Only the first (left) channel
+    Left,
+    /// Only the second (right) channel
+    Right,
+    /// Root mean square of the channels keeping the sign of the loudest
+    /// channel, out of phase channels don't cancel out
+    PowerSum,
+}
+
+impl Downmix {
+    /// Combines the samples of one input `frame` into a single sample
+    pub fn apply(self, frame: &[f32]) -> f32 {
+        match self {
+            Downmix::Average => frame.iter().sum::<f32>() / frame.len().max(1) as f32,
+            Downmix::Left => frame.first().copied().unwrap_or_default(),
+            Downmix::Right => frame.get(1).or(frame.first()).copied().unwrap_or_default(),
+            Downmix::PowerSum => {
+                let mean_square = frame.iter().map(|sample| sample * sample).sum::<f32>()
+                    / frame.len().max(1) as f32;
+                let loudest = frame
+                    .iter()
+                    .copied()
+                    .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+                    .unwrap_or_default();
+                mean_square.sqrt().copysign(loudest)
+            }
+        }
+    }
+}
+
+impl FromStr for Downmix {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "average" => Ok(Downmix::Average),
+            "left" => Ok(Downmix::Left),
+            "right" => Ok(Downmix::Right),
+            "power-sum" => Ok(Downmix::PowerSum),
+            _ => Err(format!(
+                "unknown downmix \"{value}\" (expected average, left, right or power-sum)"
+            )),
+        }
+    }
+}
+
+/// Gains applied to each input channel for each output channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct MixMatrix {
+    /// Number of input channels
+    inputs: usize,
+    /// Number of output channels
+    outputs: usize,
+    /// Gains stored row by row, one row of input gains per output
+    gains: Vec<f32>,
+}
+
+impl MixMatrix {
+    /// Creates a matrix that mixes nothing into any output
+    fn silent(inputs: usize, outputs: usize) -> Self {
+        Self {
+            inputs,
+            outputs,
+            gains: vec![0.0; inputs * outputs],
+        }
+    }
+
+    fn set(&mut self, output: usize, input: usize, gain: f32) {
+        if output < self.outputs && input < self.inputs {
+            self.gains[output * self.inputs + input] = gain;
+        }
+    }
+
+    /// Creates the default matrix for mixing `inputs` channels into
+    /// `outputs` channels. Channels are expected in the WAVE order (front
+    /// left, front right, center, LFE then the back and side channels).
+    /// Surround layouts are downmixed to stereo and mono
+    /// with the center and surrounds at -3 dB and the LFE dropped, other
+    /// combinations copy the channels they share and leave the rest silent
+    pub fn default_for(inputs: usize, outputs: usize) -> Self {
+        let mut matrix = Self::silent(inputs, outputs);
+
+        match (inputs, outputs) {
+            // Mono is sent to the front left and right channels
+            (1, _) => {
+                matrix.set(0, 0, 1.0);
+                matrix.set(1, 0, 1.0);
+            }
+            (_, 1) => {
+                let stereo = Self::default_for(inputs, 2);
+                for input in 0..inputs {
+                    let gain = (stereo.gain(0, input) + stereo.gain(1, input)) / 2.0;
+                    matrix.set(0, input, gain);
+                }
+            }
+            (3..=8, 2) => {
+                // Front channels are kept, the center and surrounds are
+                // added at -3 dB as (output, input, gain)
+                const H: f32 = FRAC_1_SQRT_2;
+                let layout: &[(usize, usize, f32)] = match inputs {
+                    // Left, right, center
+                    3 => &[(0, 0, 1.0), (1, 1, 1.0), (0, 2, H), (1, 2, H)],
+                    // Quad, front and back pairs
+                    4 => &[(0, 0, 1.0), (1, 1, 1.0), (0, 2, H), (1, 3, H)],
+                    // 5.0, center and back pair without an LFE
+                    5 => &[
+                        (0, 0, 1.0),
+                        (1, 1, 1.0),
+                        (0, 2, H),
+                        (1, 2, H),
+                        (0, 3, H),
+                        (1, 4, H),
+                    ],
+                    // 5.1, the LFE (3) is dropped
+                    6 => &[
+                        (0, 0, 1.0),
+                        (1, 1, 1.0),
+                        (0, 2, H),
+                        (1, 2, H),
+                        (0, 4, H),
+                        (1, 5, H),
+                    ],
+                    // 6.1, back center shared between the sides
+                    7 => &[
+                        (0, 0, 1.0),
+                        (1, 1, 1.0),
+                        (0, 2, H),
+                        (1, 2, H),
+                        (0, 4, H / 2.0),
+                        (1, 4, H / 2.0),
+                        (0, 5, H),
+                        (1, 6, H),
+                    ],
+                    // 7.1, back and side pairs
+                    _ => &[
+                        (0, 0, 1.0),
+                        (1, 1, 1.0),
+                        (0, 2, H),
+                        (1, 2, H),
+                        (0, 4, H),
+                        (1, 5, H),
+                        (0, 6, H),
+                        (1, 7, H),
+                    ],
+                };
+
+                for &(output, input, gain) in layout {
+                    matrix.set(output, input, gain);
+                }
+                matrix.normalize();
+            }
+            // Unknown layouts alternate the channels between the sides
+            (_, 2) => {
+                for input in 0..inputs {
+                    matrix.set(input % 2, input, 1.0);
+                }
+                matrix.normalize();
+            }
+            _ => {
+                for channel in 0..inputs.min(outputs) {
+                    matrix.set(channel, channel, 1.0);
+                }
+            }
+        }
+
+        matrix
+    }
+
+    /// Gain of the `input` channel in the `output` channel
+    pub fn gain(&self, output: usize, input: usize) -> f32 {
+        self.gains[output * self.inputs + input]
+    }
+
+    /// Scales the rows whose gains add up to more than one so that full
+    /// scale inputs can't clip the output
+    fn normalize(&mut self) {
+        if self.inputs == 0 {
+            return;
+        }
+
+        for row in self.gains.chunks_exact_mut(self.inputs) {
+            let total: f32 = row.iter().sum();
+            if total > 1.0 {
+                row.iter_mut().for_each(|gain| *gain /= total);
+            }
+        }
+    }
+
+    /// Number of input channels mixed
+    pub fn inputs(&self) -> usize {
+        self.inputs
+    }
+
+    /// Number of output channels produced
+    pub fn outputs(&self) -> usize {
+        self.outputs
+    }
+
+    /// Mixes the `input` frame into the `output` frame, without any
+    /// input channels the output is silent
+    pub fn apply(&self, input: &[f32], output: &mut [f32]) {
+        if self.inputs == 0 {
+            output.fill(0.0);
+            return;
+        }
+
+        for (out, row) in output.iter_mut().zip(self.gains.chunks_exact(self.inputs)) {
+            *out = row
+                .iter()
+                .zip(input)
+                .map(|(gain, sample)| gain * sample)
+                .sum();
+        }
+    }
+
+    /// Mixes each interleaved frame of the `input` into the `output`
+    pub fn apply_frames(&self, input: &[f32], output: &mut [f32]) {
+        if self.inputs == 0 || self.outputs == 0 {
+            output.fill(0.0);
+            return;
+        }
+
+        for (input, output) in input
+            .chunks_exact(self.inputs)
+            .zip(output.chunks_exact_mut(self.outputs))
+        {
+            self.apply(input, output);
+        }
+    }
+}
+
+/// Mixes samples pulled one at a time, buffering a frame of input and
+/// output samples
+pub struct Mixer {
+    /// Gains used for mixing
+    matrix: MixMatrix,
+    /// Input frame being mixed
+    input: Vec<f32>,
+    /// Mixed output frame
+    output: Vec<f32>,
+    /// Position of the next sample in the output frame
+    position: usize,
+}
+
+impl Mixer {
+    /// Creates a mixer using the gains of the `matrix`
+    pub fn new(matrix: MixMatrix) -> Self {
+        Self {
+            input: vec![0.0; matrix.inputs()],
+            output: vec![0.0; matrix.outputs()],
+            position: matrix.outputs(),
+            matrix,
+        }
+    }
+
+    /// Gains used for mixing
+    pub fn matrix(&self) -> &MixMatrix {
+        &self.matrix
+    }
+
+    /// Whether part of a mixed frame is still waiting to be taken
+    pub fn has_pending(&self) -> bool {
+        self.position < self.output.len()
+    }
+
+    /// Takes the next output sample, pulling the next input frame from
+    /// `next_input` when the current frame has been used. Without any
+    /// output channels there is nothing to take and silence is returned
+    pub fn next(&mut self, mut next_input: impl FnMut() -> f32) -> f32 {
+        if self.output.is_empty() {
+            return 0.0;
+        }
+
+        if !self.has_pending() {
+            self.input.fill_with(&mut next_input);
+            self.matrix.apply(&self.input, &mut self.output);
+            self.position = 0;
+        }
+
+        let sample = self.output[self.position];
+        self.position += 1;
+        sample
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    /// Mixes a single `input` frame with the default matrix
+    fn mix(input: &[f32], outputs: usize) -> Vec<f32> {
+        let mut output = vec![f32::NAN; outputs];
+        MixMatrix::default_for(input.len(), outputs).apply_frames(input, &mut output);
+        output
+    }
+
+    #[test]
+    fn surround_51_to_stereo() {
+        let matrix = MixMatrix::default_for(6, 2);
+        // Front at 1, center and surround at -3 dB, normalized by the row
+        let total = 1.0 + 2.0 * FRAC_1_SQRT_2;
+        let front = 1.0 / total;
+        let side = FRAC_1_SQRT_2 / total;
+        let left = [front, 0.0, side, 0.0, side, 0.0];
+        let right = [0.0, front, side, 0.0, 0.0, side];
+        for input in 0..6 {
+            assert_close(matrix.gain(0, input), left[input]);
+            assert_close(matrix.gain(1, input), right[input]);
+        }
+
+        // Full scale on every channel can't clip and the LFE is dropped
+        let output = mix(&[1.0; 6], 2);
+        assert_close(output[0], 1.0);
+        assert_close(output[1], 1.0);
+        assert_eq!(mix(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0], 2), [0.0, 0.0]);
+        // The surrounds only reach their own side
+        let output = mix(&[0.0, 0.0, 0.0, 0.0, 1.0, 0.0], 2);
+        assert_close(output[0], side);
+        assert_close(output[1], 0.0);
+    }
+
+    #[test]
+    fn surround_71_to_stereo() {
+        let matrix = MixMatrix::default_for(8, 2);
+        let total = 1.0 + 3.0 * FRAC_1_SQRT_2;
+        for (input, left, right) in [
+            (0, 1.0, 0.0),
+            (1, 0.0, 1.0),
+            (2, FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+            (3, 0.0, 0.0),
+            (4, FRAC_1_SQRT_2, 0.0),
+            (5, 0.0, FRAC_1_SQRT_2),
+            (6, FRAC_1_SQRT_2, 0.0),
+            (7, 0.0, FRAC_1_SQRT_2),
+        ] {
+            assert_close(matrix.gain(0, input), left / total);
+            assert_close(matrix.gain(1, input), right / total);
+        }
+
+        let output = mix(&[1.0; 8], 2);
+        assert_close(output[0], 1.0);
+        assert_close(output[1], 1.0);
+    }
+
+    #[test]
+    fn surround_51_to_mono() {
+        let matrix = MixMatrix::default_for(6, 1);
+        let stereo = MixMatrix::default_for(6, 2);
+        for input in 0..6 {
+            let expected = (stereo.gain(0, input) + stereo.gain(1, input)) / 2.0;
+            assert_close(matrix.gain(0, input), expected);
+        }
+
+        // The fold down keeps the normalization of the stereo rows
+        assert_close(mix(&[1.0; 6], 1)[0], 1.0);
+        assert_close(
+            mix(&[0.0, 0.0, 1.0, 0.0, 0.0, 0.0], 1)[0],
+            stereo.gain(0, 2),
+        );
+    }
+
+    #[test]
+    fn stereo_to_surround_copies_the_fronts() {
+        let output = mix(&[0.25, -0.5], 6);
+        assert_eq!(output, [0.25, -0.5, 0.0, 0.0, 0.0, 0.0]);
+
+        let mut mixer = Mixer::new(MixMatrix::default_for(2, 6));
+        let mut input = [0.25, -0.5, 0.75, 1.0].into_iter();
+        let output: Vec<f32> = (0..12)
+            .map(|_| mixer.next(|| input.next().unwrap()))
+            .collect();
+        assert_eq!(&output[..6], [0.25, -0.5, 0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(&output[6..], [0.75, 1.0, 0.0, 0.0, 0.0, 0.0]);
+        assert!(!mixer.has_pending());
+    }
+
+    #[test]
+    fn mono_to_stereo_and_unknown_layouts() {
+        assert_eq!(mix(&[0.5], 2), [0.5, 0.5]);
+        // Nine channels alternate between the sides
+        let matrix = MixMatrix::default_for(9, 2);
+        assert_close(matrix.gain(0, 8), 0.2);
+        assert_close(matrix.gain(1, 7), 0.25);
+        assert_close(matrix.gain(1, 8), 0.0);
+    }
+
+    #[test]
+    fn zero_channels_are_silent() {
+        for (inputs, outputs) in [(0, 2), (0, 1), (2, 0), (0, 0)] {
+            let matrix = MixMatrix::default_for(inputs, outputs);
+            let mut output = vec![f32::NAN; outputs];
+            matrix.apply_frames(&vec![1.0; inputs], &mut output);
+            assert!(output.iter().all(|sample| *sample == 0.0));
+
+            let mut mixer = Mixer::new(matrix);
+            assert_eq!(mixer.next(|| 1.0), 0.0);
+        }
+    }
+
+    #[test]
+    fn downmix_to_mono() {
+        let frame = [0.2, -0.6];
+        assert_close(Downmix::Average.apply(&frame), -0.2);
+        assert_close(Downmix::Left.apply(&frame), 0.2);
+        assert_close(Downmix::Right.apply(&frame), -0.6);
+        // The power sum takes the sign of the loudest channel
+        assert_close(Downmix::PowerSum.apply(&frame), -(0.2f32).sqrt());
+        assert_close(Downmix::PowerSum.apply(&[0.6, -0.2]), (0.2f32).sqrt());
+
+        // Out of phase channels cancel in the average but not the power sum
+        assert_close(Downmix::Average.apply(&[0.5, -0.5]), 0.0);
+        assert_close(Downmix::PowerSum.apply(&[0.5, -0.5]).abs(), 0.5);
+
+        // Mono input and empty frames
+        assert_close(Downmix::Right.apply(&[0.3]), 0.3);
+        for downmix in [
+            Downmix::Average,
+            Downmix::Left,
+            Downmix::Right,
+            Downmix::PowerSum,
+        ] {
+            assert_eq!(downmix.apply(&[]), 0.0);
+        }
+    }
+
+    #[test]
+    fn downmix_from_str() {
+        assert_eq!("Power-Sum".parse(), Ok(Downmix::PowerSum));
+        assert_eq!("left".parse(), Ok(Downmix::Left));
+        assert!("sum".parse::<Downmix>().is_err());
+    }
+}
@@ -0,0 +1,459 @@
+//! Negotiation of the stream configs used by the input and output
+//! devices, searching the supported configs of both devices for the
+//! best joint choice
+
+use crate::{
+    backend::AudioDevice,
+    error::ChemicError,
+    log::{debug, span, trace},
+    DeviceType, NamedDevice,
+};
+use cpal::{
+    SampleFormat, SampleRate, SupportedBufferSize, SupportedStreamConfig,
+    SupportedStreamConfigRange,
+};
+use std::cmp::Reverse;
+
+/// Common sample rates that are tried when searching for a shared rate
+const COMMON_SAMPLE_RATES: &[u32] = &[
+    8000, 11025, 16000, 22050, 32000, 44100, 48000, 88200, 96000, 176400, 192000,
+];
+
+/// Rates chosen in order when a device doesn't report a default config
+const PREFERRED_SAMPLE_RATES: &[u32] = &[48000, 44100, 96000, 32000, 22050, 16000];
+
+/// Supported configs of a device
+struct DeviceConfigs {
+    /// Default config of the device if it could be determined
+    default: Option<SupportedStreamConfig>,
+    /// All the supported config ranges
+    ranges: Vec<SupportedStreamConfigRange>,
+}
+
+impl DeviceConfigs {
+    fn load<D: AudioDevice>(device: &NamedDevice<D>, ty: DeviceType) -> Result<Self, ChemicError> {
+        let (default, ranges) = device.supported_configs(ty);
+
+        // Some drivers fail to report a default config while still
+        // listing the supported configs, those are searched instead
+        let default = default
+            .inspect_err(|err| {
+                debug!(
+                    "{ty} device \"{}\" has no default config ({err}), using the supported configs",
+                    device.name
+                )
+            })
+            .ok();
+        let ranges = match ranges {
+            Ok(ranges) => ranges,
+            // The default config can still be used on its own
+            Err(_) if default.is_some() => Vec::new(),
+            Err(source) => {
+                return Err(ChemicError::LoadConfigs {
+                    ty,
+                    device: device.name.clone(),
+                    source,
+                })
+            }
+        };
+
+        if default.is_none() && ranges.is_empty() {
+            return Err(ChemicError::NoConfigs {
+                ty,
+                device: device.name.clone(),
+            });
+        }
+
+        debug!(
+            "{ty} device \"{}\" default config: {:?}, {} supported ranges",
+            device.name,
+            default,
+            ranges.len()
+        );
+        for range in &ranges {
+            trace!("{ty} range: {range:?}");
+        }
+
+        Ok(Self { default, ranges })
+    }
+
+    fn default_rate(&self) -> Option<SampleRate> {
+        self.default.as_ref().map(|config| config.sample_rate())
+    }
+
+    fn default_channels(&self) -> Option<u16> {
+        self.default.as_ref().map(|config| config.channels())
+    }
+
+    /// Finds the best config for this device alone at the provided
+    /// `sample_rate` or the default rate when not specified. Without a
+    /// default config a common rate and channel count is chosen
+    fn best_alone(&self, sample_rate: Option<SampleRate>) -> Option<SupportedStreamConfig> {
+        match (sample_rate, &self.default) {
+            (None, Some(default)) => Some(default.clone()),
+            (sample_rate, _) => {
+                let default_channels = self.default_channels();
+                self.ranges
+                    .iter()
+                    .filter_map(|range| {
+                        let sample_rate = sample_rate.unwrap_or_else(|| fallback_rate(range));
+                        supports_rate(range, sample_rate).then_some((range, sample_rate))
+                    })
+                    .max_by_key(|&(range, sample_rate)| {
+                        (
+                            range.sample_format() == SampleFormat::F32,
+                            // Mono or stereo when the default is unknown
+                            default_channels.map_or(range.channels() <= 2, |channels| {
+                                channels == range.channels()
+                            }),
+                            Reverse(preferred_rate_rank(sample_rate)),
+                        )
+                    })
+                    .map(|(range, sample_rate)| range.with_sample_rate(sample_rate))
+            }
+        }
+    }
+}
+
+/// Negotiates the configs for the `input` and `output` devices. When a
+/// `sample_rate` is provided both devices must support it.
+///
+/// Prefers (in order) configs that share a sample rate, use f32 samples,
+/// keep the default output channel count, have matching channel counts,
+/// use a default sample rate and have the lowest buffer latency. Falls
+/// back to the best individual configs (requiring resampling) when the
+/// devices have no sample rate in common
+pub fn negotiate<D: AudioDevice>(
+    input: &NamedDevice<D>,
+    output: &NamedDevice<D>,
+    sample_rate: Option<u32>,
+) -> Result<(SupportedStreamConfig, SupportedStreamConfig), ChemicError> {
+    let _span = span!("negotiate");
+
+    let input_configs = DeviceConfigs::load(input, DeviceType::Input)?;
+    let output_configs = DeviceConfigs::load(output, DeviceType::Output)?;
+
+    let sample_rate = sample_rate.map(SampleRate);
+
+    if let Some((input_config, output_config)) =
+        best_joint(&input_configs, &output_configs, sample_rate)
+    {
+        debug!(
+            "negotiated shared {}Hz config: input {} {}ch, output {} {}ch",
+            input_config.sample_rate().0,
+            input_config.sample_format(),
+            input_config.channels(),
+            output_config.sample_format(),
+            output_config.channels()
+        );
+        return Ok((input_config, output_config));
+    }
+
+    debug!("no shared sample rate between the devices, using individual configs");
+
+    let input_config = input_configs.best_alone(sample_rate);
+    let output_config = output_configs.best_alone(sample_rate);
+
+    match (input_config, output_config) {
+        (Some(input_config), Some(output_config)) => Ok((input_config, output_config)),
+        (input_config, _) => {
+            let device = if input_config.is_none() {
+                input.name.clone()
+            } else {
+                output.name.clone()
+            };
+
+            Err(match sample_rate {
+                Some(sample_rate) => ChemicError::UnsupportedSampleRate {
+                    device,
+                    sample_rate: sample_rate.0,
+                },
+                None => ChemicError::NoUsableConfig { device },
+            })
+        }
+    }
+}
+
+/// Finds the config for using the `input` device on its own, the
+/// default config unless a `sample_rate` is provided
+pub fn negotiate_input<D: AudioDevice>(
+    input: &NamedDevice<D>,
+    sample_rate: Option<u32>,
+) -> Result<SupportedStreamConfig, ChemicError> {
+    let configs = DeviceConfigs::load(input, DeviceType::Input)?;
+    configs
+        .best_alone(sample_rate.map(SampleRate))
+        .ok_or_else(|| ChemicError::NoUsableConfig {
+            device: input.name.clone(),
+        })
+}
+
+/// Finds the config for playing every channel of the `output` device on
+/// its own, the supported config with the most channels at the
+/// `sample_rate` or the default rate
+pub fn negotiate_output<D: AudioDevice>(
+    output: &NamedDevice<D>,
+    sample_rate: Option<u32>,
+) -> Result<SupportedStreamConfig, ChemicError> {
+    let configs = DeviceConfigs::load(output, DeviceType::Output)?;
+    let requested = sample_rate.map(SampleRate);
+    let sample_rate = requested.or(configs.default_rate());
+    configs
+        .ranges
+        .iter()
+        .filter_map(|range| {
+            let sample_rate = sample_rate.unwrap_or_else(|| fallback_rate(range));
+            supports_rate(range, sample_rate).then_some((range, sample_rate))
+        })
+        .max_by_key(|&(range, sample_rate)| {
+            (
+                range.channels(),
+                range.sample_format() == SampleFormat::F32,
+                Reverse(preferred_rate_rank(sample_rate)),
+            )
+        })
+        .map(|(range, sample_rate)| range.with_sample_rate(sample_rate))
+        // The default config is kept unless it leaves channels out
+        .filter(|config| Some(config.channels()) > configs.default_channels())
+        .or_else(|| configs.best_alone(requested))
+        .ok_or_else(|| ChemicError::NoUsableConfig {
+            device: output.name.clone(),
+        })
+}
+
+/// Finds the best pair of configs that share a sample rate
+fn best_joint(
+    input: &DeviceConfigs,
+    output: &DeviceConfigs,
+    sample_rate: Option<SampleRate>,
+) -> Option<(SupportedStreamConfig, SupportedStreamConfig)> {
+    // Sample rates to try, using the defaults and common rates unless
+    // a specific rate was requested
+    let sample_rates: Vec<SampleRate> = match sample_rate {
+        Some(sample_rate) => vec![sample_rate],
+        None => input
+            .default_rate()
+            .into_iter()
+            .chain(output.default_rate())
+            .chain(COMMON_SAMPLE_RATES.iter().copied().map(SampleRate))
+            .collect(),
+    };
+
+    let sample_rates = sample_rates.as_slice();
+    let candidates = input.ranges.iter().flat_map(|input_range| {
+        output.ranges.iter().flat_map(move |output_range| {
+            sample_rates
+                .iter()
+                .copied()
+                .filter(move |&sample_rate| {
+                    supports_rate(input_range, sample_rate)
+                        && supports_rate(output_range, sample_rate)
+                })
+                .map(move |sample_rate| (input_range, output_range, sample_rate))
+        })
+    });
+
+    let (input_range, output_range, sample_rate) =
+        candidates.max_by_key(|&(input_range, output_range, sample_rate)| JointScore {
+            f32_samples: (input_range.sample_format() == SampleFormat::F32) as u8
+                + (output_range.sample_format() == SampleFormat::F32) as u8,
+            default_output_channels: Some(output_range.channels()) == output.default_channels(),
+            matching_channels: input_range.channels() == output_range.channels(),
+            default_rate: if Some(sample_rate) == input.default_rate() {
+                2
+            } else if Some(sample_rate) == output.default_rate() {
+                1
+            } else {
+                0
+            },
+            default_input_channels: Some(input_range.channels()) == input.default_channels(),
+            latency: Reverse(buffer_latency(input_range, output_range, sample_rate)),
+        })?;
+
+    Some((
+        input_range.with_sample_rate(sample_rate),
+        output_range.with_sample_rate(sample_rate),
+    ))
+}
+
+/// Score of a joint config choice, fields are compared in declaration
+/// order with larger values being better
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct JointScore {
+    /// Number of the configs using f32 samples
+    f32_samples: u8,
+    /// Whether the output keeps its default channel count
+    default_output_channels: bool,
+    /// Whether the channel counts match so no conversion is needed
+    matching_channels: bool,
+    /// Whether the rate is the input (2) or output (1) default rate
+    default_rate: u8,
+    /// Whether the input keeps its default channel count
+    default_input_channels: bool,
+    /// Combined minimum buffer latency in microseconds
+    latency: Reverse<u64>,
+}
+
+/// Sample rate used for a config `range` when the device has no default
+/// config, the first supported preferred rate or the closest supported
+/// rate to 48kHz
+fn fallback_rate(range: &SupportedStreamConfigRange) -> SampleRate {
+    PREFERRED_SAMPLE_RATES
+        .iter()
+        .copied()
+        .map(SampleRate)
+        .find(|&sample_rate| supports_rate(range, sample_rate))
+        .unwrap_or_else(|| {
+            SampleRate(
+                PREFERRED_SAMPLE_RATES[0]
+                    .clamp(range.min_sample_rate().0, range.max_sample_rate().0),
+            )
+        })
+}
+
+/// Position of the `sample_rate` in the preferred rates, rates that
+/// aren't preferred are ranked last
+fn preferred_rate_rank(sample_rate: SampleRate) -> usize {
+    PREFERRED_SAMPLE_RATES
+        .iter()
+        .position(|&rate| rate == sample_rate.0)
+        .unwrap_or(PREFERRED_SAMPLE_RATES.len())
+}
+
+/// Whether the config `range` supports the provided `sample_rate`
+fn supports_rate(range: &SupportedStreamConfigRange, sample_rate: SampleRate) -> bool {
+    range.min_sample_rate() <= sample_rate && sample_rate <= range.max_sample_rate()
+}
+
+/// Combined latency in microseconds of the smallest buffers supported by
+/// both config ranges at the provided `sample_rate`. Unknown buffer sizes
+/// are ranked after any known buffer size
+fn buffer_latency(
+    input: &SupportedStreamConfigRange,
+    output: &SupportedStreamConfigRange,
+    sample_rate: SampleRate,
+) -> u64 {
+    let frames = |range: &SupportedStreamConfigRange| match range.buffer_size() {
+        SupportedBufferSize::Range { min, .. } => *min as u64,
+        SupportedBufferSize::Unknown => u32::MAX as u64,
+    };
+
+    (frames(input) + frames(output)) * 1_000_000 / sample_rate.0.max(1) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::mock::{range, MockDevice};
+
+    fn named(device: MockDevice) -> NamedDevice<MockDevice> {
+        NamedDevice::from(device)
+    }
+
+    #[test]
+    fn negotiate_shares_the_input_default_rate() {
+        let input = named(
+            MockDevice::new("Input", vec![range(1, 44100, 48000, SampleFormat::F32)])
+                .with_default_rate(48000),
+        );
+        let output = named(
+            MockDevice::new("Output", vec![range(2, 44100, 96000, SampleFormat::F32)])
+                .with_default_rate(44100),
+        );
+
+        let (input_config, output_config) = negotiate(&input, &output, None).unwrap();
+        assert_eq!(input_config.sample_rate(), SampleRate(48000));
+        assert_eq!(output_config.sample_rate(), SampleRate(48000));
+        assert_eq!(output_config.channels(), 2);
+    }
+
+    #[test]
+    fn negotiate_prefers_f32_samples() {
+        let input = named(MockDevice::new(
+            "Input",
+            vec![
+                range(2, 48000, 48000, SampleFormat::I16),
+                range(2, 48000, 48000, SampleFormat::F32),
+            ],
+        ));
+        let output = named(MockDevice::new(
+            "Output",
+            vec![range(2, 48000, 48000, SampleFormat::F32)],
+        ));
+
+        let (input_config, _) = negotiate(&input, &output, None).unwrap();
+        assert_eq!(input_config.sample_format(), SampleFormat::F32);
+    }
+
+    #[test]
+    fn negotiate_falls_back_to_individual_rates() {
+        let input = named(MockDevice::new(
+            "Input",
+            vec![range(1, 16000, 16000, SampleFormat::F32)],
+        ));
+        let output = named(
+            MockDevice::new("Output", vec![range(2, 48000, 48000, SampleFormat::F32)])
+                .with_default_rate(48000),
+        );
+
+        let (input_config, output_config) = negotiate(&input, &output, None).unwrap();
+        assert_eq!(input_config.sample_rate(), SampleRate(16000));
+        assert_eq!(output_config.sample_rate(), SampleRate(48000));
+    }
+
+    #[test]
+    fn negotiate_rejects_unsupported_rates() {
+        let input = named(MockDevice::new(
+            "Input",
+            vec![range(1, 44100, 48000, SampleFormat::F32)],
+        ));
+        let output = named(MockDevice::new(
+            "Output",
+            vec![range(2, 44100, 48000, SampleFormat::F32)],
+        ));
+
+        assert!(matches!(
+            negotiate(&input, &output, Some(96000)),
+            Err(ChemicError::UnsupportedSampleRate {
+                sample_rate: 96000,
+                ..
+            })
+        ));
+        assert!(matches!(
+            negotiate_input(&named(MockDevice::new("Empty", Vec::new())), None),
+            Err(ChemicError::NoConfigs { .. })
+        ));
+    }
+
+    #[test]
+    fn negotiate_input_without_a_default_uses_a_preferred_rate() {
+        let input = named(MockDevice::new(
+            "Input",
+            vec![
+                range(8, 8000, 192000, SampleFormat::F32),
+                range(2, 8000, 192000, SampleFormat::F32),
+            ],
+        ));
+
+        let config = negotiate_input(&input, None).unwrap();
+        assert_eq!(config.sample_rate(), SampleRate(48000));
+        assert_eq!(config.channels(), 2);
+    }
+
+    #[test]
+    fn negotiate_output_uses_every_channel() {
+        let output = named(
+            MockDevice::new(
+                "Output",
+                vec![
+                    range(2, 48000, 48000, SampleFormat::F32),
+                    range(8, 48000, 48000, SampleFormat::F32),
+                ],
+            )
+            .with_default_rate(48000),
+        );
+
+        let config = negotiate_output(&output, None).unwrap();
+        assert_eq!(config.channels(), 8);
+    }
+}
@@ -0,0 +1,186 @@
+//! Null test between two inputs carrying the same source, recording both
+//! at once, aligning them and subtracting one from the other to check
+//! whether the devices or cables pass the signal through identically
+
+use crate::{
+    align::{align, overlap},
+    args::Args,
+    bed,
+    config::Config,
+    error::ChemicError,
+    find_device,
+    log::info,
+    measure::record,
+    meter::to_db,
+    negotiate::negotiate_input,
+    positive_duration, prompt_device, requested_device, use_plain_output,
+    wav::WavAudio,
+    DeviceType, NamedDevice,
+};
+use cpal::StreamConfig;
+
+/// Default number of seconds the inputs are recorded for
+const DEFAULT_DURATION: f64 = 5.0;
+/// Largest offset between the inputs that is searched for in seconds
+const MAX_OFFSET_SECONDS: f64 = 1.0;
+/// Null depths below which the inputs are considered identical and the
+/// same source with small differences
+const IDENTICAL_DB: f32 = -60.0;
+const SIMILAR_DB: f32 = -30.0;
+
+/// Records the two inputs at once and prints how deep they null
+pub fn run(mut args: Args, second: Option<String>) -> Result<(), ChemicError> {
+    let plain = use_plain_output(args.plain);
+    let config = Config::load(args.config.as_deref())?;
+    args.apply_config(&config)?;
+    let duration = positive_duration(args.duration.unwrap_or(DEFAULT_DURATION), "Duration")?;
+
+    let host = &cpal::default_host();
+    let first = match requested_device(host, &args, DeviceType::Input, true)? {
+        Some(device) => device,
+        None => prompt_device(
+            host,
+            "Select the first input",
+            DeviceType::Input,
+            &config.theme,
+            plain,
+        )?,
+    };
+    let second = match second {
+        Some(name) => find_device(
+            host,
+            DeviceType::Input,
+            config.alias(&name).unwrap_or(&name),
+        )?,
+        None => prompt_device(
+            host,
+            "Select the second input",
+            DeviceType::Input,
+            &config.theme,
+            plain,
+        )?,
+    };
+
+    let first_config: StreamConfig = negotiate_input(&first, args.sample_rate)?.into();
+    let second_config: StreamConfig = negotiate_input(&second, args.sample_rate)?.into();
+
+    println!(
+        "Recording \"{}\" and \"{}\" for {:.1}s, play the same source into both",
+        first.name,
+        second.name,
+        duration.as_secs_f64()
+    );
+    let mut recordings = record(
+        &[(&first, &first_config), (&second, &second_config)],
+        duration,
+    )?;
+    let second_samples = recordings.pop().unwrap_or_default();
+    let first_samples = recordings.pop().unwrap_or_default();
+
+    // The second input is compared at the sample rate of the first
+    let sample_rate = first_config.sample_rate.0;
+    let second_samples = if second_config.sample_rate.0 == sample_rate {
+        second_samples
+    } else {
+        println!(
+            "Resampling \"{}\" from {}Hz to {sample_rate}Hz, the result includes the resampling error",
+            second.name, second_config.sample_rate.0
+        );
+        let audio = WavAudio {
+            sample_rate: second_config.sample_rate.0,
+            channels: 1,
+            samples: second_samples,
+        };
+        bed::resample(&audio, sample_rate as f64, 1)
+    };
+
+    let max_lag = (MAX_OFFSET_SECONDS * sample_rate as f64) as usize;
+    let Some(alignment) = align(&first_samples, &second_samples, max_lag) else {
+        println!("One of the inputs is silent, check that both are receiving the source");
+        return Ok(());
+    };
+    let (a, b) = overlap(&first_samples, &second_samples, alignment.lag);
+
+    report(&first, &second, a, b, alignment.lag, sample_rate);
+    Ok(())
+}
+
+/// Prints the residual of the aligned recordings `a` and `b` of the
+/// `first` and `second` inputs which were offset by the `lag`
+fn report(
+    first: &NamedDevice,
+    second: &NamedDevice,
+    a: &[f32],
+    b: &[f32],
+    lag: isize,
+    sample_rate: u32,
+) {
+    let offset_ms = lag as f64 * 1000.0 / sample_rate as f64;
+    println!(
+        "\n\"{}\" is {:.2} ms {} than \"{}\"",
+        second.name,
+        offset_ms.abs(),
+        if lag >= 0 { "later" } else { "earlier" },
+        first.name
+    );
+
+    let energy = |samples: &[f32]| samples.iter().map(|&s| s as f64 * s as f64).sum::<f64>();
+    let rms = |energy: f64| (energy / a.len().max(1) as f64).sqrt() as f32;
+    let signal_db = to_db(rms(energy(a)));
+
+    // The gain that best matches the second to the first also takes
+    // care of inverted polarity
+    let cross: f64 = a.iter().zip(b).map(|(&a, &b)| a as f64 * b as f64).sum();
+    let gain = cross / energy(b).max(f64::EPSILON);
+    let residual = |gain: f64| {
+        a.iter()
+            .zip(b)
+            .map(|(&a, &b)| (a as f64 - b as f64 * gain).powi(2))
+            .sum::<f64>()
+    };
+    let raw_db = to_db(rms(residual(1.0)));
+    let matched_db = to_db(rms(residual(gain)));
+
+    let depth = |residual: Option<f32>| match (residual, signal_db) {
+        (Some(residual), Some(signal)) => Some(residual - signal),
+        // A residual below the meter floor is a complete null
+        (None, Some(_)) => None,
+        _ => Some(0.0),
+    };
+    let format = |depth: Option<f32>| match depth {
+        Some(depth) => format!("{depth:.1} dB"),
+        None => "complete".to_string(),
+    };
+
+    let raw_depth = depth(raw_db);
+    let matched_depth = depth(matched_db);
+    info!(
+        "null test of \"{}\" and \"{}\": offset {offset_ms:.2} ms, raw {raw_depth:?} dB, matched {matched_depth:?} dB at {gain:.3}",
+        first.name, second.name
+    );
+
+    println!("Residual after subtracting: {}", format(raw_depth));
+    println!(
+        "Residual after matching the level ({:+.2} dB{}): {}",
+        20.0 * gain.abs().log10(),
+        if gain < 0.0 {
+            ", inverted polarity"
+        } else {
+            ""
+        },
+        format(matched_depth)
+    );
+
+    match matched_depth {
+        None => println!("\nThe inputs null completely, they carry identical audio"),
+        Some(depth) if depth <= IDENTICAL_DB => {
+            println!("\nThe inputs are identical apart from noise")
+        }
+        Some(depth) if depth <= SIMILAR_DB => {
+            println!("\nThe inputs carry the same source with small differences in noise, filtering or distortion")
+        }
+        Some(_) => println!(
+            "\nThe inputs differ, they may not carry the same source or one changes the signal"
+        ),
+    }
+}
@@ -0,0 +1,289 @@
+//! Input volume set for a capture device in the mixer of the operating
+//! system, a quiet microphone is very often just a low volume slider.
+//! Read and set through the ALSA mixer of the sound card on Linux, or
+//! the sound server when the device goes through one
+
+use std::{fmt, io};
+
+/// Volume in percent below which the volume is reported as critically
+/// low, the usual cause of a microphone that is too quiet
+pub const LOW_PERCENT: f32 = 20.0;
+/// Boost in decibels above which the boost is reported as high, the
+/// usual cause of a microphone that hisses
+pub const HIGH_BOOST_DB: f32 = 20.0;
+/// Amount the volume is changed by each key press in percent
+pub const STEP_PERCENT: f32 = 5.0;
+
+/// Capture volume of a device in the mixer of the operating system
+#[derive(Debug, Clone)]
+pub struct OsGain {
+    /// Name of the mixer control (e.g. "Capture")
+    pub control: String,
+    /// Volume as a percentage of the range of the control
+    pub percent: f32,
+    /// Gain of the volume in decibels when the control reports it
+    pub db: Option<f32>,
+    /// Lowest and highest gain of the control in decibels when it
+    /// reports them
+    pub db_range: Option<(f32, f32)>,
+    /// Whether capture is switched off on the control
+    pub muted: bool,
+    /// Name and gain in decibels of the highest microphone boost control
+    /// (e.g. "Mic Boost") when the device has one
+    pub boost: Option<(String, f32)>,
+}
+
+impl OsGain {
+    /// Whether the volume is critically low or capture is switched off
+    pub fn is_low(&self) -> bool {
+        self.muted || self.percent < LOW_PERCENT
+    }
+
+    /// Whether the microphone boost is high enough to add audible hiss
+    pub fn is_boosted(&self) -> bool {
+        self.boost
+            .as_ref()
+            .is_some_and(|(_, db)| *db > HIGH_BOOST_DB)
+    }
+}
+
+impl fmt::Display for OsGain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.0}%", self.percent)?;
+        if let Some(db) = self.db {
+            write!(f, " ({db:+.1} dB)")?;
+        }
+        write!(f, ", {}", self.control)?;
+        if let Some((control, db)) = &self.boost {
+            write!(f, ", {control} {db:+.1} dB")?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the capture volume of the input device named `device_name`,
+/// [None] when the platform or device has no volume to read
+pub fn input_gain(device_name: &str) -> Option<OsGain> {
+    platform::with_control(device_name, platform::read)
+        .inspect_err(|err| crate::log::debug!("no OS input gain for \"{device_name}\": {err}"))
+        .ok()
+}
+
+/// Sets the capture volume of the input device named `device_name` to
+/// the `percent` of its range, returning the volume it was set to
+pub fn set_input_gain(device_name: &str, percent: f32) -> io::Result<OsGain> {
+    platform::with_control(device_name, |control| {
+        platform::set_percent(control, percent.clamp(0.0, 100.0))?;
+        platform::read(control)
+    })
+}
+
+/// Raises (or lowers) the capture volume of the input device named
+/// `device_name` by `db` decibels, as far as the control goes
+pub fn raise_input_gain(device_name: &str, db: f32) -> io::Result<OsGain> {
+    platform::with_control(device_name, |control| {
+        platform::raise_db(control, db)?;
+        platform::read(control)
+    })
+}
+
+/// Steps the capture volume of the input device named `device_name` up
+/// or down by the [STEP_PERCENT]
+pub fn step_input_gain(device_name: &str, up: bool) -> io::Result<OsGain> {
+    platform::with_control(device_name, |control| {
+        let step = if up { STEP_PERCENT } else { -STEP_PERCENT };
+        let percent = platform::read(control)?.percent + step;
+        platform::set_percent(control, percent.clamp(0.0, 100.0))?;
+        platform::read(control)
+    })
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::OsGain;
+    use alsa::{
+        mixer::{MilliBel, Mixer, Selem, SelemChannelId},
+        Round,
+    };
+    use std::io;
+
+    /// Controls of the input volume of a device
+    pub struct Controls<'a> {
+        /// Control of the capture volume
+        volume: &'a Selem<'a>,
+        /// Control of the microphone boost with the highest gain
+        boost: Option<&'a Selem<'a>>,
+    }
+
+    /// Opens the mixer of the device named `device_name` and calls `f`
+    /// with its input volume controls
+    pub fn with_control<T>(
+        device_name: &str,
+        f: impl FnOnce(&Controls) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let mixer_name = mixer_name(device_name);
+        let mixer = Mixer::new(&mixer_name, false).map_err(|err| {
+            io::Error::other(format!(
+                "Failed to open the ALSA mixer \"{mixer_name}\": {err}"
+            ))
+        })?;
+
+        let selems: Vec<Selem> = mixer.iter().filter_map(Selem::new).collect();
+        let (boosts, controls): (Vec<&Selem>, Vec<&Selem>) = selems
+            .iter()
+            .filter(|selem| selem.has_capture_volume() || selem.has_playback_volume())
+            .partition(|selem| name(selem).contains("Boost"));
+        let controls: Vec<&Selem> = controls
+            .into_iter()
+            .filter(|selem| selem.has_capture_volume())
+            .collect();
+        let boost = boosts
+            .into_iter()
+            .filter_map(|selem| Some((selem, boost_db(selem)?)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(selem, _)| selem);
+
+        // The main capture control is preferred over the per input controls
+        let volume = controls
+            .iter()
+            .copied()
+            .find(|selem| name(selem) == "Capture")
+            .or(controls.first().copied())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("The ALSA mixer \"{mixer_name}\" has no capture volume"),
+                )
+            })?;
+        f(&Controls { volume, boost })
+    }
+
+    /// Gain of the boost `control` in decibels
+    fn boost_db(control: &Selem) -> Option<f32> {
+        let channel = SelemChannelId::mono();
+        match control.has_capture_volume() {
+            true => control.get_capture_vol_db(channel),
+            false => control.get_playback_vol_db(channel),
+        }
+        .ok()
+        .map(MilliBel::to_db)
+    }
+
+    /// Reads the input volume of the `controls`
+    pub fn read(controls: &Controls) -> io::Result<OsGain> {
+        let control = controls.volume;
+        let channel = SelemChannelId::mono();
+        let (min, max) = control.get_capture_volume_range();
+        let volume = control
+            .get_capture_volume(channel)
+            .map_err(io::Error::other)?;
+        let percent = match max > min {
+            true => (volume - min) as f32 / (max - min) as f32 * 100.0,
+            false => 100.0,
+        };
+        let db = control
+            .get_capture_vol_db(channel)
+            .ok()
+            .map(MilliBel::to_db);
+        let db_range = db.map(|_| {
+            let (min, max) = control.get_capture_db_range();
+            (min.to_db(), max.to_db())
+        });
+        let muted = control.has_capture_switch()
+            && control
+                .get_capture_switch(channel)
+                .is_ok_and(|switch| switch == 0);
+
+        Ok(OsGain {
+            control: name(control),
+            percent,
+            db,
+            db_range,
+            muted,
+            boost: controls
+                .boost
+                .and_then(|boost| Some((name(boost), boost_db(boost)?))),
+        })
+    }
+
+    /// Sets every channel of the capture volume of the `controls` to the
+    /// `percent` of its range
+    pub fn set_percent(controls: &Controls, percent: f32) -> io::Result<()> {
+        let control = controls.volume;
+        let (min, max) = control.get_capture_volume_range();
+        let volume = min + ((max - min) as f32 * percent / 100.0).round() as i64;
+        control
+            .set_capture_volume_all(volume)
+            .map_err(io::Error::other)
+    }
+
+    /// Changes every channel of the capture volume of the `controls` by
+    /// `db` decibels
+    pub fn raise_db(controls: &Controls, db: f32) -> io::Result<()> {
+        let control = controls.volume;
+        let current = control
+            .get_capture_vol_db(SelemChannelId::mono())
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("The \"{}\" control doesn't report decibels", name(control)),
+                )
+            })?;
+        let (min, max) = control.get_capture_db_range();
+        let target = MilliBel::from_db(current.to_db() + db);
+        let target = MilliBel(target.0.clamp(min.0, max.0));
+        // Rounding down never raises past the requested gain
+        control
+            .set_capture_db_all(target, Round::Floor)
+            .map_err(io::Error::other)
+    }
+
+    fn name(control: &Selem) -> String {
+        control.get_id().get_name().unwrap_or_default().to_string()
+    }
+
+    /// Name of the ALSA mixer controlling the device named `device_name`,
+    /// the card of devices on a card (e.g. `hw:CARD=PCH,DEV=0`) or the
+    /// default mixer which is the sound server when one is running
+    fn mixer_name(device_name: &str) -> String {
+        match device_name.split_once("CARD=") {
+            Some((_, rest)) => {
+                let card = rest.split(',').next().unwrap_or(rest);
+                format!("hw:{card}")
+            }
+            None => "default".to_string(),
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use super::OsGain;
+    use std::io;
+
+    /// Controls of the input volume, the OS mixer is only supported on
+    /// Linux
+    pub enum Controls {}
+
+    pub fn with_control<T>(
+        _device_name: &str,
+        _f: impl FnOnce(&Controls) -> io::Result<T>,
+    ) -> io::Result<T> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "The OS input gain can only be read and set on Linux",
+        ))
+    }
+
+    pub fn read(controls: &Controls) -> io::Result<OsGain> {
+        match *controls {}
+    }
+
+    pub fn set_percent(controls: &Controls, _percent: f32) -> io::Result<()> {
+        match *controls {}
+    }
+
+    pub fn raise_db(controls: &Controls, _db: f32) -> io::Result<()> {
+        match *controls {}
+    }
+}
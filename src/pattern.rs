@@ -0,0 +1,422 @@
+//! Regular expressions matched against device names, so a device can be
+//! selected by a pattern when its name changes slightly between ports or
+//! hosts. Supports literals, `.`, character classes (`[a-z]`, `[^0-9]`,
+//! `\d`, `\w`, `\s`), groups with alternatives (`(a|b)`), the `*`, `+`,
+//! `?` and `{n,m}` repetitions and the `^` and `$` anchors. Matching
+//! ignores case and finds the pattern anywhere in the name unless it is
+//! anchored
+
+use std::{fmt, str::FromStr};
+
+/// Compiled device name pattern
+#[derive(Debug, Clone)]
+pub struct DevicePattern {
+    /// Pattern as it was written
+    source: String,
+    /// Alternatives of the whole pattern
+    alternatives: Vec<Vec<Item>>,
+}
+
+/// Part of a pattern with the number of times it repeats
+#[derive(Debug, Clone)]
+struct Item {
+    node: Node,
+    min: usize,
+    /// Most repetitions, [None] when unlimited
+    max: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    /// Character compared ignoring case
+    Char(char),
+    /// Any character
+    Any,
+    /// Character within (or outside when `negated`) the inclusive ranges
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+    /// Start of the name
+    Start,
+    /// End of the name
+    End,
+    /// Group of alternatives
+    Group(Vec<Vec<Item>>),
+}
+
+impl DevicePattern {
+    /// Whether the pattern is found within the device `name`
+    pub fn is_match(&self, name: &str) -> bool {
+        let text: Vec<char> = name.chars().map(lower).collect();
+        (0..=text.len()).any(|start| {
+            self.alternatives
+                .iter()
+                .any(|items| match_items(items, &text, start, &mut |_| true))
+        })
+    }
+}
+
+impl fmt::Display for DevicePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl FromStr for DevicePattern {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            chars: source.chars().collect(),
+            position: 0,
+        };
+        let alternatives = parser.alternatives()?;
+        if let Some(c) = parser.peek() {
+            return Err(format!("unexpected \"{c}\" in pattern \"{source}\""));
+        }
+        Ok(Self {
+            source: source.to_string(),
+            alternatives,
+        })
+    }
+}
+
+/// Lowercase form of the character `c`, characters are compared ignoring
+/// case
+fn lower(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Matches the `items` against the `text` from the `position`, calling
+/// `next` with the position after each way they match until it accepts
+fn match_items(
+    items: &[Item],
+    text: &[char],
+    position: usize,
+    next: &mut dyn FnMut(usize) -> bool,
+) -> bool {
+    let Some((item, rest)) = items.split_first() else {
+        return next(position);
+    };
+    match_repeated(item, 0, text, position, &mut |end| {
+        match_items(rest, text, end, next)
+    })
+}
+
+/// Matches further repetitions of the `item` after `count` of them,
+/// trying the most repetitions first
+fn match_repeated(
+    item: &Item,
+    count: usize,
+    text: &[char],
+    position: usize,
+    next: &mut dyn FnMut(usize) -> bool,
+) -> bool {
+    if item.max.is_none_or(|max| count < max) {
+        let repeated = match_node(&item.node, text, position, &mut |end| {
+            // Repeating something that matched nothing past the fewest
+            // repetitions would never end
+            (end != position || count < item.min)
+                && match_repeated(item, count + 1, text, end, next)
+        });
+        if repeated {
+            return true;
+        }
+    }
+    count >= item.min && next(position)
+}
+
+/// Matches the `node` once at the `position` of the `text`
+fn match_node(
+    node: &Node,
+    text: &[char],
+    position: usize,
+    next: &mut dyn FnMut(usize) -> bool,
+) -> bool {
+    let current = text.get(position).copied();
+    match node {
+        Node::Char(c) => current == Some(*c) && next(position + 1),
+        Node::Any => current.is_some() && next(position + 1),
+        Node::Class { ranges, negated } => match current {
+            Some(c) => {
+                let within = ranges.iter().any(|&(low, high)| low <= c && c <= high);
+                within != *negated && next(position + 1)
+            }
+            None => false,
+        },
+        Node::Start => position == 0 && next(position),
+        Node::End => position == text.len() && next(position),
+        Node::Group(alternatives) => alternatives
+            .iter()
+            .any(|items| match_items(items, text, position, next)),
+    }
+}
+
+/// Parser of the pattern characters
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += 1;
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let matched = self.peek() == Some(c);
+        if matched {
+            self.position += 1;
+        }
+        matched
+    }
+
+    /// Parses alternatives separated by `|` up to the end of the pattern
+    /// or the end of the group
+    fn alternatives(&mut self) -> Result<Vec<Vec<Item>>, String> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.eat('|') {
+            alternatives.push(self.sequence()?);
+        }
+        Ok(alternatives)
+    }
+
+    fn sequence(&mut self) -> Result<Vec<Item>, String> {
+        let mut items = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let node = self.node()?;
+            let (min, max) = self.repetition()?;
+            if (min, max) != (1, Some(1)) && matches!(node, Node::Start | Node::End) {
+                return Err("anchors can't be repeated".to_string());
+            }
+            items.push(Item { node, min, max });
+        }
+        Ok(items)
+    }
+
+    fn node(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some('.') => Ok(Node::Any),
+            Some('^') => Ok(Node::Start),
+            Some('$') => Ok(Node::End),
+            Some('(') => {
+                let alternatives = self.alternatives()?;
+                match self.eat(')') {
+                    true => Ok(Node::Group(alternatives)),
+                    false => Err("unclosed \"(\"".to_string()),
+                }
+            }
+            Some('[') => self.class(),
+            Some('\\') => self.escape(),
+            Some(c @ ('*' | '+' | '?' | '{')) => Err(format!("\"{c}\" has nothing to repeat")),
+            Some(c) => Ok(Node::Char(lower(c))),
+            None => Err("unexpected end of pattern".to_string()),
+        }
+    }
+
+    /// Parses the escaped character after a `\`
+    fn escape(&mut self) -> Result<Node, String> {
+        let class = |ranges: &[(char, char)], negated| Node::Class {
+            ranges: ranges.to_vec(),
+            negated,
+        };
+        const DIGITS: &[(char, char)] = &[('0', '9')];
+        const WORD: &[(char, char)] = &[('a', 'z'), ('0', '9'), ('_', '_')];
+        const SPACE: &[(char, char)] = &[(' ', ' '), ('\t', '\r')];
+
+        match self.next() {
+            Some('d') => Ok(class(DIGITS, false)),
+            Some('D') => Ok(class(DIGITS, true)),
+            Some('w') => Ok(class(WORD, false)),
+            Some('W') => Ok(class(WORD, true)),
+            Some('s') => Ok(class(SPACE, false)),
+            Some('S') => Ok(class(SPACE, true)),
+            Some(c) if c.is_ascii_alphanumeric() => Err(format!("unknown escape \"\\{c}\"")),
+            Some(c) => Ok(Node::Char(lower(c))),
+            None => Err("unexpected end of pattern after \"\\\"".to_string()),
+        }
+    }
+
+    /// Parses a character class after the `[`
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.eat('^');
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let low = match self.next() {
+                Some(']') if !first => break,
+                Some('\\') => lower(self.next().ok_or("unclosed \"[\"")?),
+                Some(c) => lower(c),
+                None => return Err("unclosed \"[\"".to_string()),
+            };
+            first = false;
+
+            let high = match (self.peek(), self.chars.get(self.position + 1)) {
+                (Some('-'), Some(&high)) if high != ']' => {
+                    self.position += 2;
+                    lower(high)
+                }
+                _ => low,
+            };
+            if high < low {
+                return Err(format!("invalid range \"{low}-{high}\""));
+            }
+            ranges.push((low, high));
+        }
+        Ok(Node::Class { ranges, negated })
+    }
+
+    /// Parses the repetition after a node, once when there is none
+    fn repetition(&mut self) -> Result<(usize, Option<usize>), String> {
+        let repetition = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                self.position += 1;
+                return self.counted();
+            }
+            _ => return Ok((1, Some(1))),
+        };
+        self.position += 1;
+        Ok(repetition)
+    }
+
+    /// Parses a `{n}`, `{n,}` or `{n,m}` repetition after the `{`
+    fn counted(&mut self) -> Result<(usize, Option<usize>), String> {
+        let min = self.number()?.ok_or("expected a number after \"{\"")?;
+        let max = match self.eat(',') {
+            true => self.number()?,
+            false => Some(min),
+        };
+        if !self.eat('}') {
+            return Err("unclosed \"{\"".to_string());
+        }
+        if max.is_some_and(|max| max < min) {
+            return Err(format!(
+                "invalid repetition {{{min},{}}}",
+                max.unwrap_or_default()
+            ));
+        }
+        Ok((min, max))
+    }
+
+    /// Parses the digits of a number, [None] when there are none
+    fn number(&mut self) -> Result<Option<usize>, String> {
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+        let digits: String = self.chars[start..self.position].iter().collect();
+        match digits.is_empty() {
+            true => Ok(None),
+            false => digits.parse().map(Some).map_err(|err| format!("{err}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, name: &str) -> bool {
+        pattern.parse::<DevicePattern>().unwrap().is_match(name)
+    }
+
+    fn error(pattern: &str) -> String {
+        pattern.parse::<DevicePattern>().unwrap_err()
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(matches("usb", "Front USB Mic"));
+        assert!(matches("^front", "Front USB Mic"));
+        assert!(!matches("^usb", "Front USB Mic"));
+        assert!(matches("mic$", "Front USB Mic"));
+        assert!(!matches("usb$", "Front USB Mic"));
+        assert!(matches("^$", ""));
+        assert!(!matches("^$", "Mic"));
+    }
+
+    #[test]
+    fn alternation_in_groups() {
+        assert!(matches("^(usb|hdmi) audio$", "USB Audio"));
+        assert!(matches("^(usb|hdmi) audio$", "HDMI Audio"));
+        assert!(!matches("^(usb|hdmi) audio$", "Built-in Audio"));
+        assert!(matches("^a(b|bc)d$", "abcd"));
+        assert!(matches("^(a|b(c|d))+$", "abdbca"));
+        assert!(matches("cam|mic", "Webcam"));
+    }
+
+    #[test]
+    fn counted_repetitions() {
+        assert!(matches("^a{3}$", "aaa"));
+        assert!(!matches("^a{3}$", "aa"));
+        assert!(!matches("^a{3}$", "aaaa"));
+        assert!(matches("^a{2,}$", "aaaaa"));
+        assert!(!matches("^a{2,}$", "a"));
+        assert!(matches("^a{1,2}b$", "aab"));
+        assert!(!matches("^a{1,2}b$", "aaab"));
+        assert!(matches("^x?y+z*$", "yyy"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("^mic [0-9]$", "Mic 2"));
+        assert!(!matches("^mic [^0-9]$", "Mic 2"));
+        assert!(matches("^mic [^0-9]$", "Mic B"));
+        assert!(matches("^[a-c-]+$", "ab-c"));
+        assert!(matches(r"^[\]]$", "]"));
+        assert!(matches(r"^\d+$", "2024"));
+        assert!(!matches(r"\D", "2024"));
+        assert!(matches(r"^\w+\s\w+$", "USB_2\tMic"));
+        assert!(!matches(r"\W", "USB_2"));
+        assert!(matches(r"^\S+$", "hw:1,0"));
+        assert!(matches(r"^hw:\d,\d$", "hw:1,0"));
+        assert!(matches(r"^\(\.\)$", "(.)"));
+    }
+
+    #[test]
+    fn case_is_ignored() {
+        assert!(matches("usb", "USB Audio"));
+        assert!(matches("USB", "usb audio"));
+        assert!(matches("^[A-Z]+$", "Headset"));
+        assert!(matches("^ÄUDIO$", "äudio"));
+    }
+
+    #[test]
+    fn empty_repetitions_end() {
+        assert!(matches("^(a*)*$", "aaaa"));
+        assert!(!matches("^(a*)*$", "aaab"));
+        assert!(matches("^(a?)+b$", "b"));
+        assert!(matches("^(|a)*$", "aa"));
+        assert!(matches("^(a*){2,}$", ""));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(error("*"), "\"*\" has nothing to repeat");
+        assert_eq!(error("a|+"), "\"+\" has nothing to repeat");
+        assert_eq!(error("[z-a]"), "invalid range \"z-a\"");
+        assert_eq!(error("{3,1}"), "\"{\" has nothing to repeat");
+        assert_eq!(error("a{3,1}"), "invalid repetition {3,1}");
+        assert_eq!(error("a{,2}"), "expected a number after \"{\"");
+        assert_eq!(error("a{2"), "unclosed \"{\"");
+        assert_eq!(error("(usb"), "unclosed \"(\"");
+        assert_eq!(error("[usb"), "unclosed \"[\"");
+        assert_eq!(error("usb)"), "unexpected \")\" in pattern \"usb)\"");
+        assert_eq!(error("^*"), "anchors can't be repeated");
+        assert_eq!(error(r"\q"), "unknown escape \"\\q\"");
+        assert_eq!(error("usb\\"), "unexpected end of pattern after \"\\\"");
+    }
+}
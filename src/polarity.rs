@@ -0,0 +1,205 @@
+//! Polarity test that plays pulses through the output device and checks
+//! whether they are captured by the input device with the same polarity,
+//! revealing miswired cables and inverted capsules
+
+use crate::{
+    args::Args,
+    config::Config,
+    error::ChemicError,
+    log::{debug, info, span},
+    measure::{play_and_record, select_device},
+    negotiate::negotiate,
+    DeviceType, NamedDevice,
+};
+use cpal::StreamConfig;
+use std::{f32::consts::PI, time::Duration};
+
+/// Number of pulses played
+const PULSES: usize = 8;
+/// Time between the pulses
+const PULSE_PERIOD: Duration = Duration::from_millis(250);
+/// Length of each pulse
+const PULSE_LENGTH: Duration = Duration::from_millis(2);
+/// Peak level of the pulses
+const PULSE_LEVEL: f32 = 0.5;
+/// Pulses weaker than this fraction of the strongest pulse are ignored
+const DETECTION_RATIO: f32 = 0.5;
+
+/// Result of comparing the polarity of the captured pulses
+enum Polarity {
+    /// Captured pulses have the same polarity as the played pulses
+    Normal { pulses: usize, agreeing: usize },
+    /// Captured pulses are inverted compared to the played pulses
+    Inverted { pulses: usize, agreeing: usize },
+    /// The pulses couldn't be found in the captured audio
+    NotDetected,
+}
+
+/// Plays the test pulses through the output device from the `args` while
+/// recording the input device, printing the detected polarity
+pub fn run(mut args: Args) -> Result<(), ChemicError> {
+    let config = Config::load(args.config.as_deref())?;
+    args.apply_config(&config)?;
+
+    let host = &cpal::default_host();
+    let input = select_device(host, &args, DeviceType::Input)?;
+    let output = select_device(host, &args, DeviceType::Output)?;
+
+    let (input_config, output_config) = negotiate(&input, &output, args.sample_rate)?;
+    let input_config: StreamConfig = input_config.into();
+    let output_config: StreamConfig = output_config.into();
+
+    test(&input, &input_config, &output, &output_config)
+}
+
+/// Plays the test pulses through the `output` while recording the
+/// `input`, printing the detected polarity
+pub fn test(
+    input: &NamedDevice,
+    input_config: &StreamConfig,
+    output: &NamedDevice,
+    output_config: &StreamConfig,
+) -> Result<(), ChemicError> {
+    let _span = span!("polarity");
+
+    println!(
+        "Playing {PULSES} pulses through \"{}\" and recording \"{}\"",
+        output.name, input.name
+    );
+    println!("Place the microphone close to the speaker or connect the output to the input\n");
+
+    let recording = record_pulses(input, input_config, output, output_config)?;
+    let pulse = pulse_shape(input_config.sample_rate.0);
+    let period = (PULSE_PERIOD.as_secs_f64() * input_config.sample_rate.0 as f64) as usize;
+
+    match detect(&recording, &pulse, period) {
+        Polarity::Normal { pulses, agreeing } => {
+            info!("polarity test: normal ({agreeing} of {pulses} pulses)");
+            println!("Polarity: normal ({agreeing} of {pulses} pulses agree)");
+        }
+        Polarity::Inverted { pulses, agreeing } => {
+            info!("polarity test: inverted ({agreeing} of {pulses} pulses)");
+            println!("Polarity: inverted ({agreeing} of {pulses} pulses agree)");
+            println!(
+                "Check the cable wiring and microphone, or monitor with --invert to compensate"
+            );
+        }
+        Polarity::NotDetected => {
+            info!("polarity test: pulses not detected");
+            println!("The test pulses were not detected, move the microphone closer to the speaker or raise the volume");
+        }
+    }
+
+    println!("\nThe result includes the polarity of the speaker, use a loopback cable to test the interface alone");
+
+    Ok(())
+}
+
+/// Positive only raised cosine pulse at the `sample_rate`, the played and
+/// the expected pulses share this shape
+pub fn pulse_shape(sample_rate: u32) -> Vec<f32> {
+    let length = ((PULSE_LENGTH.as_secs_f64() * sample_rate as f64) as usize).max(3);
+    (0..length)
+        .map(|index| {
+            let phase = index as f32 / (length - 1) as f32;
+            (1.0 - (2.0 * PI * phase).cos()) / 2.0
+        })
+        .collect()
+}
+
+/// Plays the pulses through the `output` returning the first channel of
+/// the audio recorded from the `input` at the same time
+fn record_pulses(
+    input: &NamedDevice,
+    input_config: &StreamConfig,
+    output: &NamedDevice,
+    output_config: &StreamConfig,
+) -> Result<Vec<f32>, ChemicError> {
+    let pulse = pulse_shape(output_config.sample_rate.0);
+    let period = (PULSE_PERIOD.as_secs_f64() * output_config.sample_rate.0 as f64) as usize;
+    // The first period is silent while the streams are starting
+    let mut frame_index = 0usize;
+    let signal = move || {
+        let pulse_index = frame_index / period;
+        let offset = frame_index % period;
+        frame_index += 1;
+        if (1..=PULSES).contains(&pulse_index) {
+            pulse.get(offset).copied().unwrap_or_default() * PULSE_LEVEL
+        } else {
+            0.0
+        }
+    };
+
+    let duration = PULSE_PERIOD * (PULSES as u32 + 1);
+    let recording = play_and_record(input, input_config, output, output_config, duration, signal)?;
+
+    debug!(
+        "recorded {} samples at {}Hz for the polarity test",
+        recording.len(),
+        input_config.sample_rate.0
+    );
+
+    Ok(recording)
+}
+
+/// Finds the pulses in the `recording` by correlating it with the
+/// `pulse`, comparing the sign of the strongest match in each `period`
+fn detect(recording: &[f32], pulse: &[f32], period: usize) -> Polarity {
+    let _span = span!("polarity_detect");
+    if recording.len() < pulse.len() || period == 0 {
+        return Polarity::NotDetected;
+    }
+
+    // Remove the DC offset so it doesn't bias the correlation
+    let mean = recording.iter().sum::<f32>() / recording.len() as f32;
+    let correlation: Vec<f32> = recording
+        .windows(pulse.len())
+        .map(|window| {
+            window
+                .iter()
+                .zip(pulse)
+                .map(|(sample, weight)| (sample - mean) * weight)
+                .sum()
+        })
+        .collect();
+
+    // Strongest match within each period
+    let peaks: Vec<f32> = correlation
+        .chunks(period)
+        .filter_map(|chunk| {
+            chunk
+                .iter()
+                .copied()
+                .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+        })
+        .collect();
+
+    let strongest = peaks.iter().map(|peak| peak.abs()).fold(0.0, f32::max);
+    // Anything quieter than a pulse at -60 dBFS isn't a pulse
+    let floor = pulse.iter().map(|weight| weight * weight).sum::<f32>() * 0.001;
+    if strongest <= floor {
+        return Polarity::NotDetected;
+    }
+
+    let detected: Vec<f32> = peaks
+        .into_iter()
+        .filter(|peak| peak.abs() >= strongest * DETECTION_RATIO)
+        .collect();
+    let pulses = detected.len();
+    let positive = detected.iter().filter(|peak| **peak > 0.0).count();
+    let negative = pulses - positive;
+
+    debug!("polarity test detected {pulses} pulses, {positive} positive and {negative} negative");
+
+    if positive >= negative {
+        Polarity::Normal {
+            pulses,
+            agreeing: positive,
+        }
+    } else {
+        Polarity::Inverted {
+            pulses,
+            agreeing: negative,
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! Custom processing stages inserted into the monitor chain by power
+//! users, run by the worker after the conversion to the output format
+//! and before the volume. Stages are built in or loaded from dynamic
+//! libraries with the `plugins` feature
+
+use cpal::StreamConfig;
+use std::{f64::consts::PI, fmt, io, path::PathBuf, str::FromStr};
+
+/// Default cutoff of the high pass stage in Hz, below the voice but
+/// above desk thumps and handling rumble
+const DEFAULT_HIGH_PASS_HZ: f64 = 80.0;
+/// Default threshold of the gate stage in dBFS
+const DEFAULT_GATE_DB: f32 = -50.0;
+/// Time the gate stays open after the level falls below the threshold
+const GATE_HOLD_SECONDS: f64 = 0.1;
+/// Time the gate fades over when it opens and closes
+const GATE_FADE_SECONDS: f64 = 0.01;
+
+/// Stage of processing applied to the monitored audio
+pub trait Processor: Send {
+    /// Processes the interleaved `samples` in place, the samples are
+    /// always whole frames in the output format the stage was built for
+    fn process(&mut self, samples: &mut [f32]);
+}
+
+/// Processing stage requested with the `--processor` flag
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProcessorSpec {
+    /// High pass filter with the cutoff in Hz
+    HighPass(f64),
+    /// Noise gate with the threshold in dBFS
+    Gate(f32),
+    /// Stage loaded from the dynamic library at the path
+    Plugin(PathBuf),
+}
+
+/// Parses "highpass" or "highpass:<HZ>", "gate" or "gate:<DB>" and
+/// "plugin:<PATH>"
+impl FromStr for ProcessorSpec {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (name, option) = match value.split_once(':') {
+            Some((name, option)) => (name, Some(option.trim())),
+            None => (value, None),
+        };
+        match (name.to_lowercase().as_str(), option) {
+            ("highpass", None) => Ok(ProcessorSpec::HighPass(DEFAULT_HIGH_PASS_HZ)),
+            ("highpass", Some(frequency)) => match frequency.parse::<f64>() {
+                Ok(frequency) if frequency.is_finite() && frequency > 0.0 => {
+                    Ok(ProcessorSpec::HighPass(frequency))
+                }
+                _ => Err(format!("invalid high pass frequency \"{frequency}\"")),
+            },
+            ("gate", None) => Ok(ProcessorSpec::Gate(DEFAULT_GATE_DB)),
+            ("gate", Some(threshold)) => match threshold.parse::<f32>() {
+                Ok(threshold) if threshold.is_finite() && threshold <= 0.0 => {
+                    Ok(ProcessorSpec::Gate(threshold))
+                }
+                _ => Err(format!("invalid gate threshold \"{threshold}\"")),
+            },
+            ("plugin", Some(path)) if !path.is_empty() => Ok(ProcessorSpec::Plugin(path.into())),
+            _ => Err(format!(
+                "unknown processor \"{value}\" (expected highpass[:HZ], gate[:DB] or plugin:PATH)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ProcessorSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessorSpec::HighPass(frequency) => write!(f, "a {frequency} Hz high pass"),
+            ProcessorSpec::Gate(threshold) => write!(f, "a gate at {threshold:.1} dBFS"),
+            ProcessorSpec::Plugin(path) => write!(f, "the plugin {}", path.display()),
+        }
+    }
+}
+
+impl ProcessorSpec {
+    /// Builds the stage for audio in the output `config`
+    pub fn build(&self, config: &StreamConfig) -> io::Result<Box<dyn Processor>> {
+        Ok(match self {
+            ProcessorSpec::HighPass(frequency) => Box::new(HighPass::new(*frequency, config)),
+            ProcessorSpec::Gate(threshold) => Box::new(Gate::new(*threshold, config)),
+            ProcessorSpec::Plugin(path) => Box::new(plugin::Plugin::load(path, config)?),
+        })
+    }
+}
+
+/// Second order Butterworth high pass filter applied to each channel
+struct HighPass {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    /// Previous two inputs and outputs of each channel
+    x: Vec<[f64; 2]>,
+    y: Vec<[f64; 2]>,
+}
+
+impl HighPass {
+    fn new(frequency: f64, config: &StreamConfig) -> Self {
+        let sample_rate = config.sample_rate.0.max(1) as f64;
+        let omega = 2.0 * PI * frequency.min(sample_rate * 0.45) / sample_rate;
+        let alpha = omega.sin() / 2.0f64.sqrt();
+        let a0 = 1.0 + alpha;
+        let b = (1.0 + omega.cos()) / 2.0 / a0;
+        let channels = config.channels.max(1) as usize;
+        Self {
+            b0: b,
+            b1: -2.0 * b,
+            b2: b,
+            a1: -2.0 * omega.cos() / a0,
+            a2: (1.0 - alpha) / a0,
+            x: vec![[0.0; 2]; channels],
+            y: vec![[0.0; 2]; channels],
+        }
+    }
+}
+
+impl Processor for HighPass {
+    fn process(&mut self, samples: &mut [f32]) {
+        let channels = self.x.len();
+        for frame in samples.chunks_exact_mut(channels) {
+            for ((sample, x), y) in frame.iter_mut().zip(&mut self.x).zip(&mut self.y) {
+                let input = *sample as f64;
+                let output = self.b0 * input + self.b1 * x[0] + self.b2 * x[1]
+                    - self.a1 * y[0]
+                    - self.a2 * y[1];
+                *x = [input, x[0]];
+                *y = [output, y[0]];
+                *sample = output as f32;
+            }
+        }
+    }
+}
+
+/// Noise gate silencing the audio while the loudest channel stays below
+/// the threshold, held open briefly so the ends of words aren't cut
+struct Gate {
+    /// Linear threshold the peak of a frame opens the gate at
+    threshold: f32,
+    channels: usize,
+    /// Frames the gate stays open for after the last loud frame
+    hold_frames: usize,
+    /// Frames left before the gate starts closing
+    held: usize,
+    /// Gain applied to the audio, faded between 0 and 1
+    gain: f32,
+    /// Change of the gain per frame while fading
+    step: f32,
+}
+
+impl Gate {
+    fn new(threshold_db: f32, config: &StreamConfig) -> Self {
+        let sample_rate = config.sample_rate.0.max(1) as f64;
+        Self {
+            threshold: 10f32.powf(threshold_db / 20.0),
+            channels: config.channels.max(1) as usize,
+            hold_frames: (GATE_HOLD_SECONDS * sample_rate) as usize,
+            held: 0,
+            gain: 0.0,
+            step: 1.0 / (GATE_FADE_SECONDS * sample_rate).max(1.0) as f32,
+        }
+    }
+}
+
+impl Processor for Gate {
+    fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_exact_mut(self.channels) {
+            let peak = frame
+                .iter()
+                .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            if peak >= self.threshold {
+                self.held = self.hold_frames;
+            } else {
+                self.held = self.held.saturating_sub(1);
+            }
+
+            self.gain = match self.held > 0 {
+                true => (self.gain + self.step).min(1.0),
+                false => (self.gain - self.step).max(0.0),
+            };
+            frame.iter_mut().for_each(|sample| *sample *= self.gain);
+        }
+    }
+}
+
+/// Stages loaded from dynamic libraries exporting the C functions
+///
+/// ```c
+/// void *chemic_processor_new(uint32_t sample_rate, uint16_t channels);
+/// void chemic_processor_process(void *state, float *samples, size_t len);
+/// void chemic_processor_free(void *state);
+/// ```
+///
+/// `chemic_processor_new` returns the state passed to the others or null
+/// when the format isn't supported, the state is used from the worker
+/// thread
+#[cfg(all(feature = "plugins", unix))]
+mod plugin {
+    use super::Processor;
+    use cpal::StreamConfig;
+    use std::{
+        ffi::{c_void, CStr, CString},
+        io,
+        os::unix::ffi::OsStrExt,
+        path::Path,
+    };
+
+    type NewFn = unsafe extern "C" fn(u32, u16) -> *mut c_void;
+    type ProcessFn = unsafe extern "C" fn(*mut c_void, *mut f32, usize);
+    type FreeFn = unsafe extern "C" fn(*mut c_void);
+
+    /// Stage from a loaded library, the library stays loaded until the
+    /// stage is dropped
+    pub struct Plugin {
+        library: *mut c_void,
+        state: *mut c_void,
+        process: ProcessFn,
+        free: FreeFn,
+    }
+
+    // The plugin interface requires the state to be usable from the
+    // thread it is moved to
+    unsafe impl Send for Plugin {}
+
+    impl Plugin {
+        pub fn load(path: &Path, config: &StreamConfig) -> io::Result<Self> {
+            let name = CString::new(path.as_os_str().as_bytes())
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+            // SAFETY: The path is a valid C string, loading runs the
+            // initializers of the library the user asked for
+            let library = unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+            if library.is_null() {
+                return Err(io::Error::other(format!(
+                    "Failed to load the processor plugin {}: {}",
+                    path.display(),
+                    last_error()
+                )));
+            }
+
+            let plugin = (|| {
+                let new: NewFn = symbol(library, c"chemic_processor_new")?;
+                let process: ProcessFn = symbol(library, c"chemic_processor_process")?;
+                let free: FreeFn = symbol(library, c"chemic_processor_free")?;
+
+                // SAFETY: Called as declared by the plugin interface
+                let state = unsafe { new(config.sample_rate.0, config.channels) };
+                if state.is_null() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        format!(
+                            "The processor plugin doesn't support {} channels at {}Hz",
+                            config.channels, config.sample_rate.0
+                        ),
+                    ));
+                }
+                Ok(Self {
+                    library,
+                    state,
+                    process,
+                    free,
+                })
+            })();
+
+            if plugin.is_err() {
+                // SAFETY: Nothing from the library is used after closing
+                unsafe { libc::dlclose(library) };
+            }
+            plugin
+                .map_err(|err| io::Error::new(err.kind(), format!("{} ({})", err, path.display())))
+        }
+    }
+
+    impl Processor for Plugin {
+        fn process(&mut self, samples: &mut [f32]) {
+            // SAFETY: The state came from the library and the samples are
+            // valid for their length
+            unsafe { (self.process)(self.state, samples.as_mut_ptr(), samples.len()) }
+        }
+    }
+
+    impl Drop for Plugin {
+        fn drop(&mut self) {
+            // SAFETY: The state is freed once by the library that made it
+            // before the library is closed
+            unsafe {
+                (self.free)(self.state);
+                libc::dlclose(self.library);
+            }
+        }
+    }
+
+    /// Looks up the function `name` in the `library`
+    fn symbol<T: Copy>(library: *mut c_void, name: &CStr) -> io::Result<T> {
+        // SAFETY: The library handle is open and the name is a C string
+        let pointer = unsafe { libc::dlsym(library, name.as_ptr()) };
+        if pointer.is_null() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "The processor plugin doesn't export {}",
+                    name.to_string_lossy()
+                ),
+            ));
+        }
+        // SAFETY: The plugin interface declares the function type, T is
+        // only ever one of the function pointer types
+        Ok(unsafe { std::mem::transmute_copy(&pointer) })
+    }
+
+    /// Error of the last failed dynamic library call
+    fn last_error() -> String {
+        // SAFETY: dlerror returns null or a C string valid until the
+        // next call
+        let error = unsafe { libc::dlerror() };
+        match error.is_null() {
+            true => "unknown error".to_string(),
+            false => unsafe { CStr::from_ptr(error) }
+                .to_string_lossy()
+                .into_owned(),
+        }
+    }
+}
+
+/// Plugin stages without plugin support, which needs the `plugins`
+/// feature on Linux or macOS
+#[cfg(not(all(feature = "plugins", unix)))]
+mod plugin {
+    use super::Processor;
+    use cpal::StreamConfig;
+    use std::{io, path::Path};
+
+    pub enum Plugin {}
+
+    impl Plugin {
+        pub fn load(path: &Path, _config: &StreamConfig) -> io::Result<Self> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "Can't load the processor plugin {}, plugins need chemic built with the \"plugins\" feature on Linux or macOS",
+                    path.display()
+                ),
+            ))
+        }
+    }
+
+    impl Processor for Plugin {
+        fn process(&mut self, _samples: &mut [f32]) {
+            match *self {}
+        }
+    }
+}
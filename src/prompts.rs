@@ -0,0 +1,171 @@
+//! Spoken prompts played through the output device to guide the tests
+//! without reading the terminal. Recordings of the prompts are loaded
+//! from the `prompts` directory next to the config file, prompts without
+//! a recording are played as a short pattern of tones instead
+
+use crate::{
+    bed, config,
+    error::ChemicError,
+    log::{debug, error},
+    meter::from_db,
+    wav::{self, WavAudio},
+    DeviceType, NamedDevice,
+};
+use cpal::{
+    traits::{DeviceTrait, StreamTrait},
+    StreamConfig,
+};
+use std::{f64::consts::TAU, path::PathBuf, thread, time::Duration};
+
+/// Level the prompts are played at in decibels
+const PROMPT_LEVEL_DB: f32 = -12.0;
+/// Length of each tone of the tone patterns
+const TONE_LENGTH: f64 = 0.15;
+/// Silence between the tones of the tone patterns
+const TONE_GAP: f64 = 0.08;
+/// Time the tones fade in and out over
+const TONE_FADE: f64 = 0.005;
+/// Time kept playing after a prompt so the end isn't cut off
+const TAIL: Duration = Duration::from_millis(300);
+
+/// Prompt played to guide the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prompt {
+    LeftChannel,
+    RightChannel,
+    SpeakNow,
+    RecordingFinished,
+}
+
+impl Prompt {
+    /// Text of the prompt, printed as it is played
+    pub fn text(self) -> &'static str {
+        match self {
+            Prompt::LeftChannel => "Left channel",
+            Prompt::RightChannel => "Right channel",
+            Prompt::SpeakNow => "Please speak now",
+            Prompt::RecordingFinished => "Recording finished",
+        }
+    }
+
+    /// Name of the recording of the prompt in the prompts directory
+    fn file_name(self) -> &'static str {
+        match self {
+            Prompt::LeftChannel => "left-channel.wav",
+            Prompt::RightChannel => "right-channel.wav",
+            Prompt::SpeakNow => "speak-now.wav",
+            Prompt::RecordingFinished => "recording-finished.wav",
+        }
+    }
+
+    /// Frequencies of the tones played when there is no recording, the
+    /// number of tones tells the channels apart and the pitch rises to
+    /// start the recording and falls once it has finished
+    fn tones(self) -> &'static [f64] {
+        match self {
+            Prompt::LeftChannel => &[660.0],
+            Prompt::RightChannel => &[880.0, 880.0],
+            Prompt::SpeakNow => &[523.0, 784.0],
+            Prompt::RecordingFinished => &[784.0, 523.0],
+        }
+    }
+
+    /// Mono samples of the prompt at the `sample_rate`
+    pub fn samples(self, sample_rate: u32) -> Vec<f32> {
+        let gain = from_db(PROMPT_LEVEL_DB);
+        let recorded = prompt_path(self).and_then(|path| match wav::read(&path) {
+            Ok(audio) => Some(bed::resample(&audio, sample_rate as f64, 1)),
+            Err(err) => {
+                // Missing recordings are expected, only others are errors
+                if path.exists() {
+                    error!("failed to load prompt {}: {err}", path.display());
+                } else {
+                    debug!("no recording of the prompt at {}", path.display());
+                }
+                None
+            }
+        });
+
+        let mut samples = recorded.unwrap_or_else(|| tone_pattern(self.tones(), sample_rate));
+        samples.iter_mut().for_each(|sample| *sample *= gain);
+        samples
+    }
+}
+
+/// Path of the recording of the `prompt`
+fn prompt_path(prompt: Prompt) -> Option<PathBuf> {
+    config::config_dir().map(|dir| dir.join("prompts").join(prompt.file_name()))
+}
+
+/// Tones at each of the `frequencies` one after another
+fn tone_pattern(frequencies: &[f64], sample_rate: u32) -> Vec<f32> {
+    let sample_rate = sample_rate as f64;
+    let length = (TONE_LENGTH * sample_rate) as usize;
+    let gap = (TONE_GAP * sample_rate) as usize;
+    let fade = (TONE_FADE * sample_rate).max(1.0);
+
+    let mut samples = Vec::with_capacity(frequencies.len() * (length + gap));
+    for frequency in frequencies {
+        samples.extend((0..length).map(|frame| {
+            let envelope = (frame as f64 / fade)
+                .min((length - frame) as f64 / fade)
+                .min(1.0);
+            ((TAU * frequency * frame as f64 / sample_rate).sin() * envelope) as f32
+        }));
+        samples.extend(std::iter::repeat_n(0.0, gap));
+    }
+    samples
+}
+
+/// Plays the mono `samples` at the sample rate of the `config` through
+/// the `output`, on only the `channel` when there is one, returning once
+/// they have been played
+pub fn play(
+    output: &NamedDevice,
+    config: &StreamConfig,
+    samples: Vec<f32>,
+    channel: Option<usize>,
+) -> Result<(), ChemicError> {
+    let duration = Duration::from_secs_f64(samples.len() as f64 / config.sample_rate.0 as f64);
+    let channels = config.channels.max(1) as usize;
+    let mut samples = samples.into_iter();
+
+    let stream = output
+        .device
+        .build_output_stream(
+            config,
+            move |data: &mut [f32], _| {
+                for frame in data.chunks_exact_mut(channels) {
+                    let value = samples.next().unwrap_or_default();
+                    for (index, sample) in frame.iter_mut().enumerate() {
+                        *sample = match channel {
+                            Some(channel) if channel != index => 0.0,
+                            _ => value,
+                        };
+                    }
+                }
+            },
+            |err| error!("output stream error: {err} ({err:?})"),
+            None,
+        )
+        .map_err(|source| ChemicError::BuildStream {
+            ty: DeviceType::Output,
+            device: output.name.clone(),
+            source,
+        })?;
+
+    stream.play()?;
+    thread::sleep(duration + TAIL);
+    Ok(())
+}
+
+/// Converts the mono `samples` recorded at the `from` sample rate to
+/// the sample rate of the output `config`
+pub fn convert(samples: Vec<f32>, from: u32, config: &StreamConfig) -> Vec<f32> {
+    let audio = WavAudio {
+        sample_rate: from,
+        channels: 1,
+        samples,
+    };
+    bed::resample(&audio, config.sample_rate.0 as f64, 1)
+}
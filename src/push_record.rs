@@ -0,0 +1,259 @@
+//! Push to record mode, holding a key records the input and letting go
+//! plays the take straight back, the quickest way to hear how the
+//! microphone sounds
+
+use crate::{
+    args::Args,
+    bed,
+    config::Config,
+    error::ChemicError,
+    log::{error, info},
+    negotiate::negotiate,
+    prompt_device, requested_device, use_plain_output,
+    wav::WavAudio,
+    DeviceType, NamedDevice,
+};
+use cpal::{
+    traits::{DeviceTrait, StreamTrait},
+    Stream, StreamConfig,
+};
+use dialoguer::console::{Key, Term};
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+use std::{
+    io::{self, IsTerminal},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Terminals only report key presses, a held key counts as released once
+/// it stops repeating for this long. Longer than the usual delay before a
+/// held key starts repeating
+const RELEASE_TIMEOUT: Duration = Duration::from_millis(600);
+/// Audio kept after the last repeat of the key, the key was let go
+/// somewhere between the repeats
+const RELEASE_MARGIN: Duration = Duration::from_millis(100);
+/// Longest take that can be recorded
+const MAX_TAKE: Duration = Duration::from_secs(30);
+
+/// Streams that are kept open between the takes
+struct Session {
+    /// Whether the input is being recorded
+    recording: Arc<AtomicBool>,
+    /// Samples of the take being recorded, mixed to mono
+    take: HeapConsumer<f32>,
+    /// Samples queued for playback
+    playback: HeapProducer<f32>,
+    input_config: StreamConfig,
+    output_config: StreamConfig,
+    _input_stream: Stream,
+    _output_stream: Stream,
+}
+
+impl Session {
+    /// Opens the streams recording the `input` and playing back through
+    /// the `output`
+    fn open(
+        input: &NamedDevice,
+        input_config: &StreamConfig,
+        output: &NamedDevice,
+        output_config: &StreamConfig,
+    ) -> Result<Self, ChemicError> {
+        let take_samples = (MAX_TAKE.as_secs_f64() * input_config.sample_rate.0 as f64) as usize;
+        let (mut take_producer, take) = HeapRb::new(take_samples).split();
+        let playback_samples = (MAX_TAKE.as_secs_f64() * output_config.sample_rate.0 as f64)
+            as usize
+            * output_config.channels.max(1) as usize;
+        let (playback, mut playback_consumer) = HeapRb::new(playback_samples).split();
+
+        let recording = Arc::new(AtomicBool::new(false));
+        let input_channels = input_config.channels.max(1) as usize;
+        let input_stream = input
+            .device
+            .build_input_stream(
+                input_config,
+                {
+                    let recording = recording.clone();
+                    move |data: &[f32], _| {
+                        if !recording.load(Ordering::Relaxed) {
+                            return;
+                        }
+                        for frame in data.chunks_exact(input_channels) {
+                            _ = take_producer
+                                .push(frame.iter().sum::<f32>() / input_channels as f32);
+                        }
+                    }
+                },
+                |err| error!("input stream error: {err} ({err:?})"),
+                None,
+            )
+            .map_err(|source| ChemicError::BuildStream {
+                ty: DeviceType::Input,
+                device: input.name.clone(),
+                source,
+            })?;
+
+        let output_stream = output
+            .device
+            .build_output_stream(
+                output_config,
+                move |data: &mut [f32], _| {
+                    let popped = playback_consumer.pop_slice(data);
+                    data[popped..].fill(0.0);
+                },
+                |err| error!("output stream error: {err} ({err:?})"),
+                None,
+            )
+            .map_err(|source| ChemicError::BuildStream {
+                ty: DeviceType::Output,
+                device: output.name.clone(),
+                source,
+            })?;
+
+        input_stream.play()?;
+        output_stream.play()?;
+
+        Ok(Self {
+            recording,
+            take,
+            playback,
+            input_config: input_config.clone(),
+            output_config: output_config.clone(),
+            _input_stream: input_stream,
+            _output_stream: output_stream,
+        })
+    }
+
+    /// Starts recording a new take
+    fn start_take(&mut self) {
+        self.take.clear();
+        self.recording.store(true, Ordering::Relaxed);
+    }
+
+    /// Stops recording, returning the take cut to the `length`
+    fn finish_take(&mut self, length: Duration) -> Vec<f32> {
+        self.recording.store(false, Ordering::Relaxed);
+        let mut samples: Vec<f32> = self.take.pop_iter().collect();
+        let kept = (length.as_secs_f64() * self.input_config.sample_rate.0 as f64) as usize;
+        samples.truncate(kept);
+        samples
+    }
+
+    /// Plays the `samples` of a take back, returning how long it plays for
+    fn play(&mut self, samples: Vec<f32>) -> Duration {
+        let audio = WavAudio {
+            sample_rate: self.input_config.sample_rate.0,
+            channels: 1,
+            samples,
+        };
+        let converted = bed::resample(
+            &audio,
+            self.output_config.sample_rate.0 as f64,
+            self.output_config.channels.max(1) as usize,
+        );
+        self.playback.push_slice(&converted);
+        Duration::from_secs_f64(audio.samples.len() as f64 / audio.sample_rate as f64)
+    }
+}
+
+/// Records a take while Space is held and plays it back once it is let
+/// go, until Escape or "q" is pressed
+pub fn run(mut args: Args) -> Result<(), ChemicError> {
+    let plain = use_plain_output(args.plain);
+    let config = Config::load(args.config.as_deref())?;
+    args.apply_config(&config)?;
+
+    if !io::stdin().is_terminal() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "push-to-record reads the held key from a terminal",
+        )
+        .into());
+    }
+
+    let host = &cpal::default_host();
+    let input = match requested_device(host, &args, DeviceType::Input, true)? {
+        Some(device) => device,
+        None => prompt_device(
+            host,
+            "Select the input to record",
+            DeviceType::Input,
+            &config.theme,
+            plain,
+        )?,
+    };
+    let output = match requested_device(host, &args, DeviceType::Output, true)? {
+        Some(device) => device,
+        None => prompt_device(
+            host,
+            "Select the output to play back through",
+            DeviceType::Output,
+            &config.theme,
+            plain,
+        )?,
+    };
+
+    let (input_config, output_config) = negotiate(&input, &output, args.sample_rate)?;
+    let input_config: StreamConfig = input_config.into();
+    let output_config: StreamConfig = output_config.into();
+    let mut session = Session::open(&input, &input_config, &output, &output_config)?;
+
+    println!(
+        "Recording \"{}\" and playing back through \"{}\"",
+        input.name, output.name
+    );
+    println!(
+        "Hold Space to record (up to {} seconds) and let go to hear it back, press Escape or \"q\" to stop\n",
+        MAX_TAKE.as_secs()
+    );
+
+    // Keys are read on their own thread so the release can be timed
+    let (keys, presses) = mpsc::channel();
+    thread::spawn(move || {
+        let term = Term::stderr();
+        while let Ok(key) = term.read_key() {
+            if keys.send(key).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut takes = 0;
+    while let Ok(key) = presses.recv() {
+        match key {
+            Key::Char(' ') => {}
+            Key::Escape | Key::Char('q') | Key::CtrlC => break,
+            _ => continue,
+        }
+
+        session.start_take();
+        let started = Instant::now();
+        let mut last_press = started;
+        print!("Recording...");
+        _ = io::Write::flush(&mut io::stdout());
+
+        // Any other key or the key no longer repeating ends the take
+        while let Ok(Key::Char(' ')) = presses.recv_timeout(RELEASE_TIMEOUT) {
+            last_press = Instant::now();
+            if started.elapsed() >= MAX_TAKE {
+                break;
+            }
+        }
+
+        let length = (last_press - started + RELEASE_MARGIN).min(MAX_TAKE);
+        let take = session.finish_take(length);
+        takes += 1;
+        let duration = session.play(take);
+        info!("take {takes}: {:.1} s", duration.as_secs_f64());
+        println!(" playing back {:.1} s", duration.as_secs_f64());
+
+        // Keys pressed during the playback are ignored
+        thread::sleep(duration);
+        while presses.try_recv().is_ok() {}
+    }
+
+    Ok(())
+}
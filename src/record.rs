@@ -0,0 +1,746 @@
+//! Recording of the monitored audio to WAV files, the stream callbacks
+//! pass the samples through ring buffers to a thread writing the files
+
+use crate::{
+    log::{error, format_timestamp, info},
+    stats::StreamStats,
+    wav::{BroadcastInfo, Finishing, WavWriter},
+};
+use cpal::StreamConfig;
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+use std::{
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+/// Audio held for each track while waiting to be written
+const BUFFER: Duration = Duration::from_secs(2);
+/// Time between writing the buffered samples
+const WRITE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// CUE sheet frames in a second
+const CUE_FRAMES: u64 = 75;
+
+/// Limit of the length of each file of a recording, longer recordings
+/// continue in a new file without losing any samples
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SegmentLimit {
+    /// Length of audio in each file
+    Duration(Duration),
+    /// Bytes of audio in each file
+    Bytes(u64),
+}
+
+impl SegmentLimit {
+    /// Number of frames of audio at the `sample_rate` with the number of
+    /// interleaved `channels` of `sample_bytes` each in each file
+    fn frames(&self, sample_rate: u32, channels: u16, sample_bytes: u16) -> u64 {
+        let frames = match self {
+            SegmentLimit::Duration(duration) => {
+                (duration.as_secs_f64() * sample_rate as f64) as u64
+            }
+            SegmentLimit::Bytes(bytes) => bytes / (channels.max(1) * sample_bytes) as u64,
+        };
+        frames.max(1)
+    }
+}
+
+/// Parses minutes (e.g. "30" or "30m"), hours ("2h"), seconds ("90s"),
+/// megabytes ("500MB") or gigabytes ("2GB")
+impl FromStr for SegmentLimit {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let lower = value.trim().to_lowercase();
+        let split = lower
+            .find(|char: char| !(char.is_ascii_digit() || char == '.'))
+            .unwrap_or(lower.len());
+        let (number, unit) = lower.split_at(split);
+        let invalid = || format!("invalid segment limit \"{value}\" (e.g. 30m, 2h or 500MB)");
+
+        let number: f64 = number.parse().map_err(|_| invalid())?;
+        if !number.is_finite() || number <= 0.0 {
+            return Err(invalid());
+        }
+
+        let seconds = |scale: f64| {
+            Ok(SegmentLimit::Duration(Duration::from_secs_f64(
+                number * scale,
+            )))
+        };
+        let bytes = |scale: f64| Ok(SegmentLimit::Bytes((number * scale) as u64));
+        match unit.trim() {
+            "s" | "sec" => seconds(1.0),
+            "" | "m" | "min" => seconds(60.0),
+            "h" => seconds(3600.0),
+            "mb" => bytes(1e6),
+            "gb" => bytes(1e9),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Producer the samples of a track are recorded through, samples that
+/// don't fit in the buffer of the track are counted in the `stats`
+pub struct TrackProducer {
+    producer: HeapProducer<f32>,
+    stats: Arc<StreamStats>,
+}
+
+impl TrackProducer {
+    pub fn new(producer: HeapProducer<f32>, stats: Arc<StreamStats>) -> Self {
+        Self { producer, stats }
+    }
+
+    /// Records the `samples`, counting those that didn't fit when the
+    /// files are written too slowly to keep up
+    pub fn push(&mut self, samples: &[f32]) {
+        let pushed = self.producer.push_slice(samples);
+        if pushed < samples.len() {
+            self.stats
+                .recording_overflow
+                .fetch_add((samples.len() - pushed) as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+/// File of a track and the frame of the recording it starts at
+struct Segment {
+    path: PathBuf,
+    start: u64,
+}
+
+/// Track being recorded and the buffer its samples arrive through, the
+/// track is split over multiple files when it has a [SegmentLimit]
+pub struct Track {
+    /// Path of the first file
+    path: PathBuf,
+    /// Sample rate of the recorded audio
+    sample_rate: u32,
+    /// Number of interleaved channels
+    channels: u16,
+    /// Provenance of the recording, the start time is moved along for
+    /// each new file
+    info: BroadcastInfo,
+    /// Frames in each file
+    segment_frames: Option<u64>,
+    /// Files of the track in the order they were recorded
+    segments: Vec<Segment>,
+    /// Samples written across all the files
+    samples: u64,
+    /// Processing applied as each file is finished
+    finishing: Finishing,
+    /// Frames of silence trimmed from the start of the first file once
+    /// it has been finished
+    trimmed_start: Option<u64>,
+    /// Files waiting for the peak of the whole recording before they are
+    /// finished, when normalizing the files are finished together so the
+    /// level doesn't jump between them
+    unfinished: Vec<WavWriter>,
+    consumer: HeapConsumer<f32>,
+    writer: WavWriter,
+}
+
+impl Track {
+    /// Creates the file at the `path` for audio at the `sample_rate` with
+    /// the number of interleaved `channels` described by the `info`,
+    /// returning the producer the samples are recorded through. The
+    /// recording moves to a new file whenever it reaches the `segment`
+    /// limit and each file gets the `finishing` when it is finished
+    pub fn create(
+        path: &Path,
+        sample_rate: u32,
+        channels: u16,
+        info: BroadcastInfo,
+        segment: Option<SegmentLimit>,
+        finishing: Finishing,
+    ) -> io::Result<(HeapProducer<f32>, Track)> {
+        let channels = channels.max(1);
+        let writer =
+            WavWriter::create(path, sample_rate, channels, info.clone())?.finishing(finishing);
+        let capacity = (BUFFER.as_secs_f64() * sample_rate as f64) as usize * channels as usize;
+        let (producer, consumer) = HeapRb::new(capacity).split();
+        Ok((
+            producer,
+            Track {
+                path: path.to_path_buf(),
+                sample_rate,
+                channels,
+                info,
+                segment_frames: segment
+                    .map(|limit| limit.frames(sample_rate, channels, finishing.format.bytes())),
+                segments: vec![Segment {
+                    path: path.to_path_buf(),
+                    start: 0,
+                }],
+                samples: 0,
+                finishing,
+                trimmed_start: None,
+                unfinished: Vec::new(),
+                consumer,
+                writer,
+            },
+        ))
+    }
+
+    /// Writes the samples waiting in the buffer, moving to a new file at
+    /// the segment limit. The `markers` that fall in a finished file are
+    /// written to it as cue points
+    fn drain(&mut self, scratch: &mut [f32], markers: &Mutex<Vec<Marker>>) -> io::Result<()> {
+        let channels = self.channels as u64;
+        loop {
+            let count = self.consumer.pop_slice(scratch);
+            if count == 0 {
+                return Ok(());
+            }
+
+            // Files are split on the exact sample so no audio is lost
+            // between them
+            let mut samples = &scratch[..count];
+            while !samples.is_empty() {
+                let Some(segment_frames) = self.segment_frames else {
+                    self.writer.write(samples)?;
+                    self.samples += samples.len() as u64;
+                    break;
+                };
+
+                let end = (self.current_start() + segment_frames) * channels;
+                let room = (end - self.samples).min(samples.len() as u64) as usize;
+                let (now, rest) = samples.split_at(room);
+                self.writer.write(now)?;
+                self.samples += now.len() as u64;
+                samples = rest;
+
+                if self.samples == end {
+                    self.next_segment(&lock(markers))?;
+                }
+            }
+        }
+    }
+
+    /// Frame of the recording the current file starts at
+    fn current_start(&self) -> u64 {
+        self.segments.last().map_or(0, |segment| segment.start)
+    }
+
+    /// Finishes the current file and continues in the next one
+    fn next_segment(&mut self, markers: &[Marker]) -> io::Result<()> {
+        let start = self.samples / self.channels as u64;
+        let path = segment_path(&self.path, self.segments.len() + 1);
+        let offset = Duration::from_secs_f64(start as f64 / self.sample_rate as f64);
+        let info = BroadcastInfo {
+            started: self.info.started + offset,
+            ..self.info.clone()
+        };
+
+        // Only the start of the recording and the end of the last file
+        // are trimmed
+        let mut finishing = self.finishing;
+        if let Some(trim) = &mut finishing.trim {
+            trim.start = false;
+        }
+
+        let cues = self.cues(markers, start);
+        let writer =
+            WavWriter::create(&path, self.sample_rate, self.channels, info)?.finishing(finishing);
+        let mut previous = std::mem::replace(&mut self.writer, writer);
+        for (frame, label) in cues {
+            previous.add_cue(frame, &label);
+        }
+        previous.continues();
+        if self.segments.len() == 1 {
+            self.trimmed_start = Some(previous.trimmed_start());
+        }
+        match self.finishing.normalize {
+            Some(_) => self.unfinished.push(previous),
+            None => previous.finish()?,
+        }
+
+        info!("recording continues in {}", path.display());
+        self.segments.push(Segment { path, start });
+        Ok(())
+    }
+
+    /// Cue points for the `markers` dropped in the current file before
+    /// the `end` frame, with the frames relative to the start of the file
+    fn cues(&self, markers: &[Marker], end: u64) -> Vec<(u64, String)> {
+        let start = self.current_start();
+        markers
+            .iter()
+            .map(|marker| (self.marker_frame(marker), marker.label()))
+            .filter(|(frame, _)| (start..end).contains(frame))
+            .map(|(frame, label)| (frame - start, label))
+            .collect()
+    }
+
+    /// Frames of silence trimmed from the start of the recording
+    fn trimmed_start(&self) -> u64 {
+        self.trimmed_start
+            .unwrap_or_else(|| self.writer.trimmed_start())
+    }
+
+    /// Frame of the recording the `marker` was dropped at
+    fn marker_frame(&self, marker: &Marker) -> u64 {
+        (marker.position.as_secs_f64() * self.sample_rate as f64) as u64
+    }
+
+    /// Finishes the current file with the `markers` dropped in it, the
+    /// markers dropped after the last sample are kept in the last file.
+    /// Files waiting to be normalized are finished along with it using
+    /// the peak of all the files
+    fn finish(self, markers: &[Marker]) -> io::Result<()> {
+        let cues = self.cues(markers, u64::MAX);
+        let mut writer = self.writer;
+        for (frame, label) in cues {
+            writer.add_cue(frame, &label);
+        }
+
+        let mut writers = self.unfinished;
+        writers.push(writer);
+        let peak = writers.iter().map(WavWriter::peak).fold(0.0, f32::max);
+        writers.into_iter().try_for_each(|mut writer| {
+            writer.normalize_with_peak(peak);
+            writer.finish()
+        })
+    }
+}
+
+/// Locks the `markers`, a panic while holding the lock leaves the
+/// markers intact
+fn lock(markers: &Mutex<Vec<Marker>>) -> MutexGuard<'_, Vec<Marker>> {
+    markers.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Path of the file numbered `number` of a recording split over
+/// multiple files, the first file keeps the `path` (e.g. the second file
+/// of "take.wav" is "take-002.wav")
+pub fn segment_path(path: &Path, number: usize) -> PathBuf {
+    if number <= 1 {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}-{number:03}.{}", extension.to_string_lossy()),
+        None => format!("{stem}-{number:03}"),
+    };
+    path.with_file_name(name)
+}
+
+/// Highest sequence number tried for the `{seq}` placeholder
+const MAX_SEQUENCE: u32 = 9999;
+
+/// Expands the placeholders in the recording path `template` for a
+/// recording of the `device` started at `started`:
+///
+/// - `{device}` name of the device, with anything but letters, digits,
+///   `-` and `_` replaced for use in file names
+/// - `{date}` UTC date the recording started (e.g. "2024-01-31")
+/// - `{time}` UTC time the recording started (e.g. "09-15-00")
+/// - `{seq}` lowest number from 001 that doesn't overwrite a recording
+///
+/// Unknown placeholders are left as they are
+pub fn expand_path(template: &Path, device: &str, started: SystemTime) -> PathBuf {
+    let template = template.to_string_lossy();
+    let timestamp = format_timestamp(started);
+    let expanded = template
+        .replace("{device}", &file_name_safe(device))
+        .replace("{date}", &timestamp[..10])
+        .replace("{time}", &timestamp[11..19].replace(':', "-"));
+
+    if !expanded.contains("{seq}") {
+        return PathBuf::from(expanded);
+    }
+
+    let numbered =
+        |sequence: u32| PathBuf::from(expanded.replace("{seq}", &format!("{sequence:03}")));
+    let taken = |path: &Path| path.exists() || processed_path(path).exists();
+    (1..=MAX_SEQUENCE)
+        .map(numbered)
+        .find(|path| !taken(path))
+        .unwrap_or_else(|| numbered(MAX_SEQUENCE))
+}
+
+/// Replaces each run of characters other than letters, digits, `-` and
+/// `_` in the `name` with a single `_`
+fn file_name_safe(name: &str) -> String {
+    let mut safe = String::with_capacity(name.len());
+    for char in name.chars() {
+        if char.is_alphanumeric() || char == '-' || char == '_' {
+            safe.push(char);
+        } else if !safe.ends_with('_') {
+            safe.push('_');
+        }
+    }
+    safe.trim_matches('_').to_string()
+}
+
+/// Path of the processed track recorded alongside the recording at the
+/// `path` (e.g. "take.wav" becomes "take-processed.wav")
+pub fn processed_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}-processed.{}", extension.to_string_lossy()),
+        None => format!("{stem}-processed"),
+    };
+    path.with_file_name(name)
+}
+
+/// Path of the CUE sheet listing the markers of the recording at the
+/// `path` (e.g. "take.wav" becomes "take.cue")
+pub fn cue_path(path: &Path) -> PathBuf {
+    path.with_extension("cue")
+}
+
+/// Marker dropped by a key press while recording
+#[derive(Clone)]
+pub struct Marker {
+    /// Number of the marker counting from one
+    pub number: usize,
+    /// Time into the recording
+    pub position: Duration,
+}
+
+impl Marker {
+    fn label(&self) -> String {
+        format!("Marker {}", self.number)
+    }
+}
+
+/// Background thread writing the tracks
+pub struct Recorder {
+    /// Whether the recording should stop
+    stop: Arc<AtomicBool>,
+    /// Thread writing the tracks
+    handle: Option<JoinHandle<io::Result<()>>>,
+    /// Markers written as cue points when the recording finishes
+    markers: Arc<Mutex<Vec<Marker>>>,
+    /// Counters of the input stream for the position of the markers
+    stats: Arc<StreamStats>,
+    /// Interleaved input samples in a second
+    input_rate: f64,
+    /// Paths of the files being recorded
+    paths: Vec<PathBuf>,
+}
+
+impl Recorder {
+    /// Starts writing the samples arriving for the `tracks`, the position
+    /// of the markers is taken from the `stats` of the input stream using
+    /// the `input_config`
+    pub fn start(
+        mut tracks: Vec<Track>,
+        stats: Arc<StreamStats>,
+        input_config: &StreamConfig,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let markers: Arc<Mutex<Vec<Marker>>> = Arc::default();
+        let paths = tracks.iter().map(|track| track.path.clone()).collect();
+        let handle = thread::spawn({
+            let stop = stop.clone();
+            let markers = markers.clone();
+            move || {
+                let mut scratch = vec![0.0f32; 4096];
+                let mut result = Ok(());
+
+                while result.is_ok() && !stop.load(Ordering::Relaxed) {
+                    thread::sleep(WRITE_INTERVAL);
+                    result = tracks
+                        .iter_mut()
+                        .try_for_each(|track| track.drain(&mut scratch, &markers));
+                }
+
+                // Everything captured before stopping is kept
+                let result = result
+                    .and_then(|_| {
+                        tracks
+                            .iter_mut()
+                            .try_for_each(|track| track.drain(&mut scratch, &markers))
+                    })
+                    .and_then(|_| {
+                        let markers = lock(&markers).clone();
+                        if let Some(track) = tracks.first().filter(|_| !markers.is_empty()) {
+                            write_cue_sheet(track, &markers)?;
+                        }
+                        tracks
+                            .into_iter()
+                            .try_for_each(|track| track.finish(&markers))
+                    });
+                if let Err(err) = &result {
+                    error!("failed to write recording: {err}");
+                }
+                result
+            }
+        });
+
+        Self {
+            stop,
+            handle: Some(handle),
+            markers,
+            stats,
+            input_rate: input_config.sample_rate.0 as f64 * input_config.channels.max(1) as f64,
+            paths,
+        }
+    }
+
+    /// Paths of the files being recorded
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Drops a marker at the latest captured audio
+    pub fn mark(&self) -> Marker {
+        let samples = self.stats.input_samples.load(Ordering::Relaxed);
+        let position = Duration::from_secs_f64(samples as f64 / self.input_rate);
+
+        let mut markers = lock(&self.markers);
+        let marker = Marker {
+            number: markers.len() + 1,
+            position,
+        };
+        markers.push(marker.clone());
+        marker
+    }
+
+    /// Stops recording once the buffered samples are written, returning
+    /// the first error writing the files
+    pub fn finish(mut self) -> io::Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        match self.handle.take() {
+            Some(handle) => handle
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("recording thread panicked"))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Recording stops when the recorder is dropped, including when opening
+/// the streams fails after the recording was started
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            _ = handle.join();
+        }
+    }
+}
+
+/// Writes the CUE sheet listing the `markers` of the `track` as tracks
+/// of their own, the first starts at the beginning of the recording and
+/// each further file of the recording starts with a track
+fn write_cue_sheet(track: &Track, markers: &[Marker]) -> io::Result<()> {
+    let index = |frames: u64| {
+        let frames = frames * CUE_FRAMES / track.sample_rate.max(1) as u64;
+        format!(
+            "{:02}:{:02}:{:02}",
+            frames / CUE_FRAMES / 60,
+            frames / CUE_FRAMES % 60,
+            frames % CUE_FRAMES
+        )
+    };
+
+    let mut sheet = String::new();
+    let mut number = 1;
+    for (position, segment) in track.segments.iter().enumerate() {
+        let end = track
+            .segments
+            .get(position + 1)
+            .map_or(u64::MAX, |next| next.start);
+        let name = segment
+            .path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        _ = writeln!(sheet, "FILE \"{name}\" WAVE");
+
+        let first = match position {
+            0 => "Start".to_string(),
+            _ => format!("Segment {}", position + 1),
+        };
+        let starts = std::iter::once((first, segment.start)).chain(
+            markers
+                .iter()
+                .map(|marker| (marker.label(), track.marker_frame(marker)))
+                .filter(|(_, frame)| (segment.start..end).contains(frame)),
+        );
+        // The first file starts where its leading silence was trimmed
+        let trimmed = match position {
+            0 => track.trimmed_start(),
+            _ => 0,
+        };
+        for (title, frame) in starts {
+            _ = writeln!(sheet, "  TRACK {number:02} AUDIO");
+            _ = writeln!(sheet, "    TITLE \"{title}\"");
+            let frame = (frame - segment.start).saturating_sub(trimmed);
+            _ = writeln!(sheet, "    INDEX 01 {}", index(frame));
+            number += 1;
+        }
+    }
+
+    fs::write(cue_path(&track.path), sheet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        meter::from_db,
+        wav::{self, SampleFormat},
+    };
+    use std::{env, process, time::UNIX_EPOCH};
+
+    #[test]
+    fn segment_limits_parse() {
+        let minutes = |minutes: u64| Ok(SegmentLimit::Duration(Duration::from_secs(minutes * 60)));
+        assert_eq!("30".parse(), minutes(30));
+        assert_eq!(
+            " 1.5M ".parse(),
+            Ok(SegmentLimit::Duration(Duration::from_secs(90)))
+        );
+        assert_eq!(
+            "90s".parse(),
+            Ok(SegmentLimit::Duration(Duration::from_secs(90)))
+        );
+        assert_eq!("2h".parse(), minutes(120));
+        assert_eq!("500MB".parse(), Ok(SegmentLimit::Bytes(500_000_000)));
+        assert_eq!("2gb".parse(), Ok(SegmentLimit::Bytes(2_000_000_000)));
+        for invalid in ["", "0", "-1m", "10x", "1.5.2", "m"] {
+            assert!(invalid.parse::<SegmentLimit>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn segment_limits_in_frames() {
+        let duration = SegmentLimit::Duration(Duration::from_millis(2500));
+        assert_eq!(duration.frames(48000, 2, 4), 120_000);
+        assert_eq!(SegmentLimit::Bytes(1000).frames(48000, 2, 4), 125);
+        assert_eq!(SegmentLimit::Bytes(1000).frames(48000, 2, 3), 166);
+        assert_eq!(SegmentLimit::Bytes(1).frames(48000, 2, 4), 1);
+    }
+
+    #[test]
+    fn segment_paths() {
+        let path = Path::new("takes/take.wav");
+        assert_eq!(segment_path(path, 1), path);
+        assert_eq!(segment_path(path, 2), Path::new("takes/take-002.wav"));
+        assert_eq!(segment_path(Path::new("take"), 12), Path::new("take-012"));
+    }
+
+    /// Records the `samples` through a track split into files of the
+    /// `segment` frames finished with the `finishing`, returning the
+    /// samples of each file read back
+    fn record_segments(
+        name: &str,
+        channels: u16,
+        finishing: Finishing,
+        segment: u64,
+        samples: &[f32],
+    ) -> Vec<Vec<f32>> {
+        let dir = env::temp_dir().join(format!("chemic-record-{}-{name}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("take.wav");
+        let info = BroadcastInfo {
+            description: "Test".to_string(),
+            device: "Mic".to_string(),
+            started: UNIX_EPOCH,
+        };
+        let limit =
+            SegmentLimit::Bytes(segment * channels as u64 * finishing.format.bytes() as u64);
+        let (mut producer, mut track) =
+            Track::create(&path, 1000, channels, info, Some(limit), finishing).unwrap();
+
+        // Scratch buffers that don't line up with the frames or files
+        let markers = Mutex::new(Vec::new());
+        let mut scratch = vec![0.0; 333];
+        for block in samples.chunks(1000) {
+            assert_eq!(producer.push_slice(block), block.len());
+            track.drain(&mut scratch, &markers).unwrap();
+        }
+        let files = track.segments.len();
+        track.finish(&[]).unwrap();
+
+        let read = (1..=files)
+            .map(|number| {
+                let audio = wav::read(&segment_path(&path, number)).unwrap();
+                assert_eq!(audio.channels, channels);
+                audio.samples
+            })
+            .collect();
+        assert!(!segment_path(&path, files + 1).exists());
+        fs::remove_dir_all(dir).unwrap();
+        read
+    }
+
+    #[test]
+    fn segments_split_without_gaps() {
+        let samples: Vec<f32> = (0..4000).map(|index| index as f32).collect();
+        let files = record_segments("float", 2, Finishing::default(), 300, &samples);
+
+        let lengths: Vec<usize> = files.iter().map(Vec::len).collect();
+        assert_eq!(lengths, [600, 600, 600, 600, 600, 600, 400]);
+        assert_eq!(files.concat(), samples);
+    }
+
+    #[test]
+    fn segments_split_odd_24_bit_files() {
+        let samples: Vec<f32> = (0..2000).map(|index| index as f32 / 4096.0).collect();
+        let finishing = Finishing {
+            format: SampleFormat::Int24,
+            ..Finishing::default()
+        };
+        let files = record_segments("24", 1, finishing, 301, &samples);
+
+        let lengths: Vec<usize> = files.iter().map(Vec::len).collect();
+        assert_eq!(lengths, [301, 301, 301, 301, 301, 301, 194]);
+        let read = files.concat();
+        assert_eq!(read.len(), samples.len());
+        for (read, sample) in read.iter().zip(&samples) {
+            assert!((read - sample).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn track_producer_counts_the_overflow() {
+        let (producer, mut consumer) = HeapRb::new(4).split();
+        let stats = Arc::new(StreamStats::default());
+        let mut producer = TrackProducer::new(producer, stats.clone());
+
+        producer.push(&[0.1, 0.2, 0.3]);
+        producer.push(&[0.4, 0.5, 0.6]);
+        assert_eq!(stats.recording_overflow.load(Ordering::Relaxed), 2);
+        let mut read = [0.0; 4];
+        assert_eq!(consumer.pop_slice(&mut read), 4);
+        assert_eq!(read, [0.1, 0.2, 0.3, 0.4]);
+    }
+
+    #[test]
+    fn segments_are_normalized_together() {
+        // A quiet file followed by a file four times as loud
+        let mut samples = vec![0.125; 500];
+        samples.extend(vec![-0.5; 400]);
+        let finishing = Finishing {
+            normalize: Some(-6.0),
+            format: SampleFormat::Int24,
+            ..Finishing::default()
+        };
+        let files = record_segments("normalize", 1, finishing, 500, &samples);
+
+        // The gain of the loudest file is used for both, keeping the
+        // difference in level between them
+        let gain = from_db(-6.0) / 0.5;
+        assert_eq!(files.len(), 2);
+        for (file, (level, length)) in files.iter().zip([(0.125, 500), (-0.5, 400)]) {
+            assert_eq!(file.len(), length);
+            assert!(file
+                .iter()
+                .all(|sample| (sample - level * gain).abs() < 1e-6));
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Acoustic measurement against a reference file played through the
+//! speakers, the capture is aligned to the file by cross correlation so
+//! the delay, level and distortion can be measured without a loopback
+//! cable
+
+use crate::{
+    align::{align, overlap},
+    args::Args,
+    bed,
+    config::Config,
+    error::ChemicError,
+    log::info,
+    measure::{play_and_record, select_device},
+    meter::to_db,
+    negotiate::negotiate,
+    wav, DeviceType,
+};
+use cpal::StreamConfig;
+use std::{io, path::Path, time::Duration};
+
+/// Silence recorded after the file so the end of it reaches the input
+const TAIL: Duration = Duration::from_secs(1);
+/// Longest delay from the output to the input that is searched for
+const MAX_DELAY_SECONDS: f64 = 2.0;
+/// Correlation below which the file wasn't found in the capture
+const MIN_CORRELATION: f64 = 0.1;
+
+/// Plays the WAV file at the `path` through the output device from the
+/// `args` while recording the input device, printing how the capture
+/// compares to the file
+pub fn run(mut args: Args, path: &Path) -> Result<(), ChemicError> {
+    let config = Config::load(args.config.as_deref())?;
+    args.apply_config(&config)?;
+
+    let audio = wav::read(path).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!("Failed to read {}: {err}", path.display()),
+        )
+    })?;
+
+    let host = &cpal::default_host();
+    let input = select_device(host, &args, DeviceType::Input)?;
+    let output = select_device(host, &args, DeviceType::Output)?;
+
+    let (input_config, output_config) = negotiate(&input, &output, args.sample_rate)?;
+    let input_config: StreamConfig = input_config.into();
+    let output_config: StreamConfig = output_config.into();
+    let input_rate = input_config.sample_rate.0;
+
+    // The file is played in mono on every channel and compared at the
+    // sample rate of the input
+    let played = bed::resample(&audio, output_config.sample_rate.0 as f64, 1);
+    let reference = bed::resample(&audio, input_rate as f64, 1);
+    let length = Duration::from_secs_f64(played.len() as f64 / output_config.sample_rate.0 as f64);
+
+    println!(
+        "Playing {} ({:.1}s) through \"{}\" and recording \"{}\"",
+        path.display(),
+        length.as_secs_f64(),
+        output.name,
+        input.name
+    );
+    let mut played = played.into_iter();
+    let recording = play_and_record(
+        &input,
+        &input_config,
+        &output,
+        &output_config,
+        length + TAIL,
+        move || played.next().unwrap_or_default(),
+    )?;
+
+    let max_lag = (MAX_DELAY_SECONDS * input_rate as f64) as usize;
+    let alignment = align(&reference, &recording, max_lag)
+        .filter(|alignment| alignment.correlation.abs() >= MIN_CORRELATION && alignment.lag >= 0);
+    let Some(alignment) = alignment else {
+        println!("The file was not found in the capture, check that the microphone picks up the speakers");
+        return Ok(());
+    };
+
+    let (reference, capture) = overlap(&reference, &recording, alignment.lag);
+    let energy = |samples: &[f32]| samples.iter().map(|&s| s as f64 * s as f64).sum::<f64>();
+    let rms = |energy: f64| (energy / reference.len().max(1) as f64).sqrt() as f32;
+
+    // The reference scaled to best match the capture, what remains is the
+    // distortion, noise and coloration of the speakers, room and input
+    let cross: f64 = reference
+        .iter()
+        .zip(capture)
+        .map(|(&a, &b)| a as f64 * b as f64)
+        .sum();
+    let gain = cross / energy(reference).max(f64::EPSILON);
+    let residual: f64 = reference
+        .iter()
+        .zip(capture)
+        .map(|(&a, &b)| (b as f64 - a as f64 * gain).powi(2))
+        .sum();
+
+    let delay = alignment.lag as f64 / input_rate as f64;
+    let level_db = 20.0 * gain.abs().log10();
+    let capture_db = to_db(rms(energy(capture)));
+    let residual_db = to_db(rms(residual));
+    let distortion_db = residual_db
+        .zip(capture_db)
+        .map(|(residual, capture)| residual - capture);
+    info!(
+        "reference {}: delay {:.1} ms, level {level_db:.1} dB, residual {distortion_db:?} dB, correlation {:.2}",
+        path.display(),
+        delay * 1000.0,
+        alignment.correlation
+    );
+
+    println!(
+        "\nDelay: {:.1} ms from the output to the input, including the stream buffers",
+        delay * 1000.0
+    );
+    println!("Level: {level_db:+.1} dB relative to the file");
+    if gain < 0.0 {
+        println!("Polarity: inverted");
+    }
+    match distortion_db {
+        Some(distortion) => println!(
+            "Distortion and noise: {distortion:.1} dB ({:.2}%) relative to the capture",
+            10f32.powf(distortion / 20.0) * 100.0
+        ),
+        None => println!("Distortion and noise: below the meter range"),
+    }
+    println!("Correlation: {:.2}", alignment.correlation.abs());
+
+    Ok(())
+}
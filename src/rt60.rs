@@ -0,0 +1,317 @@
+//! Room reverberation measurement that plays bursts of noise through the
+//! output device and estimates the RT60 from the decay captured by the
+//! input device after each burst stops
+
+use crate::{
+    args::Args,
+    config::Config,
+    error::ChemicError,
+    log::{debug, info, span},
+    measure::{play_and_record, select_device},
+    negotiate::negotiate,
+    DeviceType,
+};
+use cpal::StreamConfig;
+use std::time::Duration;
+
+/// Number of bursts recorded and averaged
+const BURSTS: usize = 4;
+/// Length of each noise burst
+const BURST_LENGTH: Duration = Duration::from_secs(1);
+/// Silence after each burst for the room to decay, longer than the
+/// reverberation of the rooms people record in
+const DECAY_LENGTH: Duration = Duration::from_secs(2);
+/// Peak level of the noise
+const NOISE_LEVEL: f32 = 0.5;
+/// Length of the blocks the energy of the recording is measured in
+const BLOCK_LENGTH: Duration = Duration::from_millis(5);
+/// Decay range needed above the noise floor for the T20 fit (from -5 dB
+/// to -25 dB with a 10 dB margin), below it the T10 fit is used
+const T20_RANGE_DB: f32 = 35.0;
+/// Decay range needed above the noise floor for the T10 fit
+const T10_RANGE_DB: f32 = 25.0;
+
+/// Result of the reverberation measurement
+enum Reverberation {
+    /// Time for the sound to decay by 60 dB extrapolated from the
+    /// decay between -5 dB and `fit_db`
+    Measured {
+        rt60: Duration,
+        fit_db: f32,
+        range_db: f32,
+    },
+    /// The noise wasn't loud enough above the noise floor of the room
+    TooQuiet { range_db: f32 },
+}
+
+/// Plays the noise bursts through the output device from the `args` while
+/// recording the input device, printing the estimated RT60
+pub fn run(mut args: Args) -> Result<(), ChemicError> {
+    let config = Config::load(args.config.as_deref())?;
+    args.apply_config(&config)?;
+
+    let host = &cpal::default_host();
+    let input = select_device(host, &args, DeviceType::Input)?;
+    let output = select_device(host, &args, DeviceType::Output)?;
+
+    let (input_config, output_config) = negotiate(&input, &output, args.sample_rate)?;
+    let input_config: StreamConfig = input_config.into();
+    let output_config: StreamConfig = output_config.into();
+
+    println!(
+        "Playing {BURSTS} noise bursts through \"{}\" and recording \"{}\"",
+        output.name, input.name
+    );
+    println!(
+        "Place the microphone where you record, away from the speaker, and keep the room quiet\n"
+    );
+
+    let period = BURST_LENGTH + DECAY_LENGTH;
+    let output_period = frames(period, &output_config);
+    let output_burst = frames(BURST_LENGTH, &output_config);
+    let mut noise = Noise::default();
+    let mut frame_index = 0usize;
+    // An extra burst is played first while the streams are starting
+    let signal = move || {
+        let playing = frame_index % output_period < output_burst;
+        frame_index += 1;
+        if playing {
+            noise.next() * NOISE_LEVEL
+        } else {
+            0.0
+        }
+    };
+
+    let recording = play_and_record(
+        &input,
+        &input_config,
+        &output,
+        &output_config,
+        period * (BURSTS as u32 + 1),
+        signal,
+    )?;
+
+    debug!(
+        "recorded {} samples at {}Hz for the reverberation measurement",
+        recording.len(),
+        input_config.sample_rate.0
+    );
+
+    let block = frames(BLOCK_LENGTH, &input_config).max(1);
+    let envelope = period_envelope(&recording, frames(period, &input_config), block);
+    let burst_blocks = frames(BURST_LENGTH, &input_config) / block;
+
+    match estimate(&envelope, burst_blocks, BLOCK_LENGTH) {
+        Some(Reverberation::Measured {
+            rt60,
+            fit_db,
+            range_db,
+        }) => {
+            info!(
+                "reverberation measurement: RT60 {:.2}s from T{:.0} over {range_db:.0} dB",
+                rt60.as_secs_f64(),
+                -fit_db - 5.0
+            );
+            println!(
+                "RT60: {:.2} s (extrapolated from the decay to {fit_db:.0} dB, {range_db:.0} dB above the noise floor)",
+                rt60.as_secs_f64()
+            );
+            println!("{}", describe(rt60));
+        }
+        Some(Reverberation::TooQuiet { range_db }) => {
+            info!("reverberation measurement: only {range_db:.0} dB above the noise floor");
+            println!("The noise was only {range_db:.0} dB above the noise floor of the room, raise the volume or reduce the background noise and try again");
+        }
+        None => {
+            info!("reverberation measurement: noise bursts not detected");
+            println!("The noise bursts were not detected, check that the speaker is playing and the microphone is working");
+        }
+    }
+
+    Ok(())
+}
+
+/// Number of frames of the stream `config` within the `duration`
+fn frames(duration: Duration, config: &StreamConfig) -> usize {
+    (duration.as_secs_f64() * config.sample_rate.0 as f64) as usize
+}
+
+/// Rough description of how a room with the `rt60` sounds
+fn describe(rt60: Duration) -> &'static str {
+    match rt60.as_secs_f32() {
+        rt60 if rt60 < 0.3 => "The room is well damped, suitable for voice recording",
+        rt60 if rt60 < 0.6 => "The room is moderately damped, typical of a furnished room",
+        _ => "The room is reverberant, absorption such as curtains, rugs or panels will help voice recordings",
+    }
+}
+
+/// White noise from a xorshift generator, good enough for a test signal
+/// without a random number dependency
+pub struct Noise(u32);
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self(0x9E37_79B9)
+    }
+}
+
+impl Noise {
+    /// Next noise sample between -1 and 1
+    pub fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 as f32 / u32::MAX as f32) * 2.0 - 1.0
+    }
+}
+
+/// Mean energy of each `block` of samples across the periods of the
+/// `recording`, skipping the first period recorded while the streams
+/// were starting. The envelope wraps around as the bursts repeat
+fn period_envelope(recording: &[f32], period: usize, block: usize) -> Vec<f64> {
+    let _span = span!("rt60_envelope");
+    let blocks = period / block;
+    let mut envelope = vec![0.0f64; blocks];
+    if blocks == 0 {
+        return envelope;
+    }
+
+    let periods: Vec<&[f32]> = recording
+        .chunks_exact(blocks * block)
+        .skip(1)
+        .take(BURSTS)
+        .collect();
+
+    for period in &periods {
+        for (energy, samples) in envelope.iter_mut().zip(period.chunks_exact(block)) {
+            *energy += samples
+                .iter()
+                .map(|&sample| (sample as f64) * (sample as f64))
+                .sum::<f64>();
+        }
+    }
+
+    let count = (periods.len() * block).max(1) as f64;
+    envelope.iter_mut().for_each(|energy| *energy /= count);
+    envelope
+}
+
+fn to_db(energy: f64) -> f32 {
+    (10.0 * energy.max(1e-20).log10()) as f32
+}
+
+/// Estimates the RT60 from the energy `envelope` of one period using the
+/// backward integrated (Schroeder) decay of the noise switched off after
+/// `burst_blocks`, [None] when no burst was found
+fn estimate(envelope: &[f64], burst_blocks: usize, block: Duration) -> Option<Reverberation> {
+    let _span = span!("rt60_estimate");
+    let blocks = envelope.len();
+    if blocks == 0 || burst_blocks == 0 || burst_blocks >= blocks {
+        return None;
+    }
+
+    let levels: Vec<f32> = envelope.iter().copied().map(to_db).collect();
+    let loudest = levels.iter().copied().fold(f32::MIN, f32::max);
+    let quietest = levels.iter().copied().fold(f32::MAX, f32::min);
+    if loudest - quietest < 10.0 {
+        return None;
+    }
+
+    // The burst starts where the level rises through the middle of the
+    // range, found circularly as the recording isn't aligned to the bursts
+    let threshold = (loudest + quietest) / 2.0;
+    let onset = (0..blocks).find(|&index| {
+        let previous = levels[(index + blocks - 1) % blocks];
+        previous < threshold && levels[index] >= threshold
+    })?;
+    let envelope: Vec<f64> = (0..blocks)
+        .map(|index| envelope[(onset + index) % blocks])
+        .collect();
+
+    // Steady level from the middle of the burst and the noise floor from
+    // the end of the decay
+    let steady = mean(&envelope[burst_blocks / 4..burst_blocks * 3 / 4]);
+    let floor = mean(&envelope[blocks - (blocks - burst_blocks) / 4..]);
+    let range_db = to_db(steady) - to_db(floor);
+
+    let fit_db = if range_db >= T20_RANGE_DB {
+        -25.0
+    } else if range_db >= T10_RANGE_DB {
+        -15.0
+    } else {
+        return Some(Reverberation::TooQuiet { range_db });
+    };
+
+    // The decay is integrated until it reaches 5 dB above the noise floor
+    // with the noise floor removed so it doesn't flatten the curve
+    let decay = &envelope[burst_blocks..];
+    let end = decay
+        .iter()
+        .position(|&energy| to_db(energy) < to_db(floor) + 5.0)
+        .unwrap_or(decay.len());
+    let decay: Vec<f64> = decay[..end]
+        .iter()
+        .map(|energy| (energy - floor).max(0.0))
+        .collect();
+
+    let mut remaining: f64 = decay.iter().sum();
+    let total = remaining;
+    if total <= 0.0 {
+        return None;
+    }
+    let schroeder: Vec<f32> = decay
+        .iter()
+        .map(|energy| {
+            let level = to_db(remaining / total);
+            remaining -= energy;
+            level
+        })
+        .collect();
+
+    // Least squares line through the part of the curve between -5 dB and
+    // the end of the fit
+    let points: Vec<(f32, f32)> = schroeder
+        .iter()
+        .enumerate()
+        .filter(|(_, &level)| level <= -5.0 && level >= fit_db)
+        .map(|(index, &level)| (index as f32 * block.as_secs_f32(), level))
+        .collect();
+    let slope = slope(&points)?;
+    if slope >= 0.0 {
+        return None;
+    }
+
+    debug!(
+        "reverberation fit over {} blocks, slope {slope:.1} dB/s, range {range_db:.1} dB",
+        points.len()
+    );
+
+    Some(Reverberation::Measured {
+        rt60: Duration::from_secs_f32(-60.0 / slope),
+        fit_db,
+        range_db,
+    })
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len().max(1) as f64
+}
+
+/// Slope of the least squares line through the `points`, [None] with too
+/// few points for a line
+fn slope(points: &[(f32, f32)]) -> Option<f32> {
+    if points.len() < 3 {
+        return None;
+    }
+
+    let count = points.len() as f32;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f32>() / count;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f32>() / count;
+    let covariance: f32 = points
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum();
+    let variance: f32 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+
+    (variance > 0.0).then(|| covariance / variance)
+}
@@ -0,0 +1,324 @@
+//! Guided setup for new users, choosing the input and output devices and
+//! setting the input gain from the level of their voice on the live
+//! meter, then saving the choices as the default profile
+
+use crate::{
+    args::Args,
+    config::{self, Config, Profile, DEFAULT_PROFILE},
+    error::ChemicError,
+    lang::{tr, Message},
+    log::{error, info},
+    meter::{to_db, Level, LevelMeter, MeterDisplay, MeterScale},
+    meter_mode,
+    negotiate::negotiate_input,
+    os_gain::{self, OsGain},
+    prompt_device, requested_device,
+    stats::StreamStats,
+    use_plain_output, DeviceType, NamedDevice,
+};
+use cpal::{
+    traits::{DeviceTrait, StreamTrait},
+    StreamConfig,
+};
+use std::{io, sync::Arc, thread, time::Duration};
+
+/// Length of each part of the gain check
+const LISTEN_LENGTH: Duration = Duration::from_secs(5);
+/// Length of the windows the levels are measured over
+const WINDOW: Duration = Duration::from_millis(100);
+/// Level in dBFS normal speech should be captured at
+const TARGET_DBFS: f32 = -18.0;
+/// Highest level in dBFS the peaks of loud speech should reach, leaving
+/// headroom before clipping
+const MAX_PEAK_DBFS: f32 = -6.0;
+/// Gain in decibels above which the gain of the interface or microphone
+/// should be raised instead, boosting that much also boosts the noise
+const MAX_DIGITAL_GAIN_DB: f32 = 20.0;
+
+/// Walks through choosing the devices and setting the input gain, saving
+/// them as the profile from the `args` or the default profile
+pub fn run(mut args: Args) -> Result<(), ChemicError> {
+    let plain = use_plain_output(args.plain);
+    let config = Config::load(args.config.as_deref())?;
+    // The devices are chosen again, the profile isn't applied
+    args.resolve_aliases(&config);
+    let profile_name = args.profile.as_deref().unwrap_or(DEFAULT_PROFILE);
+
+    println!("{}\n", tr(Message::SetupWelcome));
+
+    let host = &cpal::default_host();
+    let input = match requested_device(host, &args, DeviceType::Input, true)? {
+        Some(device) => device,
+        None => prompt_device(
+            host,
+            tr(Message::SelectMicrophone),
+            DeviceType::Input,
+            &config.theme,
+            plain,
+        )?,
+    };
+    let output = match requested_device(host, &args, DeviceType::Output, true)? {
+        Some(device) => device,
+        None => prompt_device(
+            host,
+            tr(Message::SelectHeadphones),
+            DeviceType::Output,
+            &config.theme,
+            plain,
+        )?,
+    };
+
+    if let Some(current) = os_gain::input_gain(&input.name) {
+        report_os_gain(&current);
+    }
+
+    let input_config: StreamConfig = negotiate_input(&input, args.sample_rate)?.into();
+    let gain = loop {
+        println!("\n{} \"{}\"", tr(Message::SettingGain), input.name);
+        if let Some(gain) = measure_gain(&input, &input_config, &args, &config)? {
+            break Some(gain);
+        }
+
+        println!("{}", tr(Message::RetryGain));
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line)? == 0 || line.trim().eq_ignore_ascii_case("s") {
+            break None;
+        }
+    };
+    let gain = gain.map(|gain| apply_os_gain(&input, gain)).transpose()?;
+
+    println!("\n{}: {}", pad_label(Message::Input), input.name);
+    println!("{}: {}", pad_label(Message::Output), output.name);
+    if let Some(gain) = gain {
+        println!("{}: {gain:+.1} dB", pad_label(Message::Gain));
+    }
+    println!(
+        "\n{} \"{profile_name}\"? {}",
+        tr(Message::SaveProfile),
+        tr(Message::YesNo)
+    );
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    if line.trim().eq_ignore_ascii_case("n") {
+        println!("{}", tr(Message::NothingSaved));
+        return Ok(());
+    }
+
+    let profile = Profile {
+        input: Some(input.lookup_name().to_string()),
+        output: Some(output.lookup_name().to_string()),
+        gain,
+        ..Profile::default()
+    };
+    let path = config::save_profile(args.config.as_deref(), profile_name, &profile)?;
+    info!(
+        "saved the setup of \"{}\" -> \"{}\" to the \"{profile_name}\" profile",
+        input.name, output.name
+    );
+
+    println!(
+        "{}: \"{profile_name}\" ({})",
+        tr(Message::ProfileSaved),
+        path.display()
+    );
+    match profile_name == DEFAULT_PROFILE {
+        true => println!("{}: chemic", tr(Message::StartMonitoring)),
+        false => println!(
+            "{}: chemic --profile {profile_name}",
+            tr(Message::StartMonitoring)
+        ),
+    }
+    Ok(())
+}
+
+/// Translates the `label` padding it to the width of the longest
+/// summary label so the values line up
+fn pad_label(label: Message) -> String {
+    let width = [Message::Input, Message::Output, Message::Gain]
+        .into_iter()
+        .map(|label| tr(label).chars().count())
+        .max()
+        .unwrap_or_default();
+
+    format!("{:<width$}", tr(label))
+}
+
+/// Listens to the user speaking normally and then a bit louder on the
+/// `input`, returning the gain that brings normal speech to the target
+/// level without loud speech clipping, [None] when nothing was heard
+fn measure_gain(
+    input: &NamedDevice,
+    config: &StreamConfig,
+    args: &Args,
+    chemic_config: &Config,
+) -> Result<Option<f32>, ChemicError> {
+    println!(
+        "{} ({} s)...",
+        tr(Message::SpeakNormally),
+        LISTEN_LENGTH.as_secs()
+    );
+    let normal = listen(input, config, args, chemic_config)?;
+    println!(
+        "{} ({} s)...",
+        tr(Message::SpeakLouder),
+        LISTEN_LENGTH.as_secs()
+    );
+    let loud = listen(input, config, args, chemic_config)?;
+
+    let Some(speech_db) = speech_level(&normal) else {
+        println!("{}", tr(Message::NothingHeard));
+        return Ok(None);
+    };
+    let peak_db = loud
+        .iter()
+        .chain(&normal)
+        .filter_map(|level| level.peak_db())
+        .fold(f32::NEG_INFINITY, f32::max);
+
+    // Normal speech is brought to the target unless that would push the
+    // loudest peaks too close to clipping
+    let gain = (TARGET_DBFS - speech_db).min(MAX_PEAK_DBFS - peak_db);
+    let gain = (gain * 2.0).round() / 2.0;
+    info!("setup measured speech at {speech_db:.1} dBFS peaking at {peak_db:.1} dBFS, gain {gain:+.1} dB");
+
+    println!(
+        "\n{}: {speech_db:.1} dBFS, {}: {peak_db:.1} dBFS",
+        tr(Message::Speech),
+        tr(Message::Peak)
+    );
+    if peak_db >= 0.0 {
+        println!("{}", tr(Message::SpeechClipped));
+    }
+    if gain > MAX_DIGITAL_GAIN_DB {
+        println!(
+            "{}: {} ({gain:+.1} dB)",
+            tr(Message::Warning),
+            tr(Message::HighGain)
+        );
+    }
+    Ok(Some(gain))
+}
+
+/// Warns about input volume settings in the OS mixer that are common
+/// causes of a microphone that is too quiet or hisses
+fn report_os_gain(gain: &OsGain) {
+    let warnings = [
+        (gain.is_low(), Message::OsGainLow, Message::RaiseInputVolume),
+        (
+            gain.is_boosted(),
+            Message::OsGainBoosted,
+            Message::LowerBoost,
+        ),
+    ];
+    for (_, message, hint) in warnings.into_iter().filter(|(warn, ..)| *warn) {
+        println!("\n{}: {} ({gain})", tr(Message::Warning), tr(message));
+        println!("{}", tr(hint));
+    }
+}
+
+/// Offers to apply as much of the `gain` as the input volume in the OS
+/// mixer allows, so a capture that clipped is turned down before it
+/// reaches chemic and less is boosted digitally. Returns the gain that is
+/// left to apply digitally
+fn apply_os_gain(input: &NamedDevice, gain: f32) -> io::Result<f32> {
+    let Some(current) = os_gain::input_gain(&input.name) else {
+        return Ok(gain);
+    };
+    let (Some(db), Some((min_db, max_db))) = (current.db, current.db_range) else {
+        return Ok(gain);
+    };
+    let change = gain.clamp(min_db - db, max_db - db);
+    if change.abs() < 0.5 {
+        return Ok(gain);
+    }
+
+    println!("\n{}: {current}", tr(Message::OsGain));
+    println!(
+        "{} ({change:+.1} dB)? {}",
+        tr(Message::ChangeOsGain),
+        tr(Message::YesNo)
+    );
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    if line.trim().eq_ignore_ascii_case("n") {
+        return Ok(gain);
+    }
+
+    let changed = os_gain::raise_input_gain(&input.name, change)?;
+    let applied = changed.db.unwrap_or(db) - db;
+    info!("setup changed the OS input gain by {applied:+.1} dB to {changed}");
+    println!("{}: {changed}", tr(Message::OsGain));
+    Ok(((gain - applied) * 2.0).round() / 2.0)
+}
+
+/// Level of the speech in the `levels` in dBFS, the RMS of the louder
+/// half of the windows so the pauses between words don't count
+fn speech_level(levels: &[Level]) -> Option<f32> {
+    let mut window_rms: Vec<f32> = levels.iter().map(|level| level.rms).collect();
+    window_rms.sort_by(|a, b| b.total_cmp(a));
+    let louder = &window_rms[..window_rms.len().div_ceil(2)];
+    let mean_square = louder.iter().map(|rms| rms * rms).sum::<f32>() / louder.len().max(1) as f32;
+    to_db(mean_square.sqrt())
+}
+
+/// Shows the live meter of the `input` for the [LISTEN_LENGTH], returning
+/// the levels of each window
+fn listen(
+    input: &NamedDevice,
+    config: &StreamConfig,
+    args: &Args,
+    chemic_config: &Config,
+) -> Result<Vec<Level>, ChemicError> {
+    let plain = use_plain_output(args.plain);
+    let display_meter = Arc::new(LevelMeter::new(config.channels));
+    let meter = Arc::new(LevelMeter::new(config.channels));
+
+    let stream = input
+        .device
+        .build_input_stream(
+            config,
+            {
+                let display_meter = display_meter.clone();
+                let meter = meter.clone();
+                move |data: &[f32], _| {
+                    display_meter.record(data);
+                    meter.record(data);
+                }
+            },
+            |err| error!("input stream error: {err} ({err:?})"),
+            None,
+        )
+        .map_err(|source| ChemicError::BuildStream {
+            ty: DeviceType::Input,
+            device: input.name.clone(),
+            source,
+        })?;
+    stream.play()?;
+
+    let scale = MeterScale {
+        calibration: chemic_config
+            .device(&input.name)
+            .and_then(|settings| settings.calibration),
+        ..MeterScale::default()
+    };
+    let display = meter_mode(args, &chemic_config.theme, plain)?.map(|mode| {
+        MeterDisplay::start(display_meter, Arc::new(StreamStats::default()), mode, scale)
+    });
+
+    // Skip the first window which may contain the device starting up
+    thread::sleep(WINDOW);
+    meter.take();
+
+    let windows = (LISTEN_LENGTH.as_secs_f64() / WINDOW.as_secs_f64()) as usize;
+    let mut levels = Vec::with_capacity(windows);
+    for _ in 0..windows {
+        thread::sleep(WINDOW);
+        levels.push(meter.take());
+    }
+
+    if let Some(display) = display {
+        display.stop();
+    }
+    drop(stream);
+    Ok(levels)
+}
@@ -0,0 +1,147 @@
+//! Check of whether an input device can be opened by two streams at once,
+//! explaining the common "the microphone works here but not in my app"
+//! conflict where one application holds the device exclusively
+
+use crate::{
+    args::Args,
+    audit::{open_counted, StreamCounters},
+    config::Config,
+    error::ChemicError,
+    log::info,
+    measure::select_device,
+    negotiate::negotiate_input,
+    DeviceType,
+};
+use cpal::{traits::StreamTrait, SampleFormat, Stream, StreamConfig};
+use std::{thread, time::Duration};
+
+/// Time both streams run before their audio is counted
+const LISTEN_LENGTH: Duration = Duration::from_millis(500);
+
+/// Explains how a device is shared between applications on this platform
+#[cfg(target_os = "linux")]
+fn exclusive_hint(device_name: &str) -> &'static str {
+    match device_name.starts_with("hw:") || device_name.contains("CARD=") {
+        true => "Devices opened directly on the sound card (hw:) take the card for themselves, select the \"default\" or \"pipewire\" device so the sound server shares it, or use an ALSA dsnoop device",
+        false => "Check that PipeWire or PulseAudio is running and that no application has opened the sound card directly (hw:), which takes it away from the sound server",
+    }
+}
+
+/// Explains how a device is shared between applications on this platform
+#[cfg(target_os = "windows")]
+fn exclusive_hint(_device_name: &str) -> &'static str {
+    "An application holds the device in exclusive mode, untick \"Allow applications to take exclusive control of this device\" in the Advanced tab of the microphone properties in the Sound control panel"
+}
+
+/// Explains how a device is shared between applications on this platform
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn exclusive_hint(_device_name: &str) -> &'static str {
+    "Another application may be holding the device for itself, close the other applications that use the microphone and try again"
+}
+
+/// Opens the input from the `args` from two streams at once and reports
+/// whether both receive audio, failing when the device can't be shared
+pub fn run(mut args: Args) -> Result<(), ChemicError> {
+    let config = Config::load(args.config.as_deref())?;
+    args.apply_config(&config)?;
+
+    let host = &cpal::default_host();
+    let input = select_device(host, &args, DeviceType::Input)?;
+    let supported = negotiate_input(&input, args.sample_rate)?;
+    let sample_format = supported.sample_format();
+    let input_config: StreamConfig = supported.into();
+
+    println!("Opening \"{}\" from two streams at once\n", input.name);
+
+    let first_counters = StreamCounters::default();
+    let first = match start(&input.device, &input_config, sample_format, &first_counters) {
+        Ok(stream) => stream,
+        Err(err) => {
+            info!(
+                "shared access of \"{}\": first stream failed: {err}",
+                input.name
+            );
+            println!("First stream:  {err}");
+            println!("\nThe input couldn't be opened at all, another application is probably");
+            println!("holding it. {}", exclusive_hint(&input.name));
+            return Err(ChemicError::CheckFailed(format!(
+                "\"{}\" couldn't be opened",
+                input.name
+            )));
+        }
+    };
+    println!("First stream:  opened");
+
+    let second_counters = StreamCounters::default();
+    let second = start(
+        &input.device,
+        &input_config,
+        sample_format,
+        &second_counters,
+    );
+    match &second {
+        Ok(_) => println!("Second stream: opened"),
+        Err(err) => println!("Second stream: {err}"),
+    }
+
+    thread::sleep(LISTEN_LENGTH);
+    let first_samples = first_counters.samples();
+    let second_samples = second_counters.samples();
+    let second_opened = second.is_ok();
+    drop(second);
+    drop(first);
+
+    let receives = |samples: usize| match samples > 0 {
+        true => "receives audio",
+        false => "no audio",
+    };
+    println!("First stream:  {}", receives(first_samples));
+    if second_opened {
+        println!("Second stream: {}", receives(second_samples));
+    }
+
+    info!(
+        "shared access of \"{}\": {first_samples} and {second_samples} samples",
+        input.name
+    );
+    if first_samples == 0 {
+        println!("\nThe input delivered no audio even to the first stream, check that it is");
+        println!("connected and working before checking whether it can be shared");
+        return Err(ChemicError::CheckFailed(format!(
+            "\"{}\" delivered no audio",
+            input.name
+        )));
+    }
+    if second_samples > 0 {
+        println!("\nThe input can be shared, more than one application can use it at once");
+        println!("An application that can't use it has probably selected another device");
+        return Ok(());
+    }
+
+    println!("\nThe input can only be used by one stream at a time, so whichever application");
+    println!("opens it first keeps the others from hearing it. This is the usual cause of a");
+    println!(
+        "microphone that works in one application but not in another. {}",
+        exclusive_hint(&input.name)
+    );
+    Err(ChemicError::CheckFailed(format!(
+        "\"{}\" can't be shared between streams",
+        input.name
+    )))
+}
+
+/// Opens and starts a stream on the input `device` counting into the
+/// `counters`, the error of whichever step failed
+fn start(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    counters: &StreamCounters,
+) -> Result<Stream, String> {
+    let stream = open_counted(device, DeviceType::Input, config, sample_format, counters)
+        .map_err(|err| format!("failed to open: {err}"))?;
+    stream
+        .play()
+        .map_err(|err| format!("failed to start: {err}"))?;
+    Ok(stream)
+}
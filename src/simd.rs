@@ -0,0 +1,173 @@
+//! Block operations on samples used by the hot paths of the stream
+//! callbacks, vectorized with SSE2 when the `simd` feature is enabled
+//! on x86_64 and plain loops everywhere else
+
+/// Finds the highest absolute sample value and the sum of the squared
+/// sample values of the `samples`
+pub fn levels(samples: &[f32]) -> (f32, f64) {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    {
+        // SAFETY: SSE2 is part of the x86_64 baseline
+        unsafe { sse2::levels(samples) }
+    }
+    #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+    {
+        scalar::levels(samples)
+    }
+}
+
+/// Multiplies each of the `samples` by the `factor`
+pub fn scale(samples: &mut [f32], factor: f32) {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    {
+        // SAFETY: SSE2 is part of the x86_64 baseline
+        unsafe { sse2::scale(samples, factor) }
+    }
+    #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+    {
+        scalar::scale(samples, factor)
+    }
+}
+
+/// Averages each interleaved stereo frame of the `input` into the
+/// `output`, the `input` must be twice the length of the `output`
+pub fn downmix_stereo(input: &[f32], output: &mut [f32]) {
+    debug_assert_eq!(input.len(), output.len() * 2);
+
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    {
+        // SAFETY: SSE2 is part of the x86_64 baseline
+        unsafe { sse2::downmix_stereo(input, output) }
+    }
+    #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+    {
+        scalar::downmix_stereo(input, output)
+    }
+}
+
+/// Duplicates each sample of the `input` into an interleaved stereo
+/// frame of the `output`, the `output` must be twice the length of the
+/// `input`
+pub fn upmix_mono(input: &[f32], output: &mut [f32]) {
+    debug_assert_eq!(input.len() * 2, output.len());
+
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    {
+        // SAFETY: SSE2 is part of the x86_64 baseline
+        unsafe { sse2::upmix_mono(input, output) }
+    }
+    #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+    {
+        scalar::upmix_mono(input, output)
+    }
+}
+
+/// Plain implementations, also used for the remainders that don't fill
+/// a whole vector
+mod scalar {
+    pub fn levels(samples: &[f32]) -> (f32, f64) {
+        let mut peak: f32 = 0.0;
+        let mut sum_squares: f64 = 0.0;
+        for &sample in samples {
+            peak = peak.max(sample.abs());
+            sum_squares += (sample as f64) * (sample as f64);
+        }
+        (peak, sum_squares)
+    }
+
+    pub fn scale(samples: &mut [f32], factor: f32) {
+        for sample in samples {
+            *sample *= factor;
+        }
+    }
+
+    pub fn downmix_stereo(input: &[f32], output: &mut [f32]) {
+        for (frame, out) in input.chunks_exact(2).zip(output) {
+            *out = (frame[0] + frame[1]) / 2.;
+        }
+    }
+
+    pub fn upmix_mono(input: &[f32], output: &mut [f32]) {
+        for (&sample, frame) in input.iter().zip(output.chunks_exact_mut(2)) {
+            frame[0] = sample;
+            frame[1] = sample;
+        }
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod sse2 {
+    use super::scalar;
+    use std::arch::x86_64::*;
+
+    /// Number of samples in each vector
+    const LANES: usize = 4;
+
+    pub unsafe fn levels(samples: &[f32]) -> (f32, f64) {
+        let chunks = samples.chunks_exact(LANES);
+        let (tail_peak, tail_sum) = scalar::levels(chunks.remainder());
+
+        // Clearing the sign bit gives the absolute value
+        let abs_mask = _mm_castsi128_ps(_mm_set1_epi32(i32::MAX));
+        let mut peak = _mm_setzero_ps();
+        // Squares are summed as doubles to match the precision of the
+        // scalar path, two accumulators for the low and high halves
+        let mut sum_low = _mm_setzero_pd();
+        let mut sum_high = _mm_setzero_pd();
+
+        for chunk in chunks {
+            let values = _mm_loadu_ps(chunk.as_ptr());
+            peak = _mm_max_ps(peak, _mm_and_ps(values, abs_mask));
+
+            let low = _mm_cvtps_pd(values);
+            let high = _mm_cvtps_pd(_mm_movehl_ps(values, values));
+            sum_low = _mm_add_pd(sum_low, _mm_mul_pd(low, low));
+            sum_high = _mm_add_pd(sum_high, _mm_mul_pd(high, high));
+        }
+
+        let mut peaks = [0.0f32; LANES];
+        _mm_storeu_ps(peaks.as_mut_ptr(), peak);
+        let mut sums = [0.0f64; 2];
+        _mm_storeu_pd(sums.as_mut_ptr(), _mm_add_pd(sum_low, sum_high));
+
+        let peak = peaks.into_iter().fold(tail_peak, f32::max);
+        (peak, sums[0] + sums[1] + tail_sum)
+    }
+
+    pub unsafe fn scale(samples: &mut [f32], factor: f32) {
+        let factor_vector = _mm_set1_ps(factor);
+        let mut chunks = samples.chunks_exact_mut(LANES);
+        for chunk in &mut chunks {
+            let values = _mm_loadu_ps(chunk.as_ptr());
+            _mm_storeu_ps(chunk.as_mut_ptr(), _mm_mul_ps(values, factor_vector));
+        }
+        scalar::scale(chunks.into_remainder(), factor);
+    }
+
+    pub unsafe fn downmix_stereo(input: &[f32], output: &mut [f32]) {
+        let half = _mm_set1_ps(0.5);
+        let mut inputs = input.chunks_exact(LANES * 2);
+        let mut outputs = output.chunks_exact_mut(LANES);
+        for (chunk, out) in (&mut inputs).zip(&mut outputs) {
+            // Two vectors of [l0, r0, l1, r1] deinterleaved into the
+            // left and right samples of four frames
+            let first = _mm_loadu_ps(chunk.as_ptr());
+            let second = _mm_loadu_ps(chunk.as_ptr().add(LANES));
+            let left = _mm_shuffle_ps::<0b10_00_10_00>(first, second);
+            let right = _mm_shuffle_ps::<0b11_01_11_01>(first, second);
+            _mm_storeu_ps(out.as_mut_ptr(), _mm_mul_ps(_mm_add_ps(left, right), half));
+        }
+        scalar::downmix_stereo(inputs.remainder(), outputs.into_remainder());
+    }
+
+    pub unsafe fn upmix_mono(input: &[f32], output: &mut [f32]) {
+        let mut inputs = input.chunks_exact(LANES);
+        let mut outputs = output.chunks_exact_mut(LANES * 2);
+        for (chunk, out) in (&mut inputs).zip(&mut outputs) {
+            let values = _mm_loadu_ps(chunk.as_ptr());
+            _mm_storeu_ps(out.as_mut_ptr(), _mm_unpacklo_ps(values, values));
+            _mm_storeu_ps(out.as_mut_ptr().add(LANES), _mm_unpackhi_ps(values, values));
+        }
+        scalar::upmix_mono(inputs.remainder(), outputs.into_remainder());
+    }
+}
@@ -0,0 +1,473 @@
+//! Long running soak test that monitors the input through the output and
+//! reports the clock drift, underruns, dropped input and levels at regular
+//! intervals, for validating a streaming rig before a long broadcast
+
+use crate::{
+    args::Args,
+    config::Config,
+    daemon::DEFAULT_SILENCE_THRESHOLD,
+    error::ChemicError,
+    events::{self, Event},
+    log::{info, span},
+    measure::select_device,
+    meter::LevelMeter,
+    open_streams,
+    stats::StreamStats,
+    stream_configs, DeviceType, Streams,
+};
+use cpal::StreamConfig;
+use std::{
+    fmt, io,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Default number of hours the soak test runs for
+const DEFAULT_HOURS: f64 = 8.0;
+/// Time between the rows of the report
+const REPORT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Time between the level checks
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Sample counters read at a point in time, used to measure how the
+/// counters changed between two reports
+#[derive(Clone, Copy)]
+struct Snapshot {
+    /// When the counters were read
+    time: Instant,
+    input_samples: u64,
+    output_samples: u64,
+    underrun_samples: u64,
+    dropped_samples: u64,
+    stream_errors: u64,
+    /// Dropouts and sample jumps detected in the input
+    glitches: u64,
+}
+
+impl Snapshot {
+    fn read(stats: &StreamStats) -> Self {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        Self {
+            time: Instant::now(),
+            input_samples: load(&stats.input_samples),
+            output_samples: load(&stats.output_samples),
+            underrun_samples: load(&stats.underrun_samples),
+            dropped_samples: load(&stats.dropped_samples),
+            stream_errors: load(&stats.stream_errors),
+            glitches: load(&stats.dropouts) + load(&stats.sample_jumps),
+        }
+    }
+}
+
+/// Clock rates of the devices measured against the system clock
+struct Drift {
+    /// Difference of the input clock from the system clock in parts per
+    /// million
+    input_ppm: f64,
+    /// Difference of the output clock from the system clock in parts per
+    /// million
+    output_ppm: f64,
+}
+
+impl Drift {
+    /// Measures the drift from the samples counted between the `start`
+    /// and `end` snapshots, [None] when no time has passed
+    fn measure(
+        start: &Snapshot,
+        end: &Snapshot,
+        input_config: &StreamConfig,
+        output_config: &StreamConfig,
+    ) -> Option<Self> {
+        let elapsed = end.time.duration_since(start.time).as_secs_f64();
+        if elapsed <= 0.0 {
+            return None;
+        }
+
+        let ppm = |samples: u64, config: &StreamConfig| {
+            let rate = config.sample_rate.0 as f64 * config.channels.max(1) as f64;
+            (samples as f64 / rate / elapsed - 1.0) * 1_000_000.0
+        };
+
+        Some(Self {
+            input_ppm: ppm(end.input_samples - start.input_samples, input_config),
+            output_ppm: ppm(end.output_samples - start.output_samples, output_config),
+        })
+    }
+
+    /// Drift of the input clock relative to the output clock, positive
+    /// when the input runs faster and fills the buffers
+    fn relative_ppm(&self) -> f64 {
+        self.input_ppm - self.output_ppm
+    }
+}
+
+/// Statistics of the levels checked during a period
+#[derive(Default)]
+struct LevelStats {
+    /// Number of levels checked
+    checks: u32,
+    /// Sum of the RMS levels in dBFS, silent checks are not included
+    rms_sum: f64,
+    /// Number of the checks included in the sum
+    rms_count: u32,
+    /// Lowest and highest RMS level in dBFS
+    rms_min: Option<f32>,
+    rms_max: Option<f32>,
+    /// Highest peak level in dBFS
+    peak_max: Option<f32>,
+    /// Number of checks that clipped
+    clipped: u32,
+    /// Number of checks below the silence threshold
+    silent: u32,
+}
+
+impl LevelStats {
+    /// Takes the level from the `meter`, the input counts as silent below
+    /// the `threshold` in dBFS
+    fn check(&mut self, meter: &LevelMeter, threshold: f32) {
+        let level = meter.take();
+        self.checks += 1;
+
+        match level.rms_db() {
+            Some(rms) => {
+                self.rms_sum += rms as f64;
+                self.rms_count += 1;
+                self.rms_min = Some(self.rms_min.map_or(rms, |min| min.min(rms)));
+                self.rms_max = Some(self.rms_max.map_or(rms, |max| max.max(rms)));
+                if rms < threshold {
+                    self.silent += 1;
+                }
+            }
+            None => self.silent += 1,
+        }
+        if let Some(peak) = level.peak_db() {
+            self.peak_max = Some(self.peak_max.map_or(peak, |max| max.max(peak)));
+        }
+        if level.clipped() {
+            self.clipped += 1;
+        }
+    }
+
+    /// Adds the statistics of a later period
+    fn merge(&mut self, other: &LevelStats) {
+        let min = |a: Option<f32>, b: Option<f32>| a.into_iter().chain(b).reduce(f32::min);
+        let max = |a: Option<f32>, b: Option<f32>| a.into_iter().chain(b).reduce(f32::max);
+
+        self.checks += other.checks;
+        self.rms_sum += other.rms_sum;
+        self.rms_count += other.rms_count;
+        self.rms_min = min(self.rms_min, other.rms_min);
+        self.rms_max = max(self.rms_max, other.rms_max);
+        self.peak_max = max(self.peak_max, other.peak_max);
+        self.clipped += other.clipped;
+        self.silent += other.silent;
+    }
+
+    fn rms_mean(&self) -> Option<f32> {
+        (self.rms_count > 0).then(|| (self.rms_sum / self.rms_count as f64) as f32)
+    }
+}
+
+/// Formats an optional level in dBFS, "-inf" when there was no signal
+fn format_db(db: Option<f32>) -> String {
+    match db {
+        Some(db) => format!("{db:.1}"),
+        None => "-inf".to_string(),
+    }
+}
+
+/// Formats interleaved `samples` of the stream `config` as milliseconds
+/// of audio
+fn format_samples(samples: u64, config: &StreamConfig) -> String {
+    let rate = config.sample_rate.0 as f64 * config.channels.max(1) as f64;
+    format!("{:.1} ms", samples as f64 / rate * 1000.0)
+}
+
+/// Formats the `duration` as hours, minutes and seconds (e.g. "1:05:00")
+struct Elapsed(Duration);
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.0.as_secs();
+        write!(
+            f,
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    }
+}
+
+/// Monitors the input and output devices from the `args` for the
+/// requested number of hours printing a row of statistics at each report
+/// interval and the stability report at the end
+pub fn run(mut args: Args) -> Result<(), ChemicError> {
+    let _span = span!("soak");
+
+    let config = Config::load(args.config.as_deref())?;
+    args.apply_config(&config)?;
+
+    let hours = args.hours.unwrap_or(DEFAULT_HOURS);
+    let duration = Duration::try_from_secs_f64(hours * 3600.0)
+        .ok()
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Hours must be a positive number",
+            )
+        })?;
+    let interval = REPORT_INTERVAL.min(duration);
+    let threshold = args.silence_threshold.unwrap_or(DEFAULT_SILENCE_THRESHOLD);
+
+    // The soak test runs unattended so the devices are never prompted for
+    let host = &cpal::default_host();
+    let input = select_device(host, &args, DeviceType::Input)?;
+    let output = select_device(host, &args, DeviceType::Output)?;
+    let (input_config, output_config) = stream_configs(&input, &output, &args)?;
+    let settings = config.device(&input.name).cloned().unwrap_or_default();
+
+    let Streams {
+        input_stream: _input_stream,
+        output_stream: _output_stream,
+        worker: _worker,
+        analyzer: _analyzer,
+        glitch_log: _glitch_log,
+        recorder,
+        stats,
+        meter,
+        controls: _,
+    } = open_streams(
+        &input,
+        &input_config,
+        &output,
+        &output_config,
+        &args,
+        &settings,
+        None,
+    )?;
+
+    info!(
+        "soak test started for {} using \"{}\" and \"{}\"",
+        Elapsed(duration),
+        input.name,
+        output.name
+    );
+    events::emit(Event::Started {
+        input: &input.name,
+        input_config: &input_config,
+        output: &output.name,
+        output_config: &output_config,
+    });
+
+    println!(
+        "Soak testing \"{}\" -> \"{}\" for {}, a row is printed every {}",
+        input.name,
+        output.name,
+        Elapsed(duration),
+        Elapsed(interval)
+    );
+    println!("Press Enter to stop early\n");
+    println!(
+        "{:>9}  {:>9}  {:>9}  {:>10}  {:>10}  {:>6}  {:>8}  {:>20}  {:>6}",
+        "Elapsed",
+        "Drift",
+        "Buffered",
+        "Underruns",
+        "Dropped",
+        "Errors",
+        "Glitches",
+        "RMS min/avg/max",
+        "Peak"
+    );
+
+    // Enter stops the test, the end of stdin doesn't so it can run
+    // without a terminal
+    let stop = Arc::new(AtomicBool::new(false));
+    thread::spawn({
+        let stop = stop.clone();
+        move || {
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line).is_ok_and(|read| read > 0) {
+                stop.store(true, Ordering::Relaxed);
+            }
+        }
+    });
+
+    // Counting starts once the streams are running so the start up
+    // doesn't count as drift
+    thread::sleep(CHECK_INTERVAL);
+    meter.take();
+    let first = Snapshot::read(&stats);
+    let mut previous = first;
+    let mut buffered_range: Option<(Duration, Duration)> = None;
+    let mut period = LevelStats::default();
+    let mut total = LevelStats::default();
+    let mut next_report = first.time + interval;
+    let end = first.time + duration;
+
+    loop {
+        thread::sleep(CHECK_INTERVAL);
+        period.check(&meter, threshold);
+
+        if let Some(latency) = stats.latency() {
+            let buffered = latency.buffered;
+            buffered_range = Some(buffered_range.map_or((buffered, buffered), |(min, max)| {
+                (min.min(buffered), max.max(buffered))
+            }));
+        }
+
+        let now = Instant::now();
+        let stopped = stop.load(Ordering::Relaxed) || now >= end;
+        if now < next_report && !stopped {
+            continue;
+        }
+
+        let current = Snapshot::read(&stats);
+        let drift = Drift::measure(&first, &current, &input_config, &output_config)
+            .map_or("unknown".to_string(), |drift| {
+                format!("{:+.1} ppm", drift.relative_ppm())
+            });
+        let buffered = stats.latency().map_or("unknown".to_string(), |latency| {
+            format!("{:.1} ms", latency.buffered.as_secs_f64() * 1000.0)
+        });
+        let underruns = format_samples(
+            current.underrun_samples - previous.underrun_samples,
+            &output_config,
+        );
+        let dropped = format_samples(
+            current.dropped_samples - previous.dropped_samples,
+            &input_config,
+        );
+        let errors = current.stream_errors - previous.stream_errors;
+        let glitches = current.glitches - previous.glitches;
+        let levels = format!(
+            "{}/{}/{}",
+            format_db(period.rms_min),
+            format_db(period.rms_mean()),
+            format_db(period.rms_max)
+        );
+        let peak = format_db(period.peak_max);
+
+        let elapsed = Elapsed(current.time.duration_since(first.time));
+        info!(
+            "soak {elapsed}: drift {drift}, buffered {buffered}, underruns {underruns}, dropped {dropped}, {errors} errors, {glitches} glitches, rms {levels} dBFS, peak {peak} dBFS"
+        );
+        println!(
+            "{elapsed:>9}  {drift:>9}  {buffered:>9}  {underruns:>10}  {dropped:>10}  {errors:>6}  {glitches:>8}  {levels:>20}  {peak:>6}"
+        );
+
+        total.merge(&period);
+        period = LevelStats::default();
+        previous = current;
+        next_report += interval;
+
+        if stopped {
+            break;
+        }
+    }
+
+    let last = Snapshot::read(&stats);
+    print_report(
+        &stats,
+        &first,
+        &last,
+        &input_config,
+        &output_config,
+        buffered_range,
+        &total,
+    );
+    events::emit(Event::Stopped { stats: &stats });
+
+    if let Some(recorder) = recorder {
+        recorder.finish()?;
+    }
+
+    Ok(())
+}
+
+/// Prints the stability report covering the time from the `first` to
+/// the `last` snapshot of the `stats`
+fn print_report(
+    stats: &StreamStats,
+    first: &Snapshot,
+    last: &Snapshot,
+    input_config: &StreamConfig,
+    output_config: &StreamConfig,
+    buffered_range: Option<(Duration, Duration)>,
+    levels: &LevelStats,
+) {
+    let underruns = last.underrun_samples - first.underrun_samples;
+    let dropped = last.dropped_samples - first.dropped_samples;
+    let errors = last.stream_errors - first.stream_errors;
+    let glitches = last.glitches - first.glitches;
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+
+    println!(
+        "\nStability report ({})\n",
+        Elapsed(last.time.duration_since(first.time))
+    );
+
+    match Drift::measure(first, last, input_config, output_config) {
+        Some(drift) => println!(
+            "Clock drift:       {:+.1} ppm between the input and output (input {:+.1} ppm, output {:+.1} ppm against the system clock)",
+            drift.relative_ppm(),
+            drift.input_ppm,
+            drift.output_ppm
+        ),
+        None => println!("Clock drift:       unknown"),
+    }
+    if let Some((min, max)) = buffered_range {
+        println!("Buffered audio:    {:.1} ms to {:.1} ms", ms(min), ms(max));
+    }
+    if let Some(latency) = stats.latency() {
+        println!("Latency:           {latency}");
+    }
+    println!(
+        "Underruns:         {} of silence inserted",
+        format_samples(underruns, output_config)
+    );
+    println!(
+        "Dropped input:     {}",
+        format_samples(dropped, input_config)
+    );
+    println!("Stream errors:     {errors}");
+    println!(
+        "Glitches:          {} dropouts, {} sample jumps",
+        stats.dropouts.load(Ordering::Relaxed),
+        stats.sample_jumps.load(Ordering::Relaxed)
+    );
+    println!(
+        "Longest callbacks: input {}us, output {}us",
+        stats.input_callback_max_ns.load(Ordering::Relaxed) / 1000,
+        stats.output_callback_max_ns.load(Ordering::Relaxed) / 1000
+    );
+    println!(
+        "Input level:       RMS {} to {} dBFS (average {}), peak {} dBFS",
+        format_db(levels.rms_min),
+        format_db(levels.rms_max),
+        format_db(levels.rms_mean()),
+        format_db(levels.peak_max)
+    );
+    println!(
+        "                   clipped for {}s, silent for {}s of {}s",
+        levels.clipped, levels.silent, levels.checks
+    );
+
+    let stable = underruns == 0 && dropped == 0 && errors == 0 && glitches == 0;
+    info!(
+        "soak test finished: {} underrun samples, {dropped} dropped samples, {errors} errors, {glitches} glitches, {}",
+        underruns,
+        stats.summary()
+    );
+    if stable {
+        println!("\nResult: stable, no underruns, dropped input, stream errors or glitches");
+    } else {
+        println!("\nResult: unstable, check the rows above for when the problems happened");
+    }
+}
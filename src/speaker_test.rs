@@ -0,0 +1,159 @@
+//! Speaker test for multichannel outputs, playing a tone through each
+//! channel of the output in turn while its name is shown, to check that
+//! every speaker of a 5.1 or 7.1 system is wired to the right channel
+
+use crate::{
+    args::Args, config::Config, error::ChemicError, log::info, meter::from_db,
+    negotiate::negotiate_output, positive_duration, prompt_device, prompts, requested_device,
+    use_plain_output, DeviceType,
+};
+use cpal::StreamConfig;
+use std::f64::consts::TAU;
+
+/// Default number of seconds the tone plays on each channel
+const DEFAULT_DWELL: f64 = 2.0;
+/// Default level of the tone in dBFS
+const DEFAULT_LEVEL_DB: f32 = -20.0;
+/// Frequency of the tone played on the full range channels
+const TONE_HZ: f64 = 1000.0;
+/// Frequency of the tone played on the subwoofer channel, within the
+/// range it reproduces
+const LFE_TONE_HZ: f64 = 60.0;
+/// Time the tone fades in and out over so it starts and ends without a
+/// click
+const FADE_SECONDS: f64 = 0.01;
+
+/// Name of the low frequency effects channel
+const LFE: &str = "Subwoofer (LFE)";
+
+/// Names of the channels in the order the host orders them for each
+/// channel count. ALSA places the rear speakers before the center
+#[cfg(target_os = "linux")]
+fn channel_names(channels: u16) -> &'static [&'static str] {
+    match channels {
+        1 => &["Mono"],
+        2 => &["Left", "Right"],
+        4 => &["Front Left", "Front Right", "Rear Left", "Rear Right"],
+        6 => &[
+            "Front Left",
+            "Front Right",
+            "Rear Left",
+            "Rear Right",
+            "Center",
+            LFE,
+        ],
+        8 => &[
+            "Front Left",
+            "Front Right",
+            "Rear Left",
+            "Rear Right",
+            "Center",
+            LFE,
+            "Side Left",
+            "Side Right",
+        ],
+        _ => &[],
+    }
+}
+
+/// Names of the channels in the order the host orders them for each
+/// channel count, the WAVE speaker order
+#[cfg(not(target_os = "linux"))]
+fn channel_names(channels: u16) -> &'static [&'static str] {
+    match channels {
+        1 => &["Mono"],
+        2 => &["Left", "Right"],
+        4 => &["Front Left", "Front Right", "Rear Left", "Rear Right"],
+        6 => &[
+            "Front Left",
+            "Front Right",
+            "Center",
+            LFE,
+            "Rear Left",
+            "Rear Right",
+        ],
+        8 => &[
+            "Front Left",
+            "Front Right",
+            "Center",
+            LFE,
+            "Rear Left",
+            "Rear Right",
+            "Side Left",
+            "Side Right",
+        ],
+        _ => &[],
+    }
+}
+
+/// Plays a tone through each channel of the output from the `args` for
+/// the `--duration` at the `--level`
+pub fn run(mut args: Args) -> Result<(), ChemicError> {
+    let plain = use_plain_output(args.plain);
+    let config = Config::load(args.config.as_deref())?;
+    args.apply_config(&config)?;
+
+    let dwell = positive_duration(args.duration.unwrap_or(DEFAULT_DWELL), "Duration")?;
+    let level = from_db(args.level.unwrap_or(DEFAULT_LEVEL_DB));
+
+    let host = &cpal::default_host();
+    let output = match requested_device(host, &args, DeviceType::Output, true)? {
+        Some(device) => device,
+        None => prompt_device(
+            host,
+            "Select the speakers",
+            DeviceType::Output,
+            &config.theme,
+            plain,
+        )?,
+    };
+
+    let output_config: StreamConfig = negotiate_output(&output, args.sample_rate)?.into();
+    let channels = output_config.channels;
+    let names = channel_names(channels);
+    info!(
+        "speaker test of \"{}\" with {channels} channels at {}Hz",
+        output.name, output_config.sample_rate.0
+    );
+    println!(
+        "Playing a tone through each of the {channels} channels of \"{}\" for {:.1}s\n",
+        output.name,
+        dwell.as_secs_f64()
+    );
+
+    for channel in 0..channels as usize {
+        let name = names.get(channel).copied();
+        println!(
+            "{:>2}/{channels}  {}",
+            channel + 1,
+            name.map_or(format!("Channel {}", channel + 1), str::to_string)
+        );
+
+        let frequency = match name {
+            Some(LFE) => LFE_TONE_HZ,
+            _ => TONE_HZ,
+        };
+        let frames = (dwell.as_secs_f64() * output_config.sample_rate.0 as f64) as usize;
+        let samples = tone(frequency, frames, output_config.sample_rate.0, level);
+        prompts::play(&output, &output_config, samples, Some(channel))?;
+    }
+
+    println!("\nEach speaker should have played its tone in turn");
+    println!("A tone from the wrong speaker means it is wired to another channel");
+    Ok(())
+}
+
+/// Creates `frames` of a tone at the `frequency` and linear `level`,
+/// fading in and out
+pub fn tone(frequency: f64, frames: usize, sample_rate: u32, level: f32) -> Vec<f32> {
+    let rate = sample_rate as f64;
+    let fade = (FADE_SECONDS * rate).max(1.0);
+    (0..frames)
+        .map(|frame| {
+            let envelope = (frame as f64 / fade)
+                .min((frames - frame) as f64 / fade)
+                .min(1.0);
+            ((TAU * frequency * frame as f64 / rate).sin() * envelope) as f32 * level
+        })
+        .collect()
+}
@@ -0,0 +1,139 @@
+//! Lightweight counters updated from the stream callbacks
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Counters shared between the stream callbacks and the main thread,
+/// only relaxed atomic adds are performed from the callbacks
+#[derive(Default)]
+pub struct StreamStats {
+    /// Number of times the input callback was called
+    pub input_callbacks: AtomicU64,
+    /// Number of samples received from the input device
+    pub input_samples: AtomicU64,
+    /// Number of input samples that didn't fit in the ring buffer, or
+    /// that were skipped to keep the sidetone latency from building up
+    pub dropped_samples: AtomicU64,
+    /// Number of input samples that didn't fit in the buffer of the
+    /// analysis and are left out of the meters and glitch detection
+    pub analysis_dropped: AtomicU64,
+    /// Number of times the output callback was called
+    pub output_callbacks: AtomicU64,
+    /// Number of samples written to the output device
+    pub output_samples: AtomicU64,
+    /// Number of output samples filled with silence because no input
+    /// samples were available
+    pub underrun_samples: AtomicU64,
+    /// Longest time spent in the input callback in nanoseconds
+    pub input_callback_max_ns: AtomicU64,
+    /// Longest time spent in the output callback in nanoseconds
+    pub output_callback_max_ns: AtomicU64,
+    /// Time between the capture and the input callback reported by the
+    /// backend for the latest callback in nanoseconds
+    pub capture_delay_ns: AtomicU64,
+    /// Time of audio waiting in the input buffer after the latest input
+    /// callback in nanoseconds
+    pub input_buffered_ns: AtomicU64,
+    /// Time of audio waiting in the output buffer after the latest output
+    /// callback in nanoseconds
+    pub output_buffered_ns: AtomicU64,
+    /// Time between the output callback and the playback reported by the
+    /// backend for the latest callback in nanoseconds
+    pub playback_delay_ns: AtomicU64,
+    /// Time of audio the jitter buffer currently targets in nanoseconds
+    pub jitter_target_ns: AtomicU64,
+    /// Number of output frames held down by the safety ceiling
+    pub limited_frames: AtomicU64,
+    /// Number of errors reported by the streams
+    pub stream_errors: AtomicU64,
+    /// Number of samples that didn't fit in the buffers of the recorded
+    /// tracks and are missing from the recordings
+    pub recording_overflow: AtomicU64,
+    /// Number of runs of zeros detected in the input signal
+    pub dropouts: AtomicU64,
+    /// Number of sudden jumps between input samples detected
+    pub sample_jumps: AtomicU64,
+}
+
+/// Monitoring delay split into the parts it is made up of
+#[derive(Debug, Clone, Copy)]
+pub struct Latency {
+    /// Delay between the audio being captured and reaching chemic
+    pub capture: Duration,
+    /// Audio waiting in the buffers between the streams
+    pub buffered: Duration,
+    /// Delay between the audio leaving chemic and being played
+    pub playback: Duration,
+}
+
+impl Latency {
+    /// End to end delay from capture to playback
+    pub fn total(&self) -> Duration {
+        self.capture + self.buffered + self.playback
+    }
+}
+
+/// Formats the `latency` in milliseconds with its parts
+/// (e.g. "14.2 ms (capture 2.0 ms, buffers 10.2 ms, playback 2.0 ms)")
+impl std::fmt::Display for Latency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "{:.1} ms (capture {:.1} ms, buffers {:.1} ms, playback {:.1} ms)",
+            ms(self.total()),
+            ms(self.capture),
+            ms(self.buffered),
+            ms(self.playback)
+        )
+    }
+}
+
+impl StreamStats {
+    /// Latency from the latest callbacks, [None] until both streams have
+    /// called back. Delays the backend doesn't report are counted as zero
+    pub fn latency(&self) -> Option<Latency> {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        if load(&self.input_callbacks) == 0 || load(&self.output_callbacks) == 0 {
+            return None;
+        }
+
+        Some(Latency {
+            capture: Duration::from_nanos(load(&self.capture_delay_ns)),
+            buffered: Duration::from_nanos(
+                load(&self.input_buffered_ns) + load(&self.output_buffered_ns),
+            ),
+            playback: Duration::from_nanos(load(&self.playback_delay_ns)),
+        })
+    }
+
+    /// Creates a summary of the current counter values
+    pub fn summary(&self) -> String {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let latency = match self.latency() {
+            Some(latency) => latency.to_string(),
+            None => "unknown".to_string(),
+        };
+
+        format!(
+            "input: {} callbacks, {} samples, {} dropped, {} unanalysed, max {}us | output: {} callbacks, {} samples, {} underrun, {} limited, max {}us | {} stream errors | {} recording overflow | glitches: {} dropouts, {} jumps | jitter target: {:.1} ms | latency: {latency}",
+            load(&self.input_callbacks),
+            load(&self.input_samples),
+            load(&self.dropped_samples),
+            load(&self.analysis_dropped),
+            load(&self.input_callback_max_ns) / 1000,
+            load(&self.output_callbacks),
+            load(&self.output_samples),
+            load(&self.underrun_samples),
+            load(&self.limited_frames),
+            load(&self.output_callback_max_ns) / 1000,
+            load(&self.stream_errors),
+            load(&self.recording_overflow),
+            load(&self.dropouts),
+            load(&self.sample_jumps),
+            load(&self.jitter_target_ns) as f64 / 1_000_000.0,
+        )
+    }
+}
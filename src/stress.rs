@@ -0,0 +1,260 @@
+//! Stress test of a device driver, opening, starting, stopping and
+//! dropping streams on the device over and over while counting the
+//! failures and checking the process for leaked files, threads and
+//! memory, a reproduction harness for flaky USB audio drivers
+
+use crate::{
+    args::Args,
+    audit::{open_counted, StreamCounters},
+    config::Config,
+    error::ChemicError,
+    log::{error, info},
+    measure::select_device,
+    negotiate::{negotiate_input, negotiate_output},
+    DeviceType, NamedDevice,
+};
+use cpal::{traits::StreamTrait, BufferSize, StreamConfig};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Default number of times the streams are opened and closed
+const DEFAULT_CYCLES: u32 = 100;
+/// Time each stream runs before it is stopped
+const CYCLE_RUN: Duration = Duration::from_millis(100);
+/// Time given to the audio backend to release its threads and files
+/// before the resources are measured
+const SETTLE: Duration = Duration::from_millis(200);
+/// Cycles between the progress lines
+const PROGRESS_INTERVAL: u32 = 10;
+
+/// Problems counted over the cycles
+#[derive(Default)]
+struct Failures {
+    /// Streams that failed to build
+    open: u32,
+    /// Streams that failed to start
+    start: u32,
+    /// Streams that failed to stop
+    stop: u32,
+    /// Streams that started but passed no audio
+    silent: u32,
+    /// Errors reported by the streams while running
+    stream_errors: usize,
+}
+
+impl Failures {
+    fn total(&self) -> usize {
+        (self.open + self.start + self.stop + self.silent) as usize + self.stream_errors
+    }
+}
+
+/// Resources held by the process, compared after the cycles to find
+/// leaks
+#[derive(Clone, Copy)]
+struct Resources {
+    /// Open file descriptors
+    files: usize,
+    threads: usize,
+    /// Resident memory in kB
+    memory_kb: u64,
+}
+
+/// Opens and closes streams on the input and output devices from the
+/// `args` (only the one that was named when just one was) for the
+/// `--cycles`, failing when any cycle fails or resources leaked
+pub fn run(mut args: Args) -> Result<(), ChemicError> {
+    let config = Config::load(args.config.as_deref())?;
+    args.apply_config(&config)?;
+    let cycles = args.cycles.unwrap_or(DEFAULT_CYCLES);
+
+    let types: &[DeviceType] = match (args.input.is_some(), args.output.is_some()) {
+        (true, false) => &[DeviceType::Input],
+        (false, true) => &[DeviceType::Output],
+        _ => &[DeviceType::Input, DeviceType::Output],
+    };
+
+    let host = &cpal::default_host();
+    let mut problems = Vec::new();
+    for &ty in types {
+        let device = select_device(host, &args, ty)?;
+        if let Some(problem) = stress(&device, ty, &args, cycles)? {
+            problems.push(format!("{ty} \"{}\" {problem}", device.name));
+        }
+    }
+
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(ChemicError::CheckFailed(problems.join(", "))),
+    }
+}
+
+/// Runs the `cycles` on the `device` printing a report, returns the
+/// problem found when there was one
+fn stress(
+    device: &NamedDevice,
+    ty: DeviceType,
+    args: &Args,
+    cycles: u32,
+) -> Result<Option<String>, ChemicError> {
+    let supported = match ty {
+        DeviceType::Input => negotiate_input(device, args.sample_rate)?,
+        DeviceType::Output => negotiate_output(device, args.sample_rate)?,
+    };
+    let sample_format = supported.sample_format();
+    let mut config: StreamConfig = supported.into();
+    if let Some(frames) = args.buffer_size {
+        config.buffer_size = BufferSize::Fixed(frames);
+    }
+
+    info!(
+        "stressing {ty} \"{}\" for {cycles} cycles with {sample_format} {}ch {}Hz",
+        device.name, config.channels, config.sample_rate.0
+    );
+    println!(
+        "Opening and closing the {ty} \"{}\" {cycles} times ({sample_format}, {}ch, {}Hz)",
+        device.name, config.channels, config.sample_rate.0
+    );
+
+    let mut failures = Failures::default();
+    let mut open_times = Vec::with_capacity(cycles as usize);
+    let mut before = None;
+
+    for cycle in 1..=cycles {
+        let counters = StreamCounters::default();
+        let opening = Instant::now();
+        let stream = match open_counted(&device.device, ty, &config, sample_format, &counters) {
+            Ok(stream) => stream,
+            Err(err) => {
+                failures.open += 1;
+                report(cycle, &format!("failed to open: {err}"));
+                continue;
+            }
+        };
+        open_times.push(opening.elapsed());
+
+        match stream.play() {
+            Ok(()) => {
+                thread::sleep(CYCLE_RUN);
+                if counters.samples() == 0 {
+                    failures.silent += 1;
+                    report(cycle, "passed no audio");
+                }
+                if let Err(err) = stream.pause() {
+                    failures.stop += 1;
+                    report(cycle, &format!("failed to stop: {err}"));
+                }
+            }
+            Err(err) => {
+                failures.start += 1;
+                report(cycle, &format!("failed to start: {err}"));
+            }
+        }
+        drop(stream);
+
+        let errors = counters.errors();
+        if errors > 0 {
+            failures.stream_errors += errors;
+            report(cycle, &format!("{errors} stream errors"));
+        }
+
+        // The first cycle loads the configuration of the audio backend,
+        // resources are compared from after it
+        if cycle == 1 {
+            thread::sleep(SETTLE);
+            before = resources();
+        }
+        if cycle % PROGRESS_INTERVAL == 0 {
+            println!("{cycle}/{cycles} cycles, {} failures", failures.total());
+        }
+    }
+
+    thread::sleep(SETTLE);
+    let after = resources();
+
+    println!();
+    println!("== == == == {ty} stress: {} == == == ==", device.name);
+    println!("Cycles:         {cycles}");
+    println!("Failed opens:   {}", failures.open);
+    println!("Failed starts:  {}", failures.start);
+    println!("Failed stops:   {}", failures.stop);
+    println!("Silent streams: {}", failures.silent);
+    println!("Stream errors:  {}", failures.stream_errors);
+    if let Some(slowest) = open_times.iter().max() {
+        let mean = open_times.iter().sum::<Duration>() / open_times.len() as u32;
+        println!(
+            "Open time:      {:.1} ms mean, {:.1} ms slowest",
+            mean.as_secs_f64() * 1000.0,
+            slowest.as_secs_f64() * 1000.0
+        );
+    }
+
+    let mut leaks = Vec::new();
+    if let (Some(before), Some(after)) = (before, after) {
+        println!("Open files:     {} -> {}", before.files, after.files);
+        println!("Threads:        {} -> {}", before.threads, after.threads);
+        println!(
+            "Memory:         {} kB -> {} kB",
+            before.memory_kb, after.memory_kb
+        );
+        if after.files > before.files {
+            leaks.push(format!("{} files", after.files - before.files));
+        }
+        if after.threads > before.threads {
+            leaks.push(format!("{} threads", after.threads - before.threads));
+        }
+    }
+    println!();
+
+    let mut problems = Vec::new();
+    if failures.total() > 0 {
+        problems.push(format!("failed {} times", failures.total()));
+    }
+    if !leaks.is_empty() {
+        println!(
+            "The driver kept {} after the streams were dropped\n",
+            leaks.join(" and ")
+        );
+        problems.push(format!("leaked {}", leaks.join(" and ")));
+    }
+    info!(
+        "stress of {ty} \"{}\" finished with {} failures, leaks: {leaks:?}",
+        device.name,
+        failures.total()
+    );
+
+    Ok((!problems.is_empty()).then(|| problems.join(" and ")))
+}
+
+/// Reports the `problem` found in the `cycle`
+fn report(cycle: u32, problem: &str) {
+    error!("stress cycle {cycle} {problem}");
+    println!("Cycle {cycle}: {problem}");
+}
+
+/// Measures the resources held by the process, [None] when the platform
+/// doesn't report them
+#[cfg(target_os = "linux")]
+fn resources() -> Option<Resources> {
+    use std::fs;
+
+    let count = |path| fs::read_dir(path).map(|entries| entries.count()).ok();
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let memory_kb = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())?;
+
+    Some(Resources {
+        files: count("/proc/self/fd")?,
+        threads: count("/proc/self/task")?,
+        memory_kb,
+    })
+}
+
+/// Measures the resources held by the process, only reported on Linux
+#[cfg(not(target_os = "linux"))]
+fn resources() -> Option<Resources> {
+    None
+}
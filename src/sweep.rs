@@ -0,0 +1,188 @@
+//! Testing every input device in turn to find the device that is
+//! actually picking up the user's voice
+
+use crate::{
+    error::ChemicError,
+    list_devices,
+    log::{debug, error, span},
+    meter::{to_db, LevelMeter},
+    negotiate::negotiate_input,
+    weighting::{Weighting, WeightingFilter},
+    DeviceType, NamedDevice,
+};
+use cpal::{
+    traits::{DeviceTrait, StreamTrait},
+    Host, StreamConfig,
+};
+use std::{sync::Arc, thread, time::Duration};
+
+/// Default number of seconds each device is tested for
+pub const DEFAULT_DURATION: f64 = 3.0;
+/// Length of the windows the levels are measured over
+const WINDOW: Duration = Duration::from_millis(100);
+
+/// Levels measured from one device
+pub struct Measurement {
+    /// Overall RMS level in dBFS
+    pub rms_db: Option<f32>,
+    /// Highest peak level in dBFS
+    pub peak_db: Option<f32>,
+    /// Quietest 10% of the windows in dBFS, an estimate of the noise floor
+    pub noise_db: Option<f32>,
+}
+
+/// Opens each input device on the `host` for the provided `duration`
+/// printing a table of the levels measured with the `weighting`
+pub fn run(host: &Host, duration: Duration, weighting: Weighting) -> Result<(), ChemicError> {
+    let devices = list_devices(host, DeviceType::Input)?;
+    if devices.is_empty() {
+        return Err(ChemicError::NoDevices(DeviceType::Input));
+    }
+
+    println!(
+        "Speak continuously while each of the {} input devices is tested for {:.1}s\n",
+        devices.len(),
+        duration.as_secs_f64()
+    );
+
+    let mut results: Vec<(String, Result<Measurement, ChemicError>)> = Vec::new();
+    for device in devices {
+        println!("Testing \"{}\"...", device.name);
+        let result = measure(&device, duration, weighting);
+        if let Err(err) = &result {
+            error!("failed to test input device \"{}\": {err}", device.name);
+        }
+        results.push((device.name, result));
+    }
+
+    // Loudest devices first, failed devices last
+    results.sort_by(|(_, a), (_, b)| {
+        let level = |result: &Result<Measurement, ChemicError>| {
+            result
+                .as_ref()
+                .ok()
+                .and_then(|measurement| measurement.rms_db)
+                .unwrap_or(f32::NEG_INFINITY)
+        };
+        level(b).total_cmp(&level(a))
+    });
+
+    let width = results
+        .iter()
+        .map(|(name, _)| name.chars().count())
+        .max()
+        .unwrap_or_default()
+        .max("Device".len());
+
+    println!();
+    if weighting != Weighting::Z {
+        println!("Levels in dB{}\n", weighting.suffix());
+    }
+    println!(
+        "{:<width$}  {:>9}  {:>9}  {:>9}",
+        "Device", "RMS", "Peak", "Noise"
+    );
+    for (index, (name, result)) in results.iter().enumerate() {
+        match result {
+            Ok(measurement) => {
+                let marker = if index == 0 && measurement.rms_db.is_some() {
+                    "  <- picking up the most signal"
+                } else {
+                    ""
+                };
+                println!(
+                    "{name:<width$}  {:>9}  {:>9}  {:>9}{marker}",
+                    format_db(measurement.rms_db),
+                    format_db(measurement.peak_db),
+                    format_db(measurement.noise_db)
+                );
+            }
+            Err(err) => println!("{name:<width$}  error: {err}"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Records the levels of the `device` for the provided `duration` with
+/// the frequency `weighting`
+pub fn measure(
+    device: &NamedDevice,
+    duration: Duration,
+    weighting: Weighting,
+) -> Result<Measurement, ChemicError> {
+    let _span = span!("sweep_measure");
+
+    let config: StreamConfig = negotiate_input(device, None)?.into();
+    let meter = Arc::new(LevelMeter::default());
+    let mut filter = WeightingFilter::new(weighting, config.sample_rate.0, config.channels);
+
+    let stream = device
+        .device
+        .build_input_stream(
+            &config,
+            {
+                let meter = meter.clone();
+                move |data: &[f32], _| match &mut filter {
+                    Some(filter) => meter.record(filter.process(data)),
+                    None => meter.record(data),
+                }
+            },
+            |err| error!("input stream error: {err} ({err:?})"),
+            None,
+        )
+        .map_err(|source| ChemicError::BuildStream {
+            ty: DeviceType::Input,
+            device: device.name.clone(),
+            source,
+        })?;
+    stream.play()?;
+
+    // Skip the first window which may contain the device starting up
+    thread::sleep(WINDOW);
+    meter.take();
+
+    let windows = (duration.as_secs_f64() / WINDOW.as_secs_f64())
+        .ceil()
+        .max(1.0) as usize;
+    let mut levels = Vec::with_capacity(windows);
+    for _ in 0..windows {
+        thread::sleep(WINDOW);
+        levels.push(meter.take());
+    }
+
+    drop(stream);
+
+    let mean_square = levels
+        .iter()
+        .map(|level| level.rms * level.rms)
+        .sum::<f32>()
+        / levels.len() as f32;
+    let peak = levels.iter().map(|level| level.peak).fold(0.0, f32::max);
+
+    let mut window_rms: Vec<f32> = levels.iter().map(|level| level.rms).collect();
+    window_rms.sort_by(f32::total_cmp);
+    let noise = window_rms[window_rms.len() / 10];
+
+    debug!(
+        "measured \"{}\" over {} windows at {}Hz {}ch",
+        device.name,
+        levels.len(),
+        config.sample_rate.0,
+        config.channels
+    );
+
+    Ok(Measurement {
+        rms_db: to_db(mean_square.sqrt()),
+        peak_db: to_db(peak),
+        noise_db: to_db(noise),
+    })
+}
+
+/// Formats an optional decibel level, [None] is below the meter floor
+fn format_db(db: Option<f32>) -> String {
+    match db {
+        Some(db) => format!("{db:.1} dB"),
+        None => "silent".to_string(),
+    }
+}
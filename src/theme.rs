@@ -0,0 +1,240 @@
+//! Colors and characters used by the prompts and the level meter,
+//! configured through the `[theme]` section of the config
+
+use dialoguer::{
+    console::{style, Color, Style, StyledObject},
+    theme::ColorfulTheme,
+};
+use std::str::FromStr;
+
+/// Base set of colors and characters that the theme settings
+/// are applied on top of
+#[derive(Clone, Copy, Default)]
+pub enum Preset {
+    /// Default colored theme
+    #[default]
+    Colorful,
+    /// Bright bold colors and solid meter characters
+    HighContrast,
+    /// No colors, only bold and reversed text
+    Monochrome,
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "colorful" => Ok(Preset::Colorful),
+            "high-contrast" => Ok(Preset::HighContrast),
+            "monochrome" => Ok(Preset::Monochrome),
+            _ => Err(format!(
+                "unknown preset \"{value}\" (expected colorful, high-contrast or monochrome)"
+            )),
+        }
+    }
+}
+
+/// Foreground color from the config
+#[derive(Clone, Copy)]
+pub struct ThemeColor {
+    /// Color to use
+    color: Color,
+    /// Whether to use the bright variant of the color
+    bright: bool,
+}
+
+impl ThemeColor {
+    /// Creates a color from a 256 color palette `index`
+    pub fn indexed(index: u8) -> Self {
+        Self {
+            color: Color::Color256(index),
+            bright: false,
+        }
+    }
+
+    /// Applies the color to the `style`
+    fn apply(self, style: Style) -> Style {
+        let style = style.fg(self.color);
+        if self.bright {
+            style.bright()
+        } else {
+            style
+        }
+    }
+
+    /// Applies the color to the styled `text`
+    fn apply_to(self, text: StyledObject<String>) -> StyledObject<String> {
+        let text = text.fg(self.color);
+        if self.bright {
+            text.bright()
+        } else {
+            text
+        }
+    }
+}
+
+impl FromStr for ThemeColor {
+    type Err = String;
+
+    /// Parses a color name optionally prefixed with "bright-"
+    /// (e.g. "cyan" or "bright-red")
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let lower = value.to_lowercase();
+        let (name, bright) = match lower.strip_prefix("bright-") {
+            Some(name) => (name, true),
+            None => (lower.as_str(), false),
+        };
+
+        let color = match name {
+            "black" => Color::Black,
+            "red" => Color::Red,
+            "green" => Color::Green,
+            "yellow" => Color::Yellow,
+            "blue" => Color::Blue,
+            "magenta" => Color::Magenta,
+            "cyan" => Color::Cyan,
+            "white" => Color::White,
+            _ => return Err(format!("unknown color \"{value}\"")),
+        };
+
+        Ok(Self { color, bright })
+    }
+}
+
+/// Theme settings, unset values use the values from the preset
+#[derive(Clone, Default)]
+pub struct UiTheme {
+    /// Base theme
+    pub preset: Preset,
+    /// Color of the prompt prefix and the selected item
+    pub accent: Option<ThemeColor>,
+    /// Text shown before the prompt
+    pub prompt_prefix: Option<String>,
+    /// Text shown before the selected item
+    pub active_item_prefix: Option<String>,
+    /// Character used for the filled part of the meter
+    pub meter_fill: Option<char>,
+    /// Character used for the empty part of the meter
+    pub meter_empty: Option<char>,
+    /// Color of the meter at low levels
+    pub meter_low: Option<ThemeColor>,
+    /// Color of the meter at levels above -18 dB
+    pub meter_mid: Option<ThemeColor>,
+    /// Color of the meter at levels above -6 dB
+    pub meter_high: Option<ThemeColor>,
+    /// Color of the clipping indicator
+    pub meter_clip: Option<ThemeColor>,
+}
+
+/// Resolved characters and styles of the level meter
+#[derive(Clone)]
+pub struct MeterTheme {
+    pub fill: char,
+    pub empty: char,
+    pub low: Style,
+    pub mid: Style,
+    pub high: Style,
+    pub clip: Style,
+}
+
+impl UiTheme {
+    /// Creates the theme used by the select prompts
+    pub fn prompt_theme(&self) -> ColorfulTheme {
+        let mut theme = match self.preset {
+            Preset::Colorful => ColorfulTheme::default(),
+            Preset::HighContrast => ColorfulTheme {
+                defaults_style: Style::new().for_stderr().yellow().bright().bold(),
+                prompt_style: Style::new().for_stderr().white().bright().bold(),
+                prompt_prefix: style("?".to_string()).for_stderr().yellow().bright().bold(),
+                prompt_suffix: style(">".to_string()).for_stderr().white().bright(),
+                success_prefix: style("✔".to_string()).for_stderr().green().bright().bold(),
+                success_suffix: style("-".to_string()).for_stderr().white().bright(),
+                hint_style: Style::new().for_stderr().white().bright(),
+                values_style: Style::new().for_stderr().green().bright().bold(),
+                active_item_style: Style::new().for_stderr().black().on_yellow().bold(),
+                active_item_prefix: style(">".to_string()).for_stderr().yellow().bright().bold(),
+                ..ColorfulTheme::default()
+            },
+            Preset::Monochrome => ColorfulTheme {
+                defaults_style: Style::new().for_stderr(),
+                prompt_style: Style::new().for_stderr().bold(),
+                prompt_prefix: style("?".to_string()).for_stderr().bold(),
+                prompt_suffix: style(">".to_string()).for_stderr(),
+                success_prefix: style("*".to_string()).for_stderr().bold(),
+                success_suffix: style("-".to_string()).for_stderr(),
+                error_prefix: style("x".to_string()).for_stderr().bold(),
+                error_style: Style::new().for_stderr().bold(),
+                hint_style: Style::new().for_stderr(),
+                values_style: Style::new().for_stderr().bold(),
+                active_item_style: Style::new().for_stderr().reverse(),
+                active_item_prefix: style(">".to_string()).for_stderr().bold(),
+                ..ColorfulTheme::default()
+            },
+        };
+
+        if let Some(prefix) = &self.prompt_prefix {
+            theme.prompt_prefix = style(prefix.clone()).for_stderr();
+        }
+        if let Some(prefix) = &self.active_item_prefix {
+            theme.active_item_prefix = style(prefix.clone()).for_stderr();
+            theme.inactive_item_prefix = style(" ".repeat(prefix.chars().count())).for_stderr();
+        }
+
+        if let Some(accent) = self.accent {
+            theme.prompt_prefix = accent.apply_to(theme.prompt_prefix);
+            theme.active_item_prefix = accent.apply_to(theme.active_item_prefix);
+            theme.active_item_style = accent.apply(theme.active_item_style);
+        }
+
+        theme
+    }
+
+    /// Resolves the characters and styles used by the level meter
+    pub fn meter_theme(&self) -> MeterTheme {
+        let base = Style::new().for_stderr();
+        let mut meter = match self.preset {
+            Preset::Colorful => MeterTheme {
+                fill: '#',
+                empty: '.',
+                low: base.clone().green(),
+                mid: base.clone().yellow(),
+                high: base.clone().red(),
+                clip: base.red().bold(),
+            },
+            Preset::HighContrast => MeterTheme {
+                fill: '█',
+                empty: '░',
+                low: base.clone().green().bright().bold(),
+                mid: base.clone().yellow().bright().bold(),
+                high: base.clone().red().bright().bold(),
+                clip: base.white().bright().on_red().bold(),
+            },
+            Preset::Monochrome => MeterTheme {
+                fill: '#',
+                empty: '-',
+                low: base.clone(),
+                mid: base.clone(),
+                high: base.clone().bold(),
+                clip: base.reverse().bold(),
+            },
+        };
+
+        meter.fill = self.meter_fill.unwrap_or(meter.fill);
+        meter.empty = self.meter_empty.unwrap_or(meter.empty);
+        if let Some(color) = self.meter_low {
+            meter.low = color.apply(meter.low);
+        }
+        if let Some(color) = self.meter_mid {
+            meter.mid = color.apply(meter.mid);
+        }
+        if let Some(color) = self.meter_high {
+            meter.high = color.apply(meter.high);
+        }
+        if let Some(color) = self.meter_clip {
+            meter.clip = color.apply(meter.clip);
+        }
+
+        meter
+    }
+}
@@ -0,0 +1,253 @@
+//! Sweep of the buffer sizes from large to small, monitoring the input
+//! through the output briefly at each size to find the smallest buffer
+//! the device pair plays without underruns, or to report the latency and
+//! underrun rate of every size
+
+use crate::{
+    args::Args,
+    config::Config,
+    error::ChemicError,
+    log::info,
+    measure::select_device,
+    negotiate::negotiate,
+    open_streams, positive_duration,
+    stats::{Latency, StreamStats},
+    stream_configs, DeviceType,
+};
+use cpal::{StreamConfig, SupportedBufferSize};
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::Duration,
+};
+
+/// Default number of seconds each buffer size is monitored for
+const DEFAULT_DURATION: f64 = 5.0;
+/// Time the streams run before counting starts, so problems while they
+/// start up don't count against the buffer size
+const SETTLE: Duration = Duration::from_secs(1);
+/// Buffer size in frames the sweep starts from
+const LARGEST: u32 = 2048;
+/// Buffer size in frames the sweep stops at when the devices don't report
+/// the sizes they support
+const SMALLEST: u32 = 16;
+
+/// Problems counted while monitoring with one buffer size
+struct Trial {
+    /// Buffer size in frames
+    frames: u32,
+    /// Interleaved samples played by the output
+    output_samples: u64,
+    /// Interleaved samples of silence played because the output ran out
+    underrun_samples: u64,
+    /// Interleaved samples of input dropped because the buffers were full
+    dropped_samples: u64,
+    stream_errors: u64,
+    /// Latency at the end of the trial
+    latency: Option<Latency>,
+}
+
+impl Trial {
+    fn stable(&self) -> bool {
+        self.underrun_samples == 0 && self.dropped_samples == 0 && self.stream_errors == 0
+    }
+
+    /// Percentage of the played audio that was silence inserted because
+    /// the output ran out
+    fn underrun_rate(&self) -> f64 {
+        self.underrun_samples as f64 / self.output_samples.max(1) as f64 * 100.0
+    }
+}
+
+/// Monitors the input and output devices from the `args` with each buffer
+/// size in turn, from the largest down, and reports the smallest size
+/// that ran without problems. Stops once a size has problems unless every
+/// size is wanted for the `report`
+pub fn run(mut args: Args, report: bool) -> Result<(), ChemicError> {
+    let config = Config::load(args.config.as_deref())?;
+    args.apply_config(&config)?;
+    // Each size is only monitored briefly, there's nothing to record
+    args.record = None;
+
+    let duration = positive_duration(args.duration.unwrap_or(DEFAULT_DURATION), "Duration")?;
+
+    let host = &cpal::default_host();
+    let input = select_device(host, &args, DeviceType::Input)?;
+    let output = select_device(host, &args, DeviceType::Output)?;
+    let settings = config.device(&input.name).cloned().unwrap_or_default();
+
+    let (supported_input, supported_output) = negotiate(&input, &output, args.sample_rate)?;
+    let sizes = buffer_sizes(
+        supported_input.buffer_size(),
+        supported_output.buffer_size(),
+    );
+
+    let action = match report {
+        true => "Measuring the latency and underruns at each buffer size",
+        false => "Tuning the buffer size",
+    };
+    println!(
+        "{action} of \"{}\" -> \"{}\", monitoring each size for {:.1}s\n",
+        input.name,
+        output.name,
+        duration.as_secs_f64()
+    );
+    println!(
+        "{:>7}  {:>9}  {:>9}  {:>10}  {:>8}  {:>10}  {:>6}  Result",
+        "Frames", "Buffer", "Latency", "Underruns", "Rate", "Dropped", "Errors"
+    );
+
+    // Sizes smaller than one with problems aren't trusted even when they
+    // happened to run without them
+    let mut smallest: Option<(Trial, StreamConfig)> = None;
+    let mut unstable = false;
+    for &frames in &sizes {
+        args.buffer_size = Some(frames);
+        let (input_config, output_config) = stream_configs(&input, &output, &args)?;
+
+        let streams = match open_streams(
+            &input,
+            &input_config,
+            &output,
+            &output_config,
+            &args,
+            &settings,
+            None,
+        ) {
+            Ok(streams) => streams,
+            Err(err) => {
+                println!("{frames:>7}  failed to open the streams: {err}");
+                if report {
+                    continue;
+                }
+                break;
+            }
+        };
+
+        thread::sleep(SETTLE);
+        let start = Counters::read(&streams.stats);
+        thread::sleep(duration);
+        let end = Counters::read(&streams.stats);
+        let trial = Trial {
+            frames,
+            output_samples: end.output_samples - start.output_samples,
+            underrun_samples: end.underrun_samples - start.underrun_samples,
+            dropped_samples: end.dropped_samples - start.dropped_samples,
+            stream_errors: end.stream_errors - start.stream_errors,
+            latency: streams.stats.latency(),
+        };
+        drop(streams);
+
+        let latency = trial.latency.map_or("unknown".to_string(), |latency| {
+            format!("{:.1} ms", latency.total().as_secs_f64() * 1000.0)
+        });
+        let stable = trial.stable();
+        info!(
+            "buffer size {frames} frames: {} underrun samples, {} dropped samples, {} errors",
+            trial.underrun_samples, trial.dropped_samples, trial.stream_errors
+        );
+        println!(
+            "{frames:>7}  {:>9}  {latency:>9}  {:>10}  {:>8}  {:>10}  {:>6}  {}",
+            format_frames(frames, &output_config),
+            format_samples(trial.underrun_samples, &output_config),
+            format!("{:.2}%", trial.underrun_rate()),
+            format_samples(trial.dropped_samples, &input_config),
+            trial.stream_errors,
+            if stable { "stable" } else { "unstable" }
+        );
+
+        if !stable {
+            unstable = true;
+            if !report {
+                break;
+            }
+        } else if !unstable {
+            smallest = Some((trial, output_config));
+        }
+    }
+
+    match smallest {
+        Some((trial, output_config)) => {
+            println!(
+                "\nSmallest stable buffer: {} frames ({} at {}Hz)",
+                trial.frames,
+                format_frames(trial.frames, &output_config),
+                output_config.sample_rate.0
+            );
+            if let Some(latency) = trial.latency {
+                println!("Latency:                {latency}");
+            }
+            if sizes.last() == Some(&trial.frames) {
+                println!("This is the smallest buffer the devices support");
+            }
+            println!(
+                "\nMonitor with it using --buffer-size {}, or set buffer_size in a profile",
+                trial.frames
+            );
+        }
+        None => {
+            println!();
+            return Err(ChemicError::CheckFailed(format!(
+                "No buffer size ran without problems, starting from {LARGEST} frames"
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Counters of the problems read at a point in time
+struct Counters {
+    output_samples: u64,
+    underrun_samples: u64,
+    dropped_samples: u64,
+    stream_errors: u64,
+}
+
+impl Counters {
+    fn read(stats: &StreamStats) -> Self {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        Self {
+            output_samples: load(&stats.output_samples),
+            underrun_samples: load(&stats.underrun_samples),
+            dropped_samples: load(&stats.dropped_samples),
+            stream_errors: load(&stats.stream_errors),
+        }
+    }
+}
+
+/// Buffer sizes in frames to try from the largest to the smallest, halving
+/// from [LARGEST] down to the smallest size both devices support
+fn buffer_sizes(input: &SupportedBufferSize, output: &SupportedBufferSize) -> Vec<u32> {
+    let range = |supported: &SupportedBufferSize| match supported {
+        SupportedBufferSize::Range { min, max } => (*min, *max),
+        SupportedBufferSize::Unknown => (SMALLEST, u32::MAX),
+    };
+    let (input_min, input_max) = range(input);
+    let (output_min, output_max) = range(output);
+    let smallest = input_min.max(output_min).max(1);
+    let largest = LARGEST.min(input_max).min(output_max).max(smallest);
+
+    let mut sizes = vec![largest];
+    let mut frames = largest;
+    while frames > smallest {
+        frames = (frames / 2).max(smallest);
+        sizes.push(frames);
+    }
+    sizes
+}
+
+/// Formats `frames` of the stream `config` as milliseconds of audio
+fn format_frames(frames: u32, config: &StreamConfig) -> String {
+    format!(
+        "{:.1} ms",
+        frames as f64 / config.sample_rate.0.max(1) as f64 * 1000.0
+    )
+}
+
+/// Formats interleaved `samples` of the stream `config` as milliseconds
+/// of audio
+fn format_samples(samples: u64, config: &StreamConfig) -> String {
+    let rate = config.sample_rate.0 as f64 * config.channels.max(1) as f64;
+    format!("{:.1} ms", samples as f64 / rate * 1000.0)
+}
@@ -0,0 +1,96 @@
+//! Watching the device list for devices being added and removed
+
+use crate::{
+    error::ChemicError,
+    events::{self, Event},
+    list_devices,
+    log::{debug, error, format_timestamp},
+    DeviceType,
+};
+use cpal::Host;
+use std::{
+    collections::BTreeMap,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// Time between polls of the device list
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of devices with each name for each type of device
+type DeviceCounts = BTreeMap<(DeviceType, String), usize>;
+
+/// Polls the devices on the `host` printing the devices that are added
+/// and removed until the process is terminated
+pub fn run(host: &Host) -> Result<(), ChemicError> {
+    let mut known = device_counts(host)?;
+
+    if !events::enabled() {
+        for (ty, name) in known.keys() {
+            println!("{} present {ty} device \"{name}\"", timestamp());
+        }
+    }
+
+    loop {
+        thread::sleep(POLL_INTERVAL);
+
+        // A failed poll is retried on the next interval
+        let current = match device_counts(host) {
+            Ok(current) => current,
+            Err(err) => {
+                error!("failed to poll devices: {err}");
+                continue;
+            }
+        };
+        if current == known {
+            continue;
+        }
+
+        for (key, &count) in &current {
+            let previous = known.get(key).copied().unwrap_or_default();
+            for _ in previous..count {
+                report(key, true);
+            }
+        }
+        for (key, &previous) in &known {
+            let count = current.get(key).copied().unwrap_or_default();
+            for _ in count..previous {
+                report(key, false);
+            }
+        }
+
+        known = current;
+    }
+}
+
+/// Counts the devices of each type on the `host` by name, devices
+/// can share a name so a device list would lose duplicates
+fn device_counts(host: &Host) -> Result<DeviceCounts, ChemicError> {
+    let mut counts = DeviceCounts::new();
+    for ty in [DeviceType::Input, DeviceType::Output] {
+        for device in list_devices(host, ty)? {
+            *counts.entry((ty, device.name)).or_default() += 1;
+        }
+    }
+    debug!("polled {} devices", counts.values().sum::<usize>());
+    Ok(counts)
+}
+
+/// Reports that the device `key` was `added` or removed
+fn report((ty, name): &(DeviceType, String), added: bool) {
+    if events::enabled() {
+        events::emit(if added {
+            Event::DeviceAdded { device: *ty, name }
+        } else {
+            Event::DeviceRemoved { device: *ty, name }
+        });
+        return;
+    }
+
+    let change = if added { "added" } else { "removed" };
+    println!("{} {change} {ty} device \"{name}\"", timestamp());
+}
+
+fn timestamp() -> String {
+    format_timestamp(SystemTime::now())
+}
@@ -0,0 +1,923 @@
+//! Writing of recordings as Broadcast WAV files holding 32 bit float
+//! samples, converted to integer samples when they are finished if asked
+//! for, the bext chunk records where and when the audio was captured,
+//! and reading of WAV files played alongside the monitored audio
+
+use crate::{
+    log::format_timestamp,
+    meter::{from_db, to_db},
+    rt60::Noise,
+    VERSION,
+};
+use decode::{decode, FORMAT_EXTENSIBLE, FORMAT_FLOAT, FORMAT_PCM};
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+mod decode;
+
+pub use decode::WavAudio;
+
+/// Bytes in each sample while recording, files in other formats are
+/// converted when they are finished
+pub const SAMPLE_BYTES: u16 = 4;
+/// Offset of the fmt chunk from the start of the file
+const FMT_OFFSET: u64 = 12;
+/// Size of the body of the extensible fmt chunk, plain fmt chunks are
+/// followed by a JUNK chunk taking up the rest of the space so the fmt
+/// chunk can be rewritten in place when the file is finished
+const FMT_EXTENSIBLE_SIZE: usize = 40;
+/// Size of the body of the plain integer fmt chunk
+const FMT_PCM_SIZE: usize = 16;
+/// End of the sub format GUIDs shared by the PCM and IEEE float formats,
+/// the format tag makes up their first two bytes
+const SUB_FORMAT_GUID: [u8; 14] = [
+    0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+];
+/// Offset of the RIFF chunk size from the start of the file
+const RIFF_SIZE_OFFSET: u64 = 4;
+/// Size of the fixed fields of the bext chunk
+const BEXT_FIELDS_SIZE: usize = 602;
+/// Space reserved for the coding history of the bext chunk
+const CODING_HISTORY_SIZE: usize = 256;
+/// Version of the bext chunk, version 2 has the loudness fields
+const BEXT_VERSION: u16 = 2;
+/// Loudness field value for levels that weren't measured
+const LOUDNESS_UNKNOWN: i16 = 0x7fff;
+/// Audio kept before the first and after the last sample above the
+/// trim threshold, so the start of speech and its tail aren't cut off
+const TRIM_LEAD: f64 = 0.1;
+const TRIM_TAIL: f64 = 0.25;
+
+/// Provenance of a recording written to its bext chunk
+#[derive(Clone)]
+pub struct BroadcastInfo {
+    /// Description of the recorded audio, the measured levels are added
+    /// to it when the recording is finished
+    pub description: String,
+    /// Name of the device the audio was captured from
+    pub device: String,
+    /// When the recording started
+    pub started: SystemTime,
+}
+
+/// Leading and trailing silence trimmed from a recording when it is
+/// finished
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trim {
+    /// Level in dBFS below which the audio counts as silence
+    pub threshold_db: f32,
+    /// Whether the silence at the start is trimmed, files continuing a
+    /// recording split over multiple files keep theirs
+    pub start: bool,
+    /// Whether the silence at the end is trimmed
+    pub end: bool,
+}
+
+/// Format of the samples in a finished file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// 32 bit float, what the audio is processed in
+    #[default]
+    Float32,
+    /// 16 bit integer
+    Int16,
+    /// 24 bit integer, the resolution most interfaces deliver
+    Int24,
+}
+
+impl SampleFormat {
+    /// Bytes in each sample
+    pub fn bytes(self) -> u16 {
+        match self {
+            SampleFormat::Float32 => 4,
+            SampleFormat::Int16 => 2,
+            SampleFormat::Int24 => 3,
+        }
+    }
+
+    fn tag(self) -> u16 {
+        match self {
+            SampleFormat::Float32 => FORMAT_FLOAT,
+            SampleFormat::Int16 | SampleFormat::Int24 => FORMAT_PCM,
+        }
+    }
+
+    /// Name of the coding algorithm in the coding history of the bext
+    /// chunk
+    fn algorithm(self) -> &'static str {
+        match self {
+            SampleFormat::Float32 => "IEEE_FLOAT",
+            SampleFormat::Int16 | SampleFormat::Int24 => "PCM",
+        }
+    }
+
+    /// Appends the `sample` to the `out` bytes, integer formats add the
+    /// `dither` offset in steps of the smallest value before rounding
+    fn encode(self, sample: f32, dither: f32, out: &mut Vec<u8>) {
+        match self {
+            SampleFormat::Float32 => out.extend_from_slice(&sample.to_le_bytes()),
+            SampleFormat::Int16 => {
+                let value = (sample * 32_768.0 + dither)
+                    .round()
+                    .clamp(-32_768.0, 32_767.0);
+                out.extend_from_slice(&(value as i16).to_le_bytes());
+            }
+            SampleFormat::Int24 => {
+                let value = (sample * 8_388_608.0 + dither)
+                    .round()
+                    .clamp(-8_388_608.0, 8_388_607.0);
+                out.extend_from_slice(&(value as i32).to_le_bytes()[..3]);
+            }
+        }
+    }
+}
+
+/// Parses "32f" for float or "16" and "24" for integer samples
+impl FromStr for SampleFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "32f" | "float" => Ok(SampleFormat::Float32),
+            "16" => Ok(SampleFormat::Int16),
+            "24" => Ok(SampleFormat::Int24),
+            _ => Err(format!(
+                "unknown bit depth \"{value}\" (expected 16, 24 or 32f)"
+            )),
+        }
+    }
+}
+
+/// Processing applied to the audio when the file is finished
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Finishing {
+    pub trim: Option<Trim>,
+    /// Level in dBFS the peak of the audio is brought to
+    pub normalize: Option<f32>,
+    /// Format the samples are saved in
+    pub format: SampleFormat,
+    /// Whether triangular (TPDF) dither is added when the samples are
+    /// saved as integers, so quiet audio isn't distorted by rounding
+    pub dither: bool,
+}
+
+/// Writer of a WAV file, the sizes in the header and the levels in the
+/// bext chunk are filled in when the writer is finished
+pub struct WavWriter {
+    file: BufWriter<File>,
+    /// Path of the file, read back when the audio is processed
+    path: PathBuf,
+    /// Sample rate of the audio
+    sample_rate: u32,
+    /// Number of interleaved channels
+    channels: u16,
+    /// Provenance written to the bext chunk
+    info: BroadcastInfo,
+    /// Offset of the body of the bext chunk
+    bext_offset: u64,
+    /// Offset of the number of frames in the fact chunk
+    fact_frames_offset: u64,
+    /// Offset of the data chunk size
+    data_size_offset: u64,
+    /// Bytes of samples written to the data chunk
+    data_bytes: u64,
+    /// Highest absolute sample value written
+    peak: f32,
+    /// Sum of the squared samples written
+    sum_squares: f64,
+    /// Frames and labels of the cue points written after the data
+    cues: Vec<(u64, String)>,
+    /// Processing applied when the file is finished
+    finishing: Finishing,
+    /// First and last frames above the trim threshold
+    loud: Option<(u64, u64)>,
+    /// Peak normalized to instead of the peak of this file, for files
+    /// of a recording split over multiple files
+    normalize_peak: Option<f32>,
+}
+
+impl WavWriter {
+    /// Creates the file at the `path` for audio at the `sample_rate` with
+    /// the number of interleaved `channels`, described by the `info`
+    pub fn create(
+        path: &Path,
+        sample_rate: u32,
+        channels: u16,
+        info: BroadcastInfo,
+    ) -> io::Result<Self> {
+        let channels = channels.max(1);
+        let mut file = BufWriter::new(File::create(path)?);
+
+        file.write_all(b"RIFF")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        file.write_all(b"WAVE")?;
+        file.write_all(&fmt_chunk(sample_rate, channels, SampleFormat::Float32))?;
+
+        // The bext chunk comes before the data so it is found without
+        // reading the whole file
+        file.write_all(b"bext")?;
+        file.write_all(&((BEXT_FIELDS_SIZE + CODING_HISTORY_SIZE) as u32).to_le_bytes())?;
+        let bext_offset = file.stream_position()?;
+        file.write_all(&bext_body(
+            &info,
+            sample_rate,
+            channels,
+            SampleFormat::Float32,
+            None,
+        ))?;
+
+        file.write_all(b"fact")?;
+        file.write_all(&4u32.to_le_bytes())?;
+        let fact_frames_offset = file.stream_position()?;
+        file.write_all(&0u32.to_le_bytes())?;
+
+        file.write_all(b"data")?;
+        let data_size_offset = file.stream_position()?;
+        file.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+            sample_rate,
+            channels,
+            info,
+            bext_offset,
+            fact_frames_offset,
+            data_size_offset,
+            data_bytes: 0,
+            peak: 0.0,
+            sum_squares: 0.0,
+            cues: Vec::new(),
+            finishing: Finishing::default(),
+            loud: None,
+            normalize_peak: None,
+        })
+    }
+
+    /// Applies the `finishing` to the audio when the file is finished
+    pub fn finishing(mut self, finishing: Finishing) -> Self {
+        self.finishing = finishing;
+        self
+    }
+
+    /// Keeps the silence at the end of the file, for files continued in
+    /// another file of the same recording
+    pub fn continues(&mut self) {
+        if let Some(trim) = &mut self.finishing.trim {
+            trim.end = false;
+        }
+    }
+
+    /// Highest absolute sample value written
+    pub fn peak(&self) -> f32 {
+        self.peak
+    }
+
+    /// Normalizes the audio using the `peak` of the whole recording
+    /// instead of the peak of this file, so the files of a recording
+    /// split over multiple files get the same gain
+    pub fn normalize_with_peak(&mut self, peak: f32) {
+        self.normalize_peak = Some(peak);
+    }
+
+    /// Appends the interleaved `samples`
+    pub fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        if let Some(trim) = self.finishing.trim {
+            let threshold = from_db(trim.threshold_db);
+            let first = self.data_bytes / SAMPLE_BYTES as u64;
+            let channels = self.channels as u64;
+            for (index, sample) in (first..).zip(samples) {
+                if sample.abs() >= threshold {
+                    let frame = index / channels;
+                    let start = self.loud.map_or(frame, |(start, _)| start);
+                    self.loud = Some((start, frame));
+                }
+            }
+        }
+
+        for sample in samples {
+            self.file.write_all(&sample.to_le_bytes())?;
+            self.peak = self.peak.max(sample.abs());
+            self.sum_squares += (*sample as f64) * (*sample as f64);
+        }
+        self.data_bytes += samples.len() as u64 * SAMPLE_BYTES as u64;
+        Ok(())
+    }
+
+    /// Adds a cue point with the `label` at the `frame`, shown as a
+    /// marker by most audio editors
+    pub fn add_cue(&mut self, frame: u64, label: &str) {
+        self.cues.push((frame, label.to_string()));
+    }
+
+    /// Writes the cue points and their labels
+    fn write_cues(&mut self) -> io::Result<()> {
+        let size = |bytes: usize| bytes as u32;
+        let file = &mut self.file;
+
+        file.write_all(b"cue ")?;
+        file.write_all(&size(4 + 24 * self.cues.len()).to_le_bytes())?;
+        file.write_all(&size(self.cues.len()).to_le_bytes())?;
+        for (id, (frame, _)) in (1u32..).zip(&self.cues) {
+            let frame = u32::try_from(*frame).unwrap_or(u32::MAX);
+            file.write_all(&id.to_le_bytes())?;
+            file.write_all(&frame.to_le_bytes())?;
+            file.write_all(b"data")?;
+            // Chunk and block start are zero for a single data chunk
+            file.write_all(&0u32.to_le_bytes())?;
+            file.write_all(&0u32.to_le_bytes())?;
+            file.write_all(&frame.to_le_bytes())?;
+        }
+
+        // Labels are null terminated and padded to an even size
+        let label_size = |label: &str| 4 + label.len() + 1;
+        let padded = |size: usize| size + size % 2;
+        let list_size: usize = 4 + self
+            .cues
+            .iter()
+            .map(|(_, label)| 8 + padded(label_size(label)))
+            .sum::<usize>();
+
+        file.write_all(b"LIST")?;
+        file.write_all(&size(list_size).to_le_bytes())?;
+        file.write_all(b"adtl")?;
+        for (id, (_, label)) in (1u32..).zip(&self.cues) {
+            file.write_all(b"labl")?;
+            file.write_all(&size(label_size(label)).to_le_bytes())?;
+            file.write_all(&id.to_le_bytes())?;
+            file.write_all(label.as_bytes())?;
+            file.write_all(&[0])?;
+            if label_size(label) % 2 == 1 {
+                file.write_all(&[0])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Frames kept from the start and end of the `frames` written, the
+    /// silence around the audio above the trim threshold is left out.
+    /// Files without audio above the threshold are kept whole
+    fn kept_frames(&self, frames: u64) -> (u64, u64) {
+        match (self.finishing.trim, self.loud) {
+            (Some(trim), Some((first, last))) => {
+                let seconds = |seconds: f64| (seconds * self.sample_rate as f64) as u64;
+                let start = match trim.start {
+                    true => first.saturating_sub(seconds(TRIM_LEAD)),
+                    false => 0,
+                };
+                let end = match trim.end {
+                    true => (last + 1 + seconds(TRIM_TAIL)).min(frames),
+                    false => frames,
+                };
+                (start, end)
+            }
+            _ => (0, frames),
+        }
+    }
+
+    /// Frames of silence trimmed from the start of the file
+    pub fn trimmed_start(&self) -> u64 {
+        let frames = self.data_bytes / (self.channels * SAMPLE_BYTES) as u64;
+        self.kept_frames(frames).0
+    }
+
+    /// Gain that brings the peak to the normalized level, silent audio
+    /// is left as it is
+    fn normalize_gain(&self) -> f32 {
+        let peak = self.normalize_peak.unwrap_or(self.peak);
+        match self.finishing.normalize {
+            Some(target_db) if peak > 0.0 => from_db(target_db) / peak,
+            _ => 1.0,
+        }
+    }
+
+    /// Rewrites the audio with the finishing applied, moving the kept
+    /// frames to the start of the data chunk. The audio is never longer
+    /// than before so it is rewritten in place behind the reads
+    fn process(&mut self) -> io::Result<()> {
+        let channels = self.channels as u64;
+        let frame_bytes = channels * SAMPLE_BYTES as u64;
+        let frames = self.data_bytes / frame_bytes;
+        let (start, end) = self.kept_frames(frames);
+        // The trimmed silence is quieter than the peak so the gain is the
+        // same with or without it
+        let gain = self.normalize_gain();
+        let format = self.finishing.format;
+        if (start, end) == (0, frames) && gain == 1.0 && format == SampleFormat::Float32 {
+            return Ok(());
+        }
+
+        self.file.flush()?;
+        let data_offset = self.data_size_offset + 4;
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        reader.seek(SeekFrom::Start(data_offset + start * frame_bytes))?;
+        self.file.seek(SeekFrom::Start(data_offset))?;
+
+        // The sum of two uniform values between -0.5 and 0.5 has the
+        // triangular distribution
+        let mut noise = Noise::default();
+        let dither = self.finishing.dither && format != SampleFormat::Float32;
+        let mut dither = move || match dither {
+            true => (noise.next() + noise.next()) * 0.5,
+            false => 0.0,
+        };
+
+        self.peak = 0.0;
+        self.sum_squares = 0.0;
+        let mut bytes = [0; SAMPLE_BYTES as usize];
+        let mut encoded = Vec::with_capacity(SAMPLE_BYTES as usize);
+        for _ in 0..(end - start) * channels {
+            reader.read_exact(&mut bytes)?;
+            let sample = f32::from_le_bytes(bytes) * gain;
+            encoded.clear();
+            format.encode(sample, dither(), &mut encoded);
+            self.file.write_all(&encoded)?;
+            self.peak = self.peak.max(sample.abs());
+            self.sum_squares += (sample as f64) * (sample as f64);
+        }
+        self.data_bytes = (end - start) * channels * format.bytes() as u64;
+        // Chunks are padded to an even size, 24 bit audio can leave an
+        // odd number of bytes
+        if self.data_bytes % 2 == 1 {
+            self.file.write_all(&[0])?;
+        }
+
+        // Cue points stay on the same audio, those in the trimmed
+        // silence move to its edge
+        for (frame, _) in &mut self.cues {
+            *frame = frame.saturating_sub(start).min(end - start);
+        }
+        Ok(())
+    }
+
+    /// Applies the finishing, writes the cue points, fills in the sizes in
+    /// the header and flushes the file
+    pub fn finish(mut self) -> io::Result<()> {
+        self.process()?;
+        if !self.cues.is_empty() {
+            self.write_cues()?;
+        }
+        let end = self.file.stream_position()?;
+
+        // Sizes past the limit of the format are capped, most readers
+        // then read to the end of the file
+        let size = |bytes: u64| u32::try_from(bytes).unwrap_or(u32::MAX);
+        let format = self.finishing.format;
+        let sample_bytes = format.bytes();
+        let frames = self.data_bytes / (self.channels * sample_bytes) as u64;
+        let riff_size = end - 8;
+
+        let samples = self.data_bytes / sample_bytes as u64;
+        let levels = (samples > 0).then(|| Levels {
+            peak_db: to_db(self.peak),
+            rms_db: to_db((self.sum_squares / samples as f64).sqrt() as f32),
+        });
+        let bext = bext_body(&self.info, self.sample_rate, self.channels, format, levels);
+
+        self.file.seek(SeekFrom::Start(RIFF_SIZE_OFFSET))?;
+        self.file.write_all(&size(riff_size).to_le_bytes())?;
+        if format != SampleFormat::Float32 {
+            self.file.seek(SeekFrom::Start(FMT_OFFSET))?;
+            self.file
+                .write_all(&fmt_chunk(self.sample_rate, self.channels, format))?;
+        }
+        self.file.seek(SeekFrom::Start(self.bext_offset))?;
+        self.file.write_all(&bext)?;
+        self.file.seek(SeekFrom::Start(self.fact_frames_offset))?;
+        self.file.write_all(&size(frames).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(self.data_size_offset))?;
+        self.file.write_all(&size(self.data_bytes).to_le_bytes())?;
+        self.file.flush()?;
+
+        // Processed audio can leave the previous end past the cue points
+        self.file.get_ref().set_len(end)
+    }
+}
+
+/// Levels measured over the whole recording in dBFS, [None] when silent
+#[derive(Clone, Copy)]
+struct Levels {
+    peak_db: Option<f32>,
+    rms_db: Option<f32>,
+}
+
+/// Creates the fmt chunk for the `channels` of audio at the `sample_rate`
+/// in the `format`. Formats of more than 16 bits or 2 channels use the
+/// extensible format with the channel mask of the WAVE channel order,
+/// others use the plain format followed by a JUNK chunk of the same size
+fn fmt_chunk(sample_rate: u32, channels: u16, format: SampleFormat) -> Vec<u8> {
+    let bits = format.bytes() * 8;
+    let block_align = channels * format.bytes();
+    let extensible = bits > 16 || channels > 2;
+
+    let mut chunk = Vec::with_capacity(8 + FMT_EXTENSIBLE_SIZE);
+    chunk.extend_from_slice(b"fmt ");
+    let (tag, size) = match extensible {
+        true => (FORMAT_EXTENSIBLE, FMT_EXTENSIBLE_SIZE),
+        false => (format.tag(), FMT_PCM_SIZE),
+    };
+    chunk.extend_from_slice(&(size as u32).to_le_bytes());
+    chunk.extend_from_slice(&tag.to_le_bytes());
+    chunk.extend_from_slice(&channels.to_le_bytes());
+    chunk.extend_from_slice(&sample_rate.to_le_bytes());
+    chunk.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    chunk.extend_from_slice(&block_align.to_le_bytes());
+    chunk.extend_from_slice(&bits.to_le_bytes());
+
+    if extensible {
+        // Extension size, valid bits, channel mask and the sub format
+        chunk.extend_from_slice(&22u16.to_le_bytes());
+        chunk.extend_from_slice(&bits.to_le_bytes());
+        chunk.extend_from_slice(&channel_mask(channels).to_le_bytes());
+        chunk.extend_from_slice(&format.tag().to_le_bytes());
+        chunk.extend_from_slice(&SUB_FORMAT_GUID);
+    } else {
+        chunk.extend_from_slice(b"JUNK");
+        let junk_size = FMT_EXTENSIBLE_SIZE - FMT_PCM_SIZE - 8;
+        chunk.extend_from_slice(&(junk_size as u32).to_le_bytes());
+        chunk.resize(8 + FMT_EXTENSIBLE_SIZE, 0);
+    }
+
+    chunk
+}
+
+/// Speaker positions of the `channels` in the WAVE channel order, the
+/// layouts match the surround layouts downmixed by the mix matrices.
+/// Other channel counts have no assigned positions
+fn channel_mask(channels: u16) -> u32 {
+    const FRONT_LEFT: u32 = 0x1;
+    const FRONT_RIGHT: u32 = 0x2;
+    const FRONT_CENTER: u32 = 0x4;
+    const LFE: u32 = 0x8;
+    const BACK_LEFT: u32 = 0x10;
+    const BACK_RIGHT: u32 = 0x20;
+    const BACK_CENTER: u32 = 0x100;
+    const SIDE_LEFT: u32 = 0x200;
+    const SIDE_RIGHT: u32 = 0x400;
+    const STEREO: u32 = FRONT_LEFT | FRONT_RIGHT;
+
+    match channels {
+        1 => FRONT_CENTER,
+        2 => STEREO,
+        3 => STEREO | FRONT_CENTER,
+        4 => STEREO | BACK_LEFT | BACK_RIGHT,
+        5 => STEREO | FRONT_CENTER | BACK_LEFT | BACK_RIGHT,
+        6 => STEREO | FRONT_CENTER | LFE | BACK_LEFT | BACK_RIGHT,
+        7 => STEREO | FRONT_CENTER | LFE | BACK_CENTER | SIDE_LEFT | SIDE_RIGHT,
+        8 => STEREO | FRONT_CENTER | LFE | BACK_LEFT | BACK_RIGHT | SIDE_LEFT | SIDE_RIGHT,
+        _ => 0,
+    }
+}
+
+/// Creates the body of the bext chunk for the recording described by the
+/// `info`, the `levels` are included once they are known
+fn bext_body(
+    info: &BroadcastInfo,
+    sample_rate: u32,
+    channels: u16,
+    format: SampleFormat,
+    levels: Option<Levels>,
+) -> Vec<u8> {
+    let mut body = Vec::with_capacity(BEXT_FIELDS_SIZE + CODING_HISTORY_SIZE);
+
+    let format_db = |db: Option<f32>| match db {
+        Some(db) => format!("{db:.1} dBFS"),
+        None => "silent".to_string(),
+    };
+    let description = match levels {
+        Some(levels) => format!(
+            "{}, peak {}, RMS {}",
+            info.description,
+            format_db(levels.peak_db),
+            format_db(levels.rms_db)
+        ),
+        None => info.description.clone(),
+    };
+
+    // Dates and times are in UTC (e.g. "2024-01-31T09:15:00.123Z")
+    let timestamp = format_timestamp(info.started);
+    let seconds_of_day = info
+        .started
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+        % 86_400.0;
+    let time_reference = (seconds_of_day * sample_rate as f64) as u64;
+
+    write_text(&mut body, &description, 256);
+    write_text(&mut body, &format!("chemic {VERSION}"), 32);
+    write_text(&mut body, &info.device, 32);
+    write_text(&mut body, &timestamp[..10], 10);
+    write_text(&mut body, &timestamp[11..19], 8);
+    body.extend_from_slice(&time_reference.to_le_bytes());
+    body.extend_from_slice(&BEXT_VERSION.to_le_bytes());
+    // No UMID
+    body.extend_from_slice(&[0; 64]);
+
+    // Only the sample peak is measured, it stands in for the true peak
+    let peak = levels
+        .and_then(|levels| levels.peak_db)
+        .map_or(LOUDNESS_UNKNOWN, |db| (db * 100.0).round() as i16);
+    for value in [
+        LOUDNESS_UNKNOWN,
+        LOUDNESS_UNKNOWN,
+        peak,
+        LOUDNESS_UNKNOWN,
+        LOUDNESS_UNKNOWN,
+    ] {
+        body.extend_from_slice(&value.to_le_bytes());
+    }
+    body.extend_from_slice(&[0; 180]);
+
+    let mode = match channels {
+        1 => ",M=mono",
+        2 => ",M=stereo",
+        _ => "",
+    };
+    let history = format!(
+        "A={},F={sample_rate},W={}{mode},T=chemic {VERSION}\r\n",
+        format.algorithm(),
+        format.bytes() * 8
+    );
+    write_text(&mut body, &history, CODING_HISTORY_SIZE);
+
+    body
+}
+
+/// Writes the `text` into a field of `size` bytes padded with zeros,
+/// cutting it short at a character boundary when it doesn't fit
+fn write_text(out: &mut Vec<u8>, text: &str, size: usize) {
+    let mut end = text.len().min(size);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    out.extend_from_slice(&text.as_bytes()[..end]);
+    out.resize(out.len() + size - end, 0);
+}
+
+/// Reads the WAV file at the `path`, integer samples of 8 to 32 bits
+/// and float samples of 32 or 64 bits are converted to floats
+pub fn read(path: &Path) -> io::Result<WavAudio> {
+    decode(&fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    /// Path of a file named `name` in a directory of its own for the test
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("chemic-wav-{}-{name}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    fn info() -> BroadcastInfo {
+        BroadcastInfo {
+            description: "Test".to_string(),
+            device: "Mic".to_string(),
+            started: UNIX_EPOCH,
+        }
+    }
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Ids, sizes and body offsets of the chunks of the file, checking
+    /// that the padded chunks end exactly at the end of the file
+    fn chunks(bytes: &[u8]) -> Vec<(String, u32, usize)> {
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(u32_at(bytes, 4) as usize, bytes.len() - 8);
+        let mut chunks = Vec::new();
+        let mut offset = 12;
+        while offset < bytes.len() {
+            let id = String::from_utf8_lossy(&bytes[offset..offset + 4]).to_string();
+            let size = u32_at(bytes, offset + 4);
+            chunks.push((id, size, offset + 8));
+            offset += 8 + size as usize + size as usize % 2;
+        }
+        assert_eq!(offset, bytes.len());
+        chunks
+    }
+
+    fn chunk<'a>(chunks: &'a [(String, u32, usize)], id: &str) -> &'a (String, u32, usize) {
+        chunks.iter().find(|(name, ..)| name == id).unwrap()
+    }
+
+    /// Writes the `samples` to a file finished with the `finishing` and
+    /// the cues, returning its bytes and the audio read back from it
+    fn write(
+        name: &str,
+        channels: u16,
+        samples: &[f32],
+        finishing: Finishing,
+        cues: &[(u64, &str)],
+    ) -> (Vec<u8>, WavAudio) {
+        let path = temp_path(name);
+        let mut writer = WavWriter::create(&path, 1000, channels, info())
+            .unwrap()
+            .finishing(finishing);
+        writer.write(samples).unwrap();
+        for (frame, label) in cues {
+            writer.add_cue(*frame, label);
+        }
+        writer.finish().unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        let audio = read(&path).unwrap();
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        (bytes, audio)
+    }
+
+    #[test]
+    fn float_files_keep_the_samples_and_cues() {
+        let samples: Vec<f32> = (0..300).map(|index| index as f32 / 300.0 - 0.5).collect();
+        let (bytes, audio) = write(
+            "float.wav",
+            2,
+            &samples,
+            Finishing::default(),
+            &[(10, "Marker 1"), (20, "Marker 10")],
+        );
+
+        assert_eq!(audio.sample_rate, 1000);
+        assert_eq!(audio.channels, 2);
+        assert_eq!(audio.samples, samples);
+
+        let chunks = chunks(&bytes);
+        let ids: Vec<&str> = chunks.iter().map(|(id, ..)| id.as_str()).collect();
+        assert_eq!(ids, ["fmt ", "bext", "fact", "data", "cue ", "LIST"]);
+        let &(_, size, fmt) = chunk(&chunks, "fmt ");
+        assert_eq!(size, 40);
+        assert_eq!(u16_at(&bytes, fmt), FORMAT_EXTENSIBLE);
+        assert_eq!(u16_at(&bytes, fmt + 12), 8);
+        assert_eq!(u16_at(&bytes, fmt + 14), 32);
+        assert_eq!(u16_at(&bytes, fmt + 16), 22);
+        assert_eq!(u16_at(&bytes, fmt + 18), 32);
+        assert_eq!(u32_at(&bytes, fmt + 20), 0x3);
+        assert_eq!(u16_at(&bytes, fmt + 24), FORMAT_FLOAT);
+        assert_eq!(&bytes[fmt + 26..fmt + 40], SUB_FORMAT_GUID);
+        assert!(coding_history(&bytes).starts_with("A=IEEE_FLOAT,F=1000,W=32,M=stereo,"));
+        let &(_, _, fact) = chunk(&chunks, "fact");
+        assert_eq!(u32_at(&bytes, fact), 150);
+        assert_eq!(chunk(&chunks, "data").1, 300 * 4);
+
+        let &(_, size, cue) = chunk(&chunks, "cue ");
+        assert_eq!(size, 4 + 24 * 2);
+        assert_eq!(u32_at(&bytes, cue), 2);
+        assert_eq!(u32_at(&bytes, cue + 8), 10);
+        assert_eq!(u32_at(&bytes, cue + 24 + 8), 20);
+
+        // Labels with the id and null of 13 bytes padded to 14 and of 14
+        let &(_, size, list) = chunk(&chunks, "LIST");
+        assert_eq!(size, 4 + (8 + 14) + (8 + 14));
+        assert_eq!(&bytes[list..list + 4], b"adtl");
+        assert_eq!(&bytes[list + 4..list + 8], b"labl");
+        assert_eq!(u32_at(&bytes, list + 8), 13);
+        assert_eq!(&bytes[list + 12..list + 24], b"\x01\0\0\0Marker 1");
+        assert_eq!(&bytes[list + 26..list + 30], b"labl");
+        assert_eq!(u32_at(&bytes, list + 30), 14);
+    }
+
+    #[test]
+    fn integer_samples_clamp_at_full_scale() {
+        let samples = [1.0, -1.0, 1.5, -1.5, 0.5, 0.0];
+
+        let (bytes, audio) = write("16.wav", 2, &samples, int(SampleFormat::Int16), &[]);
+        let found = chunks(&bytes);
+        let ids: Vec<&str> = found.iter().map(|(id, ..)| id.as_str()).collect();
+        assert_eq!(ids, ["fmt ", "JUNK", "bext", "fact", "data"]);
+        let &(_, size, fmt) = chunk(&found, "fmt ");
+        assert_eq!(size, 16);
+        assert_eq!(chunk(&found, "JUNK").1, 16);
+        assert_eq!(u16_at(&bytes, fmt), FORMAT_PCM);
+        assert!(coding_history(&bytes).starts_with("A=PCM,F=1000,W=16,M=stereo,"));
+        assert_eq!(u16_at(&bytes, fmt + 12), 4);
+        assert_eq!(u16_at(&bytes, fmt + 14), 16);
+        let max = 32_767.0 / 32_768.0;
+        assert_eq!(audio.samples, [max, -1.0, max, -1.0, 0.5, 0.0]);
+
+        let (bytes, audio) = write("24.wav", 2, &samples, int(SampleFormat::Int24), &[]);
+        let &(_, _, fmt) = chunk(&chunks(&bytes), "fmt ");
+        assert_eq!(u16_at(&bytes, fmt), FORMAT_EXTENSIBLE);
+        assert_eq!(u16_at(&bytes, fmt + 12), 6);
+        assert_eq!(u16_at(&bytes, fmt + 14), 24);
+        assert_eq!(u16_at(&bytes, fmt + 18), 24);
+        assert_eq!(u16_at(&bytes, fmt + 24), FORMAT_PCM);
+        assert!(coding_history(&bytes).starts_with("A=PCM,F=1000,W=24,M=stereo,"));
+        let max = 8_388_607.0 / 8_388_608.0;
+        assert_eq!(audio.samples, [max, -1.0, max, -1.0, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn surround_files_have_a_channel_mask() {
+        let samples: Vec<f32> = (0..60).map(|index| index as f32 / 64.0).collect();
+        let (bytes, audio) = write("51.wav", 6, &samples, int(SampleFormat::Int16), &[]);
+
+        let chunks = chunks(&bytes);
+        let &(_, size, fmt) = chunk(&chunks, "fmt ");
+        assert_eq!(size, 40);
+        assert!(!chunks.iter().any(|(id, ..)| id == "JUNK"));
+        assert_eq!(u16_at(&bytes, fmt), FORMAT_EXTENSIBLE);
+        assert_eq!(u16_at(&bytes, fmt + 2), 6);
+        assert_eq!(u16_at(&bytes, fmt + 12), 12);
+        assert_eq!(u32_at(&bytes, fmt + 20), 0x3f);
+        assert_eq!(u16_at(&bytes, fmt + 24), FORMAT_PCM);
+        assert_eq!(audio.channels, 6);
+        assert_eq!(audio.samples, samples);
+
+        assert_eq!(channel_mask(1), 0x4);
+        assert_eq!(channel_mask(8), 0x63f);
+        assert_eq!(channel_mask(7), 0x70f);
+        assert_eq!(channel_mask(12), 0);
+    }
+
+    #[test]
+    fn odd_24_bit_data_is_padded() {
+        let (bytes, audio) = write(
+            "odd.wav",
+            1,
+            &[0.25, -0.25, 0.5],
+            int(SampleFormat::Int24),
+            &[(1, "Marker 1")],
+        );
+
+        // The cue chunk starts after the padding byte
+        let chunks = chunks(&bytes);
+        let &(_, size, data) = chunk(&chunks, "data");
+        assert_eq!(size, 9);
+        assert_eq!(chunk(&chunks, "cue ").2, data + 10 + 8);
+        assert_eq!(u32_at(&bytes, chunk(&chunks, "fact").2), 3);
+        assert_eq!(audio.samples, [0.25, -0.25, 0.5]);
+    }
+
+    #[test]
+    fn trimming_keeps_the_lead_and_tail() {
+        // Silence around 100 frames of audio at 1000 Hz
+        let mut samples = vec![0.0; 500];
+        samples.extend([0.5; 100]);
+        samples.extend(vec![0.0; 1000]);
+        let finishing = Finishing {
+            trim: Some(Trim {
+                threshold_db: -40.0,
+                start: true,
+                end: true,
+            }),
+            normalize: Some(-6.0),
+            ..Finishing::default()
+        };
+        let (bytes, audio) = write(
+            "trim.wav",
+            1,
+            &samples,
+            finishing,
+            &[(100, "Marker 1"), (550, "Marker 2"), (1500, "Marker 3")],
+        );
+
+        // 100 ms before and 250 ms after the audio are kept
+        assert_eq!(audio.samples.len(), 100 + 100 + 250);
+        assert!(audio.samples[..100].iter().all(|&sample| sample == 0.0));
+        let normalized = from_db(-6.0);
+        assert!(audio.samples[100..200]
+            .iter()
+            .all(|&sample| (sample - normalized).abs() < 1e-6));
+        assert!(audio.samples[200..].iter().all(|&sample| sample == 0.0));
+
+        let chunks = chunks(&bytes);
+        assert_eq!(u32_at(&bytes, chunk(&chunks, "fact").2), 450);
+        assert_eq!(chunk(&chunks, "data").1, 450 * 4);
+        let &(_, _, cue) = chunk(&chunks, "cue ");
+        let frames: Vec<u32> = (0..3)
+            .map(|index| u32_at(&bytes, cue + 4 + 24 * index + 4))
+            .collect();
+        assert_eq!(frames, [0, 150, 450]);
+    }
+
+    /// Coding history at the end of the bext chunk of the file
+    fn coding_history(bytes: &[u8]) -> String {
+        let &(_, _, bext) = chunk(&chunks(bytes), "bext");
+        let history = &bytes[bext + BEXT_FIELDS_SIZE..][..CODING_HISTORY_SIZE];
+        String::from_utf8_lossy(history)
+            .trim_end_matches('\0')
+            .to_string()
+    }
+
+    fn int(format: SampleFormat) -> Finishing {
+        Finishing {
+            format,
+            ..Finishing::default()
+        }
+    }
+}
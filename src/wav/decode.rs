@@ -0,0 +1,122 @@
+//! Decoding of WAV files into float samples, kept free of the rest of
+//! chemic so the fuzz targets can build it on its own
+
+use std::io;
+
+/// Format tag of integer samples
+pub const FORMAT_PCM: u16 = 1;
+/// Format tag of IEEE float samples
+pub const FORMAT_FLOAT: u16 = 3;
+/// Format tag of the extensible format, the actual format tag is the
+/// start of the sub format
+pub const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// Audio read from a WAV file
+pub struct WavAudio {
+    /// Sample rate of the audio
+    pub sample_rate: u32,
+    /// Number of interleaved channels
+    pub channels: u16,
+    /// Interleaved samples
+    pub samples: Vec<f32>,
+}
+
+/// Decodes the `bytes` of a WAV file, integer samples of 8 to 32 bits
+/// and float samples of 32 or 64 bits are converted to floats
+pub fn decode(bytes: &[u8]) -> io::Result<WavAudio> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+    if bytes.len() < 12 || &bytes[..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(invalid("not a WAV file"));
+    }
+
+    let u16_at = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+    let u32_at = |offset: usize| {
+        u32::from_le_bytes([
+            bytes[offset],
+            bytes[offset + 1],
+            bytes[offset + 2],
+            bytes[offset + 3],
+        ])
+    };
+
+    // Format tag, channels, sample rate and bits per sample
+    let mut format = None;
+    let mut data = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = u32_at(offset + 4) as usize;
+        let body = offset + 8;
+        // Sizes capped by writers that ran out of room reach to the end
+        let end = body.saturating_add(size).min(bytes.len());
+
+        match id {
+            b"fmt " if end - body >= 16 => {
+                let mut tag = u16_at(body);
+                if tag == FORMAT_EXTENSIBLE && end - body >= 26 {
+                    tag = u16_at(body + 24);
+                }
+                format = Some((tag, u16_at(body + 2), u32_at(body + 4), u16_at(body + 14)));
+            }
+            b"data" => data = Some(&bytes[body..end]),
+            _ => {}
+        }
+
+        // Chunks are padded to an even size
+        offset = body.saturating_add(size).saturating_add(size % 2);
+    }
+
+    let (tag, channels, sample_rate, bits) = format.ok_or_else(|| invalid("missing fmt chunk"))?;
+    let data = data.ok_or_else(|| invalid("missing data chunk"))?;
+    if channels == 0 || sample_rate == 0 {
+        return Err(invalid("invalid format"));
+    }
+
+    let mut samples: Vec<f32> = match (tag, bits) {
+        (FORMAT_PCM, 8) => data
+            .iter()
+            .map(|&sample| (sample as f32 - 128.0) / 128.0)
+            .collect(),
+        (FORMAT_PCM, 16) => data
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]) as f32 / 32_768.0)
+            .collect(),
+        (FORMAT_PCM, 24) => data
+            .chunks_exact(3)
+            .map(|sample| {
+                let value = i32::from_le_bytes([0, sample[0], sample[1], sample[2]]) >> 8;
+                value as f32 / 8_388_608.0
+            })
+            .collect(),
+        (FORMAT_PCM, 32) => data
+            .chunks_exact(4)
+            .map(|sample| {
+                let value = i32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]);
+                value as f32 / 2_147_483_648.0
+            })
+            .collect(),
+        (FORMAT_FLOAT, 32) => data
+            .chunks_exact(4)
+            .map(|sample| f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]))
+            .collect(),
+        (FORMAT_FLOAT, 64) => data
+            .chunks_exact(8)
+            .map(|sample| f64::from_le_bytes(sample.try_into().unwrap_or_default()) as f32)
+            .collect(),
+        _ => {
+            return Err(invalid(&format!(
+                "unsupported format {tag} with {bits} bits per sample"
+            )))
+        }
+    };
+
+    // A partial frame at the end is left out
+    samples.truncate(samples.len() / channels as usize * channels as usize);
+
+    Ok(WavAudio {
+        sample_rate,
+        channels,
+        samples,
+    })
+}
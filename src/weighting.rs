@@ -0,0 +1,174 @@
+//! A and C frequency weighting filters for the level meters so that
+//! measured levels are comparable to the weighted noise figures on the
+//! spec sheets of microphones and interfaces
+
+use std::{f64::consts::PI, str::FromStr};
+
+/// Analog pole frequencies of the weighting curves from IEC 61672
+const POLE_LOW: f64 = 20.598997;
+const POLE_MID_LOW: f64 = 107.65265;
+const POLE_MID_HIGH: f64 = 737.86223;
+const POLE_HIGH: f64 = 12194.217;
+/// Frequency the weighted response is normalized to 0 dB at
+const REFERENCE_FREQUENCY: f64 = 1000.0;
+
+/// Frequency weighting applied to the measured levels
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Weighting {
+    /// No weighting, every frequency counts equally
+    #[default]
+    Z,
+    /// A-weighting, follows the sensitivity of hearing at low levels and
+    /// is used for noise figures
+    A,
+    /// C-weighting, nearly flat with the extreme lows and highs reduced
+    C,
+}
+
+impl Weighting {
+    /// Suffix added to decibel values measured with the weighting
+    /// (e.g. "(A)" for "dB(A)"), empty without weighting
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Weighting::Z => "",
+            Weighting::A => "(A)",
+            Weighting::C => "(C)",
+        }
+    }
+}
+
+impl FromStr for Weighting {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "z" | "none" => Ok(Weighting::Z),
+            "a" => Ok(Weighting::A),
+            "c" => Ok(Weighting::C),
+            _ => Err(format!(
+                "unknown weighting \"{value}\" (expected a, c or z)"
+            )),
+        }
+    }
+}
+
+/// Second order filter section with the leading denominator
+/// coefficient normalized to one
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+}
+
+impl Biquad {
+    /// Creates a section from a pair of real `zeros` and `poles` in
+    /// the z-plane
+    fn from_roots(zeros: [f64; 2], poles: [f64; 2]) -> Self {
+        Self {
+            b: [1.0, -(zeros[0] + zeros[1]), zeros[0] * zeros[1]],
+            a: [-(poles[0] + poles[1]), poles[0] * poles[1]],
+        }
+    }
+
+    /// Magnitude of the response at the normalized `frequency` in
+    /// radians per sample
+    fn magnitude(&self, frequency: f64) -> f64 {
+        // Evaluates the polynomials at e^-jw
+        let evaluate = |coefficients: [f64; 3]| {
+            let (mut real, mut imaginary) = (0.0, 0.0);
+            for (index, coefficient) in coefficients.into_iter().enumerate() {
+                let angle = -frequency * index as f64;
+                real += coefficient * angle.cos();
+                imaginary += coefficient * angle.sin();
+            }
+            real.hypot(imaginary)
+        };
+
+        evaluate(self.b) / evaluate([1.0, self.a[0], self.a[1]])
+    }
+}
+
+/// Weighting filter for interleaved samples keeping separate state for
+/// each channel
+pub struct WeightingFilter {
+    /// Sections applied one after another
+    sections: Vec<Biquad>,
+    /// Gain that brings the response to 0 dB at the reference frequency
+    gain: f64,
+    /// Number of interleaved channels
+    channels: usize,
+    /// Two state values for each section of each channel
+    state: Vec<[f64; 2]>,
+    /// Filtered samples returned by [WeightingFilter::process]
+    output: Vec<f32>,
+}
+
+impl WeightingFilter {
+    /// Creates the filter for the `weighting` at the `sample_rate`,
+    /// [None] when the weighting doesn't filter
+    pub fn new(weighting: Weighting, sample_rate: u32, channels: u16) -> Option<Self> {
+        let sample_rate = sample_rate as f64;
+        // Analog poles mapped to the z-plane with the bilinear transform,
+        // prewarped so the high pole stays in place at the common sample
+        // rates. The zeros at zero frequency map to 1 and those at
+        // infinity to -1
+        let pole = |frequency: f64| {
+            let omega = 2.0 * sample_rate * (PI * frequency / sample_rate).tan();
+            (2.0 * sample_rate - omega) / (2.0 * sample_rate + omega)
+        };
+        let (low, high) = (pole(POLE_LOW), pole(POLE_HIGH));
+
+        let sections = match weighting {
+            Weighting::Z => return None,
+            Weighting::A => vec![
+                Biquad::from_roots([-1.0, -1.0], [high, high]),
+                Biquad::from_roots([1.0, 1.0], [low, low]),
+                Biquad::from_roots([1.0, 1.0], [pole(POLE_MID_LOW), pole(POLE_MID_HIGH)]),
+            ],
+            Weighting::C => vec![
+                Biquad::from_roots([-1.0, -1.0], [high, high]),
+                Biquad::from_roots([1.0, 1.0], [low, low]),
+            ],
+        };
+
+        let reference = 2.0 * PI * REFERENCE_FREQUENCY / sample_rate;
+        let gain = 1.0
+            / sections
+                .iter()
+                .map(|section| section.magnitude(reference))
+                .product::<f64>();
+
+        let channels = channels.max(1) as usize;
+        Some(Self {
+            state: vec![[0.0; 2]; sections.len() * channels],
+            sections,
+            gain,
+            channels,
+            output: Vec::new(),
+        })
+    }
+
+    /// Filters the interleaved `samples` returning the weighted samples
+    pub fn process(&mut self, samples: &[f32]) -> &[f32] {
+        self.output.clear();
+        self.output.reserve(samples.len());
+
+        for (index, &sample) in samples.iter().enumerate() {
+            let channel = index % self.channels;
+            let states = &mut self.state[channel * self.sections.len()..];
+
+            let mut value = sample as f64 * self.gain;
+            // Transposed direct form II
+            for (section, state) in self.sections.iter().zip(states.iter_mut()) {
+                let output = section.b[0] * value + state[0];
+                state[0] = section.b[1] * value - section.a[0] * output + state[1];
+                state[1] = section.b[2] * value - section.a[1] * output;
+                value = output;
+            }
+
+            self.output.push(value as f32);
+        }
+
+        &self.output
+    }
+}
@@ -0,0 +1,149 @@
+//! Worker thread that runs the sample rate and channel conversion and
+//! the effects, filling a buffer that the output callback only copies
+//! from so heavier processing doesn't delay the audio callbacks. The
+//! buffer is only filled up to the target of the jitter buffer
+
+use crate::{
+    bed::Bed,
+    controls::Controls,
+    jitter::{JitterTarget, MAX_TARGET},
+    processor::Processor,
+    record::TrackProducer,
+    simd, ChannelConverter, SampleConverter,
+};
+use cpal::StreamConfig;
+use ringbuf::{HeapConsumer, HeapRb};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Number of frames processed at a time
+const BLOCK_FRAMES: usize = 256;
+/// Time to wait when there is no input or the output buffer is filled
+const IDLE_WAIT: Duration = Duration::from_millis(1);
+
+/// Processing applied by the worker after the conversion
+pub struct Effects {
+    /// Custom stages applied in order before the controls
+    pub processors: Vec<Box<dyn Processor>>,
+    /// Mute, volume and polarity applied to the samples
+    pub controls: Arc<Controls>,
+    /// Beds mixed in after the controls are applied
+    pub beds: Vec<Bed>,
+    /// Processed samples for the recording
+    pub recording: Option<TrackProducer>,
+}
+
+/// Background thread moving samples from the input ring buffer through
+/// the converters into the output ring buffer
+pub struct Worker {
+    /// Whether the worker should stop
+    stop: Arc<AtomicBool>,
+    /// Thread doing the processing
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    /// Starts converting the samples from the `converter`, audio in the
+    /// `input_config` format is converted to the `output_config` format
+    /// and can be taken from the returned consumer once the `effects` are
+    /// applied. The consumer is kept filled up to the jitter `target`
+    pub fn start(
+        mut channel_converter: ChannelConverter,
+        mut converter: SampleConverter,
+        input_config: &StreamConfig,
+        output_config: &StreamConfig,
+        effects: Effects,
+        target: Arc<JitterTarget>,
+    ) -> (Self, HeapConsumer<f32>) {
+        let Effects {
+            mut processors,
+            controls,
+            mut beds,
+            mut recording,
+        } = effects;
+        let channels = output_config.channels.max(1) as usize;
+        let block = BLOCK_FRAMES * channels;
+
+        let capacity =
+            (output_config.sample_rate.0 as f64 * MAX_TARGET.as_secs_f64()) as usize * channels;
+        let ring: HeapRb<f32> = HeapRb::new(capacity + block * 2);
+        let (mut output, consumer) = ring.split();
+
+        // Input samples required to produce a block, with room for the
+        // samples held by the interpolator
+        let required = (block as f64
+            * channel_converter.input_per_output()
+            * input_config.sample_rate.0 as f64
+            / output_config.sample_rate.0 as f64)
+            .ceil() as usize
+            + 2 * input_config.channels as usize;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                let mut buffer = vec![0.0f32; block];
+                let mut scratch = Vec::new();
+
+                while !stop.load(Ordering::Relaxed) {
+                    // Only convert when the whole block can be produced
+                    // from real input, the output callback inserts the
+                    // silence when the input falls behind. Filling stops
+                    // at the jitter target so the latency stays low
+                    if output.len() >= target.samples()
+                        || output.free_len() < block
+                        || converter.input_available() < required
+                    {
+                        thread::sleep(IDLE_WAIT);
+                        continue;
+                    }
+
+                    channel_converter.fill(&mut converter, &mut scratch, &mut buffer);
+                    for processor in &mut processors {
+                        processor.process(&mut buffer);
+                    }
+
+                    let factor = controls.factor();
+                    if factor != 1.0 {
+                        simd::scale(&mut buffer, factor);
+                    }
+                    // The beds follow the volume but not the polarity or
+                    // the sensitivity offset of the microphone
+                    for bed in &mut beds {
+                        bed.mix_into(&mut buffer, controls.volume_factor());
+                    }
+
+                    output.push_slice(&buffer);
+                    if let Some(recording) = &mut recording {
+                        recording.push(&buffer);
+                    }
+                }
+            }
+        });
+
+        (
+            Self {
+                stop,
+                handle: Some(handle),
+            },
+            consumer,
+        )
+    }
+}
+
+/// Processing stops when the worker is dropped, including when opening
+/// the streams fails after the worker was started
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            _ = handle.join();
+        }
+    }
+}